@@ -0,0 +1,226 @@
+//! Throughput and latency of `LockFreeQueue`, `LockFreeStack` and `HazardEpoch`, compared with
+//! `Mutex<VecDeque>`, `Mutex<Vec>` and `crossbeam`.
+//!
+//! Run by `cargo +nightly bench --bench bench_containers`. Each iteration is one pair of
+//! operations in every thread, so the reported time is the latency of a pair under contention and
+//! the reported throughput counts operations of all threads.
+//!
+#[macro_use]
+extern crate criterion;
+extern crate crossbeam;
+extern crate rs_lockfree;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use crossbeam::epoch;
+use crossbeam::queue::SegQueue;
+use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch, HazardNodeT};
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use rs_lockfree::lockfree_stack::LockFreeStack;
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const THREAD_COUNTS: [usize; 4] = [1, 2, 4, 8];
+
+struct SharedPtr<T>(*mut T);
+
+unsafe impl<T> Send for SharedPtr<T> {}
+
+unsafe impl<T> Sync for SharedPtr<T> {}
+
+impl<T> SharedPtr<T> {
+    fn as_mut(&self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+type Job = Box<Fn(u64) + Send + Sync>;
+
+/// Worker threads are created once and reused by all benchmarks, because `HazardEpoch` never
+/// recycles thread ids.
+struct Pool {
+    senders: Vec<mpsc::Sender<(Arc<Job>, u64, Arc<Barrier>)>>,
+    elapsed: mpsc::Receiver<Duration>,
+}
+
+impl Pool {
+    fn new(size: usize) -> Pool {
+        let (elapsed_sender, elapsed) = mpsc::channel();
+        let senders = (0..size)
+            .map(|_| {
+                let (sender, receiver) = mpsc::channel::<(Arc<Job>, u64, Arc<Barrier>)>();
+                let elapsed_sender = elapsed_sender.clone();
+                thread::spawn(move || {
+                    for (job, iters, barrier) in receiver {
+                        barrier.wait();
+                        let start = Instant::now();
+                        job(iters);
+                        elapsed_sender.send(start.elapsed()).unwrap();
+                    }
+                });
+                sender
+            })
+            .collect();
+        Pool { senders, elapsed }
+    }
+
+    /// Run `job` with `iters` in `threads` workers at the same time, and return the longest time.
+    fn run(&self, threads: usize, iters: u64, job: Job) -> Duration {
+        let job = Arc::new(job);
+        let barrier = Arc::new(Barrier::new(threads));
+        for sender in &self.senders[..threads] {
+            sender.send((job.clone(), iters, barrier.clone())).unwrap();
+        }
+        (0..threads)
+            .map(|_| self.elapsed.recv().unwrap())
+            .max()
+            .unwrap()
+    }
+}
+
+fn bench_queue(c: &mut Criterion, pool: &Pool) {
+    let mut group = c.benchmark_group("queue");
+    for &threads in THREAD_COUNTS.iter() {
+        group.throughput(Throughput::Elements(2 * threads as u64));
+        group.bench_with_input(BenchmarkId::new("LockFreeQueue", threads), &threads, |b, &n| {
+            let queue = Arc::new(SharedPtr(Box::into_raw(LockFreeQueue::default_new_in_heap())));
+            b.iter_custom(|iters| {
+                let queue = queue.clone();
+                pool.run(n, iters, Box::new(move |iters| {
+                    for i in 0..iters {
+                        queue.as_mut().push(i);
+                        criterion::black_box(queue.as_mut().pop());
+                    }
+                }))
+            });
+            unsafe { drop(Box::from_raw(queue.0)) };
+        });
+        group.bench_with_input(BenchmarkId::new("Mutex<VecDeque>", threads), &threads, |b, &n| {
+            let queue = Arc::new(Mutex::new(VecDeque::new()));
+            b.iter_custom(|iters| {
+                let queue = queue.clone();
+                pool.run(n, iters, Box::new(move |iters| {
+                    for i in 0..iters {
+                        queue.lock().unwrap().push_back(i);
+                        criterion::black_box(queue.lock().unwrap().pop_front());
+                    }
+                }))
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("SegQueue", threads), &threads, |b, &n| {
+            let queue = Arc::new(SegQueue::new());
+            b.iter_custom(|iters| {
+                let queue = queue.clone();
+                pool.run(n, iters, Box::new(move |iters| {
+                    for i in 0..iters {
+                        queue.push(i);
+                        criterion::black_box(queue.pop());
+                    }
+                }))
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_stack(c: &mut Criterion, pool: &Pool) {
+    let mut group = c.benchmark_group("stack");
+    for &threads in THREAD_COUNTS.iter() {
+        group.throughput(Throughput::Elements(2 * threads as u64));
+        group.bench_with_input(BenchmarkId::new("LockFreeStack", threads), &threads, |b, &n| {
+            let stack = Arc::new(SharedPtr(Box::into_raw(LockFreeStack::default_new_in_heap())));
+            b.iter_custom(|iters| {
+                let stack = stack.clone();
+                pool.run(n, iters, Box::new(move |iters| {
+                    for i in 0..iters {
+                        stack.as_mut().push(i);
+                        criterion::black_box(stack.as_mut().pop());
+                    }
+                }))
+            });
+            unsafe { drop(Box::from_raw(stack.0)) };
+        });
+        group.bench_with_input(BenchmarkId::new("Mutex<Vec>", threads), &threads, |b, &n| {
+            let stack = Arc::new(Mutex::new(Vec::new()));
+            b.iter_custom(|iters| {
+                let stack = stack.clone();
+                pool.run(n, iters, Box::new(move |iters| {
+                    for i in 0..iters {
+                        stack.lock().unwrap().push(i);
+                        criterion::black_box(stack.lock().unwrap().pop());
+                    }
+                }))
+            });
+        });
+    }
+    group.finish();
+}
+
+struct RetiredObj {
+    base: BaseHazardNode,
+    _v: u64,
+}
+
+impl HazardNodeT for RetiredObj {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut BaseHazardNode
+    }
+}
+
+impl Drop for RetiredObj {
+    fn drop(&mut self) {}
+}
+
+/// One iteration protects a critical section, then retires an object, which is the pattern of
+/// containers. The cost of reclamation is included.
+fn bench_reclamation(c: &mut Criterion, pool: &Pool) {
+    let mut group = c.benchmark_group("reclamation");
+    for &threads in THREAD_COUNTS.iter() {
+        group.throughput(Throughput::Elements(threads as u64));
+        group.bench_with_input(BenchmarkId::new("HazardEpoch", threads), &threads, |b, &n| {
+            let hazard_epoch = Arc::new(SharedPtr(Box::into_raw(HazardEpoch::default_new_in_heap())));
+            b.iter_custom(|iters| {
+                let hazard_epoch = hazard_epoch.clone();
+                pool.run(n, iters, Box::new(move |iters| {
+                    let hazard_epoch = hazard_epoch.as_mut();
+                    for i in 0..iters {
+                        let handle = hazard_epoch.try_acquire().unwrap();
+                        let obj = Box::into_raw(Box::new(RetiredObj {
+                            base: BaseHazardNode::default(),
+                            _v: i,
+                        }));
+                        unsafe {
+                            hazard_epoch.release(handle);
+                            hazard_epoch.try_add_node(obj).unwrap();
+                        }
+                    }
+                }))
+            });
+            unsafe { drop(Box::from_raw(hazard_epoch.0)) };
+        });
+        group.bench_with_input(BenchmarkId::new("crossbeam-epoch", threads), &threads, |b, &n| {
+            b.iter_custom(|iters| {
+                pool.run(n, iters, Box::new(move |iters| {
+                    for i in 0..iters {
+                        let guard = epoch::pin();
+                        let obj = epoch::Owned::new(i).into_shared(&guard);
+                        unsafe { guard.defer_destroy(obj) };
+                    }
+                }))
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_containers(c: &mut Criterion) {
+    let pool = Pool::new(THREAD_COUNTS[THREAD_COUNTS.len() - 1]);
+    bench_queue(c, &pool);
+    bench_stack(c, &pool);
+    bench_reclamation(c, &pool);
+}
+
+criterion_group!(benches, bench_containers);
+criterion_main!(benches);
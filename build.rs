@@ -0,0 +1,43 @@
+#[cfg(feature = "ffi")]
+extern crate cbindgen;
+
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    use std::env;
+    use std::path::Path;
+
+    // Never write the source tree, which may be read-only, like a registry checkout. The copy in
+    // `include` is refreshed by running `cbindgen` explicitly.
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let config = cbindgen::Config::from_file(Path::new(&crate_dir).join("cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("unable to generate C header")
+        .write_to_file(Path::new(&out_dir).join("rs_lockfree.h"));
+}
+
+/// Return true if target has no threads, like `wasm32-unknown-unknown` without feature
+/// `atomics`, or feature `single_thread` is enabled.
+fn is_single_thread() -> bool {
+    use std::env;
+
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    env::var("CARGO_FEATURE_SINGLE_THREAD").is_ok()
+        || ("wasm32" == arch && !features.split(',').any(|f| "atomics" == f))
+}
+
+fn main() {
+    #[cfg(feature = "ffi")]
+    generate_header();
+    println!("cargo:rustc-check-cfg=cfg(single_thread)");
+    if is_single_thread() {
+        println!("cargo:rustc-cfg=single_thread");
+    }
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=src/error.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
@@ -1,6 +1,5 @@
 #![feature(core_intrinsics)]
 
-extern crate core_affinity;
 extern crate env_logger;
 extern crate rs_lockfree;
 #[macro_use]
@@ -14,7 +13,6 @@ use std::ops::DerefMut;
 use std::time;
 use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch, HazardNodeT};
 use rs_lockfree::util;
-use rs_lockfree::error::Status;
 use std::ptr;
 
 struct TestObj {
@@ -87,14 +85,24 @@ impl GlobalControl {
     }
 }
 
+#[cfg(feature = "affinity")]
 fn set_cpu_affinity() {
-    let cpus = core_affinity::get_core_ids().unwrap();
-    core_affinity::set_for_current(cpus[util::get_thread_id() as usize % cpus.len()]);
-    info!(
-        "set_cpu_affinity {} {}",
-        util::get_thread_id(),
-        util::get_thread_id() as usize % cpus.len()
-    );
+    let cpu = util::affinity::pin_round_robin().unwrap();
+    info!("set_cpu_affinity {} {}", util::get_thread_id(), cpu);
+}
+
+/// Threads are left to the scheduler without feature `affinity`.
+#[cfg(not(feature = "affinity"))]
+fn set_cpu_affinity() {}
+
+#[cfg(feature = "affinity")]
+fn cpu_count() -> i64 {
+    util::affinity::cpus().len() as i64
+}
+
+#[cfg(not(feature = "affinity"))]
+fn cpu_count() -> i64 {
+    thread::available_parallelism().map_or(1, |n| n.get()) as i64
 }
 
 unsafe fn reader_thread_func(mut global_control: ShardPtr<GlobalControl>) {
@@ -103,9 +111,7 @@ unsafe fn reader_thread_func(mut global_control: ShardPtr<GlobalControl>) {
     let mut tol = 0;
     let read_loops = global_control.read_loops;
     for _ in 0..read_loops {
-        let mut handle = 0u64;
-        let ret = global_control.h.acquire(&mut handle);
-        assert_eq!(ret, Status::Success);
+        let handle = global_control.h.try_acquire().unwrap();
         let v = util::atomic_load_raw_ptr(&global_control.v);
         assert!((*v).data.is_some());
         global_control.h.release(handle);
@@ -139,7 +145,7 @@ unsafe fn producer_thread_func(mut global_control: ShardPtr<GlobalControl>) {
             global_control.add_written_cnt(tol);
             tol = 0;
         }
-        global_control.h.add_node(old);
+        global_control.h.try_add_node(old).unwrap();
     }
     global_control.add_written_cnt(tol);
 }
@@ -206,7 +212,7 @@ fn main() {
 fn run() {
     env_logger::init();
 
-    let cpu_count = core_affinity::get_core_ids().unwrap().len() as i64;
+    let cpu_count = cpu_count();
 
     let read_count = (cpu_count + 1) / 2;
     let write_count = (cpu_count + 1) / 2;
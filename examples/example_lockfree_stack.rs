@@ -1,6 +1,5 @@
 #![feature(core_intrinsics)]
 
-extern crate core_affinity;
 extern crate rs_lockfree;
 #[macro_use]
 extern crate log;
@@ -69,14 +68,24 @@ impl<T> DerefMut for ShardPtr<T> {
     }
 }
 
+#[cfg(feature = "affinity")]
 fn set_cpu_affinity() {
-    let cpus = core_affinity::get_core_ids().unwrap();
-    core_affinity::set_for_current(cpus[util::get_thread_id() as usize % cpus.len()]);
-    info!(
-        "set_cpu_affinity {} {}",
-        util::get_thread_id(),
-        util::get_thread_id() as usize % cpus.len()
-    );
+    let cpu = util::affinity::pin_round_robin().unwrap();
+    info!("set_cpu_affinity {} {}", util::get_thread_id(), cpu);
+}
+
+/// Threads are left to the scheduler without feature `affinity`.
+#[cfg(not(feature = "affinity"))]
+fn set_cpu_affinity() {}
+
+#[cfg(feature = "affinity")]
+fn cpu_count() -> i64 {
+    util::affinity::cpus().len() as i64
+}
+
+#[cfg(not(feature = "affinity"))]
+fn cpu_count() -> i64 {
+    thread::available_parallelism().map_or(1, |n| n.get()) as i64
 }
 
 unsafe fn consumer_thread(mut global_control: ShardPtr<GlobalControl>) {
@@ -143,7 +152,7 @@ unsafe fn debug_thread(mut global_control: ShardPtr<GlobalControl>) {
 fn test_multi_threads() {
     env_logger::init();
 
-    let cpu_count = core_affinity::get_core_ids().unwrap().len() as i64;
+    let cpu_count = cpu_count();
 
     let producer_count = (cpu_count + 1) / 2;
     let consumer_count = cpu_count - producer_count;
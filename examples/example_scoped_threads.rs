@@ -0,0 +1,134 @@
+extern crate env_logger;
+extern crate rs_lockfree;
+#[macro_use]
+extern crate log;
+
+use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch, HazardNodeT};
+use rs_lockfree::util;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::thread;
+
+struct Config {
+    base: BaseHazardNode,
+    generation: i64,
+    live: *const AtomicI64,
+}
+
+impl HazardNodeT for Config {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut BaseHazardNode
+    }
+}
+
+impl Drop for Config {
+    fn drop(&mut self) {
+        unsafe { (*self.live).fetch_sub(1, Ordering::SeqCst) };
+    }
+}
+
+impl Config {
+    fn new(generation: i64, live: &AtomicI64) -> *mut Config {
+        live.fetch_add(1, Ordering::SeqCst);
+        Box::into_raw(Box::new(Config {
+            base: BaseHazardNode::default(),
+            generation,
+            live,
+        }))
+    }
+}
+
+struct SharedPtr<T>(*mut T);
+
+unsafe impl<T> Send for SharedPtr<T> {}
+
+unsafe impl<T> Sync for SharedPtr<T> {}
+
+impl<T> SharedPtr<T> {
+    fn as_mut(&self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+/// Replace the config `loops` times, retiring old ones.
+fn writer(
+    h: &mut HazardEpoch,
+    config: &util::AtomicRawPtr<Config>,
+    live: &AtomicI64,
+    loops: i64,
+) {
+    for i in 0..loops {
+        let old = config.swap(Config::new(i, live), Ordering::SeqCst);
+        unsafe { h.try_add_node(old).unwrap() };
+    }
+}
+
+/// Read the config `loops` times, and return the newest generation seen.
+fn reader(h: &mut HazardEpoch, config: &util::AtomicRawPtr<Config>, loops: i64) -> i64 {
+    let mut newest = -1;
+    for _ in 0..loops {
+        let handle = h.try_acquire().unwrap();
+        let generation = unsafe { (*config.load(Ordering::SeqCst)).generation };
+        unsafe { h.release(handle) };
+        newest = newest.max(generation);
+    }
+    newest
+}
+
+/// Crash in the middle of an access, with a handle held.
+fn faulty_reader(h: &mut HazardEpoch, config: &util::AtomicRawPtr<Config>) {
+    let _handle = h.try_acquire().unwrap();
+    let generation = unsafe { (*config.load(Ordering::SeqCst)).generation };
+    panic!("faulty reader crashed at generation {}", generation);
+}
+
+// Short-lived workers borrow the config and `HazardEpoch` from `main` by scoped threads, and each
+// wraps its work in `HazardEpoch::scope`, so it leaves no held handle behind when it exits, even by
+// a panic, and the configs it retired are reclaimed unless others still read them.
+fn main() {
+    env_logger::init();
+
+    let loops = 100000;
+    let live = AtomicI64::new(0);
+    let config = util::AtomicRawPtr::new(Config::new(-1, &live));
+    let mut h = HazardEpoch::default_new_in_heap();
+    let h_ptr = SharedPtr(&mut *h as *mut HazardEpoch);
+
+    // Thread IDs are never reused, so 4 workers per round fit in the default 16 slots.
+    for round in 0..3 {
+        thread::scope(|s| {
+            let mut readers = vec![];
+            for _ in 0..2 {
+                readers.push(s.spawn(|| {
+                    h_ptr
+                        .as_mut()
+                        .scope(|h| reader(h, &config, loops))
+                        .unwrap()
+                }));
+            }
+            let faulty = s.spawn(|| h_ptr.as_mut().scope(|h| faulty_reader(h, &config)).unwrap());
+            s.spawn(|| {
+                h_ptr
+                    .as_mut()
+                    .scope(|h| writer(h, &config, &live, loops))
+                    .unwrap();
+            });
+            for r in readers {
+                info!("round {} reader saw generation {}", round, r.join().unwrap());
+            }
+            assert!(faulty.join().is_err());
+        });
+        // Configs retired while others read them wait in the store of the writer, which has exited.
+        unsafe { h.flush_all() };
+        info!(
+            "round {} waiting_count {} live configs {}",
+            round,
+            h.atomic_load_hazard_waiting_count(),
+            live.load(Ordering::SeqCst)
+        );
+        assert_eq!(h.atomic_load_hazard_waiting_count(), 0);
+        assert_eq!(live.load(Ordering::SeqCst), 1);
+    }
+
+    unsafe { drop(Box::from_raw(config.swap(std::ptr::null_mut(), Ordering::SeqCst))) };
+    assert_eq!(live.load(Ordering::SeqCst), 0);
+}
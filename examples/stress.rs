@@ -0,0 +1,247 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::hazard_epoch::MAX_THREAD_COUNT;
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use rs_lockfree::lockfree_stack::LockFreeStack;
+use rs_lockfree::tagged_stack::TaggedLockFreeStack;
+use std::env;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: stress [options]
+    --structure <queue|stack|tagged_stack>  structure to stress, default queue
+    --threads <n>                           worker threads, default 4
+    --push-percent <0-100>                  percentage of pushes in the operation mix, default 50
+    --element-size <bytes>                  payload bytes of each element, default 16
+    --prefill <n>                           elements pushed before workers start, default 0
+    --duration-ms <ms>                      running time of workers, default 1000";
+
+/// Element carrying `id` and a payload filled with its low byte, checked when popped.
+struct Element {
+    id: u64,
+    payload: Vec<u8>,
+}
+
+impl Element {
+    fn new(id: u64, size: usize) -> Element {
+        Element {
+            id,
+            payload: vec![id as u8; size],
+        }
+    }
+
+    fn check(&self, size: usize) -> bool {
+        self.payload.len() == size && self.payload.iter().all(|&b| b == self.id as u8)
+    }
+}
+
+trait Structure: 'static {
+    fn push(&mut self, v: Element);
+    fn pop(&mut self) -> Option<Element>;
+}
+
+impl Structure for LockFreeQueue<Element> {
+    fn push(&mut self, v: Element) {
+        LockFreeQueue::push(self, v)
+    }
+
+    fn pop(&mut self) -> Option<Element> {
+        LockFreeQueue::pop(self)
+    }
+}
+
+impl Structure for LockFreeStack<Element> {
+    fn push(&mut self, v: Element) {
+        LockFreeStack::push(self, v)
+    }
+
+    fn pop(&mut self) -> Option<Element> {
+        LockFreeStack::pop(self)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Structure for TaggedLockFreeStack<Element> {
+    fn push(&mut self, v: Element) {
+        TaggedLockFreeStack::push(self, v)
+    }
+
+    fn pop(&mut self) -> Option<Element> {
+        TaggedLockFreeStack::pop(self)
+    }
+}
+
+struct Config {
+    structure: String,
+    threads: usize,
+    push_percent: u64,
+    element_size: usize,
+    prefill: u64,
+    duration: Duration,
+}
+
+#[derive(Default)]
+struct Counter {
+    pushes: u64,
+    pops: u64,
+    empty_pops: u64,
+    corrupted: u64,
+}
+
+fn parse_args() -> Result<Config, String> {
+    let mut config = Config {
+        structure: "queue".to_string(),
+        threads: 4,
+        push_percent: 50,
+        element_size: 16,
+        prefill: 0,
+        duration: Duration::from_millis(1000),
+    };
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        if "--help" == flag || "-h" == flag {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        let value = args.next()
+            .ok_or_else(|| format!("missing value of {}", flag))?;
+        let invalid = |_| format!("invalid value of {}: {}", flag, value);
+        match flag.as_str() {
+            "--structure" => config.structure = value.clone(),
+            "--threads" => config.threads = value.parse().map_err(invalid)?,
+            "--push-percent" => config.push_percent = value.parse().map_err(invalid)?,
+            "--element-size" => config.element_size = value.parse().map_err(invalid)?,
+            "--prefill" => config.prefill = value.parse().map_err(invalid)?,
+            "--duration-ms" => {
+                config.duration = Duration::from_millis(value.parse().map_err(invalid)?)
+            }
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    // Workers and the main thread each take a thread id of `HazardEpoch`.
+    if 0 == config.threads || config.threads >= MAX_THREAD_COUNT {
+        return Err(format!("--threads must be in [1, {})", MAX_THREAD_COUNT));
+    }
+    if 100 < config.push_percent {
+        return Err("--push-percent must be in [0, 100]".to_string());
+    }
+    Ok(config)
+}
+
+fn xorshift(seed: &mut u64) -> u64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed
+}
+
+fn stress<S: Structure>(structure: Box<S>, config: &Config) {
+    let addr = Box::into_raw(structure) as usize;
+    let s = unsafe { &mut *(addr as *mut S) };
+    for i in 0..config.prefill {
+        s.push(Element::new(i, config.element_size));
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let barrier = Arc::new(Barrier::new(config.threads + 1));
+    let workers: Vec<_> = (0..config.threads)
+        .map(|tid| {
+            let stop = stop.clone();
+            let barrier = barrier.clone();
+            let (push_percent, element_size) = (config.push_percent, config.element_size);
+            thread::spawn(move || {
+                let s = unsafe { &mut *(addr as *mut S) };
+                let mut counter = Counter::default();
+                let mut seed = tid as u64 + 1;
+                let mut seq = 0;
+                barrier.wait();
+                while !stop.load(Ordering::Relaxed) {
+                    if xorshift(&mut seed) % 100 < push_percent {
+                        seq += 1;
+                        s.push(Element::new(((tid as u64 + 1) << 40) | seq, element_size));
+                        counter.pushes += 1;
+                    } else {
+                        match s.pop() {
+                            Some(v) => {
+                                counter.pops += 1;
+                                counter.corrupted += !v.check(element_size) as u64;
+                            }
+                            None => counter.empty_pops += 1,
+                        }
+                    }
+                }
+                counter
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let start = Instant::now();
+    thread::sleep(config.duration);
+    stop.store(true, Ordering::Relaxed);
+    let counters: Vec<Counter> = workers.into_iter().map(|t| t.join().unwrap()).collect();
+    let elapsed = start.elapsed();
+
+    let mut remaining = 0;
+    let mut corrupted: u64 = counters.iter().map(|c| c.corrupted).sum();
+    while let Some(v) = s.pop() {
+        remaining += 1;
+        corrupted += !v.check(config.element_size) as u64;
+    }
+    drop(unsafe { Box::from_raw(addr as *mut S) });
+
+    let pushes: u64 = counters.iter().map(|c| c.pushes).sum();
+    let pops: u64 = counters.iter().map(|c| c.pops).sum();
+    let empty_pops: u64 = counters.iter().map(|c| c.empty_pops).sum();
+    let ops = pushes + pops + empty_pops;
+    let per_thread: Vec<u64> = counters
+        .iter()
+        .map(|c| c.pushes + c.pops + c.empty_pops)
+        .collect();
+    let lost = (config.prefill + pushes) as i64 - (pops + remaining) as i64;
+    let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+    println!(
+        "{{\"structure\":\"{}\",\"threads\":{},\"push_percent\":{},\"element_size\":{},\
+         \"prefill\":{},\"duration_ms\":{},\"ops\":{},\"ops_per_sec\":{:.0},\"pushes\":{},\
+         \"pops\":{},\"empty_pops\":{},\"remaining\":{},\"per_thread_ops\":{:?},\"lost\":{},\
+         \"corrupted\":{},\"ok\":{}}}",
+        config.structure,
+        config.threads,
+        config.push_percent,
+        config.element_size,
+        config.prefill,
+        elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis()),
+        ops,
+        ops as f64 / secs,
+        pushes,
+        pops,
+        empty_pops,
+        remaining,
+        per_thread,
+        lost,
+        corrupted,
+        0 == lost && 0 == corrupted
+    );
+    if 0 != lost || 0 != corrupted {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let config = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+    match config.structure.as_str() {
+        "queue" => stress(LockFreeQueue::default_new_in_heap(), &config),
+        "stack" => stress(LockFreeStack::default_new_in_heap(), &config),
+        "tagged_stack" => stress(TaggedLockFreeStack::default_new_in_heap(), &config),
+        _ => {
+            eprintln!("unknown structure {}\n{}", config.structure, USAGE);
+            process::exit(2);
+        }
+    }
+}
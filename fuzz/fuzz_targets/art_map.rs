@@ -0,0 +1,66 @@
+//! Random operations on `ArtMap` checked against `BTreeMap`.
+//!
+#![no_main]
+#[macro_use]
+extern crate arbitrary;
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rs_lockfree;
+
+mod common;
+
+use common::Tracked;
+use rs_lockfree::art_map::ArtMap;
+use std::collections::BTreeMap;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Insert(Vec<u8>, u16),
+    Get(Vec<u8>),
+    Remove(Vec<u8>),
+    ScanPrefix(Vec<u8>),
+}
+
+/// Keys are short and made of few bytes, so that they share prefixes and split nodes often.
+fn key(raw: Vec<u8>) -> Vec<u8> {
+    raw.into_iter().take(6).map(|b| b % 4).collect()
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let base = common::live();
+    {
+        let mut map = ArtMap::default_new_in_heap();
+        let mut model = BTreeMap::new();
+        for op in ops {
+            match op {
+                Op::Insert(k, v) => {
+                    let k = key(k);
+                    assert_eq!(map.insert(&k, Tracked::new(v)), model.insert(k, v).is_none());
+                }
+                Op::Get(k) => {
+                    let k = key(k);
+                    assert_eq!(map.get(&k).map(|v| v.0), model.get(&k).cloned());
+                }
+                Op::Remove(k) => {
+                    let k = key(k);
+                    assert_eq!(map.remove(&k), model.remove(&k).is_some());
+                }
+                Op::ScanPrefix(prefix) => {
+                    let prefix = key(prefix);
+                    let mut scanned = vec![];
+                    map.scan_prefix(&prefix, |k, v| scanned.push((k.to_vec(), v.0)));
+                    let expected: Vec<_> = model
+                        .iter()
+                        .filter(|&(k, _)| k.starts_with(&prefix))
+                        .map(|(k, v)| (k.clone(), *v))
+                        .collect();
+                    assert_eq!(scanned, expected);
+                }
+            }
+            assert_eq!(map.len(), model.len());
+            // replaced and removed values may be waiting for reclamation.
+            assert!(common::live() - base >= model.len() as isize);
+        }
+    }
+    assert_eq!(common::live(), base);
+});
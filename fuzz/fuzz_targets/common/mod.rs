@@ -0,0 +1,33 @@
+//! Values which count live instances, so that leaks and double frees of the reclamation logic are
+//! found by comparing the count with the model after every input.
+//!
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+static LIVE: AtomicIsize = AtomicIsize::new(0);
+
+/// Return the number of `Tracked` not dropped yet.
+pub fn live() -> isize {
+    LIVE.load(Ordering::SeqCst)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Tracked(pub u16);
+
+impl Tracked {
+    pub fn new(v: u16) -> Tracked {
+        LIVE.fetch_add(1, Ordering::SeqCst);
+        Tracked(v)
+    }
+}
+
+impl Clone for Tracked {
+    fn clone(&self) -> Tracked {
+        Tracked::new(self.0)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        assert!(LIVE.fetch_sub(1, Ordering::SeqCst) > 0, "double free of {}", self.0);
+    }
+}
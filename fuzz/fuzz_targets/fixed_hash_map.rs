@@ -0,0 +1,48 @@
+//! Random operations on `FixedHashMap` checked against `HashMap`.
+//!
+#![no_main]
+#[macro_use]
+extern crate arbitrary;
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rs_lockfree;
+
+use rs_lockfree::fixed_hash_map::FixedHashMap;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Insert(u8, u16),
+    Get(u8),
+    ContainsKey(u8),
+    Remove(u8),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    capacity: u8,
+    ops: Vec<Op>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut map = FixedHashMap::with_capacity(input.capacity as usize % 32 + 1);
+    let mut model = HashMap::new();
+    // A slot claimed by a key is never released, even if its value is removed.
+    let mut claimed = HashSet::new();
+    for op in input.ops {
+        match op {
+            Op::Insert(k, v) => {
+                let expected = claimed.contains(&k) || claimed.len() < map.capacity();
+                assert_eq!(map.insert(k, v), expected);
+                if expected {
+                    claimed.insert(k);
+                    model.insert(k, v);
+                }
+            }
+            Op::Get(k) => assert_eq!(map.get(&k), model.get(&k).cloned()),
+            Op::ContainsKey(k) => assert_eq!(map.contains_key(&k), model.contains_key(&k)),
+            Op::Remove(k) => assert_eq!(map.remove(&k), model.remove(&k)),
+        }
+        assert_eq!(map.len(), model.len());
+    }
+});
@@ -0,0 +1,47 @@
+//! Random operations on `LockFreeQueue` checked against `VecDeque`.
+//!
+#![no_main]
+#[macro_use]
+extern crate arbitrary;
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rs_lockfree;
+
+mod common;
+
+use common::Tracked;
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use std::collections::VecDeque;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Push(u16),
+    TryPush(u16),
+    Pop,
+    TryPop,
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let base = common::live();
+    {
+        let mut queue = LockFreeQueue::default_new_in_heap();
+        let mut model = VecDeque::new();
+        for op in ops {
+            match op {
+                Op::Push(v) => {
+                    queue.push(Tracked::new(v));
+                    model.push_back(v);
+                }
+                Op::TryPush(v) => {
+                    assert!(queue.try_push(Tracked::new(v)).is_ok());
+                    model.push_back(v);
+                }
+                Op::Pop => assert_eq!(queue.pop().map(|v| v.0), model.pop_front()),
+                Op::TryPop => assert_eq!(queue.try_pop().unwrap().map(|v| v.0), model.pop_front()),
+            }
+            // popped values are moved out, only values inside are alive.
+            assert_eq!(common::live() - base, model.len() as isize);
+        }
+    }
+    assert_eq!(common::live(), base);
+});
@@ -0,0 +1,46 @@
+//! Random operations on `LockFreeStack` checked against `Vec`.
+//!
+#![no_main]
+#[macro_use]
+extern crate arbitrary;
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rs_lockfree;
+
+mod common;
+
+use common::Tracked;
+use rs_lockfree::lockfree_stack::LockFreeStack;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Push(u16),
+    TryPush(u16),
+    Pop,
+    TryPop,
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let base = common::live();
+    {
+        let mut stack = LockFreeStack::default_new_in_heap();
+        let mut model = Vec::new();
+        for op in ops {
+            match op {
+                Op::Push(v) => {
+                    stack.push(Tracked::new(v));
+                    model.push(v);
+                }
+                Op::TryPush(v) => {
+                    assert!(stack.try_push(Tracked::new(v)).is_ok());
+                    model.push(v);
+                }
+                Op::Pop => assert_eq!(stack.pop().map(|v| v.0), model.pop()),
+                Op::TryPop => assert_eq!(stack.try_pop().unwrap().map(|v| v.0), model.pop()),
+            }
+            // popped values are moved out, only values inside are alive.
+            assert_eq!(common::live() - base, model.len() as isize);
+        }
+    }
+    assert_eq!(common::live(), base);
+});
@@ -0,0 +1,177 @@
+#ifndef RS_LOCKFREE_H
+#define RS_LOCKFREE_H
+
+/* Generated with cbindgen:0.24.5 */
+
+/* Generated by cbindgen from src/ffi.rs, don't edit it manually. */
+
+#include <stdint.h>
+
+/**
+ * Byte filling reclaimed objects.
+ */
+#define RsLockfreePOISON 222
+
+/**
+ * Status of `HazardEpoch`, locks and queues
+ */
+typedef enum RsLockfreeStatus {
+  /**
+   * Success
+   */
+  RsLockfreeStatus_Success,
+  /**
+   * Current thread has already assigned a version handle
+   */
+  RsLockfreeStatus_Busy,
+  /**
+   * Thread number overflow
+   */
+  RsLockfreeStatus_ThreadNumOverflow,
+  /**
+   * Invalid parameter
+   */
+  RsLockfreeStatus_InvalidParam,
+  /**
+   * Timed out before the operation completes
+   */
+  RsLockfreeStatus_Timeout,
+  /**
+   * Lock is poisoned by a panicked holder
+   */
+  RsLockfreeStatus_Poisoned,
+  /**
+   * Memory allocation failed
+   */
+  RsLockfreeStatus_AllocFailed,
+  /**
+   * Container is empty
+   */
+  RsLockfreeStatus_Empty,
+  /**
+   * Container is full
+   */
+  RsLockfreeStatus_QueueFull,
+  /**
+   * Container is closed
+   */
+  RsLockfreeStatus_Closed,
+  /**
+   * Internal structure is corrupted
+   */
+  RsLockfreeStatus_Corrupted,
+  /**
+   * Too many objects are waiting to be reclaimed
+   */
+  RsLockfreeStatus_PendingLimit,
+  /**
+   * Consumer fell behind and elements were overwritten before being read
+   */
+  RsLockfreeStatus_Lagged,
+} RsLockfreeStatus;
+
+/**
+ * Opaque handle of `HazardEpoch`, which reclaims `void *` objects retired by C.
+ */
+typedef struct RsLockfreeHazardDomain RsLockfreeHazardDomain;
+
+/**
+ * Opaque handle of `LockFreeQueue` holding `void *` payloads.
+ */
+typedef struct RsLockfreeQueue RsLockfreeQueue;
+
+/**
+ * Opaque handle of `LockFreeStack` holding `void *` payloads.
+ */
+typedef struct RsLockfreeStack RsLockfreeStack;
+
+/**
+ * Destructor of payload, can be NULL.
+ */
+typedef void (*RsLockfreeDestructor)(void*);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create a queue.
+ */
+struct RsLockfreeQueue *rs_lockfree_queue_new(RsLockfreeDestructor destructor);
+
+/**
+ * Free `queue` and destroy the payloads left inside. No other thread may use it at the same time.
+ */
+void rs_lockfree_queue_free(struct RsLockfreeQueue *queue);
+
+/**
+ * Push `payload` to the end of `queue`.
+ */
+enum RsLockfreeStatus rs_lockfree_queue_push(struct RsLockfreeQueue *queue, void *payload);
+
+/**
+ * Pop the payload at the head of `queue` into `out`. Return `Empty` if there is none.
+ */
+enum RsLockfreeStatus rs_lockfree_queue_pop(struct RsLockfreeQueue *queue, void **out);
+
+/**
+ * Create a stack.
+ */
+struct RsLockfreeStack *rs_lockfree_stack_new(RsLockfreeDestructor destructor);
+
+/**
+ * Free `stack` and destroy the payloads left inside. No other thread may use it at the same time.
+ */
+void rs_lockfree_stack_free(struct RsLockfreeStack *stack);
+
+/**
+ * Push `payload` to the top of `stack`.
+ */
+enum RsLockfreeStatus rs_lockfree_stack_push(struct RsLockfreeStack *stack, void *payload);
+
+/**
+ * Pop the payload at the top of `stack` into `out`. Return `Empty` if there is none.
+ */
+enum RsLockfreeStatus rs_lockfree_stack_pop(struct RsLockfreeStack *stack, void **out);
+
+/**
+ * Create a hazard domain, see `HazardEpoch::new_in_stack` for the parameters.
+ */
+struct RsLockfreeHazardDomain *rs_lockfree_hazard_epoch_new(int64_t thread_waiting_threshold,
+                                                            int64_t min_version_cache_time_us);
+
+/**
+ * Free `hazard_epoch` and destroy all objects retired to it.
+ */
+void rs_lockfree_hazard_epoch_free(struct RsLockfreeHazardDomain *hazard_epoch);
+
+/**
+ * Before accessing shared objects, get `handle` of this operation, see
+ * `HazardEpoch::try_acquire`.
+ */
+enum RsLockfreeStatus rs_lockfree_hazard_epoch_acquire(struct RsLockfreeHazardDomain *hazard_epoch,
+                                                       uint64_t *handle);
+
+/**
+ * After accessing shared objects, release `handle` got by `rs_lockfree_hazard_epoch_acquire`.
+ */
+void rs_lockfree_hazard_epoch_release(struct RsLockfreeHazardDomain *hazard_epoch, uint64_t handle);
+
+/**
+ * Retire `payload` which is no longer reachable by new readers. `destructor` is called with it
+ * once no thread may still access it.
+ */
+enum RsLockfreeStatus rs_lockfree_hazard_epoch_retire(struct RsLockfreeHazardDomain *hazard_epoch,
+                                                      void *payload,
+                                                      RsLockfreeDestructor destructor);
+
+/**
+ * Reclaim all retired objects which are no longer accessed, see `HazardEpoch::retire`.
+ */
+void rs_lockfree_hazard_epoch_reclaim(struct RsLockfreeHazardDomain *hazard_epoch);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RS_LOCKFREE_H */
@@ -0,0 +1,167 @@
+//! Definition and implementations of `HazardArcSwap`
+//!
+use error::HazardError;
+use hazard_epoch::{Cursor, HazardEpoch};
+use hazard_pointer::{BaseHazardNode, HazardNodeT};
+use util;
+use std::fmt;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+struct ArcNode<T> {
+    base: BaseHazardNode,
+    /// Reference held by current slot, dropped when the node is reclaimed.
+    arc: Arc<T>,
+}
+
+impl<T> HazardNodeT for ArcNode<T> {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut _
+    }
+}
+
+impl<T> Drop for ArcNode<T> {
+    fn drop(&mut self) {}
+}
+
+impl<T> ArcNode<T> {
+    fn new_raw(arc: Arc<T>) -> *mut ArcNode<T> {
+        Box::into_raw(Box::new(ArcNode {
+            base: BaseHazardNode::default(),
+            arc,
+        }))
+    }
+}
+
+/// Slot of an `Arc<T>`, whose readers borrow the value under a handle of `HazardEpoch` instead of
+/// cloning the `Arc`, like hot configuration read on every request and replaced rarely.
+///
+/// The slot holds its own reference in a node. Loading only acquires a handle, so readers never
+/// write the cache line of the reference count. Storing swaps in a new node and adds the old one
+/// to `HazardEpoch`, which drops its reference once no handle may read it, so the old value lives
+/// on while an `Arc` of it is held elsewhere.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::arc_swap::HazardArcSwap;
+/// use std::sync::Arc;
+///
+/// let mut config = HazardArcSwap::new_in_heap(Arc::new(String::from("v1")));
+/// assert_eq!(*config.load(), "v1");
+/// let old = config.swap(Arc::new(String::from("v2")));
+/// assert_eq!(*old, "v1");
+/// assert_eq!(*config.load(), "v2");
+/// assert_eq!(*config.load_full(), "v2");
+/// ```
+///
+pub struct HazardArcSwap<T> {
+    hazard_epoch: HazardEpoch,
+    ptr: util::WrappedAlign64Type<util::AtomicRawPtr<ArcNode<T>>>,
+}
+
+unsafe impl<T: Send + Sync> Send for HazardArcSwap<T> {}
+
+unsafe impl<T: Send + Sync> Sync for HazardArcSwap<T> {}
+
+impl<T> HazardArcSwap<T> {
+    /// Return HazardArcSwap in stack holding `arc`, with default setting of HazardEpoch.
+    ///
+    /// # Safety
+    ///
+    /// It can't be moved after loading or storing.
+    pub unsafe fn new_in_stack(arc: Arc<T>) -> HazardArcSwap<T> {
+        HazardArcSwap {
+            hazard_epoch: HazardEpoch::default_new_in_stack(),
+            ptr: util::WrappedAlign64Type(util::AtomicRawPtr::new(ArcNode::new_raw(arc))),
+        }
+    }
+
+    /// Return HazardArcSwap in heap holding `arc`, with default setting of HazardEpoch.
+    pub fn new_in_heap(arc: Arc<T>) -> Box<HazardArcSwap<T>> {
+        unsafe { Box::new(Self::new_in_stack(arc)) }
+    }
+
+    /// Borrow current value until the guard is dropped. Panic if `try_load` fails.
+    pub fn load<'a>(&'a mut self) -> ArcSwapGuard<'a, T> {
+        match self.try_load() {
+            Ok(ret) => ret,
+            Err(e) => panic!("load fail, {}", e),
+        }
+    }
+
+    /// Same as `load`, but return error like `HazardEpoch::cursor`.
+    pub fn try_load<'a>(&'a mut self) -> Result<ArcSwapGuard<'a, T>, HazardError> {
+        let cursor = self.hazard_epoch.cursor()?;
+        // Loaded under the handle, so the node is added after it's acquired, if ever.
+        let node = self.ptr.load(Ordering::SeqCst);
+        Ok(ArcSwapGuard {
+            _cursor: cursor,
+            value: unsafe { &*(*node).arc },
+        })
+    }
+
+    /// Return a new `Arc` of current value, which outlives the handle. Panic if a handle can't be
+    /// acquired like `try_load`.
+    pub fn load_full(&mut self) -> Arc<T> {
+        let cursor = match self.hazard_epoch.cursor() {
+            Ok(cursor) => cursor,
+            Err(e) => panic!("load_full fail, {}", e),
+        };
+        let ret = unsafe { (*self.ptr.load(Ordering::SeqCst)).arc.clone() };
+        drop(cursor);
+        ret
+    }
+
+    /// Replace current value by `arc`. The old one is released once no reader holds it.
+    pub fn store(&mut self, arc: Arc<T>) {
+        let old = self.ptr.swap(ArcNode::new_raw(arc), Ordering::SeqCst);
+        unsafe { self.retire(old) };
+    }
+
+    /// Replace current value by `arc`, and return the old one.
+    pub fn swap(&mut self, arc: Arc<T>) -> Arc<T> {
+        let old = self.ptr.swap(ArcNode::new_raw(arc), Ordering::SeqCst);
+        // The node is unlinked but may still be read, so its own reference is cloned.
+        let ret = unsafe { (*old).arc.clone() };
+        unsafe { self.retire(old) };
+        ret
+    }
+
+    unsafe fn retire(&mut self, node: *mut ArcNode<T>) {
+        if let Err(e) = self.hazard_epoch.try_add_node(node) {
+            panic!("retire fail, {}", e);
+        }
+    }
+}
+
+impl<T> Drop for HazardArcSwap<T> {
+    fn drop(&mut self) {
+        let node = self.ptr.swap(ptr::null_mut(), Ordering::SeqCst);
+        if !node.is_null() {
+            unsafe { drop(Box::from_raw(node)) };
+        }
+    }
+}
+
+/// Value borrowed from `HazardArcSwap` by `load`, valid until dropped, which releases the handle.
+pub struct ArcSwapGuard<'a, T: 'a> {
+    _cursor: Cursor<'a>,
+    value: *const T,
+}
+
+impl<'a, T> Deref for ArcSwapGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.value }
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for ArcSwapGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
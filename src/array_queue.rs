@@ -0,0 +1,333 @@
+//! Definition and implementations of `ArrayQueue`
+//!
+use error::{PushError, QueueError};
+use traits::ConcurrentQueue;
+use util;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+struct Slot<T> {
+    /// Twice the lap of the position which may use current slot next, plus 1 if it holds a value.
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    const EMPTY: Slot<T> = Slot {
+        stamp: AtomicUsize::new(0),
+        value: UnsafeCell::new(MaybeUninit::uninit()),
+    };
+}
+
+/// Bounded LockFree queue which holds at most `N` elements in an inline array.
+///
+/// Unlike `LockFreeQueue`, it doesn't allocate or depend on `HazardEpoch`, and `new` is `const`,
+/// so that it can be put in `static` items, for example on embedded targets without heap
+/// allocation at startup. Each slot has a stamp telling which lap of positions may use it and
+/// whether it holds a value, so producers and consumers only contend on `tail` and `head`.
+///
+/// `push_blocking` and `push_timeout` park the producer while current queue is full, until a pop
+/// makes room, so producers get backpressure without a retry loop. Pops only take the lock to wake
+/// producers when some are parked.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::array_queue::ArrayQueue;
+///
+/// static QUEUE: ArrayQueue<u32, 2> = ArrayQueue::new();
+///
+/// assert!(QUEUE.try_push(1).is_ok());
+/// assert!(QUEUE.try_push(2).is_ok());
+/// assert_eq!(QUEUE.try_push(3).unwrap_err().value, 3);
+/// assert_eq!(QUEUE.pop(), Some(1));
+/// assert_eq!(QUEUE.len(), 1);
+/// ```
+///
+pub struct ArrayQueue<T, const N: usize> {
+    head: util::WrappedAlign64Type<AtomicUsize>,
+    tail: util::WrappedAlign64Type<AtomicUsize>,
+    waiting_pushers: AtomicUsize,
+    mutex: Mutex<()>,
+    not_full: Condvar,
+    slots: [Slot<T>; N],
+}
+
+unsafe impl<T: Send, const N: usize> Send for ArrayQueue<T, N> {}
+
+unsafe impl<T: Send, const N: usize> Sync for ArrayQueue<T, N> {}
+
+impl<T, const N: usize> Default for ArrayQueue<T, N> {
+    fn default() -> Self {
+        ArrayQueue::new()
+    }
+}
+
+impl<T, const N: usize> ArrayQueue<T, N> {
+    /// Return empty ArrayQueue. `N` must be positive.
+    pub const fn new() -> Self {
+        assert!(0 < N, "capacity must be positive");
+        ArrayQueue {
+            head: util::WrappedAlign64Type(AtomicUsize::new(0)),
+            tail: util::WrappedAlign64Type(AtomicUsize::new(0)),
+            waiting_pushers: AtomicUsize::new(0),
+            mutex: Mutex::new(()),
+            not_full: Condvar::new(),
+            slots: [Slot::EMPTY; N],
+        }
+    }
+
+    /// Return the maximum number of elements.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Return the number of elements, which may be out of date once returned.
+    pub fn len(&self) -> usize {
+        loop {
+            let tail = self.tail.load(Ordering::SeqCst);
+            let head = self.head.load(Ordering::SeqCst);
+            if tail == self.tail.load(Ordering::SeqCst) {
+                return tail.wrapping_sub(head);
+            }
+        }
+    }
+
+    /// Return true if there is no element.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+
+    #[inline]
+    fn slot(&self, pos: usize) -> (&Slot<T>, usize) {
+        (&self.slots[pos % N], (pos / N).wrapping_mul(2))
+    }
+
+    /// Push an element to the end of current queue. Return `QueueError::Full` with the element if
+    /// current queue is full.
+    pub fn try_push(&self, v: T) -> Result<(), PushError<T>> {
+        let mut backoff = util::Backoff::new();
+        let mut pos = self.tail.load(util::ORDER_RELAXED);
+        loop {
+            let (slot, empty_stamp) = self.slot(pos);
+            let stamp = slot.stamp.load(util::ORDER_ACQUIRE);
+            if stamp == empty_stamp {
+                match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::SeqCst,
+                    util::ORDER_RELAXED,
+                ) {
+                    Ok(_) => {
+                        unsafe { ptr::write((*slot.value.get()).as_mut_ptr(), v) };
+                        slot.stamp.store(empty_stamp.wrapping_add(1), util::ORDER_RELEASE);
+                        return Ok(());
+                    }
+                    Err(cur) => pos = cur,
+                }
+            } else if stamp.wrapping_add(1) == empty_stamp {
+                // The value of last lap is not popped yet.
+                return Err(PushError {
+                    error: QueueError::Full { op: "try_push" },
+                    value: v,
+                });
+            } else {
+                // Another producer has taken `pos`.
+                backoff.spin();
+                pos = self.tail.load(util::ORDER_RELAXED);
+            }
+        }
+    }
+
+    /// Push an element to the end of current queue, parking current thread while it's full.
+    pub fn push_blocking(&self, v: T) {
+        let mut v = v;
+        loop {
+            match self.try_push(v) {
+                Ok(()) => return,
+                Err(e) => v = e.value,
+            }
+            // Register as a waiter before checking again, so that a consumer either sees the
+            // waiter or makes room before the check.
+            let guard = self.mutex.lock().unwrap();
+            self.waiting_pushers.fetch_add(1, Ordering::SeqCst);
+            if self.len() >= N {
+                let _guard = self.not_full.wait(guard).unwrap();
+            }
+            self.waiting_pushers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Push an element to the end of current queue, parking current thread while it's full for at
+    /// most `timeout`. Return `QueueError::Timeout` with the element if it's still full.
+    pub fn push_timeout(&self, v: T, timeout: Duration) -> Result<(), PushError<T>> {
+        let deadline = Instant::now() + timeout;
+        let mut v = v;
+        loop {
+            match self.try_push(v) {
+                Ok(()) => return Ok(()),
+                Err(e) => v = e.value,
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(PushError {
+                    error: QueueError::Timeout {
+                        op: "push_timeout",
+                        timeout,
+                    },
+                    value: v,
+                });
+            }
+            let guard = self.mutex.lock().unwrap();
+            self.waiting_pushers.fetch_add(1, Ordering::SeqCst);
+            if self.len() >= N {
+                let _guard = self.not_full.wait_timeout(guard, deadline - now).unwrap();
+            }
+            self.waiting_pushers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Wake up a parked producer if there is any.
+    #[inline]
+    fn notify_pusher(&self) {
+        if 0 != self.waiting_pushers.load(Ordering::SeqCst) {
+            let _guard = self.mutex.lock().unwrap();
+            self.not_full.notify_one();
+        }
+    }
+
+    /// Pop the element at the head of current queue.
+    pub fn pop(&self) -> Option<T> {
+        let mut backoff = util::Backoff::new();
+        let mut pos = self.head.load(util::ORDER_RELAXED);
+        loop {
+            let (slot, empty_stamp) = self.slot(pos);
+            let stamp = slot.stamp.load(util::ORDER_ACQUIRE);
+            if stamp == empty_stamp.wrapping_add(1) {
+                match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::SeqCst,
+                    util::ORDER_RELAXED,
+                ) {
+                    Ok(_) => {
+                        let v = unsafe { ptr::read((*slot.value.get()).as_ptr()) };
+                        slot.stamp.store(empty_stamp.wrapping_add(2), util::ORDER_RELEASE);
+                        self.notify_pusher();
+                        return Some(v);
+                    }
+                    Err(cur) => pos = cur,
+                }
+            } else if stamp == empty_stamp {
+                // Nothing has been pushed at `pos`.
+                return None;
+            } else {
+                // Another consumer has taken `pos`.
+                backoff.spin();
+                pos = self.head.load(util::ORDER_RELAXED);
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> ConcurrentQueue<T> for ArrayQueue<T, N> {
+    fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        ArrayQueue::try_push(self, v)
+    }
+
+    fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
+        Ok(self.pop())
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayQueue<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use array_queue::ArrayQueue;
+        let queue = ArrayQueue::<usize, 4>::new();
+        assert_eq!(queue.capacity(), 4);
+        assert!(queue.is_empty());
+        for lap in 0..3 {
+            for i in 0..4 {
+                assert!(queue.try_push(lap * 4 + i).is_ok());
+            }
+            assert_eq!(queue.try_push(100).unwrap_err().value, 100);
+            assert_eq!(queue.len(), 4);
+            for i in 0..4 {
+                assert_eq!(queue.pop(), Some(lap * 4 + i));
+            }
+            assert_eq!(queue.pop(), None);
+        }
+    }
+
+    #[test]
+    fn test_concurrent() {
+        use array_queue::ArrayQueue;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::thread;
+
+        static QUEUE: ArrayQueue<usize, 8> = ArrayQueue::new();
+        static SUM: AtomicUsize = AtomicUsize::new(0);
+        let test_num = 10000;
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                thread::spawn(move || {
+                    let mut cnt = 0;
+                    while cnt < test_num {
+                        match QUEUE.pop() {
+                            Some(v) => {
+                                SUM.fetch_add(v, Ordering::SeqCst);
+                                cnt += 1;
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                })
+            })
+            .collect();
+        let producers: Vec<_> = (0..2)
+            .map(|_| {
+                thread::spawn(move || {
+                    for i in 0..test_num {
+                        let mut v = i;
+                        while let Err(e) = QUEUE.try_push(v) {
+                            v = e.value;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for t in producers.into_iter().chain(consumers) {
+            t.join().unwrap();
+        }
+        assert_eq!(SUM.load(Ordering::SeqCst), test_num * (test_num - 1));
+        assert!(QUEUE.is_empty());
+    }
+
+    #[test]
+    fn test_drop() {
+        use array_queue::ArrayQueue;
+        use std::rc::Rc;
+        let v = Rc::new(0);
+        {
+            let queue = ArrayQueue::<Rc<i32>, 4>::new();
+            queue.try_push(v.clone()).ok().unwrap();
+            queue.try_push(v.clone()).ok().unwrap();
+            assert_eq!(Rc::strong_count(&v), 3);
+        }
+        assert_eq!(Rc::strong_count(&v), 1);
+    }
+}
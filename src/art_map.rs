@@ -0,0 +1,872 @@
+//! Definition and implementations of `ArtMap`
+//!
+use error::HazardError;
+use hazard_epoch::{Cursor, HazardEpoch};
+use hazard_pointer::{BaseHazardNode, HazardNodeT};
+use spin_lock::SpinLock;
+use util;
+use std::cmp;
+use std::ptr;
+use std::intrinsics;
+use std::sync::PoisonError;
+
+type ArtNodePtr<V> = *mut ArtNode<V>;
+
+/// Children of an inner node. Keys of `Node4` and `Node16` are kept sorted, so that all kinds can
+/// be traversed in byte order.
+enum Children<V> {
+    Node4 {
+        len: usize,
+        keys: [u8; 4],
+        ptrs: [ArtNodePtr<V>; 4],
+    },
+    Node16 {
+        len: usize,
+        keys: [u8; 16],
+        ptrs: [ArtNodePtr<V>; 16],
+    },
+    Node48 {
+        len: usize,
+        index: [u8; 256],
+        ptrs: [ArtNodePtr<V>; 48],
+    },
+    Node256 {
+        len: usize,
+        ptrs: [ArtNodePtr<V>; 256],
+    },
+}
+
+impl<V> Clone for Children<V> {
+    fn clone(&self) -> Self {
+        match *self {
+            Children::Node4 { len, keys, ptrs } => Children::Node4 { len, keys, ptrs },
+            Children::Node16 { len, keys, ptrs } => Children::Node16 { len, keys, ptrs },
+            Children::Node48 { len, index, ptrs } => Children::Node48 { len, index, ptrs },
+            Children::Node256 { len, ptrs } => Children::Node256 { len, ptrs },
+        }
+    }
+}
+
+impl<V> Children<V> {
+    fn with_capacity(cap: usize) -> Self {
+        if cap <= 4 {
+            Children::Node4 {
+                len: 0,
+                keys: [0; 4],
+                ptrs: [ptr::null_mut(); 4],
+            }
+        } else if cap <= 16 {
+            Children::Node16 {
+                len: 0,
+                keys: [0; 16],
+                ptrs: [ptr::null_mut(); 16],
+            }
+        } else if cap <= 48 {
+            Children::Node48 {
+                len: 0,
+                index: [0; 256],
+                ptrs: [ptr::null_mut(); 48],
+            }
+        } else {
+            Children::Node256 {
+                len: 0,
+                ptrs: [ptr::null_mut(); 256],
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Children::Node4 { len, .. }
+            | Children::Node16 { len, .. }
+            | Children::Node48 { len, .. }
+            | Children::Node256 { len, .. } => len,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        match *self {
+            Children::Node4 { .. } => 4,
+            Children::Node16 { .. } => 16,
+            Children::Node48 { .. } => 48,
+            Children::Node256 { .. } => 256,
+        }
+    }
+
+    /// Return the address of the child slot keyed by `byte`, which can be accessed atomically.
+    fn slot(&mut self, byte: u8) -> Option<*mut ArtNodePtr<V>> {
+        match *self {
+            Children::Node4 {
+                len,
+                ref keys,
+                ref mut ptrs,
+            } => keys[..len]
+                .iter()
+                .position(|&k| k == byte)
+                .map(|i| &mut ptrs[i] as *mut _),
+            Children::Node16 {
+                len,
+                ref keys,
+                ref mut ptrs,
+            } => keys[..len]
+                .iter()
+                .position(|&k| k == byte)
+                .map(|i| &mut ptrs[i] as *mut _),
+            Children::Node48 {
+                ref index,
+                ref mut ptrs,
+                ..
+            } => match index[byte as usize] {
+                0 => None,
+                i => Some(&mut ptrs[i as usize - 1] as *mut _),
+            },
+            Children::Node256 { ref mut ptrs, .. } => {
+                if ptrs[byte as usize].is_null() {
+                    None
+                } else {
+                    Some(&mut ptrs[byte as usize] as *mut _)
+                }
+            }
+        }
+    }
+
+    /// Atomic load the child keyed by `byte`, null if not exists.
+    unsafe fn find(&self, byte: u8) -> ArtNodePtr<V> {
+        let slot: *const ArtNodePtr<V> = match *self {
+            Children::Node4 {
+                len,
+                ref keys,
+                ref ptrs,
+            } => match keys[..len].iter().position(|&k| k == byte) {
+                Some(i) => &ptrs[i],
+                None => return ptr::null_mut(),
+            },
+            Children::Node16 {
+                len,
+                ref keys,
+                ref ptrs,
+            } => match keys[..len].iter().position(|&k| k == byte) {
+                Some(i) => &ptrs[i],
+                None => return ptr::null_mut(),
+            },
+            Children::Node48 {
+                ref index,
+                ref ptrs,
+                ..
+            } => match index[byte as usize] {
+                0 => return ptr::null_mut(),
+                i => &ptrs[i as usize - 1],
+            },
+            Children::Node256 { ref ptrs, .. } => &ptrs[byte as usize],
+        };
+        util::atomic_load_raw_ptr(slot)
+    }
+
+    /// Add a child which must not exist yet. Caller should make sure there is enough capacity.
+    fn add(&mut self, byte: u8, child: ArtNodePtr<V>) {
+        assert!(self.len() < self.capacity());
+        match *self {
+            Children::Node4 {
+                ref mut len,
+                ref mut keys,
+                ref mut ptrs,
+            } => Self::sorted_add(len, keys, ptrs, byte, child),
+            Children::Node16 {
+                ref mut len,
+                ref mut keys,
+                ref mut ptrs,
+            } => Self::sorted_add(len, keys, ptrs, byte, child),
+            Children::Node48 {
+                ref mut len,
+                ref mut index,
+                ref mut ptrs,
+            } => {
+                assert_eq!(index[byte as usize], 0);
+                let pos = ptrs.iter().position(|p| p.is_null()).unwrap();
+                ptrs[pos] = child;
+                index[byte as usize] = pos as u8 + 1;
+                *len += 1;
+            }
+            Children::Node256 {
+                ref mut len,
+                ref mut ptrs,
+            } => {
+                assert!(ptrs[byte as usize].is_null());
+                ptrs[byte as usize] = child;
+                *len += 1;
+            }
+        }
+    }
+
+    fn sorted_add(
+        len: &mut usize,
+        keys: &mut [u8],
+        ptrs: &mut [ArtNodePtr<V>],
+        byte: u8,
+        child: ArtNodePtr<V>,
+    ) {
+        let pos = keys[..*len]
+            .iter()
+            .position(|&k| k > byte)
+            .unwrap_or(*len);
+        let mut idx = *len;
+        while idx > pos {
+            keys[idx] = keys[idx - 1];
+            ptrs[idx] = ptrs[idx - 1];
+            idx -= 1;
+        }
+        keys[pos] = byte;
+        ptrs[pos] = child;
+        *len += 1;
+    }
+
+    /// Remove the child keyed by `byte` if exists.
+    fn remove(&mut self, byte: u8) {
+        match *self {
+            Children::Node4 {
+                ref mut len,
+                ref mut keys,
+                ref mut ptrs,
+            } => Self::sorted_remove(len, keys, ptrs, byte),
+            Children::Node16 {
+                ref mut len,
+                ref mut keys,
+                ref mut ptrs,
+            } => Self::sorted_remove(len, keys, ptrs, byte),
+            Children::Node48 {
+                ref mut len,
+                ref mut index,
+                ref mut ptrs,
+            } => {
+                let pos = index[byte as usize];
+                if 0 != pos {
+                    ptrs[pos as usize - 1] = ptr::null_mut();
+                    index[byte as usize] = 0;
+                    *len -= 1;
+                }
+            }
+            Children::Node256 {
+                ref mut len,
+                ref mut ptrs,
+            } => {
+                if !ptrs[byte as usize].is_null() {
+                    ptrs[byte as usize] = ptr::null_mut();
+                    *len -= 1;
+                }
+            }
+        }
+    }
+
+    fn sorted_remove(len: &mut usize, keys: &mut [u8], ptrs: &mut [ArtNodePtr<V>], byte: u8) {
+        if let Some(pos) = keys[..*len].iter().position(|&k| k == byte) {
+            for idx in pos..*len - 1 {
+                keys[idx] = keys[idx + 1];
+                ptrs[idx] = ptrs[idx + 1];
+            }
+            *len -= 1;
+            ptrs[*len] = ptr::null_mut();
+        }
+    }
+
+    /// Return all children in byte order. A slot may be transiently null while a writer is
+    /// unlinking a leaf, such slots are skipped.
+    unsafe fn entries(&self) -> Vec<(u8, ArtNodePtr<V>)> {
+        let mut ret = Vec::with_capacity(self.len());
+        match *self {
+            Children::Node4 {
+                len,
+                ref keys,
+                ref ptrs,
+            } => for idx in 0..len {
+                let child = util::atomic_load_raw_ptr(&ptrs[idx]);
+                if !child.is_null() {
+                    ret.push((keys[idx], child));
+                }
+            },
+            Children::Node16 {
+                len,
+                ref keys,
+                ref ptrs,
+            } => for idx in 0..len {
+                let child = util::atomic_load_raw_ptr(&ptrs[idx]);
+                if !child.is_null() {
+                    ret.push((keys[idx], child));
+                }
+            },
+            Children::Node48 {
+                ref index,
+                ref ptrs,
+                ..
+            } => for byte in 0..256 {
+                if 0 != index[byte] {
+                    let child = util::atomic_load_raw_ptr(&ptrs[index[byte] as usize - 1]);
+                    if !child.is_null() {
+                        ret.push((byte as u8, child));
+                    }
+                }
+            },
+            Children::Node256 { ref ptrs, .. } => for byte in 0..256 {
+                let child = util::atomic_load_raw_ptr(&ptrs[byte]);
+                if !child.is_null() {
+                    ret.push((byte as u8, child));
+                }
+            },
+        }
+        ret
+    }
+
+    /// Return a copy with the smallest node kind that can hold `cap` children.
+    unsafe fn resize(&self, cap: usize) -> Self {
+        let mut ret = Self::with_capacity(cmp::max(cap, self.len()));
+        for (byte, child) in self.entries() {
+            ret.add(byte, child);
+        }
+        ret
+    }
+}
+
+enum NodeKind<V> {
+    Leaf {
+        key: Vec<u8>,
+        value: V,
+    },
+    Inner {
+        prefix: Vec<u8>,
+        term: ArtNodePtr<V>,
+        children: Children<V>,
+    },
+}
+
+struct ArtNode<V> {
+    base: BaseHazardNode,
+    kind: NodeKind<V>,
+}
+
+impl<V> HazardNodeT for ArtNode<V> {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut _
+    }
+}
+
+/// Children are owned by the tree rather than by a single node, because a retired inner node
+/// shares them with its replacement.
+impl<V> Drop for ArtNode<V> {
+    fn drop(&mut self) {}
+}
+
+impl<V> ArtNode<V> {
+    fn new_leaf(key: &[u8], value: V) -> ArtNodePtr<V> {
+        Box::into_raw(Box::new(ArtNode {
+            base: BaseHazardNode::default(),
+            kind: NodeKind::Leaf {
+                key: key.to_vec(),
+                value,
+            },
+        }))
+    }
+
+    fn new_inner(prefix: Vec<u8>, term: ArtNodePtr<V>, children: Children<V>) -> ArtNodePtr<V> {
+        Box::into_raw(Box::new(ArtNode {
+            base: BaseHazardNode::default(),
+            kind: NodeKind::Inner {
+                prefix,
+                term,
+                children,
+            },
+        }))
+    }
+
+    fn is_leaf(&self) -> bool {
+        match self.kind {
+            NodeKind::Leaf { .. } => true,
+            NodeKind::Inner { .. } => false,
+        }
+    }
+}
+
+#[inline]
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).take_while(|&(x, y)| x == y).count()
+}
+
+/// Put `child` whose key is `key` under an inner node at `depth`.
+#[inline]
+fn attach<V>(
+    term: &mut ArtNodePtr<V>,
+    children: &mut Children<V>,
+    key: &[u8],
+    depth: usize,
+    child: ArtNodePtr<V>,
+) {
+    if key.len() == depth {
+        *term = child;
+    } else {
+        children.add(key[depth], child);
+    }
+}
+
+/// Concurrent adaptive radix tree keyed by byte strings, implemented based on `HazardEpoch`.
+///
+/// Readers never block: they traverse the tree under the protection of a hazard handle. Writers
+/// are serialized by a `SpinLock` and never modify the structure of a published node; instead
+/// they build a replacement, publish it by an atomic store into the parent's slot, and hand the
+/// old node to `HazardEpoch`. Keys are kept in byte order, so prefix scans return sorted results.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::art_map::ArtMap;
+/// let mut map = unsafe { ArtMap::default_new_in_stack() };
+/// assert!(map.insert(b"apple", 1));
+/// assert!(map.insert(b"apply", 2));
+/// assert!(map.insert(b"banana", 3));
+/// assert!(!map.insert(b"apple", 4));
+/// assert_eq!(map.get(b"apple"), Some(4));
+/// let mut keys = vec![];
+/// map.scan_prefix(b"appl", |k, _| keys.push(k.to_vec()));
+/// assert_eq!(keys, vec![b"apple".to_vec(), b"apply".to_vec()]);
+/// assert!(map.remove(b"apple"));
+/// assert_eq!(map.len(), 2);
+/// ```
+///
+pub struct ArtMap<V> {
+    hazard_epoch: HazardEpoch,
+    write_lock: util::WrappedAlign64Type<SpinLock>,
+    root: util::WrappedAlign64Type<ArtNodePtr<V>>,
+    len: util::WrappedAlign64Type<i64>,
+}
+
+impl<V> ArtMap<V> {
+    /// Return ArtMap in stack with default setting of HazardEpoch
+    pub unsafe fn default_new_in_stack() -> ArtMap<V> {
+        ArtMap {
+            hazard_epoch: HazardEpoch::default_new_in_stack(),
+            write_lock: util::WrappedAlign64Type(SpinLock::default()),
+            root: util::WrappedAlign64Type(ptr::null_mut()),
+            len: util::WrappedAlign64Type(0),
+        }
+    }
+
+    /// Return ArtMap in heap with default setting of HazardEpoch
+    pub fn default_new_in_heap() -> Box<ArtMap<V>> {
+        unsafe { Box::new(Self::default_new_in_stack()) }
+    }
+
+    /// Return the number of keys in current map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { intrinsics::atomic_load(self.len.as_ptr()) as usize }
+    }
+
+    /// Return true if current map contains no key.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+
+    /// Insert `value` with `key`. Return true if `key` is new, otherwise the old value is replaced
+    /// and reclaimed by `HazardEpoch`. Panic if `try_insert` fails.
+    pub fn insert(&mut self, key: &[u8], value: V) -> bool {
+        match self.try_insert(key, value) {
+            Ok(ret) => ret,
+            Err(e) => panic!("insert fail, {}", e),
+        }
+    }
+
+    /// Same as `insert`, but return `HazardError` if `HazardEpoch` fails.
+    pub fn try_insert(&mut self, key: &[u8], value: V) -> Result<bool, HazardError> {
+        let cursor = self.hazard_epoch.cursor()?;
+        // A writer panicking in the middle leaves a consistent tree, so poisoning is ignored.
+        let _lock = self.write_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        unsafe {
+            let ret = Self::insert_at(&cursor, self.root.as_mut_ptr(), key, 0, value)?;
+            if ret {
+                util::sync_fetch_and_add(self.len.as_mut_ptr(), 1);
+            }
+            Ok(ret)
+        }
+    }
+
+    /// Return a clone of the value of `key`.
+    pub fn get(&mut self, key: &[u8]) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.get_with(key, |v| v.clone())
+    }
+
+    /// Return true if `key` exists.
+    pub fn contains_key(&mut self, key: &[u8]) -> bool {
+        self.get_with(key, |_| ()).is_some()
+    }
+
+    /// Call `f` with the value of `key` under the protection of `HazardEpoch`. Panic if
+    /// `try_get_with` fails.
+    pub fn get_with<R, F>(&mut self, key: &[u8], f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        match self.try_get_with(key, f) {
+            Ok(ret) => ret,
+            Err(e) => panic!("get_with fail, {}", e),
+        }
+    }
+
+    /// Same as `get_with`, but return `HazardError` if `HazardEpoch` fails.
+    pub fn try_get_with<R, F>(&mut self, key: &[u8], f: F) -> Result<Option<R>, HazardError>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        let _cursor = self.hazard_epoch.cursor()?;
+        Ok(unsafe { Self::inner_get(self.root.as_ptr(), key, f) })
+    }
+
+    /// Remove `key`. Return true if it existed. Panic if `try_remove` fails.
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        match self.try_remove(key) {
+            Ok(ret) => ret,
+            Err(e) => panic!("remove fail, {}", e),
+        }
+    }
+
+    /// Same as `remove`, but return `HazardError` if `HazardEpoch` fails.
+    pub fn try_remove(&mut self, key: &[u8]) -> Result<bool, HazardError> {
+        let cursor = self.hazard_epoch.cursor()?;
+        let _lock = self.write_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        unsafe {
+            let ret = Self::remove_at(&cursor, self.root.as_mut_ptr(), key, 0)?;
+            if ret {
+                util::sync_fetch_and_add(self.len.as_mut_ptr(), -1);
+            }
+            Ok(ret)
+        }
+    }
+
+    /// Call `f` with every key starting with `prefix` and its value, in ascending key order.
+    /// Panic if `try_scan_prefix` fails.
+    pub fn scan_prefix<F>(&mut self, prefix: &[u8], f: F)
+    where
+        F: FnMut(&[u8], &V),
+    {
+        if let Err(e) = self.try_scan_prefix(prefix, f) {
+            panic!("scan_prefix fail, {}", e);
+        }
+    }
+
+    /// Same as `scan_prefix`, but return `HazardError` if `HazardEpoch` fails.
+    pub fn try_scan_prefix<F>(&mut self, prefix: &[u8], mut f: F) -> Result<(), HazardError>
+    where
+        F: FnMut(&[u8], &V),
+    {
+        let _cursor = self.hazard_epoch.cursor()?;
+        unsafe {
+            let node = Self::seek_prefix(self.root.as_ptr(), prefix);
+            if !node.is_null() {
+                Self::walk(node, prefix, &mut f);
+            }
+        }
+        Ok(())
+    }
+
+    unsafe fn inner_get<R, F>(root: *const ArtNodePtr<V>, key: &[u8], f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        let mut node = util::atomic_load_raw_ptr(root);
+        let mut depth = 0;
+        while !node.is_null() {
+            match (*node).kind {
+                NodeKind::Leaf {
+                    key: ref leaf_key,
+                    ref value,
+                } => {
+                    if leaf_key.as_slice() == key {
+                        return Some(f(value));
+                    }
+                    break;
+                }
+                NodeKind::Inner {
+                    ref prefix,
+                    ref term,
+                    ref children,
+                } => {
+                    if !key[depth..].starts_with(prefix) {
+                        break;
+                    }
+                    depth += prefix.len();
+                    if depth == key.len() {
+                        node = util::atomic_load_raw_ptr(term);
+                    } else {
+                        node = children.find(key[depth]);
+                        depth += 1;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Return the root of the subtree whose keys may start with `prefix`.
+    unsafe fn seek_prefix(root: *const ArtNodePtr<V>, prefix: &[u8]) -> ArtNodePtr<V> {
+        let mut node = util::atomic_load_raw_ptr(root);
+        let mut depth = 0;
+        while !node.is_null() && depth < prefix.len() {
+            match (*node).kind {
+                NodeKind::Leaf { .. } => break,
+                NodeKind::Inner {
+                    prefix: ref node_prefix,
+                    ref children,
+                    ..
+                } => {
+                    let rest = &prefix[depth..];
+                    let n = cmp::min(rest.len(), node_prefix.len());
+                    if node_prefix[..n] != rest[..n] {
+                        return ptr::null_mut();
+                    }
+                    depth += node_prefix.len();
+                    if depth >= prefix.len() {
+                        break;
+                    }
+                    node = children.find(prefix[depth]);
+                    depth += 1;
+                }
+            }
+        }
+        node
+    }
+
+    unsafe fn walk<F>(node: ArtNodePtr<V>, prefix: &[u8], f: &mut F)
+    where
+        F: FnMut(&[u8], &V),
+    {
+        match (*node).kind {
+            NodeKind::Leaf { ref key, ref value } => {
+                if key.starts_with(prefix) {
+                    f(key, value);
+                }
+            }
+            NodeKind::Inner {
+                ref term,
+                ref children,
+                ..
+            } => {
+                let term = util::atomic_load_raw_ptr(term);
+                if !term.is_null() {
+                    Self::walk(term, prefix, f);
+                }
+                for (_, child) in children.entries() {
+                    Self::walk(child, prefix, f);
+                }
+            }
+        }
+    }
+
+    unsafe fn insert_at(
+        cursor: &Cursor,
+        slot: *mut ArtNodePtr<V>,
+        key: &[u8],
+        depth: usize,
+        value: V,
+    ) -> Result<bool, HazardError> {
+        let node = util::atomic_load_raw_ptr(slot);
+        if node.is_null() {
+            util::atomic_store_raw_ptr(slot, ArtNode::new_leaf(key, value));
+            return Ok(true);
+        }
+        match (*node).kind {
+            NodeKind::Leaf {
+                key: ref leaf_key, ..
+            } => {
+                if leaf_key.as_slice() == key {
+                    util::atomic_store_raw_ptr(slot, ArtNode::new_leaf(key, value));
+                    cursor.retire(node)?;
+                    return Ok(false);
+                }
+                // Both keys share `key[..depth]`, split at the first different byte.
+                let p = common_prefix_len(&leaf_key[depth..], &key[depth..]);
+                let mut term = ptr::null_mut();
+                let mut children = Children::with_capacity(2);
+                attach(&mut term, &mut children, leaf_key, depth + p, node);
+                let leaf = ArtNode::new_leaf(key, value);
+                attach(&mut term, &mut children, key, depth + p, leaf);
+                let inner = ArtNode::new_inner(key[depth..depth + p].to_vec(), term, children);
+                util::atomic_store_raw_ptr(slot, inner);
+                Ok(true)
+            }
+            NodeKind::Inner {
+                ref prefix,
+                ref mut term,
+                ref mut children,
+            } => {
+                let p = common_prefix_len(prefix, &key[depth..]);
+                if p < prefix.len() {
+                    // Split the compressed path.
+                    let lower = ArtNode::new_inner(prefix[p + 1..].to_vec(), *term, children.clone());
+                    let mut new_term = ptr::null_mut();
+                    let mut new_children = Children::with_capacity(2);
+                    new_children.add(prefix[p], lower);
+                    let leaf = ArtNode::new_leaf(key, value);
+                    attach(&mut new_term, &mut new_children, key, depth + p, leaf);
+                    let upper = ArtNode::new_inner(prefix[..p].to_vec(), new_term, new_children);
+                    util::atomic_store_raw_ptr(slot, upper);
+                    cursor.retire(node)?;
+                    return Ok(true);
+                }
+                let depth = depth + prefix.len();
+                if depth == key.len() {
+                    return Self::insert_at(cursor, term as *mut _, key, depth, value);
+                }
+                let byte = key[depth];
+                if let Some(child_slot) = children.slot(byte) {
+                    return Self::insert_at(cursor, child_slot, key, depth + 1, value);
+                }
+                let mut new_children = children.resize(children.len() + 1);
+                new_children.add(byte, ArtNode::new_leaf(key, value));
+                let inner = ArtNode::new_inner(prefix.clone(), *term, new_children);
+                util::atomic_store_raw_ptr(slot, inner);
+                cursor.retire(node)?;
+                Ok(true)
+            }
+        }
+    }
+
+    unsafe fn remove_at(
+        cursor: &Cursor,
+        slot: *mut ArtNodePtr<V>,
+        key: &[u8],
+        depth: usize,
+    ) -> Result<bool, HazardError> {
+        let node = util::atomic_load_raw_ptr(slot);
+        if node.is_null() {
+            return Ok(false);
+        }
+        let ret = match (*node).kind {
+            NodeKind::Leaf {
+                key: ref leaf_key, ..
+            } => {
+                if leaf_key.as_slice() != key {
+                    return Ok(false);
+                }
+                util::atomic_store_raw_ptr(slot, ptr::null_mut());
+                cursor.retire(node)?;
+                return Ok(true);
+            }
+            NodeKind::Inner {
+                ref prefix,
+                ref mut term,
+                ref mut children,
+            } => {
+                if !key[depth..].starts_with(prefix) {
+                    return Ok(false);
+                }
+                let depth = depth + prefix.len();
+                if depth == key.len() {
+                    Self::remove_at(cursor, term as *mut _, key, depth)?
+                } else if let Some(child_slot) = children.slot(key[depth]) {
+                    let ret = Self::remove_at(cursor, child_slot, key, depth + 1)?;
+                    if ret && util::atomic_load_raw_ptr(child_slot).is_null() {
+                        let mut new_children = children.clone();
+                        new_children.remove(key[depth]);
+                        let new_children = new_children.resize(0);
+                        let inner = ArtNode::new_inner(prefix.clone(), *term, new_children);
+                        util::atomic_store_raw_ptr(slot, inner);
+                        cursor.retire(node)?;
+                    }
+                    ret
+                } else {
+                    false
+                }
+            }
+        };
+        if ret {
+            Self::compact(cursor, slot)?;
+        }
+        Ok(ret)
+    }
+
+    /// Replace the inner node in `slot` by something smaller if it has at most one entry left.
+    unsafe fn compact(cursor: &Cursor, slot: *mut ArtNodePtr<V>) -> Result<(), HazardError> {
+        let node = util::atomic_load_raw_ptr(slot);
+        if node.is_null() {
+            return Ok(());
+        }
+        // The inner child merged up, retired with `node` once unlinked by the store below.
+        let mut merged_child = ptr::null_mut();
+        let replacement = match (*node).kind {
+            NodeKind::Leaf { .. } => return Ok(()),
+            NodeKind::Inner {
+                ref prefix,
+                term,
+                ref children,
+            } => match (children.len(), term.is_null()) {
+                (0, _) => term,
+                (1, true) => {
+                    let (byte, child) = children.entries()[0];
+                    if (*child).is_leaf() {
+                        // Leaves keep the full key, so they can be moved up directly.
+                        child
+                    } else {
+                        match (*child).kind {
+                            NodeKind::Inner {
+                                prefix: ref child_prefix,
+                                term: child_term,
+                                children: ref child_children,
+                            } => {
+                                let mut merged = prefix.clone();
+                                merged.push(byte);
+                                merged.extend_from_slice(child_prefix);
+                                merged_child = child;
+                                ArtNode::new_inner(merged, child_term, child_children.clone())
+                            }
+                            NodeKind::Leaf { .. } => unreachable!(),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            },
+        };
+        util::atomic_store_raw_ptr(slot, replacement);
+        cursor.retire(node)?;
+        if !merged_child.is_null() {
+            cursor.retire(merged_child)?;
+        }
+        Ok(())
+    }
+
+    unsafe fn destroy_node(node: ArtNodePtr<V>) {
+        if node.is_null() {
+            return;
+        }
+        if let NodeKind::Inner {
+            term, ref children, ..
+        } = (*node).kind
+        {
+            Self::destroy_node(term);
+            for (_, child) in children.entries() {
+                Self::destroy_node(child);
+            }
+        }
+        drop(Box::from_raw(node));
+    }
+
+    /// Free all nodes reachable from the root and drop their values, leaving current map without a
+    /// root. It's called when current map is dropped, and calling it again does nothing.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may use current map at the same time, and current map must not be used
+    /// afterwards except being dropped.
+    pub unsafe fn destroy(&mut self) {
+        Self::destroy_node(*self.root);
+        self.root = util::WrappedAlign64Type(ptr::null_mut());
+        self.len = util::WrappedAlign64Type(0);
+    }
+}
+
+impl<V> Drop for ArtMap<V> {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy();
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Definition and implementations of `BravoRwLock`
+//!
+use hazard_epoch::MAX_THREAD_COUNT;
+use spin_rwlock::SpinRWLock;
+use util;
+use std::cell::UnsafeCell;
+use std::intrinsics;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::Ordering;
+
+/// After revoking read bias, it's inhibited for `INHIBIT_MULTIPLIER` times of the revocation cost.
+const INHIBIT_MULTIPLIER: i64 = 9;
+
+/// Read-biased reader-writer lock based on
+/// [`BRAVO`](https://arxiv.org/abs/1810.01553). While read bias is enabled, readers only mark
+/// their own slot in the visible readers array, which is 64 bytes aligned, so read acquisition
+/// touches no shared cache line. A writer revokes the bias and waits for visible readers to leave,
+/// then bias is inhibited for a while in proportion to the revocation cost, so write-heavy phases
+/// fall back to the underlying `SpinRWLock`.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::bravo_rwlock::BravoRwLock;
+///
+/// let lock = BravoRwLock::new(1);
+/// assert_eq!(*lock.read(), 1);
+/// *lock.write() += 1;
+/// assert_eq!(*lock.read(), 2);
+/// assert_eq!(lock.into_inner(), 2);
+/// ```
+///
+pub struct BravoRwLock<T: ?Sized> {
+    lock: SpinRWLock,
+    read_bias: util::WrappedAlign64Type<u8>,
+    inhibit_until: util::AtomicI64,
+    readers: Vec<util::WrappedAlign64Type<u8>>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for BravoRwLock<T> {}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for BravoRwLock<T> {}
+
+impl<T: Default> Default for BravoRwLock<T> {
+    fn default() -> Self {
+        BravoRwLock::new(T::default())
+    }
+}
+
+impl<T> BravoRwLock<T> {
+    /// Return BravoRwLock which protects `data`, with read bias enabled.
+    pub fn new(data: T) -> Self {
+        let mut readers = Vec::with_capacity(MAX_THREAD_COUNT);
+        for _ in 0..MAX_THREAD_COUNT {
+            readers.push(util::WrappedAlign64Type(0));
+        }
+        BravoRwLock {
+            lock: SpinRWLock::default(),
+            read_bias: util::WrappedAlign64Type(1),
+            inhibit_until: util::AtomicI64::new(0),
+            readers,
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consume current lock and return the protected data.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> BravoRwLock<T> {
+    /// Lock for reading, then return guard which derefs to `&T`.
+    pub fn read(&self) -> BravoReadGuard<T> {
+        if self.is_read_biased() {
+            let slot = util::get_thread_id() as usize % self.readers.len();
+            let reader = self.readers[slot].as_mut_ptr();
+            if unsafe { intrinsics::atomic_cxchg(reader, 0, 1).1 } {
+                // Recheck, a writer may have revoked the bias before seeing current reader.
+                if self.is_read_biased() {
+                    return BravoReadGuard {
+                        lock: self,
+                        slot: Some(slot),
+                    };
+                }
+                unsafe { intrinsics::atomic_store(reader, 0) };
+            }
+        }
+        self.lock.rlock();
+        if !self.is_read_biased() && util::get_cur_microseconds_time() >= self.inhibit_until() {
+            // No writer can be revoking while read lock is held.
+            unsafe { intrinsics::atomic_store(self.read_bias.as_mut_ptr(), 1) };
+        }
+        BravoReadGuard {
+            lock: self,
+            slot: None,
+        }
+    }
+
+    /// Lock for writing, revoking read bias if enabled, then return guard which derefs to
+    /// `&mut T`.
+    pub fn write(&self) -> BravoWriteGuard<T> {
+        self.lock.lock();
+        if self.is_read_biased() {
+            self.revoke();
+        }
+        BravoWriteGuard {
+            lock: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return true if readers take the fast path.
+    #[inline]
+    pub fn is_read_biased(&self) -> bool {
+        0 != unsafe { intrinsics::atomic_load(self.read_bias.as_ptr()) }
+    }
+
+    /// Return mutable reference of the data. No locking is needed because of `&mut self`.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    #[inline]
+    fn inhibit_until(&self) -> i64 {
+        self.inhibit_until.load(Ordering::SeqCst)
+    }
+
+    /// Disable read bias and wait for all visible readers. Write lock must be held.
+    fn revoke(&self) {
+        let start = util::get_cur_microseconds_time();
+        unsafe {
+            intrinsics::atomic_store(self.read_bias.as_mut_ptr(), 0);
+            for reader in self.readers.iter() {
+                let mut backoff = util::Backoff::new();
+                while 0 != intrinsics::atomic_load(reader.as_ptr()) {
+                    backoff.snooze();
+                }
+            }
+            let now = util::get_cur_microseconds_time();
+            self.inhibit_until
+                .store(now + (now - start) * INHIBIT_MULTIPLIER, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Read guard of BravoRwLock, which derefs to `&T` and unlocks it when dropped.
+pub struct BravoReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a BravoRwLock<T>,
+    slot: Option<usize>,
+}
+
+impl<'a, T: ?Sized> BravoReadGuard<'a, T> {
+    /// Return true if current guard is got by the fast path.
+    #[inline]
+    pub fn is_fast(&self) -> bool {
+        self.slot.is_some()
+    }
+}
+
+impl<'a, T: ?Sized> Deref for BravoReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for BravoReadGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            match self.slot {
+                Some(slot) => intrinsics::atomic_store(self.lock.readers[slot].as_mut_ptr(), 0),
+                None => self.lock.lock.unrlock(),
+            }
+        }
+    }
+}
+
+/// Write guard of BravoRwLock, which derefs to `&mut T` and unlocks it when dropped. It can't be
+/// sent to another thread, like `SpinRwLockWriteGuard`.
+pub struct BravoWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a BravoRwLock<T>,
+    /// Opt out of the auto traits, which would follow `BravoRwLock` instead of `T`.
+    marker: PhantomData<*const ()>,
+}
+
+unsafe impl<'a, T: ?Sized + Sync> Sync for BravoWriteGuard<'a, T> {}
+
+impl<'a, T: ?Sized> Deref for BravoWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for BravoWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for BravoWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.lock.unlock();
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_bravo_rwlock() {
+        use bravo_rwlock::BravoRwLock;
+        let mut lock = BravoRwLock::new(0);
+        assert!(lock.is_read_biased());
+        {
+            let r1 = lock.read();
+            assert!(r1.is_fast());
+            // Slot of current thread is taken, so fall back to the underlying lock.
+            let r2 = lock.read();
+            assert!(!r2.is_fast());
+            assert_eq!(*r1 + *r2, 0);
+        }
+        *lock.write() += 1;
+        assert!(!lock.is_read_biased());
+        {
+            let r = lock.read();
+            assert!(!r.is_fast());
+            assert_eq!(*r, 1);
+        }
+        *lock.get_mut() += 1;
+        assert_eq!(lock.into_inner(), 2);
+    }
+}
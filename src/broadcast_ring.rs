@@ -0,0 +1,231 @@
+//! Definition and implementations of single-producer multi-consumer broadcast ring
+//!
+use error::QueueError;
+use util;
+use std::cell::UnsafeCell;
+use std::cmp;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::sync::atomic::{self, Ordering};
+
+struct Slot<T> {
+    /// `2 * pos + 1` while element of position `pos` is being written, `2 * pos + 2` once written,
+    /// 0 before the first write.
+    seq: util::AtomicU64,
+    data: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct Ring<T> {
+    /// Position of the next element to publish.
+    tail: util::WrappedAlign64Type<util::AtomicU64>,
+    slots: Box<[util::WrappedAlign64Type<Slot<T>>]>,
+}
+
+unsafe impl<T: Copy + Send> Send for Ring<T> {}
+
+unsafe impl<T: Copy + Send> Sync for Ring<T> {}
+
+impl<T: Copy> Ring<T> {
+    #[inline]
+    fn capacity(&self) -> u64 {
+        self.slots.len() as u64
+    }
+
+    #[inline]
+    fn slot(&self, pos: u64) -> &Slot<T> {
+        &self.slots[(pos % self.capacity()) as usize]
+    }
+
+    #[inline]
+    fn tail(&self) -> u64 {
+        self.tail.load(Ordering::SeqCst)
+    }
+}
+
+/// Producing half of a broadcast ring created by [`broadcast`]. There is only one, so it can't be
+/// cloned.
+///
+/// [`broadcast`]: fn.broadcast.html
+pub struct Publisher<T: Copy> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T: Copy> Publisher<T> {
+    /// Publish `v` to all subscribers, overwriting the oldest element if current ring is full. It
+    /// never waits for subscribers.
+    pub fn publish(&mut self, v: T) {
+        let pos = self.ring.tail();
+        let slot = self.ring.slot(pos);
+        unsafe {
+            slot.seq.store(2 * pos + 1, Ordering::SeqCst);
+            // Keep the odd sequence visible before any byte of the new element.
+            atomic::fence(util::ORDER_RELEASE);
+            util::racy_write(slot.data.get(), MaybeUninit::new(v));
+        }
+        slot.seq.store(2 * pos + 2, Ordering::SeqCst);
+        self.ring.tail.store(pos + 1, Ordering::SeqCst);
+    }
+
+    /// Return the number of published elements, which is the position of the next one.
+    #[inline]
+    pub fn published_count(&self) -> u64 {
+        self.ring.tail()
+    }
+
+    /// Return a new subscriber, which receives elements published from now on.
+    pub fn subscribe(&self) -> Subscriber<T> {
+        Subscriber {
+            ring: self.ring.clone(),
+            pos: self.ring.tail(),
+        }
+    }
+}
+
+/// Consuming half of a broadcast ring created by [`broadcast`]. Each subscriber reads every
+/// element at its own pace, and a clone goes on from the position of the original.
+///
+/// [`broadcast`]: fn.broadcast.html
+pub struct Subscriber<T: Copy> {
+    ring: Arc<Ring<T>>,
+    /// Position of the next element to receive.
+    pos: u64,
+}
+
+impl<T: Copy> Subscriber<T> {
+    /// Receive the next element, or None if it's not published yet. Return `QueueError::Lagged`
+    /// if it has been overwritten, then current subscriber skips to the oldest element still held,
+    /// so the next call goes on from there.
+    pub fn try_recv(&mut self) -> Result<Option<T>, QueueError> {
+        let want = 2 * self.pos + 2;
+        let slot = self.ring.slot(self.pos);
+        let seq = slot.seq.load(Ordering::SeqCst);
+        if seq == want {
+            let v = unsafe { util::racy_read(slot.data.get()) };
+            if slot.seq.load(Ordering::SeqCst) == want {
+                self.pos += 1;
+                return Ok(Some(unsafe { v.assume_init() }));
+            }
+        } else if seq < want {
+            // The slot holds an older lap, or the element is being written.
+            return Ok(None);
+        }
+        // Overwritten by a later lap, before or while reading.
+        let oldest = cmp::max(
+            self.pos + 1,
+            self.ring.tail().saturating_sub(self.ring.capacity()),
+        );
+        let missed = oldest - self.pos;
+        self.pos = oldest;
+        Err(QueueError::Lagged {
+            op: "try_recv",
+            missed,
+        })
+    }
+
+    /// Return the number of published elements current subscriber hasn't received, which may
+    /// exceed the capacity if it has lagged.
+    #[inline]
+    pub fn lag(&self) -> u64 {
+        self.ring.tail().saturating_sub(self.pos)
+    }
+}
+
+impl<T: Copy> Clone for Subscriber<T> {
+    fn clone(&self) -> Self {
+        Subscriber {
+            ring: self.ring.clone(),
+            pos: self.pos,
+        }
+    }
+}
+
+/// Create a single-producer multi-consumer broadcast ring of `capacity` slots, where each
+/// subscriber receives every element, like market data fanned out to strategies. `capacity` must
+/// be positive.
+///
+/// The publisher overwrites slots in ring order and never waits, and each slot is guarded by a
+/// sequence like `SeqLock`, so subscribers never write shared memory. A subscriber which falls more
+/// than `capacity` elements behind finds its next element overwritten, and gets
+/// `QueueError::Lagged` telling how many it missed. Elements are `Copy`, because a torn read is
+/// discarded without dropping.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::broadcast_ring::broadcast;
+/// use rs_lockfree::error::QueueError;
+///
+/// let (mut publisher, mut fast) = broadcast(2);
+/// let mut slow = fast.clone();
+/// for i in 0..2 {
+///     publisher.publish(i);
+///     assert_eq!(fast.try_recv(), Ok(Some(i)));
+/// }
+/// assert_eq!(fast.try_recv(), Ok(None));
+/// publisher.publish(2);
+/// assert_eq!(
+///     slow.try_recv(),
+///     Err(QueueError::Lagged {
+///         op: "try_recv",
+///         missed: 1,
+///     })
+/// );
+/// assert_eq!(slow.try_recv(), Ok(Some(1)));
+/// assert_eq!(slow.try_recv(), Ok(Some(2)));
+/// ```
+///
+pub fn broadcast<T: Copy>(capacity: usize) -> (Publisher<T>, Subscriber<T>) {
+    assert!(0 < capacity, "capacity must be positive");
+    let ring = Arc::new(Ring {
+        tail: util::WrappedAlign64Type(util::AtomicU64::new(0)),
+        slots: (0..capacity)
+            .map(|_| {
+                util::WrappedAlign64Type(Slot {
+                    seq: util::AtomicU64::new(0),
+                    data: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice(),
+    });
+    (
+        Publisher { ring: ring.clone() },
+        Subscriber { ring, pos: 0 },
+    )
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use broadcast_ring::broadcast;
+        use error::QueueError;
+        let (mut publisher, mut sub) = broadcast(4);
+        assert_eq!(sub.try_recv(), Ok(None));
+        for i in 0..3 {
+            publisher.publish(i);
+        }
+        assert_eq!((publisher.published_count(), sub.lag()), (3, 3));
+        assert_eq!(sub.try_recv(), Ok(Some(0)));
+        let mut late = publisher.subscribe();
+        assert_eq!(late.try_recv(), Ok(None));
+
+        // Overrun by more than one lap.
+        for i in 3..13 {
+            publisher.publish(i);
+        }
+        assert_eq!(
+            sub.try_recv(),
+            Err(QueueError::Lagged {
+                op: "try_recv",
+                missed: 8,
+            })
+        );
+        for i in 9..13 {
+            assert_eq!(sub.try_recv(), Ok(Some(i)));
+        }
+        assert_eq!(sub.try_recv(), Ok(None));
+        assert_eq!(late.lag(), 10);
+        assert!(late.try_recv().is_err());
+        assert_eq!(late.try_recv(), Ok(Some(9)));
+    }
+}
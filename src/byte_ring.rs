@@ -0,0 +1,260 @@
+//! Definition and implementations of `ByteRing`
+//!
+use error::QueueError;
+use util;
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Size of the header before each record, which also aligns records.
+const HEADER_SIZE: usize = 8;
+/// Header flag of a committed record, whose data is ready.
+const COMMITTED: u64 = 1 << 63;
+/// Header flag of a reservation dropped without committing, which is skipped.
+const SKIPPED: u64 = 1 << 62;
+/// Header flag of the unused tail of the buffer, after which records wrap to its start.
+const WRAPPED: u64 = 1 << 61;
+
+#[inline]
+fn record_size(len: usize) -> usize {
+    (HEADER_SIZE + len + HEADER_SIZE - 1) & !(HEADER_SIZE - 1)
+}
+
+/// LockFree ring of variable-length byte records with multiple producers and a single consumer,
+/// for low-latency logging or event pipelines where producers never block and the consumer drains
+/// in batches.
+///
+/// A producer reserves space for a record by CAS on the tail, writes the data in place through
+/// `Reservation`, and commits it by setting the header. A record which doesn't fit before the end
+/// of the buffer is placed at its start, after a header marking the rest as unused. The consumer
+/// reads committed records in order until one is not committed yet, zeroes the space they took,
+/// and then moves the head, which frees it for producers.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::byte_ring::ByteRing;
+///
+/// let ring = ByteRing::with_capacity(64);
+/// let mut r = ring.reserve(5).unwrap();
+/// r.copy_from_slice(b"hello");
+/// r.commit();
+/// ring.try_push(b"world").unwrap();
+/// let mut records = Vec::new();
+/// assert_eq!(unsafe { ring.drain(|r| records.push(r.to_vec())) }, 2);
+/// assert_eq!(records, vec![b"hello".to_vec(), b"world".to_vec()]);
+/// ```
+///
+pub struct ByteRing {
+    /// Position of the next record to read, only moved by the consumer.
+    head: util::WrappedAlign64Type<util::AtomicU64>,
+    /// Position of the next reservation.
+    tail: util::WrappedAlign64Type<util::AtomicU64>,
+    buf: UnsafeCell<Box<[u64]>>,
+    mask: usize,
+}
+
+unsafe impl Send for ByteRing {}
+
+unsafe impl Sync for ByteRing {}
+
+impl ByteRing {
+    /// Return empty ByteRing of at least `capacity` bytes, which is rounded up to a power of two
+    /// no less than 16. Each record takes its length plus an 8-byte header, rounded up to 8 bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(2 * HEADER_SIZE).next_power_of_two();
+        ByteRing {
+            head: util::WrappedAlign64Type(util::AtomicU64::new(0)),
+            tail: util::WrappedAlign64Type(util::AtomicU64::new(0)),
+            buf: UnsafeCell::new(vec![0u64; capacity / HEADER_SIZE].into_boxed_slice()),
+            mask: capacity - 1,
+        }
+    }
+
+    /// Return the size of the buffer in bytes.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// Return true if there is no reserved record.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::SeqCst) == self.tail.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    fn ptr(&self, offset: usize) -> *mut u8 {
+        unsafe { ((*self.buf.get()).as_mut_ptr() as *mut u8).add(offset) }
+    }
+
+    #[inline]
+    fn header(&self, offset: usize) -> &AtomicU64 {
+        unsafe { &*(self.ptr(offset) as *const AtomicU64) }
+    }
+
+    /// Reserve space for a record of `len` bytes. Return `QueueError::Full` if there isn't enough
+    /// free space, or the record is larger than the buffer. The record is read by the consumer once
+    /// committed.
+    pub fn reserve(&self, len: usize) -> Result<Reservation, QueueError> {
+        let size = record_size(len);
+        if len > u32::max_value() as usize || size > self.capacity() {
+            return Err(QueueError::Full { op: "reserve" });
+        }
+        let mut backoff = util::Backoff::new();
+        let mut tail = self.tail.load(Ordering::SeqCst);
+        loop {
+            let offset = tail as usize & self.mask;
+            let rest = self.capacity() - offset;
+            let total = if size > rest { rest + size } else { size };
+            if tail + total as u64 - self.head.load(Ordering::SeqCst) > self.capacity() as u64 {
+                return Err(QueueError::Full { op: "reserve" });
+            }
+            let (cur, ok) = self.tail.cas(tail, tail + total as u64, Ordering::SeqCst);
+            if ok {
+                let offset = if size > rest {
+                    self.header(offset).store(COMMITTED | WRAPPED, Ordering::SeqCst);
+                    0
+                } else {
+                    offset
+                };
+                return Ok(Reservation {
+                    ring: self,
+                    offset,
+                    len,
+                    committed: false,
+                });
+            }
+            tail = cur;
+            backoff.spin();
+        }
+    }
+
+    /// Push a record holding a copy of `data`. Return `QueueError::Full` like `reserve`.
+    pub fn try_push(&self, data: &[u8]) -> Result<(), QueueError> {
+        let mut reservation = self.reserve(data.len())?;
+        reservation.copy_from_slice(data);
+        reservation.commit();
+        Ok(())
+    }
+
+    /// Call `f` with each committed record in order, until the first one not committed yet, and
+    /// then free their space at once. Return the number of records read.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread may drain at a time.
+    pub unsafe fn drain<F: FnMut(&[u8])>(&self, mut f: F) -> usize {
+        let head = self.head.load(Ordering::SeqCst);
+        let tail = self.tail.load(Ordering::SeqCst);
+        let mut pos = head;
+        let mut cnt = 0;
+        while pos < tail {
+            let offset = pos as usize & self.mask;
+            let header = self.header(offset).load(Ordering::SeqCst);
+            if 0 == header & COMMITTED {
+                break;
+            }
+            let len = header as u32 as usize;
+            if 0 != header & WRAPPED {
+                pos += (self.capacity() - offset) as u64;
+                continue;
+            }
+            if 0 == header & SKIPPED {
+                f(slice::from_raw_parts(self.ptr(offset + HEADER_SIZE), len));
+                cnt += 1;
+            }
+            pos += record_size(len) as u64;
+        }
+        // Headers of later records must read as not committed until written.
+        let mut zero = head;
+        while zero < pos {
+            let offset = zero as usize & self.mask;
+            let n = ((pos - zero) as usize).min(self.capacity() - offset);
+            ptr::write_bytes(self.ptr(offset), 0, n);
+            zero += n as u64;
+        }
+        self.head.store(pos, Ordering::SeqCst);
+        cnt
+    }
+}
+
+/// Space reserved for a record in `ByteRing`, which derefs to its bytes. It's committed by
+/// `commit`, and skipped by the consumer if dropped without committing.
+pub struct Reservation<'a> {
+    ring: &'a ByteRing,
+    offset: usize,
+    len: usize,
+    committed: bool,
+}
+
+impl<'a> Reservation<'a> {
+    /// Publish the record to the consumer.
+    pub fn commit(mut self) {
+        self.publish(COMMITTED);
+    }
+
+    fn publish(&mut self, flags: u64) {
+        self.ring
+            .header(self.offset)
+            .store(flags | self.len as u64, Ordering::SeqCst);
+        self.committed = true;
+    }
+}
+
+impl<'a> Deref for Reservation<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ring.ptr(self.offset + HEADER_SIZE), self.len) }
+    }
+}
+
+impl<'a> DerefMut for Reservation<'a> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ring.ptr(self.offset + HEADER_SIZE), self.len) }
+    }
+}
+
+impl<'a> Drop for Reservation<'a> {
+    fn drop(&mut self) {
+        if !self.committed {
+            // The consumer can't pass an uncommitted record, so skip it.
+            self.publish(COMMITTED | SKIPPED);
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use byte_ring::ByteRing;
+        let ring = ByteRing::with_capacity(60);
+        assert_eq!(ring.capacity(), 64);
+        assert!(ring.is_empty());
+        let mut records = Vec::new();
+        for lap in 0..4u8 {
+            // 24 + 16 + 8 + 8 bytes, so records wrap in later laps.
+            ring.try_push(&[lap; 13]).unwrap();
+            let mut r = ring.reserve(3).unwrap();
+            r.copy_from_slice(&[lap; 3]);
+            // Committed out of order, the consumer stops at the first one not committed.
+            ring.try_push(&[]).unwrap();
+            assert_eq!(unsafe { ring.drain(|r| records.push(r.to_vec())) }, 1);
+            r.commit();
+            // Dropped without committing.
+            drop(ring.reserve(0).unwrap());
+            assert!(ring.reserve(32).is_err());
+            assert_eq!(unsafe { ring.drain(|r| records.push(r.to_vec())) }, 2);
+            assert!(ring.is_empty());
+            assert_eq!(records, vec![vec![lap; 13], vec![lap; 3], vec![]]);
+            records.clear();
+        }
+        let ring = ByteRing::with_capacity(64);
+        assert!(ring.reserve(57).is_err());
+        ring.try_push(&[7; 56]).unwrap();
+        assert_eq!(unsafe { ring.drain(|r| assert_eq!(r, &[7; 56][..])) }, 1);
+    }
+}
@@ -0,0 +1,356 @@
+//! Definition and implementations of bounded blocking channel based on `LockFreeQueue`
+//!
+use error::{PushError, QueueError};
+use lockfree_queue::LockFreeQueue;
+use util;
+use std::cell::UnsafeCell;
+use std::intrinsics;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+pub use std::sync::mpsc::{RecvError, SendError, TryRecvError, TrySendError};
+
+struct Channel<T> {
+    queue: UnsafeCell<Box<LockFreeQueue<T>>>,
+    capacity: i64,
+    len: util::WrappedAlign64Type<i64>,
+    sender_count: util::WrappedAlign64Type<i64>,
+    receiver_count: util::WrappedAlign64Type<i64>,
+    waiting_senders: util::WrappedAlign64Type<i64>,
+    waiting_receivers: util::WrappedAlign64Type<i64>,
+    mutex: Mutex<()>,
+    not_full: Condvar,
+    not_empty: Condvar,
+}
+
+unsafe impl<T: Send> Send for Channel<T> {}
+
+unsafe impl<T: Send> Sync for Channel<T> {}
+
+impl<T> Channel<T> {
+    fn new(capacity: usize) -> Self {
+        Channel {
+            queue: UnsafeCell::new(LockFreeQueue::default_new_in_heap()),
+            capacity: capacity as i64,
+            len: util::WrappedAlign64Type(0),
+            sender_count: util::WrappedAlign64Type(1),
+            receiver_count: util::WrappedAlign64Type(1),
+            waiting_senders: util::WrappedAlign64Type(0),
+            waiting_receivers: util::WrappedAlign64Type(0),
+            mutex: Mutex::new(()),
+            not_full: Condvar::new(),
+            not_empty: Condvar::new(),
+        }
+    }
+
+    #[inline]
+    fn queue(&self) -> &mut LockFreeQueue<T> {
+        unsafe { &mut **self.queue.get() }
+    }
+
+    #[inline]
+    fn load(v: &util::WrappedAlign64Type<i64>) -> i64 {
+        unsafe { intrinsics::atomic_load(v.as_ptr()) }
+    }
+
+    #[inline]
+    fn add(v: &util::WrappedAlign64Type<i64>, delta: i64) -> i64 {
+        unsafe { util::sync_add_and_fetch(v.as_mut_ptr(), delta) }
+    }
+
+    #[inline]
+    fn is_disconnected_for_sender(&self) -> bool {
+        0 == Self::load(&self.receiver_count)
+    }
+
+    #[inline]
+    fn is_disconnected_for_receiver(&self) -> bool {
+        0 == Self::load(&self.sender_count)
+    }
+
+    /// Reserve a slot for one element. Return false if current channel is full.
+    fn try_reserve(&self) -> bool {
+        let mut len = Self::load(&self.len);
+        while len < self.capacity {
+            let (tmp, ok) = unsafe { intrinsics::atomic_cxchg(self.len.as_mut_ptr(), len, len + 1) };
+            if ok {
+                return true;
+            }
+            len = tmp;
+        }
+        false
+    }
+
+    fn try_send(&self, v: T) -> Result<(), TrySendError<T>> {
+        if self.is_disconnected_for_sender() {
+            Err(TrySendError::Disconnected(v))
+        } else if !self.try_reserve() {
+            Err(TrySendError::Full(v))
+        } else {
+            self.queue().push(v);
+            self.notify(&self.waiting_receivers, &self.not_empty, false);
+            Ok(())
+        }
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        match self.queue().pop() {
+            Some(v) => {
+                Self::add(&self.len, -1);
+                self.notify(&self.waiting_senders, &self.not_full, false);
+                Ok(v)
+            }
+            None => {
+                // Senders push before being dropped, so check again after disconnected.
+                if self.is_disconnected_for_receiver() {
+                    match self.queue().pop() {
+                        Some(v) => {
+                            Self::add(&self.len, -1);
+                            Ok(v)
+                        }
+                        None => Err(TryRecvError::Disconnected),
+                    }
+                } else {
+                    Err(TryRecvError::Empty)
+                }
+            }
+        }
+    }
+
+    fn send(&self, v: T) -> Result<(), SendError<T>> {
+        let mut v = v;
+        loop {
+            match self.try_send(v) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(x)) => return Err(SendError(x)),
+                Err(TrySendError::Full(x)) => v = x,
+            }
+            // Register as a waiter before checking again, so that a receiver either sees the
+            // waiter or makes room before the check.
+            let guard = self.mutex.lock().unwrap();
+            Self::add(&self.waiting_senders, 1);
+            if Self::load(&self.len) >= self.capacity && !self.is_disconnected_for_sender() {
+                let _guard = self.not_full.wait(guard).unwrap();
+            }
+            Self::add(&self.waiting_senders, -1);
+        }
+    }
+
+    fn send_timeout(&self, v: T, timeout: Duration) -> Result<(), PushError<T>> {
+        let deadline = Instant::now() + timeout;
+        let mut v = v;
+        loop {
+            match self.try_send(v) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(x)) => v = x,
+                Err(e) => return Err(e.into()),
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(PushError {
+                    error: QueueError::Timeout {
+                        op: "send_timeout",
+                        timeout,
+                    },
+                    value: v,
+                });
+            }
+            let guard = self.mutex.lock().unwrap();
+            Self::add(&self.waiting_senders, 1);
+            if Self::load(&self.len) >= self.capacity && !self.is_disconnected_for_sender() {
+                let _guard = self.not_full.wait_timeout(guard, deadline - now).unwrap();
+            }
+            Self::add(&self.waiting_senders, -1);
+        }
+    }
+
+    fn recv(&self) -> Result<T, RecvError> {
+        loop {
+            match self.try_recv() {
+                Ok(v) => return Ok(v),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {}
+            }
+            let guard = self.mutex.lock().unwrap();
+            Self::add(&self.waiting_receivers, 1);
+            if 0 == Self::load(&self.len) && !self.is_disconnected_for_receiver() {
+                let _guard = self.not_empty.wait(guard).unwrap();
+            }
+            Self::add(&self.waiting_receivers, -1);
+        }
+    }
+
+    fn recv_timeout(&self, timeout: Duration) -> Result<T, QueueError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.try_recv() {
+                Ok(v) => return Ok(v),
+                Err(TryRecvError::Disconnected) => {
+                    return Err(QueueError::Closed { op: "recv_timeout" })
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(QueueError::Timeout {
+                    op: "recv_timeout",
+                    timeout,
+                });
+            }
+            let guard = self.mutex.lock().unwrap();
+            Self::add(&self.waiting_receivers, 1);
+            if 0 == Self::load(&self.len) && !self.is_disconnected_for_receiver() {
+                let _guard = self.not_empty.wait_timeout(guard, deadline - now).unwrap();
+            }
+            Self::add(&self.waiting_receivers, -1);
+        }
+    }
+
+    /// Wake up waiters if there is any.
+    #[inline]
+    fn notify(&self, waiting: &util::WrappedAlign64Type<i64>, cond: &Condvar, all: bool) {
+        if 0 != Self::load(waiting) {
+            let _guard = self.mutex.lock().unwrap();
+            if all {
+                cond.notify_all();
+            } else {
+                cond.notify_one();
+            }
+        }
+    }
+}
+
+impl<T> From<TrySendError<T>> for PushError<T> {
+    fn from(e: TrySendError<T>) -> PushError<T> {
+        match e {
+            TrySendError::Full(value) => PushError {
+                error: QueueError::Full { op: "try_send" },
+                value,
+            },
+            TrySendError::Disconnected(value) => PushError {
+                error: QueueError::Closed { op: "try_send" },
+                value,
+            },
+        }
+    }
+}
+
+/// Sending half of a channel created by [`sync_channel`], which can be cloned.
+///
+/// [`sync_channel`]: fn.sync_channel.html
+pub struct SyncSender<T> {
+    inner: Arc<Channel<T>>,
+}
+
+impl<T> SyncSender<T> {
+    /// Send `v`, block while current channel is full. Return error if all receivers are dropped.
+    pub fn send(&self, v: T) -> Result<(), SendError<T>> {
+        self.inner.send(v)
+    }
+
+    /// Send `v` without blocking.
+    pub fn try_send(&self, v: T) -> Result<(), TrySendError<T>> {
+        self.inner.try_send(v)
+    }
+
+    /// Send `v`, block while current channel is full for at most `timeout`. Return
+    /// `QueueError::Timeout` if still full, or `QueueError::Closed` if all receivers are dropped.
+    pub fn send_timeout(&self, v: T, timeout: Duration) -> Result<(), PushError<T>> {
+        self.inner.send_timeout(v, timeout)
+    }
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> Self {
+        Channel::<T>::add(&self.inner.sender_count, 1);
+        SyncSender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for SyncSender<T> {
+    fn drop(&mut self) {
+        if 0 == Channel::<T>::add(&self.inner.sender_count, -1) {
+            let inner = &self.inner;
+            inner.notify(&inner.waiting_receivers, &inner.not_empty, true);
+        }
+    }
+}
+
+/// Receiving half of a channel created by [`sync_channel`], which can be cloned.
+///
+/// [`sync_channel`]: fn.sync_channel.html
+pub struct Receiver<T> {
+    inner: Arc<Channel<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receive one element, block while current channel is empty. Return error if current channel
+    /// is empty and all senders are dropped.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.inner.recv()
+    }
+
+    /// Receive one element without blocking.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.inner.try_recv()
+    }
+
+    /// Receive one element, block while current channel is empty for at most `timeout`. Return
+    /// `QueueError::Timeout` if still empty, or `QueueError::Closed` if current channel is empty
+    /// and all senders are dropped.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, QueueError> {
+        self.inner.recv_timeout(timeout)
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Channel::<T>::add(&self.inner.receiver_count, 1);
+        Receiver {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if 0 == Channel::<T>::add(&self.inner.receiver_count, -1) {
+            let inner = &self.inner;
+            inner.notify(&inner.waiting_senders, &inner.not_full, true);
+        }
+    }
+}
+
+/// Create a bounded multi-producer multi-consumer channel which holds at most `capacity`
+/// elements, like `std::sync::mpsc::sync_channel`. Elements are transferred by `LockFreeQueue`,
+/// and threads are parked only when current channel is full or empty. `capacity` must be positive.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::channel::{sync_channel, TrySendError};
+/// use std::thread;
+///
+/// let (tx, rx) = sync_channel(1);
+/// tx.send(1).unwrap();
+/// assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+/// let t = thread::spawn(move || {
+///     tx.send(2).unwrap();
+/// });
+/// assert_eq!(rx.recv().unwrap(), 1);
+/// assert_eq!(rx.recv().unwrap(), 2);
+/// t.join().unwrap();
+/// assert!(rx.recv().is_err());
+/// ```
+///
+pub fn sync_channel<T>(capacity: usize) -> (SyncSender<T>, Receiver<T>) {
+    assert!(0 < capacity, "capacity must be positive");
+    let inner = Arc::new(Channel::new(capacity));
+    (
+        SyncSender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
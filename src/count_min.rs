@@ -0,0 +1,174 @@
+//! Definition and implementations of `CountMinSketch`
+//!
+use util;
+use std::collections::hash_map::DefaultHasher;
+use std::f64;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::Ordering;
+
+/// LockFree count-min sketch, which estimates how many times each key has been added in fixed
+/// memory, for frequency estimation like hot-key detection under high throughput.
+///
+/// Counters form `depth` rows of `width`. Adding a key increases one counter in each row by relaxed
+/// atomic increments, and the estimate of a key is the minimum of its counters, which never
+/// underestimates and overestimates by at most `epsilon * total` with probability `1 - delta`,
+/// where `width = e / epsilon` and `depth = ln(1 / delta)`. Counters of each row are picked by
+/// double hashing on one hash of the key.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::count_min::CountMinSketch;
+///
+/// let sketch = CountMinSketch::with_error(0.01, 0.01);
+/// for i in 0..1000 {
+///     sketch.add(&(i % 10), 1);
+/// }
+/// sketch.add("hot", 500);
+/// assert!(sketch.estimate("hot") >= 500);
+/// assert!(sketch.estimate(&3) >= 100);
+/// assert_eq!(sketch.total(), 1500);
+/// ```
+///
+pub struct CountMinSketch {
+    counters: Vec<util::AtomicU64>,
+    mask: usize,
+    depth: usize,
+    total: util::WrappedAlign64Type<util::AtomicU64>,
+}
+
+impl CountMinSketch {
+    /// Return CountMinSketch of `depth` rows of at least `width` counters, which is rounded up to
+    /// a power of two. Both must be positive.
+    pub fn with_dimensions(width: usize, depth: usize) -> Self {
+        assert!(0 < width && 0 < depth, "width and depth must be positive");
+        let width = width.next_power_of_two();
+        CountMinSketch {
+            counters: (0..width * depth).map(|_| util::AtomicU64::new(0)).collect(),
+            mask: width - 1,
+            depth,
+            total: util::WrappedAlign64Type(util::AtomicU64::new(0)),
+        }
+    }
+
+    /// Return CountMinSketch whose estimate exceeds the real count by at most `epsilon` times
+    /// the total with probability `1 - delta`. Both must be in (0, 1).
+    pub fn with_error(epsilon: f64, delta: f64) -> Self {
+        assert!(
+            0.0 < epsilon && epsilon < 1.0 && 0.0 < delta && delta < 1.0,
+            "epsilon and delta must be in (0, 1)"
+        );
+        let width = (f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil() as usize;
+        Self::with_dimensions(width, depth.max(1))
+    }
+
+    /// Return the number of counters in each row.
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// Return the number of rows.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Return the sum of all counts added.
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.total.load(util::ORDER_RELAXED)
+    }
+
+    /// Return the counter of `key` in each row.
+    fn counters<'a, K: Hash + ?Sized>(
+        &'a self,
+        key: &K,
+    ) -> impl Iterator<Item = &'a util::AtomicU64> + 'a {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        let h1 = hash as u32 as usize;
+        // Odd step, so rows differ whenever the width is more than 1.
+        let h2 = (hash >> 32) as usize | 1;
+        let width = self.width();
+        (0..self.depth).map(move |i| {
+            &self.counters[i * width + (h1.wrapping_add(i.wrapping_mul(h2)) & self.mask)]
+        })
+    }
+
+    /// Add `count` to `key`.
+    pub fn add<K: Hash + ?Sized>(&self, key: &K, count: u64) {
+        for counter in self.counters(key) {
+            counter.fetch_add(count, util::ORDER_RELAXED);
+        }
+        self.total.fetch_add(count, util::ORDER_RELAXED);
+    }
+
+    /// Return the estimated count of `key`, which is never less than counts of `key` added before.
+    pub fn estimate<K: Hash + ?Sized>(&self, key: &K) -> u64 {
+        self.counters(key)
+            .map(|counter| counter.load(util::ORDER_RELAXED))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Reset all counters. Counts added at the same time may be partly kept.
+    pub fn clear(&self) {
+        for counter in self.counters.iter() {
+            counter.store(0, util::ORDER_RELAXED);
+        }
+        self.total.store(0, util::ORDER_RELAXED);
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use count_min::CountMinSketch;
+        let sketch = CountMinSketch::with_dimensions(100, 4);
+        assert_eq!(sketch.width(), 128);
+        assert_eq!(sketch.depth(), 4);
+        assert_eq!(sketch.estimate(&0), 0);
+        for i in 0..1000u64 {
+            sketch.add(&i, i % 7);
+        }
+        for i in 0..1000u64 {
+            assert!(sketch.estimate(&i) >= i % 7);
+        }
+        assert_eq!(sketch.total(), (0..1000u64).map(|i| i % 7).sum::<u64>());
+        sketch.clear();
+        assert_eq!(sketch.estimate(&6), 0);
+        assert_eq!(sketch.total(), 0);
+
+        let sketch = CountMinSketch::with_error(0.001, 0.01);
+        assert_eq!(sketch.width(), 4096);
+        assert_eq!(sketch.depth(), 5);
+    }
+
+    #[test]
+    fn test_concurrent() {
+        use count_min::CountMinSketch;
+        use std::sync::Arc;
+        use std::thread;
+        let sketch = Arc::new(CountMinSketch::with_dimensions(1024, 4));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let sketch = sketch.clone();
+                thread::spawn(move || {
+                    for i in 0..10000 {
+                        sketch.add(&(i % 100), 1);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(sketch.total(), 40000);
+        for i in 0..100 {
+            assert!(sketch.estimate(&i) >= 400);
+        }
+    }
+}
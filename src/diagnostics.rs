@@ -0,0 +1,95 @@
+//! Diagnostics of unexpected conditions, like thread number overflow or invalid handles
+//!
+//! Messages are passed to a `DiagnosticHook`, which can be set for the process by `set_hook`, or
+//! for one `HazardEpoch` by `HazardEpoch::set_diagnostic_hook`. Without any hook, they are logged
+//! by `warn!` of crate `log` with feature `log`(default), or dropped otherwise. Set a hook which
+//! does nothing to suppress them.
+//!
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// Message of an unexpected condition. `args` is not formatted until the hook writes it.
+pub struct Diagnostic<'a> {
+    /// Module where the condition is found, like `rs_lockfree::hazard_epoch`.
+    pub target: &'static str,
+    /// Arguments of the message.
+    pub args: fmt::Arguments<'a>,
+}
+
+impl<'a> fmt::Display for Diagnostic<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.args)
+    }
+}
+
+/// Callback receiving diagnostics. It may be called by any thread in the middle of an operation,
+/// so it should be quick, and must not panic or use the container reporting it.
+pub type DiagnosticHook = fn(&Diagnostic);
+
+static HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Set the process-wide hook, None to restore the default behavior.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::diagnostics::{self, Diagnostic};
+///
+/// fn ignore(_: &Diagnostic) {}
+///
+/// diagnostics::set_hook(Some(ignore));
+/// assert!(diagnostics::hook().is_some());
+/// diagnostics::set_hook(None);
+/// ```
+///
+pub fn set_hook(hook: Option<DiagnosticHook>) {
+    HOOK.store(hook.map_or(ptr::null_mut(), |f| f as *mut ()), Ordering::SeqCst);
+}
+
+/// Return the process-wide hook.
+pub fn hook() -> Option<DiagnosticHook> {
+    let f = HOOK.load(Ordering::SeqCst);
+    if f.is_null() {
+        None
+    } else {
+        Some(unsafe { mem::transmute::<*mut (), DiagnosticHook>(f) })
+    }
+}
+
+/// Pass `diagnostic` to `hook`, or the process-wide hook if it's None.
+pub fn emit(hook: Option<DiagnosticHook>, diagnostic: &Diagnostic) {
+    match hook.or_else(self::hook) {
+        Some(f) => f(diagnostic),
+        None => default_emit(diagnostic),
+    }
+}
+
+cfg_if! {
+    if #[cfg(feature = "log")] {
+        fn default_emit(diagnostic: &Diagnostic) {
+            warn!(target: diagnostic.target, "{}", diagnostic.args);
+        }
+    } else {
+        fn default_emit(_diagnostic: &Diagnostic) {}
+    }
+}
+
+mod test {
+    #[test]
+    fn test_emit() {
+        use diagnostics::Diagnostic;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CNT: AtomicUsize = AtomicUsize::new(0);
+        fn count(diagnostic: &Diagnostic) {
+            assert_eq!(diagnostic.target, "rs_lockfree::diagnostics::test");
+            assert_eq!(diagnostic.to_string(), "invalid 1");
+            CNT.fetch_add(1, Ordering::SeqCst);
+        }
+
+        diagnose!(hook: Some(count), "invalid {}", 1);
+        assert_eq!(CNT.load(Ordering::SeqCst), 1);
+    }
+}
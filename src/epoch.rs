@@ -0,0 +1,392 @@
+//! Adapter of the API of `crossbeam-epoch` backed by `HazardEpoch`
+//!
+//! `Atomic`, `Owned`, `Shared`, `Guard` and `Pointer` follow their namesakes in `crossbeam-epoch`,
+//! so a data structure written against it is retargeted by replacing `epoch::pin()` with
+//! `pin(&mut hazard_epoch)`, and objects are reclaimed by `HazardEpoch` instead. Each value is
+//! boxed with a `BaseHazardNode`, so no `HazardNodeT` needs to be implemented. Pointers carry no
+//! tag bits.
+//!
+//! # Examples
+//!
+//! ```
+//! use rs_lockfree::epoch::{self, Atomic, Owned};
+//! use rs_lockfree::hazard_epoch::HazardEpoch;
+//! use std::sync::atomic::Ordering;
+//!
+//! let mut h = HazardEpoch::default_new_in_heap();
+//! let config = Atomic::new(1);
+//! {
+//!     let guard = epoch::pin(&mut h);
+//!     let old = config.swap(Owned::new(2), Ordering::AcqRel, &guard);
+//!     assert_eq!(unsafe { old.as_ref() }, Some(&1));
+//!     unsafe { guard.defer_destroy(old) };
+//!     // Still readable until the guard is dropped.
+//!     assert_eq!(unsafe { *old.deref() }, 1);
+//! }
+//! let guard = epoch::pin(&mut h);
+//! let cur = config.load(Ordering::Acquire, &guard);
+//! assert_eq!(unsafe { cur.as_ref() }, Some(&2));
+//! unsafe { guard.defer_destroy(cur) };
+//! ```
+//!
+use error::HazardError;
+use hazard_epoch::{Cursor, HazardEpoch};
+use hazard_pointer::{BaseHazardNode, HazardNodeT};
+use util;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+/// Object in heap, which boxes a value with `BaseHazardNode`. Pointers carry it opaquely.
+pub struct Node<T> {
+    base: BaseHazardNode,
+    value: T,
+}
+
+impl<T> HazardNodeT for Node<T> {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut _
+    }
+}
+
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {}
+}
+
+/// Pointer which can be stored into `Atomic`, implemented by `Owned` and `Shared`.
+pub trait Pointer<T> {
+    /// Return the raw pointer to the node and give up ownership.
+    fn into_ptr(self) -> *mut Node<T>;
+
+    /// Return the pointer of node `ptr` returned by `into_ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be returned by `into_ptr` of the same type, and taken back only once if owned.
+    unsafe fn from_ptr(ptr: *mut Node<T>) -> Self;
+}
+
+/// Owned object in heap, which isn't shared yet.
+pub struct Owned<T> {
+    node: Box<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for Owned<T> {}
+
+impl<T> Owned<T> {
+    /// Allocate `value` in heap.
+    pub fn new(value: T) -> Owned<T> {
+        Owned {
+            node: Box::new(Node {
+                base: BaseHazardNode::default(),
+                value,
+            }),
+        }
+    }
+
+    /// Turn into a pointer shared under `guard`.
+    pub fn into_shared<'g>(self, _guard: &'g Guard) -> Shared<'g, T> {
+        unsafe { Shared::from_ptr(self.into_ptr()) }
+    }
+
+    /// Consume current object and return the value.
+    pub fn into_inner(self) -> T {
+        let node = Box::into_raw(self.node);
+        unsafe {
+            let value = ptr::read(&(*node).value);
+            // Free the memory without dropping the value moved out.
+            drop(Box::from_raw(node as *mut ManuallyDrop<Node<T>>));
+            value
+        }
+    }
+}
+
+impl<T> Pointer<T> for Owned<T> {
+    #[inline]
+    fn into_ptr(self) -> *mut Node<T> {
+        Box::into_raw(self.node)
+    }
+
+    #[inline]
+    unsafe fn from_ptr(ptr: *mut Node<T>) -> Self {
+        Owned {
+            node: Box::from_raw(ptr),
+        }
+    }
+}
+
+impl<T> Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node.value
+    }
+}
+
+impl<T> DerefMut for Owned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.node.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Owned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Owned").field(&self.node.value).finish()
+    }
+}
+
+/// Pointer to a shared object, valid while guard `'g` is alive.
+pub struct Shared<'g, T: 'g> {
+    node: *mut Node<T>,
+    _marker: PhantomData<(&'g (), *const T)>,
+}
+
+impl<'g, T> Clone for Shared<'g, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'g, T> Copy for Shared<'g, T> {}
+
+impl<'g, T> PartialEq for Shared<'g, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<'g, T> Eq for Shared<'g, T> {}
+
+impl<'g, T> fmt::Debug for Shared<'g, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Shared").field(&self.node).finish()
+    }
+}
+
+impl<'g, T> Shared<'g, T> {
+    /// Return a null pointer.
+    #[inline]
+    pub fn null() -> Shared<'g, T> {
+        Shared {
+            node: ptr::null_mut(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Return true if it's null.
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        self.node.is_null()
+    }
+
+    /// Return the raw pointer to the value, null if it's null.
+    #[inline]
+    pub fn as_raw(&self) -> *const T {
+        if self.node.is_null() {
+            ptr::null()
+        } else {
+            unsafe { &(*self.node).value }
+        }
+    }
+
+    /// Return the value, None if it's null.
+    ///
+    /// # Safety
+    ///
+    /// It must have been loaded from an `Atomic` under guard `'g`, or be owned by current thread.
+    #[inline]
+    pub unsafe fn as_ref(&self) -> Option<&'g T> {
+        self.node.as_ref().map(|node| &node.value)
+    }
+
+    /// Return the value, which must not be null.
+    ///
+    /// # Safety
+    ///
+    /// Same as `as_ref`, and it must not be null.
+    #[inline]
+    pub unsafe fn deref(&self) -> &'g T {
+        &(*self.node).value
+    }
+
+    /// Take ownership of the object.
+    ///
+    /// # Safety
+    ///
+    /// It must not be null, and no other thread may reach it any more, like after `Atomic` is
+    /// dropped.
+    #[inline]
+    pub unsafe fn into_owned(self) -> Owned<T> {
+        Owned::from_ptr(self.into_ptr())
+    }
+}
+
+impl<'g, T> Pointer<T> for Shared<'g, T> {
+    #[inline]
+    fn into_ptr(self) -> *mut Node<T> {
+        self.node
+    }
+
+    #[inline]
+    unsafe fn from_ptr(ptr: *mut Node<T>) -> Self {
+        Shared {
+            node: ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Error of `Atomic::compare_exchange`, which gives back the new pointer.
+pub struct CompareExchangeError<'g, T: 'g, P: Pointer<T>> {
+    /// Value found in the atomic pointer
+    pub current: Shared<'g, T>,
+    /// New pointer which was not stored
+    pub new: P,
+}
+
+impl<'g, T, P: Pointer<T>> fmt::Debug for CompareExchangeError<'g, T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CompareExchangeError")
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+/// Atomic pointer to a shared object. Like `crossbeam-epoch`, the object isn't dropped with it.
+pub struct Atomic<T> {
+    ptr: util::AtomicRawPtr<Node<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for Atomic<T> {}
+
+unsafe impl<T: Send + Sync> Sync for Atomic<T> {}
+
+impl<T> Default for Atomic<T> {
+    fn default() -> Self {
+        Atomic::null()
+    }
+}
+
+impl<T> From<Owned<T>> for Atomic<T> {
+    fn from(owned: Owned<T>) -> Self {
+        Atomic {
+            ptr: util::AtomicRawPtr::new(owned.into_ptr()),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Atomic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Atomic")
+            .field(&self.ptr.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+impl<T> Atomic<T> {
+    /// Return a null atomic pointer.
+    pub fn null() -> Atomic<T> {
+        Atomic {
+            ptr: util::AtomicRawPtr::default(),
+        }
+    }
+
+    /// Allocate `value` in heap and return an atomic pointer to it.
+    pub fn new(value: T) -> Atomic<T> {
+        Atomic::from(Owned::new(value))
+    }
+
+    /// Load the pointer, valid while `guard` is alive.
+    #[inline]
+    pub fn load<'g>(&self, ord: Ordering, _guard: &'g Guard) -> Shared<'g, T> {
+        unsafe { Shared::from_ptr(self.ptr.load(ord)) }
+    }
+
+    /// Store `new`.
+    #[inline]
+    pub fn store<P: Pointer<T>>(&self, new: P, ord: Ordering) {
+        self.ptr.store(new.into_ptr(), ord);
+    }
+
+    /// Store `new` and return the old pointer.
+    #[inline]
+    pub fn swap<'g, P: Pointer<T>>(&self, new: P, ord: Ordering, _guard: &'g Guard) -> Shared<'g, T> {
+        unsafe { Shared::from_ptr(self.ptr.swap(new.into_ptr(), ord)) }
+    }
+
+    /// Store `new` if the pointer is `current`, and return the new one stored. Return the pointer
+    /// found and `new` otherwise. The ordering of failure is covered by `success`.
+    pub fn compare_exchange<'g, P: Pointer<T>>(
+        &self,
+        current: Shared<T>,
+        new: P,
+        success: Ordering,
+        _failure: Ordering,
+        _guard: &'g Guard,
+    ) -> Result<Shared<'g, T>, CompareExchangeError<'g, T, P>> {
+        let new = new.into_ptr();
+        let (found, ok) = self.ptr.cas(current.node, new, success);
+        if ok {
+            Ok(unsafe { Shared::from_ptr(new) })
+        } else {
+            Err(CompareExchangeError {
+                current: unsafe { Shared::from_ptr(found) },
+                new: unsafe { P::from_ptr(new) },
+            })
+        }
+    }
+
+    /// Take ownership of the object, which must not be null.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may reach the object any more.
+    pub unsafe fn into_owned(self) -> Owned<T> {
+        Owned::from_ptr(self.ptr.load(Ordering::SeqCst))
+    }
+}
+
+/// Guard holding a handle of `HazardEpoch`, returned by `pin`. Objects loaded under it stay valid
+/// until it's dropped, like `Cursor`.
+pub struct Guard<'a> {
+    cursor: Cursor<'a>,
+}
+
+impl<'a> Guard<'a> {
+    /// Return the handle held by current guard.
+    #[inline]
+    pub fn handle(&self) -> u64 {
+        self.cursor.handle()
+    }
+
+    /// Add the object unlinked by current thread to `HazardEpoch`, which drops it once no handle
+    /// may read it. Panic if `HazardEpoch::try_add_node` fails.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must not be null, be unlinked from every `Atomic`, and not be destroyed before.
+    pub unsafe fn defer_destroy<T>(&self, ptr: Shared<T>) {
+        if let Err(e) = self.cursor.retire(ptr.node) {
+            panic!("defer_destroy fail, {}", e);
+        }
+    }
+}
+
+/// Acquire a handle of `hazard_epoch` and return a guard releasing it when dropped. Panic if
+/// `try_pin` fails.
+pub fn pin<'a>(hazard_epoch: &'a mut HazardEpoch) -> Guard<'a> {
+    match try_pin(hazard_epoch) {
+        Ok(ret) => ret,
+        Err(e) => panic!("pin fail, {}", e),
+    }
+}
+
+/// Same as `pin`, but return error like `HazardEpoch::cursor`.
+pub fn try_pin<'a>(hazard_epoch: &'a mut HazardEpoch) -> Result<Guard<'a>, HazardError> {
+    Ok(Guard {
+        cursor: hazard_epoch.cursor()?,
+    })
+}
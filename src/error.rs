@@ -1,8 +1,15 @@
 //! Definition of error and status.
+//!
+//! Each module reports failures by its own error type carrying context, like `HazardError`,
+//! `LockError` and `QueueError`, which can be converted into the crate-level `Error` by `?`.
+//! `Status` is the flat code returned by C-style methods.
 
+use std::error;
 use std::fmt;
+use std::time::Duration;
 
-/// Status of `HazardEpoch`
+/// Status of `HazardEpoch`, locks and queues
+#[repr(C)]
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum Status {
     /// Success
@@ -13,6 +20,24 @@ pub enum Status {
     ThreadNumOverflow,
     /// Invalid parameter
     InvalidParam,
+    /// Timed out before the operation completes
+    Timeout,
+    /// Lock is poisoned by a panicked holder
+    Poisoned,
+    /// Memory allocation failed
+    AllocFailed,
+    /// Container is empty
+    Empty,
+    /// Container is full
+    QueueFull,
+    /// Container is closed
+    Closed,
+    /// Internal structure is corrupted
+    Corrupted,
+    /// Too many objects are waiting to be reclaimed
+    PendingLimit,
+    /// Consumer fell behind and elements were overwritten before being read
+    Lagged,
 }
 
 impl fmt::Display for Status {
@@ -21,6 +46,381 @@ impl fmt::Display for Status {
     }
 }
 
+/// Error of `HazardEpoch`
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum HazardError {
+    /// Thread `tid` has already assigned a version handle, which must be released first.
+    Busy {
+        /// Thread id of the caller
+        tid: i64,
+    },
+    /// Thread id `tid` is not less than `max_thread_count`, so no thread store is available.
+    ThreadNumOverflow {
+        /// Thread id of the caller
+        tid: i64,
+        /// Configured `MAX_THREAD_COUNT`
+        max_thread_count: usize,
+    },
+    /// Invalid parameter of operation `op`.
+    InvalidParam {
+        /// Name of the operation
+        op: &'static str,
+    },
+    /// `pending` objects are still waiting to be reclaimed after reclaiming, reaching `limit`.
+    PendingLimit {
+        /// Count of objects waiting to be reclaimed
+        pending: i64,
+        /// Configured limit
+        limit: i64,
+    },
+}
+
+impl fmt::Display for HazardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HazardError::Busy { tid } => {
+                write!(f, "thread {} has already assigned a version handle", tid)
+            }
+            HazardError::ThreadNumOverflow {
+                tid,
+                max_thread_count,
+            } => write!(
+                f,
+                "thread id {} overflows max thread count {}",
+                tid, max_thread_count
+            ),
+            HazardError::InvalidParam { op } => write!(f, "invalid parameter of {}", op),
+            HazardError::PendingLimit { pending, limit } => write!(
+                f,
+                "{} objects are waiting to be reclaimed, reaching limit {}",
+                pending, limit
+            ),
+        }
+    }
+}
+
+impl error::Error for HazardError {}
+
+impl From<HazardError> for Status {
+    fn from(e: HazardError) -> Status {
+        match e {
+            HazardError::Busy { .. } => Status::Busy,
+            HazardError::ThreadNumOverflow { .. } => Status::ThreadNumOverflow,
+            HazardError::InvalidParam { .. } => Status::InvalidParam,
+            HazardError::PendingLimit { .. } => Status::PendingLimit,
+        }
+    }
+}
+
+/// Error of timed or fallible locking
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum LockError {
+    /// Operation `op` didn't acquire the lock within `timeout`.
+    Timeout {
+        /// Name of the operation
+        op: &'static str,
+        /// Time waited
+        timeout: Duration,
+    },
+    /// Lock is poisoned by a panicked holder, found by operation `op`.
+    Poisoned {
+        /// Name of the operation
+        op: &'static str,
+    },
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LockError::Timeout { op, timeout } => write!(f, "{} timed out after {:?}", op, timeout),
+            LockError::Poisoned { op } => write!(f, "{} found lock poisoned", op),
+        }
+    }
+}
+
+impl error::Error for LockError {}
+
+impl From<LockError> for Status {
+    fn from(e: LockError) -> Status {
+        match e {
+            LockError::Timeout { .. } => Status::Timeout,
+            LockError::Poisoned { .. } => Status::Poisoned,
+        }
+    }
+}
+
+/// Error of queues, stacks and channels
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum QueueError {
+    /// Operation `op` failed to allocate memory for a new element.
+    AllocFailed {
+        /// Name of the operation
+        op: &'static str,
+    },
+    /// Operation `op` found the container full.
+    Full {
+        /// Name of the operation
+        op: &'static str,
+    },
+    /// Operation `op` found the container closed, so no more element can be transferred.
+    Closed {
+        /// Name of the operation
+        op: &'static str,
+    },
+    /// Operation `op` didn't complete within `timeout`.
+    Timeout {
+        /// Name of the operation
+        op: &'static str,
+        /// Time waited
+        timeout: Duration,
+    },
+    /// Operation `op` got a node of an intrusive container which is already linked.
+    Linked {
+        /// Name of the operation
+        op: &'static str,
+    },
+    /// Operation `op` fell behind the producer, which overwrote `missed` elements before they
+    /// were read.
+    Lagged {
+        /// Name of the operation
+        op: &'static str,
+        /// Number of elements skipped
+        missed: u64,
+    },
+    /// `HazardEpoch` of the container failed.
+    Hazard(HazardError),
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueueError::AllocFailed { op } => write!(f, "{} failed to allocate memory", op),
+            QueueError::Full { op } => write!(f, "{} found container full", op),
+            QueueError::Closed { op } => write!(f, "{} found container closed", op),
+            QueueError::Timeout { op, timeout } => {
+                write!(f, "{} timed out after {:?}", op, timeout)
+            }
+            QueueError::Linked { op } => write!(f, "{} got a node already linked", op),
+            QueueError::Lagged { op, missed } => {
+                write!(f, "{} fell behind, {} elements were overwritten", op, missed)
+            }
+            QueueError::Hazard(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for QueueError {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            QueueError::Hazard(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<HazardError> for QueueError {
+    fn from(e: HazardError) -> QueueError {
+        QueueError::Hazard(e)
+    }
+}
+
+impl From<QueueError> for Status {
+    fn from(e: QueueError) -> Status {
+        match e {
+            QueueError::AllocFailed { .. } => Status::AllocFailed,
+            QueueError::Full { .. } => Status::QueueFull,
+            QueueError::Closed { .. } => Status::Closed,
+            QueueError::Linked { .. } => Status::InvalidParam,
+            QueueError::Lagged { .. } => Status::Lagged,
+            QueueError::Timeout { .. } => Status::Timeout,
+            QueueError::Hazard(e) => e.into(),
+        }
+    }
+}
+
+/// Broken internal invariant, found by `verify` methods with feature `debug-assert-invariants`
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum InvariantError {
+    /// List `list` links back to one of its nodes.
+    Cyclic {
+        /// Name of the list
+        list: &'static str,
+    },
+    /// Counter `counter` is `found`, but `expected` by walking the lists it counts.
+    CountMismatch {
+        /// Name of the counter
+        counter: &'static str,
+        /// Count of walked nodes
+        expected: i64,
+        /// Value of the counter
+        found: i64,
+    },
+    /// Version `version` of `what` is newer than the global version `global`.
+    VersionAhead {
+        /// Owner of the version
+        what: &'static str,
+        /// Version found
+        version: u64,
+        /// Global version of `HazardEpoch`
+        global: u64,
+    },
+    /// Node `what` is in a wrong state, like a tail which is not the last node.
+    BadNode {
+        /// Description of the node
+        what: &'static str,
+    },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvariantError::Cyclic { list } => write!(f, "{} is cyclic", list),
+            InvariantError::CountMismatch {
+                counter,
+                expected,
+                found,
+            } => write!(f, "{} is {}, but {} nodes are found", counter, found, expected),
+            InvariantError::VersionAhead {
+                what,
+                version,
+                global,
+            } => write!(
+                f,
+                "version {} of {} is newer than global version {}",
+                version, what, global
+            ),
+            InvariantError::BadNode { what } => write!(f, "{} is in a wrong state", what),
+        }
+    }
+}
+
+impl error::Error for InvariantError {}
+
+impl From<InvariantError> for Status {
+    fn from(_: InvariantError) -> Status {
+        Status::Corrupted
+    }
+}
+
+/// Error of pushing an element, which gives the element back.
+pub struct PushError<T> {
+    /// Reason of failure
+    pub error: QueueError,
+    /// Element failed to be pushed
+    pub value: T,
+}
+
+impl<T> PushError<T> {
+    /// Return the element failed to be pushed.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> fmt::Debug for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PushError({:?}, ..)", self.error)
+    }
+}
+
+impl<T> fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<T> PartialEq for PushError<T>
+where
+    T: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.error == other.error && self.value == other.value
+    }
+}
+
+impl<T> error::Error for PushError<T> {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Error of this crate, which any module-specific error can be converted into.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Error {
+    /// Error of `HazardEpoch`
+    Hazard(HazardError),
+    /// Error of locks
+    Lock(LockError),
+    /// Error of queues, stacks and channels
+    Queue(QueueError),
+    /// Broken internal invariant
+    Invariant(InvariantError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Hazard(ref e) => e.fmt(f),
+            Error::Lock(ref e) => e.fmt(f),
+            Error::Queue(ref e) => e.fmt(f),
+            Error::Invariant(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Hazard(ref e) => Some(e),
+            Error::Lock(ref e) => Some(e),
+            Error::Queue(ref e) => Some(e),
+            Error::Invariant(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<HazardError> for Error {
+    fn from(e: HazardError) -> Error {
+        Error::Hazard(e)
+    }
+}
+
+impl From<LockError> for Error {
+    fn from(e: LockError) -> Error {
+        Error::Lock(e)
+    }
+}
+
+impl From<QueueError> for Error {
+    fn from(e: QueueError) -> Error {
+        Error::Queue(e)
+    }
+}
+
+impl From<InvariantError> for Error {
+    fn from(e: InvariantError) -> Error {
+        Error::Invariant(e)
+    }
+}
+
+impl<T> From<PushError<T>> for Error {
+    fn from(e: PushError<T>) -> Error {
+        Error::Queue(e.error)
+    }
+}
+
+impl From<Error> for Status {
+    fn from(e: Error) -> Status {
+        match e {
+            Error::Hazard(e) => e.into(),
+            Error::Lock(e) => e.into(),
+            Error::Queue(e) => e.into(),
+            Error::Invariant(e) => e.into(),
+        }
+    }
+}
+
 mod test {
 
     #[test]
@@ -30,5 +430,84 @@ mod test {
         let s = Status::Success;
         let a = format!("{}", s);
         assert_eq!(a, "Success");
+        assert_eq!(format!("{}", Status::Timeout), "Timeout");
+    }
+
+    #[test]
+    fn test_conversion() {
+        use error::{Error, HazardError, InvariantError, LockError, Status};
+        use std::error::Error as StdError;
+        use std::time::Duration;
+
+        fn fail() -> Result<(), Error> {
+            Err(HazardError::ThreadNumOverflow {
+                tid: 20,
+                max_thread_count: 16,
+            })?;
+            Ok(())
+        }
+
+        let e = fail().unwrap_err();
+        assert_eq!(format!("{}", e), "thread id 20 overflows max thread count 16");
+        assert!(e.source().is_some());
+        assert_eq!(Status::from(e), Status::ThreadNumOverflow);
+        let e = LockError::Timeout {
+            op: "try_lock_for",
+            timeout: Duration::from_millis(1),
+        };
+        assert_eq!(format!("{}", e), "try_lock_for timed out after 1ms");
+        assert_eq!(Status::from(Error::from(e)), Status::Timeout);
+        assert_eq!(Status::from(HazardError::Busy { tid: 1 }), Status::Busy);
+        let e = HazardError::PendingLimit {
+            pending: 9,
+            limit: 8,
+        };
+        assert_eq!(
+            format!("{}", e),
+            "9 objects are waiting to be reclaimed, reaching limit 8"
+        );
+        assert_eq!(Status::from(e), Status::PendingLimit);
+        let e = Error::from(InvariantError::CountMismatch {
+            counter: "thread count",
+            expected: 2,
+            found: 3,
+        });
+        assert_eq!(format!("{}", e), "thread count is 3, but 2 nodes are found");
+        assert_eq!(Status::from(e), Status::Corrupted);
+    }
+
+    #[test]
+    fn test_queue_error() {
+        use error::{Error, HazardError, PushError, QueueError, Status};
+        use std::error::Error as StdError;
+
+        fn push() -> Result<(), Error> {
+            Err(PushError {
+                error: QueueError::Full { op: "try_send" },
+                value: 1,
+            })?;
+            Ok(())
+        }
+
+        let e = push().unwrap_err();
+        assert_eq!(e, Error::Queue(QueueError::Full { op: "try_send" }));
+        assert_eq!(format!("{}", e), "try_send found container full");
+        assert_eq!(Status::from(e), Status::QueueFull);
+        let e = PushError {
+            error: QueueError::AllocFailed { op: "try_push" },
+            value: vec![1],
+        };
+        assert_eq!(format!("{:?}", e), "PushError(AllocFailed { op: \"try_push\" }, ..)");
+        assert_eq!(e.into_inner(), vec![1]);
+        let e = QueueError::from(HazardError::Busy { tid: 1 });
+        assert!(e.source().is_some());
+        assert_eq!(Status::from(e), Status::Busy);
+        assert_eq!(Status::from(QueueError::Closed { op: "recv" }), Status::Closed);
+        let e = QueueError::Lagged {
+            op: "try_recv",
+            missed: 3,
+        };
+        assert_eq!(format!("{}", e), "try_recv fell behind, 3 elements were overwritten");
+        assert_eq!(Status::from(e), Status::Lagged);
     }
 }
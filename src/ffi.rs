@@ -0,0 +1,330 @@
+//! C interface of `LockFreeQueue`, `LockFreeStack` and `HazardEpoch`
+//!
+//! Containers hold `void *` payloads and are passed to C as opaque handles. Each container takes
+//! an optional destructor, which is called with every payload still inside when it's freed.
+//! Methods return `Status` instead of panicking, because unwinding across `extern "C"` functions
+//! is undefined behavior. The header `include/rs_lockfree.h` is generated by `cbindgen`, and
+//! building with feature `ffi` writes a fresh copy under `OUT_DIR`.
+//!
+use error::{QueueError, Status};
+use hazard_epoch::{BaseHazardNode, HazardEpoch, HazardNodeT};
+use lockfree_queue::LockFreeQueue;
+use lockfree_stack::LockFreeStack;
+use std::os::raw::c_void;
+use util;
+
+/// Destructor of payload, can be NULL.
+pub type Destructor = Option<unsafe extern "C" fn(*mut c_void)>;
+
+#[inline]
+unsafe fn destroy_payload(destructor: Destructor, payload: *mut c_void) {
+    if let Some(f) = destructor {
+        f(payload);
+    }
+}
+
+/// Store `ret` to `out` if it's a payload.
+unsafe fn output_pop(
+    ret: Result<Option<*mut c_void>, QueueError>,
+    out: *mut *mut c_void,
+) -> Status {
+    match ret {
+        Ok(Some(payload)) => {
+            *out = payload;
+            Status::Success
+        }
+        Ok(None) => Status::Empty,
+        Err(e) => {
+            diagnose!("pop fail, {}", e);
+            e.into()
+        }
+    }
+}
+
+/// Opaque handle of `LockFreeQueue` holding `void *` payloads.
+pub struct Queue {
+    queue: Box<LockFreeQueue<*mut c_void>>,
+    destructor: Destructor,
+}
+
+/// Create a queue.
+#[no_mangle]
+pub extern "C" fn rs_lockfree_queue_new(destructor: Destructor) -> *mut Queue {
+    Box::into_raw(Box::new(Queue {
+        queue: LockFreeQueue::default_new_in_heap(),
+        destructor,
+    }))
+}
+
+/// Free `queue` and destroy the payloads left inside. No other thread may use it at the same time.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_queue_free(queue: *mut Queue) {
+    if queue.is_null() {
+        return;
+    }
+    let mut queue = Box::from_raw(queue);
+    while let Ok(Some(payload)) = queue.queue.try_pop() {
+        destroy_payload(queue.destructor, payload);
+    }
+}
+
+/// Push `payload` to the end of `queue`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_queue_push(queue: *mut Queue, payload: *mut c_void) -> Status {
+    if queue.is_null() {
+        return Status::InvalidParam;
+    }
+    match (*queue).queue.try_push(payload) {
+        Ok(()) => Status::Success,
+        Err(e) => {
+            diagnose!("push fail, {}", e);
+            e.error.into()
+        }
+    }
+}
+
+/// Pop the payload at the head of `queue` into `out`. Return `Empty` if there is none.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_queue_pop(queue: *mut Queue, out: *mut *mut c_void) -> Status {
+    if queue.is_null() || out.is_null() {
+        return Status::InvalidParam;
+    }
+    output_pop((*queue).queue.try_pop(), out)
+}
+
+/// Opaque handle of `LockFreeStack` holding `void *` payloads.
+pub struct Stack {
+    stack: Box<LockFreeStack<*mut c_void>>,
+    destructor: Destructor,
+}
+
+/// Create a stack.
+#[no_mangle]
+pub extern "C" fn rs_lockfree_stack_new(destructor: Destructor) -> *mut Stack {
+    Box::into_raw(Box::new(Stack {
+        stack: LockFreeStack::default_new_in_heap(),
+        destructor,
+    }))
+}
+
+/// Free `stack` and destroy the payloads left inside. No other thread may use it at the same time.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_stack_free(stack: *mut Stack) {
+    if stack.is_null() {
+        return;
+    }
+    let mut stack = Box::from_raw(stack);
+    while let Ok(Some(payload)) = stack.stack.try_pop() {
+        destroy_payload(stack.destructor, payload);
+    }
+}
+
+/// Push `payload` to the top of `stack`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_stack_push(stack: *mut Stack, payload: *mut c_void) -> Status {
+    if stack.is_null() {
+        return Status::InvalidParam;
+    }
+    match (*stack).stack.try_push(payload) {
+        Ok(()) => Status::Success,
+        Err(e) => {
+            diagnose!("push fail, {}", e);
+            e.error.into()
+        }
+    }
+}
+
+/// Pop the payload at the top of `stack` into `out`. Return `Empty` if there is none.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_stack_pop(stack: *mut Stack, out: *mut *mut c_void) -> Status {
+    if stack.is_null() || out.is_null() {
+        return Status::InvalidParam;
+    }
+    output_pop((*stack).stack.try_pop(), out)
+}
+
+/// Opaque handle of `HazardEpoch`, which reclaims `void *` objects retired by C.
+pub struct HazardDomain {
+    hazard_epoch: Box<HazardEpoch>,
+}
+
+struct RetiredNode {
+    base: BaseHazardNode,
+    payload: *mut c_void,
+    destructor: Destructor,
+}
+
+impl HazardNodeT for RetiredNode {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut _
+    }
+}
+
+impl Drop for RetiredNode {
+    fn drop(&mut self) {
+        unsafe { destroy_payload(self.destructor, self.payload) }
+    }
+}
+
+/// Create a hazard domain, see `HazardEpoch::new_in_stack` for the parameters.
+#[no_mangle]
+pub extern "C" fn rs_lockfree_hazard_epoch_new(
+    thread_waiting_threshold: i64,
+    min_version_cache_time_us: i64,
+) -> *mut HazardDomain {
+    Box::into_raw(Box::new(HazardDomain {
+        hazard_epoch: HazardEpoch::new_in_heap(thread_waiting_threshold, min_version_cache_time_us),
+    }))
+}
+
+/// Free `hazard_epoch` and destroy all objects retired to it.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_hazard_epoch_free(hazard_epoch: *mut HazardDomain) {
+    if !hazard_epoch.is_null() {
+        drop(Box::from_raw(hazard_epoch));
+    }
+}
+
+/// Before accessing shared objects, get `handle` of this operation, see
+/// `HazardEpoch::try_acquire`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_hazard_epoch_acquire(
+    hazard_epoch: *mut HazardDomain,
+    handle: *mut u64,
+) -> Status {
+    if hazard_epoch.is_null() || handle.is_null() {
+        return Status::InvalidParam;
+    }
+    match (*hazard_epoch).hazard_epoch.try_acquire() {
+        Ok(h) => {
+            *handle = h;
+            Status::Success
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// After accessing shared objects, release `handle` got by `rs_lockfree_hazard_epoch_acquire`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_hazard_epoch_release(
+    hazard_epoch: *mut HazardDomain,
+    handle: u64,
+) {
+    if !hazard_epoch.is_null() {
+        (*hazard_epoch).hazard_epoch.release(handle);
+    }
+}
+
+/// Retire `payload` which is no longer reachable by new readers. `destructor` is called with it
+/// once no thread may still access it.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_hazard_epoch_retire(
+    hazard_epoch: *mut HazardDomain,
+    payload: *mut c_void,
+    destructor: Destructor,
+) -> Status {
+    if hazard_epoch.is_null() {
+        return Status::InvalidParam;
+    }
+    let node = match util::try_box_into_raw(RetiredNode {
+        base: BaseHazardNode::default(),
+        payload,
+        destructor,
+    }) {
+        Ok(node) => node,
+        Err(mut node) => {
+            node.destructor = None;
+            return Status::AllocFailed;
+        }
+    };
+    match (*hazard_epoch).hazard_epoch.try_add_node(node) {
+        Ok(()) => Status::Success,
+        Err(e) => {
+            // Not retired, so the caller still owns `payload`.
+            (*node).destructor = None;
+            drop(Box::from_raw(node));
+            e.into()
+        }
+    }
+}
+
+/// Reclaim all retired objects which are no longer accessed, see `HazardEpoch::retire`.
+#[no_mangle]
+pub unsafe extern "C" fn rs_lockfree_hazard_epoch_reclaim(hazard_epoch: *mut HazardDomain) {
+    if !hazard_epoch.is_null() {
+        (*hazard_epoch).hazard_epoch.retire();
+    }
+}
+
+mod test {
+    use std::os::raw::c_void;
+
+    unsafe extern "C" fn free_i32(payload: *mut c_void) {
+        drop(Box::from_raw(payload as *mut i32));
+    }
+
+    #[test]
+    fn test_queue_stack() {
+        use error::Status;
+        use ffi::*;
+        use std::ptr;
+        unsafe {
+            let queue = rs_lockfree_queue_new(Some(free_i32));
+            let stack = rs_lockfree_stack_new(None);
+            for i in 0..4 {
+                let payload = Box::into_raw(Box::new(i)) as *mut c_void;
+                assert_eq!(rs_lockfree_queue_push(queue, payload), Status::Success);
+                let payload = i as usize as *mut c_void;
+                assert_eq!(rs_lockfree_stack_push(stack, payload), Status::Success);
+            }
+            let mut out = ptr::null_mut();
+            assert_eq!(rs_lockfree_queue_pop(queue, &mut out), Status::Success);
+            free_i32(out);
+            assert_eq!(rs_lockfree_stack_pop(stack, &mut out), Status::Success);
+            assert_eq!(out as usize, 3);
+            assert_eq!(rs_lockfree_queue_pop(queue, ptr::null_mut()), Status::InvalidParam);
+            assert_eq!(rs_lockfree_queue_push(ptr::null_mut(), out), Status::InvalidParam);
+            // payloads left in queue are freed by destructor
+            rs_lockfree_queue_free(queue);
+            rs_lockfree_stack_free(stack);
+
+            let stack = rs_lockfree_stack_new(None);
+            assert_eq!(rs_lockfree_stack_pop(stack, &mut out), Status::Empty);
+            rs_lockfree_stack_free(stack);
+        }
+    }
+
+    #[test]
+    fn test_hazard_epoch() {
+        use error::Status;
+        use ffi::*;
+        use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+        static FREED: AtomicUsize = ATOMIC_USIZE_INIT;
+        unsafe extern "C" fn count(_: *mut c_void) {
+            FREED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        unsafe {
+            let h = rs_lockfree_hazard_epoch_new(64, 200000);
+            let mut handle = 0;
+            assert_eq!(rs_lockfree_hazard_epoch_acquire(h, &mut handle), Status::Success);
+            assert_eq!(rs_lockfree_hazard_epoch_acquire(h, &mut handle), Status::Busy);
+            assert_eq!(
+                rs_lockfree_hazard_epoch_retire(h, 1 as *mut c_void, Some(count)),
+                Status::Success
+            );
+            rs_lockfree_hazard_epoch_reclaim(h);
+            assert_eq!(FREED.load(Ordering::SeqCst), 0);
+            rs_lockfree_hazard_epoch_release(h, handle);
+            rs_lockfree_hazard_epoch_reclaim(h);
+            assert_eq!(FREED.load(Ordering::SeqCst), 1);
+            assert_eq!(
+                rs_lockfree_hazard_epoch_retire(h, 2 as *mut c_void, Some(count)),
+                Status::Success
+            );
+            rs_lockfree_hazard_epoch_free(h);
+            assert_eq!(FREED.load(Ordering::SeqCst), 2);
+        }
+    }
+}
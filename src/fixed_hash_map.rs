@@ -0,0 +1,294 @@
+//! Definition and implementations of `FixedHashMap`
+//!
+use util;
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::intrinsics;
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+const KEY_EMPTY: u8 = 0;
+const KEY_CLAIMED: u8 = 1;
+const KEY_READY: u8 = 2;
+
+/// Once a key is set in a slot, it never changes. Value is guarded by a sequence like `SeqLock`.
+struct Slot<K, V> {
+    key_state: u8,
+    key: K,
+    seq: util::AtomicU64,
+    present: bool,
+    value: V,
+}
+
+impl<K, V> Default for Slot<K, V>
+where
+    K: Default,
+    V: Default,
+{
+    fn default() -> Self {
+        Slot {
+            key_state: KEY_EMPTY,
+            key: K::default(),
+            seq: util::AtomicU64::new(0),
+            present: false,
+            value: V::default(),
+        }
+    }
+}
+
+impl<K, V> Slot<K, V>
+where
+    K: Copy,
+    V: Copy,
+{
+    #[inline]
+    fn key_state(&self) -> u8 {
+        unsafe { intrinsics::atomic_load(&self.key_state) }
+    }
+
+    /// Wait until the key of current slot is ready, return false if it's empty.
+    #[inline]
+    fn wait_key_ready(&self) -> bool {
+        let mut backoff = util::Backoff::new();
+        loop {
+            match self.key_state() {
+                KEY_EMPTY => return false,
+                KEY_READY => return true,
+                _ => backoff.snooze(),
+            }
+        }
+    }
+
+    /// Return true if claim current slot for `key` successfully.
+    #[inline]
+    fn claim(&mut self, key: K) -> bool {
+        unsafe {
+            if intrinsics::atomic_cxchg(&mut self.key_state, KEY_EMPTY, KEY_CLAIMED).1 {
+                ptr::write_volatile(&mut self.key, key);
+                intrinsics::atomic_store(&mut self.key_state, KEY_READY);
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    #[inline]
+    fn key(&self) -> K {
+        unsafe { ptr::read_volatile(&self.key) }
+    }
+
+    fn read_value(&self) -> Option<V> {
+        let mut backoff = util::Backoff::new();
+        unsafe {
+            loop {
+                let seq = self.seq.load(Ordering::SeqCst);
+                if 0 == seq & 1 {
+                    let present = util::racy_read(&self.present);
+                    let value = util::racy_read(&self.value);
+                    if seq == self.seq.load(Ordering::SeqCst) {
+                        return if present { Some(value) } else { None };
+                    }
+                }
+                backoff.snooze();
+            }
+        }
+    }
+
+    /// Replace value by `value`, return the old one.
+    fn write_value(&mut self, value: Option<V>) -> Option<V> {
+        let mut backoff = util::Backoff::new();
+        unsafe {
+            let seq = loop {
+                let seq = self.seq.load(Ordering::SeqCst);
+                if 0 == seq & 1 && self.seq.cas(seq, seq + 1, Ordering::SeqCst).1 {
+                    break seq;
+                }
+                backoff.snooze();
+            };
+            let old = if ptr::read_volatile(&self.present) {
+                Some(ptr::read_volatile(&self.value))
+            } else {
+                None
+            };
+            util::racy_write(&mut self.present, value.is_some());
+            if let Some(value) = value {
+                util::racy_write(&mut self.value, value);
+            }
+            self.seq.store(seq + 2, Ordering::SeqCst);
+            old
+        }
+    }
+}
+
+/// Pre-sized and non-resizing concurrent hash map with open addressing. All memory is allocated
+/// upfront when constructed and never reclaimed until dropped, so no `HazardEpoch` is needed.
+///
+/// A key occupies its slot forever once inserted, and removing only clears the value of the slot,
+/// so the capacity limits the number of distinct keys ever inserted. Values are read by sequence
+/// validation rather than locks, readers never write shared memory.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::fixed_hash_map::FixedHashMap;
+///
+/// let mut map = FixedHashMap::with_capacity(4);
+/// assert!(map.insert(1, 10));
+/// assert!(map.insert(2, 20));
+/// assert_eq!(map.get(&1), Some(10));
+/// assert_eq!(map.remove(&1), Some(10));
+/// assert_eq!(map.get(&1), None);
+/// assert_eq!(map.len(), 1);
+/// ```
+///
+pub struct FixedHashMap<K, V> {
+    slots: Vec<Slot<K, V>>,
+    mask: usize,
+    len: util::WrappedAlign64Type<i64>,
+}
+
+impl<K, V> FixedHashMap<K, V>
+where
+    K: Copy + Default + Eq + Hash,
+    V: Copy + Default,
+{
+    /// Return FixedHashMap which can hold at least `capacity` distinct keys.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let cap = capacity.next_power_of_two();
+        let mut slots = Vec::with_capacity(cap);
+        for _ in 0..cap {
+            slots.push(Slot::default());
+        }
+        FixedHashMap {
+            slots,
+            mask: cap - 1,
+            len: util::WrappedAlign64Type(0),
+        }
+    }
+
+    /// Return the maximum number of distinct keys.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Return the number of keys with value.
+    #[inline]
+    pub fn len(&self) -> usize {
+        // A remover may decrease the count before the inserter of the same key increases it.
+        unsafe { cmp::max(0, intrinsics::atomic_load(self.len.as_ptr())) as usize }
+    }
+
+    /// Return true if no key has value.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+
+    #[inline]
+    fn hash(key: &K) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() as usize
+    }
+
+    /// Return index of the slot of `key`, None if not exists.
+    fn find(&self, key: &K) -> Option<usize> {
+        let hash = Self::hash(key);
+        for i in 0..self.slots.len() {
+            let idx = hash.wrapping_add(i) & self.mask;
+            let slot = &self.slots[idx];
+            if !slot.wait_key_ready() {
+                return None;
+            }
+            if slot.key() == *key {
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    /// Return index of the slot of `key`, claim a new one if not exists. None if there is no slot.
+    fn find_or_claim(&mut self, key: K) -> Option<usize> {
+        let hash = Self::hash(&key);
+        for i in 0..self.slots.len() {
+            let idx = hash.wrapping_add(i) & self.mask;
+            let slot = &mut self.slots[idx];
+            // Inserters of the same key probe the same sequence, so only one of them can claim
+            // the first empty slot, and others will find the key there.
+            if !slot.wait_key_ready() && slot.claim(key) {
+                return Some(idx);
+            }
+            if slot.wait_key_ready() && slot.key() == key {
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    /// Insert or update `key`. Return false if there is no slot for a new key.
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        match self.find_or_claim(key) {
+            Some(idx) => {
+                if self.slots[idx].write_value(Some(value)).is_none() {
+                    unsafe {
+                        util::sync_fetch_and_add(self.len.as_mut_ptr(), 1);
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Return the value of `key`.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.find(key).and_then(|idx| self.slots[idx].read_value())
+    }
+
+    /// Return true if `key` has value.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Clear the value of `key` and return it.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let ret = match self.find(key) {
+            Some(idx) => self.slots[idx].write_value(None),
+            None => None,
+        };
+        if ret.is_some() {
+            unsafe {
+                util::sync_fetch_and_add(self.len.as_mut_ptr(), -1);
+            }
+        }
+        ret
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use fixed_hash_map::FixedHashMap;
+        let mut map = FixedHashMap::with_capacity(100);
+        assert_eq!(map.capacity(), 128);
+        assert!(map.is_empty());
+        for i in 0..128 {
+            assert!(map.insert(i, i * 2));
+        }
+        assert!(!map.insert(128, 0));
+        assert!(map.insert(5, 0));
+        assert_eq!(map.len(), 128);
+        assert_eq!(map.get(&5), Some(0));
+        assert_eq!(map.remove(&5), Some(0));
+        assert_eq!(map.remove(&5), None);
+        assert!(!map.contains_key(&5));
+        assert!(!map.contains_key(&128));
+        assert!(map.insert(5, 10));
+        for i in 0..128 {
+            assert_eq!(map.get(&i), Some(if i == 5 { 10 } else { i * 2 }));
+        }
+    }
+}
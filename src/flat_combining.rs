@@ -0,0 +1,172 @@
+//! Definition and implementations of `FlatCombining`
+//!
+use hazard_epoch::MAX_THREAD_COUNT;
+use spin_lock::SpinLock;
+use util;
+use std::intrinsics;
+use std::mem;
+use std::ptr;
+use std::sync::PoisonError;
+
+const RECORD_EMPTY: u8 = 0;
+const RECORD_PENDING: u8 = 1;
+const RECORD_DONE: u8 = 2;
+
+/// Passes over the publication list one combiner makes before giving up the lock.
+const COMBINE_PASSES: usize = 4;
+
+/// Operation record published by one thread. `op` is None until the first publication, it's
+/// valid only while `state` is `RECORD_PENDING`.
+struct Record<T> {
+    state: u8,
+    op: Option<*mut (FnMut(&mut T) + 'static)>,
+}
+
+/// `FlatCombining` turns any single-threaded structure into a concurrent one. Each thread publishes
+/// its operation in its own record, and whichever thread grabs the lock becomes the combiner,
+/// executing pending operations of all threads in batches. Under high contention this usually
+/// beats CAS retry loops, because the structure stays in the cache of one core.
+///
+/// Operations must not panic: a panic while combining would leave the lock held.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::flat_combining::FlatCombining;
+/// use std::collections::BinaryHeap;
+///
+/// let mut heap = FlatCombining::new_in_heap(BinaryHeap::new());
+/// heap.apply(|h| h.push(3));
+/// heap.apply(|h| h.push(5));
+/// assert_eq!(heap.apply(|h| h.pop()), Some(5));
+/// assert_eq!(heap.into_inner().into_vec(), vec![3]);
+/// ```
+///
+pub struct FlatCombining<T> {
+    data: T,
+    lock: util::WrappedAlign64Type<SpinLock>,
+    record_count: util::WrappedAlign64Type<i64>,
+    records: [util::WrappedAlign64Type<Record<T>>; MAX_THREAD_COUNT],
+}
+
+impl<T> FlatCombining<T> {
+    /// `FlatCombining` can be allocated in stack directly, but it can't be moved after calling
+    /// `apply`.
+    pub unsafe fn new_in_stack(data: T) -> FlatCombining<T> {
+        let mut ret = FlatCombining {
+            data,
+            lock: util::WrappedAlign64Type(SpinLock::default()),
+            record_count: util::WrappedAlign64Type(0),
+            records: mem::zeroed(),
+        };
+        for record in ret.records.iter_mut() {
+            ptr::write(&mut record.state, RECORD_EMPTY);
+            ptr::write(&mut record.op, None);
+        }
+        ret
+    }
+
+    /// Alloc `FlatCombining` in heap.
+    pub fn new_in_heap(data: T) -> Box<Self> {
+        unsafe { Box::new(Self::new_in_stack(data)) }
+    }
+
+    /// Consume current wrapper and return the wrapped structure.
+    pub fn into_inner(self) -> T {
+        self.data
+    }
+
+    /// Execute `f` on the wrapped structure exclusively, maybe by another thread, and return its
+    /// result.
+    pub fn apply<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut f = Some(f);
+        let mut ret = None;
+        {
+            let mut op = |data: &mut T| {
+                ret = Some((f.take().unwrap())(data));
+            };
+            let tid = util::get_thread_id() as usize;
+            if MAX_THREAD_COUNT <= tid {
+                // No record for current thread, just execute under the lock.
+                let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+                op(&mut self.data);
+            } else {
+                unsafe {
+                    self.publish_and_wait(tid, &mut op);
+                }
+            }
+        }
+        ret.unwrap()
+    }
+
+    unsafe fn publish_and_wait(&mut self, tid: usize, op: &mut FnMut(&mut T)) {
+        self.update_record_count(tid as i64 + 1);
+        let record = self.records[tid].as_mut_ptr();
+        // Only the lifetime is erased, `op` outlives the record because it's waited for below.
+        (*record).op = Some(mem::transmute::<_, *mut (FnMut(&mut T) + 'static)>(
+            op as *mut FnMut(&mut T),
+        ));
+        intrinsics::atomic_store(&mut (*record).state, RECORD_PENDING);
+        let mut backoff = util::Backoff::new();
+        while RECORD_DONE != intrinsics::atomic_load(&(*record).state) {
+            if self.lock.raw_try_lock() {
+                self.combine();
+                self.lock.raw_unlock();
+            } else {
+                backoff.snooze();
+            }
+        }
+        intrinsics::atomic_store(&mut (*record).state, RECORD_EMPTY);
+    }
+
+    unsafe fn update_record_count(&mut self, count: i64) {
+        let mut cur = intrinsics::atomic_load(self.record_count.as_ptr());
+        while cur < count {
+            let (tmp, ok) = intrinsics::atomic_cxchg(self.record_count.as_mut_ptr(), cur, count);
+            if ok {
+                break;
+            }
+            cur = tmp;
+        }
+    }
+
+    /// Execute pending operations of all threads. Lock must be held.
+    unsafe fn combine(&mut self) {
+        let record_count = intrinsics::atomic_load(self.record_count.as_ptr()) as usize;
+        for _ in 0..COMBINE_PASSES {
+            let mut applied = 0;
+            for idx in 0..record_count {
+                let record = self.records[idx].as_mut_ptr();
+                if RECORD_PENDING == intrinsics::atomic_load(&(*record).state) {
+                    let op = (*record).op.expect("pending record without op");
+                    (*op)(&mut self.data);
+                    intrinsics::atomic_store(&mut (*record).state, RECORD_DONE);
+                    applied += 1;
+                }
+            }
+            if 0 == applied {
+                break;
+            }
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use flat_combining::FlatCombining;
+        let mut fc = unsafe { FlatCombining::new_in_stack(vec![]) };
+        let test_num = 100;
+        for i in 0..test_num {
+            assert_eq!(fc.apply(|v| {
+                v.push(i);
+                v.len()
+            }), i + 1);
+        }
+        assert_eq!(fc.apply(|v| v.pop()), Some(test_num - 1));
+        assert_eq!(fc.into_inner().len(), test_num - 1);
+    }
+}
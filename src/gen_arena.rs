@@ -0,0 +1,297 @@
+//! Definition and implementations of `GenArena`
+//!
+use error::{PushError, QueueError};
+use hazard_epoch::HazardEpoch;
+use hazard_pointer::{BaseHazardNode, HazardNodeT};
+use util;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Index of no slot.
+const NIL: u32 = u32::max_value();
+
+/// Link to free slot `index`, packed with a count which is increased on every update of the link.
+#[inline]
+fn pack(index: u32, count: u32) -> u64 {
+    u64::from(count) << 32 | u64::from(index)
+}
+
+#[inline]
+fn index(link: u64) -> u32 {
+    link as u32
+}
+
+#[inline]
+fn count(link: u64) -> u32 {
+    (link >> 32) as u32
+}
+
+struct ArenaNode<T> {
+    value: Option<T>,
+    base: BaseHazardNode,
+    generation: u32,
+}
+
+impl<T> HazardNodeT for ArenaNode<T> {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut _
+    }
+}
+
+impl<T> Drop for ArenaNode<T> {
+    fn drop(&mut self) {}
+}
+
+struct Slot<T> {
+    node: util::AtomicRawPtr<ArenaNode<T>>,
+    /// Generation of the last object inserted to current slot.
+    generation: AtomicU32,
+    /// Next free slot, while current slot is free.
+    next: util::AtomicU64,
+}
+
+/// Handle of an object in `GenArena`, which fails to refer to anything once the object is
+/// removed, even after its slot is reused.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Handle {
+    /// Index of the slot
+    pub index: u32,
+    /// Generation of the slot when the object was inserted
+    pub generation: u32,
+}
+
+/// Bounded concurrent arena, implemented based on `HazardEpoch`, which returns a generational
+/// handle for each inserted object, for graphs or entity tables which refer to objects by handle
+/// instead of pointer.
+///
+/// Each slot holds a node of its object tagged with a generation, which is increased each time
+/// the slot is reused, so a stale handle finds the generation changed and fails instead of
+/// aliasing a new object. Removing swaps the node out by CAS and adds it to `HazardEpoch`, so a
+/// thread reading it by a handle at the same time still reads valid memory, and the slot goes back
+/// to a free list right away. The free list is a stack linked by counted indices like `IndexQueue`.
+/// Generations are 32-bit, so a handle could alias again after its slot is reused 2^32 times.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::gen_arena::GenArena;
+///
+/// let mut arena = GenArena::with_capacity(1);
+/// let a = arena.insert("a").unwrap();
+/// assert!(arena.insert("b").is_err());
+/// assert_eq!(arena.get(a, |v| v.len()), Some(1));
+/// assert!(arena.remove(a));
+/// let b = arena.insert("b").unwrap();
+/// // Same slot, but the stale handle fails.
+/// assert_eq!(a.index, b.index);
+/// assert_eq!(arena.get(a, |v| *v), None);
+/// assert_eq!(arena.get(b, |v| *v), Some("b"));
+/// assert!(!arena.remove(a));
+/// ```
+///
+pub struct GenArena<T> {
+    hazard_epoch: HazardEpoch,
+    free: util::WrappedAlign64Type<util::AtomicU64>,
+    slots: Box<[Slot<T>]>,
+}
+
+impl<T> GenArena<T> {
+    /// Return empty GenArena in heap holding at most `capacity` objects, which must be positive
+    /// and less than `u32::max_value()`, with default setting of HazardEpoch.
+    pub fn with_capacity(capacity: usize) -> Box<GenArena<T>> {
+        assert!(
+            0 < capacity && capacity < NIL as usize,
+            "capacity must be in [1, {})",
+            NIL
+        );
+        Box::new(GenArena {
+            hazard_epoch: unsafe { HazardEpoch::default_new_in_stack() },
+            free: util::WrappedAlign64Type(util::AtomicU64::new(pack(0, 0))),
+            slots: (0..capacity)
+                .map(|i| Slot {
+                    node: util::AtomicRawPtr::default(),
+                    generation: AtomicU32::new(0),
+                    next: util::AtomicU64::new(if i + 1 == capacity {
+                        pack(NIL, 0)
+                    } else {
+                        pack(i as u32 + 1, 0)
+                    }),
+                })
+                .collect::<Vec<_>>()
+                .into_boxed_slice(),
+        })
+    }
+
+    /// Return the maximum number of objects.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Take a slot from the free list, None if there is none.
+    fn alloc(&self) -> Option<u32> {
+        let mut backoff = util::Backoff::new();
+        let mut free = self.free.load(Ordering::SeqCst);
+        loop {
+            let i = index(free);
+            if NIL == i {
+                return None;
+            }
+            // Slot `i` may have been taken already, then the count makes the CAS fail.
+            let next = self.slots[i as usize].next.load(Ordering::SeqCst);
+            let (cur, ok) = self.free.cas(
+                free,
+                pack(index(next), count(free).wrapping_add(1)),
+                Ordering::SeqCst,
+            );
+            if ok {
+                return Some(i);
+            }
+            free = cur;
+            backoff.spin();
+        }
+    }
+
+    /// Put slot `i` back to the free list.
+    fn dealloc(&self, i: u32) {
+        let slot = &self.slots[i as usize];
+        let mut backoff = util::Backoff::new();
+        let mut free = self.free.load(Ordering::SeqCst);
+        loop {
+            slot.next.store(free, Ordering::SeqCst);
+            let (cur, ok) = self.free
+                .cas(free, pack(i, count(free).wrapping_add(1)), Ordering::SeqCst);
+            if ok {
+                return;
+            }
+            free = cur;
+            backoff.spin();
+        }
+    }
+
+    /// Insert an object and return its handle. Return `QueueError::Full` if all slots are in use,
+    /// or `QueueError::AllocFailed` if memory of node can't be allocated. The object is given back
+    /// in both cases.
+    pub fn insert(&mut self, v: T) -> Result<Handle, PushError<T>> {
+        let i = match self.alloc() {
+            Some(i) => i,
+            None => {
+                return Err(PushError {
+                    error: QueueError::Full { op: "insert" },
+                    value: v,
+                })
+            }
+        };
+        let slot = &self.slots[i as usize];
+        let generation = slot.generation.load(Ordering::SeqCst).wrapping_add(1);
+        let node = match util::try_box_into_raw(ArenaNode {
+            value: Some(v),
+            base: BaseHazardNode::default(),
+            generation,
+        }) {
+            Ok(node) => node,
+            Err(mut node) => {
+                self.dealloc(i);
+                return Err(PushError {
+                    error: QueueError::AllocFailed { op: "insert" },
+                    value: node.value.take().unwrap(),
+                });
+            }
+        };
+        slot.generation.store(generation, Ordering::SeqCst);
+        slot.node.store(node, Ordering::SeqCst);
+        Ok(Handle {
+            index: i,
+            generation,
+        })
+    }
+
+    /// Return the node of `handle` in `slots`, null if it's stale. It only borrows the slots, so
+    /// that a cursor of `hazard_epoch` can be held meanwhile.
+    #[inline]
+    fn load(slots: &[Slot<T>], handle: Handle) -> *mut ArenaNode<T> {
+        let node = match slots.get(handle.index as usize) {
+            Some(slot) => slot.node.load(Ordering::SeqCst),
+            None => return ptr::null_mut(),
+        };
+        if node.is_null() || unsafe { (*node).generation } != handle.generation {
+            ptr::null_mut()
+        } else {
+            node
+        }
+    }
+
+    /// Call `f` with the object of `handle`, and return its result. Return None if the object has
+    /// been removed. Panic if `try_get` fails.
+    pub fn get<R, F: FnOnce(&T) -> R>(&mut self, handle: Handle, f: F) -> Option<R> {
+        match self.try_get(handle, f) {
+            Ok(ret) => ret,
+            Err(e) => panic!("get fail, {}", e),
+        }
+    }
+
+    /// Same as `get`, but return `QueueError::Hazard` if `HazardEpoch` fails. The object is
+    /// protected while `f` runs, even if another thread removes it meanwhile.
+    pub fn try_get<R, F: FnOnce(&T) -> R>(
+        &mut self,
+        handle: Handle,
+        f: F,
+    ) -> Result<Option<R>, QueueError> {
+        // Released when dropped, even if `f` panics.
+        let _cursor = self.hazard_epoch.cursor()?;
+        let node = Self::load(&self.slots, handle);
+        let ret = if node.is_null() {
+            None
+        } else {
+            (unsafe { &(*node).value }).as_ref().map(f)
+        };
+        Ok(ret)
+    }
+
+    /// Return true if the object of `handle` has not been removed.
+    pub fn contains(&mut self, handle: Handle) -> bool {
+        self.get(handle, |_| ()).is_some()
+    }
+
+    /// Remove the object of `handle`, which is dropped once no thread reads it. Return false if
+    /// it has been removed. Panic if `try_remove` fails.
+    pub fn remove(&mut self, handle: Handle) -> bool {
+        match self.try_remove(handle) {
+            Ok(ret) => ret,
+            Err(e) => panic!("remove fail, {}", e),
+        }
+    }
+
+    /// Same as `remove`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    pub fn try_remove(&mut self, handle: Handle) -> Result<bool, QueueError> {
+        let (ok, retired) = {
+            let cursor = self.hazard_epoch.cursor()?;
+            let node = Self::load(&self.slots, handle);
+            // A node can't be reused while held, so a CAS on a stale node fails.
+            let ok = !node.is_null()
+                && self.slots[handle.index as usize]
+                    .node
+                    .cas(node, ptr::null_mut(), Ordering::SeqCst)
+                    .1;
+            let retired = if ok { unsafe { cursor.retire(node) } } else { Ok(()) };
+            (ok, retired)
+        };
+        // The slot is emptied already, so it's freed even if the node can't be retired.
+        if ok {
+            self.dealloc(handle.index);
+        }
+        retired?;
+        Ok(ok)
+    }
+}
+
+impl<T> Drop for GenArena<T> {
+    fn drop(&mut self) {
+        for slot in self.slots.iter() {
+            let node = slot.node.swap(ptr::null_mut(), Ordering::SeqCst);
+            if !node.is_null() {
+                unsafe { drop(Box::from_raw(node)) };
+            }
+        }
+    }
+}
@@ -3,15 +3,30 @@
 use util::WrappedAlign64Type;
 use spin_lock::SpinLock;
 use hazard_pointer::{ThreadStore, VersionHandle};
+use std::cmp;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::marker::PhantomData;
 use std::ptr;
 use std::mem;
 use std::intrinsics;
+use std::sync::atomic::Ordering;
+use std::thread;
 use util;
 use error;
+use stats;
+use error::HazardError;
+#[cfg(feature = "debug-assert-invariants")]
+use error::InvariantError;
+use diagnostics::DiagnosticHook;
 use util::sync_fetch_and_add;
 use util::sync_add_and_fetch;
 
 pub use hazard_pointer::{BaseHazardNode, HazardNodeT};
+#[cfg(feature = "debug-poison")]
+pub use quarantine::{flush_quarantine, quarantine_period, quarantined_count, set_quarantine_period,
+                     POISON};
 
 cfg_if! {
     if #[cfg(feature = "max_thread_count_4096")] {
@@ -24,25 +39,91 @@ cfg_if! {
     }
 }
 
+/// Number of shared objects added by a thread between two advances of the global version.
+const VERSION_ADVANCE_BATCH: u32 = 16;
+
+/// Adaptive interval to update minimum version cache never drops below the configured one divided
+/// by it.
+const CACHE_TIME_SHRINK_LIMIT: i64 = 64;
+
+/// Number of shards counting held handles, a thread counts to shard `tid % HANDLE_SHARD_COUNT`.
+const HANDLE_SHARD_COUNT: usize = 4;
+
+/// What `HazardEpoch::check_pending` does when objects waiting to be reclaimed reach the limit
+/// set by `HazardEpoch::set_pending_limit`, after reclaiming synchronously.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum PendingPolicy {
+    /// Go on, reclaiming is all it can do.
+    Reclaim,
+    /// Return `HazardError::PendingLimit` if the limit is still reached.
+    Reject,
+}
+
+/// Handle held by a thread, returned by `HazardEpoch::active_handles`.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct ActiveHandle {
+    /// Thread id by `util::get_thread_id`.
+    pub tid: u16,
+    /// Version the handle holds. Objects added at or after it wait until it's released.
+    pub version: u64,
+    /// Microseconds the handle has been held for.
+    pub held_us: i64,
+}
+
 struct VersionTimestamp {
     curr_min_version: u64,
     curr_min_version_timestamp: i64,
+    /// Current interval to update minimum version cache, adapted between
+    /// `min_version_cache_time_us / CACHE_TIME_SHRINK_LIMIT` and `min_version_cache_time_us`.
+    cache_time_us: i64,
+    /// Waiting count observed by last update.
+    waiting_count: i64,
 }
 
 /// `HazardEpoch` a practical implementation of `Hazard Pointers`, which use global incremental
 /// version to identify shared object to be reclaimed. Because of [`False sharing`](https://en.wikipedia.org/wiki/False_sharing),
 /// a part of the member variables, might be frequently modified by different threads, are aligned
 /// to 64 bytes.
+///
+/// A shared object is tagged with the global version when added, and reclaimed once the minimum
+/// version of all handles is newer than its tag. Adding only loads the global version, which is
+/// advanced by a thread every `VERSION_ADVANCE_BATCH`(16) objects it adds and by `retire`, so the
+/// cache line of the global version is rarely written on the hot path.
+///
+/// Minimum version is cached to save scans of thread stores. Each update of the cache halves its
+/// interval if objects waiting to be reclaimed have grown since last update, and doubles it
+/// otherwise, so reclaiming keeps up with heavy retiring while idle scans stay rare.
+///
+/// Held handles are also counted in `HANDLE_SHARD_COUNT`(4) shards. Updating the minimum version
+/// reads the global version and then the shards, and if no handle is held, the global version is
+/// the minimum without walking thread stores. A thread acquiring a handle counts it before loading
+/// the global version once, so an update missing the count loaded the global version before it,
+/// and objects tagged older were unlinked before the handle is published.
 pub struct HazardEpoch {
     thread_waiting_threshold: i64,
     min_version_cache_time_us: i64,
     version: WrappedAlign64Type<u64>,
     thread_lock: WrappedAlign64Type<SpinLock>,
-    threads: [ThreadStore; MAX_THREAD_COUNT],
-    thread_list: *mut ThreadStore,
-    thread_count: i64,
+    /// Slot of each thread id, allocated when the thread registers.
+    threads: [util::AtomicRawPtr<ThreadStore>; MAX_THREAD_COUNT],
+    thread_list: util::AtomicRawPtr<ThreadStore>,
+    thread_count: util::AtomicI64,
     hazard_waiting_count: WrappedAlign64Type<i64>,
+    handle_counts: [WrappedAlign64Type<util::AtomicI64>; HANDLE_SHARD_COUNT],
     curr_min_version_info: WrappedAlign64Type<VersionTimestamp>,
+    diagnostic_hook: Option<DiagnosticHook>,
+    pending_limit: i64,
+    pending_policy: PendingPolicy,
+    max_reclaim_per_release: i64,
+    max_foreign_reclaim_per_release: i64,
+    /// Position in `thread_list` where the next reclaiming starts to visit thread stores of other
+    /// threads, so that they take turns to be reclaimed first.
+    reclaim_cursor: util::AtomicU64,
+    frame_mode: bool,
+    /// Reclaiming operations started and finished, which tell `wait_until_reclaimed` whether
+    /// another thread may hold waiting objects out of thread stores.
+    retire_started: util::AtomicU64,
+    retire_finished: util::AtomicU64,
 }
 
 impl HazardEpoch {
@@ -59,6 +140,34 @@ impl HazardEpoch {
         );
     }
 
+    /// The interval only decides how often the cache is updated, not which version is safe, so
+    /// it's relaxed.
+    #[inline]
+    unsafe fn cache_time_us(&self) -> i64 {
+        util::atomic_load_relaxed(&self.curr_min_version_info.cache_time_us)
+    }
+
+    /// Adapt interval to update minimum version cache by the growth of waiting count since last
+    /// update.
+    #[inline]
+    unsafe fn adapt_cache_time(&mut self) {
+        let waiting_count = self.atomic_load_hazard_waiting_count();
+        let last = intrinsics::atomic_xchg(
+            &mut self.curr_min_version_info.waiting_count,
+            waiting_count,
+        );
+        let curr = self.cache_time_us();
+        let next = if waiting_count > last {
+            cmp::max(
+                curr / 2,
+                self.min_version_cache_time_us / CACHE_TIME_SHRINK_LIMIT,
+            )
+        } else {
+            cmp::min(cmp::max(curr * 2, 1), self.min_version_cache_time_us)
+        };
+        util::atomic_store_relaxed(&mut self.curr_min_version_info.cache_time_us, next);
+    }
+
     #[inline]
     unsafe fn curr_min_version_timestamp(&self) -> i64 {
         intrinsics::atomic_load(&self.curr_min_version_info.curr_min_version_timestamp)
@@ -74,8 +183,9 @@ impl HazardEpoch {
 
     /// To improve performance, `HazardEpoch` can be allocated in stack directly, but it can't be
     /// moved after calling any method. `thread_waiting_threshold` means the maximum of the number of
-    /// shared objects to be reclaimed under one thread. `min_version_cache_time_us` means the time
-    /// interval(microsecond) to update minimum version cache.
+    /// shared objects to be reclaimed under one thread. `min_version_cache_time_us` means the
+    /// maximum time interval(microsecond) to update minimum version cache, the interval in use
+    /// shrinks down to 1/64 of it under retire pressure.
     ///
     /// # Examples
     ///
@@ -92,24 +202,32 @@ impl HazardEpoch {
         thread_waiting_threshold: i64,
         min_version_cache_time_us: i64,
     ) -> HazardEpoch {
-        let mut ret = HazardEpoch {
+        HazardEpoch {
             thread_waiting_threshold,
             min_version_cache_time_us,
             version: WrappedAlign64Type(0),
             thread_lock: WrappedAlign64Type(SpinLock::default()),
             threads: mem::zeroed(),
-            thread_list: ptr::null_mut(),
-            thread_count: 0,
+            thread_list: util::AtomicRawPtr::default(),
+            thread_count: util::AtomicI64::new(0),
             hazard_waiting_count: WrappedAlign64Type(0),
+            handle_counts: Default::default(),
             curr_min_version_info: WrappedAlign64Type(VersionTimestamp {
                 curr_min_version: 0,
                 curr_min_version_timestamp: 0,
+                cache_time_us: min_version_cache_time_us,
+                waiting_count: 0,
             }),
-        };
-        for idx in 0..ret.threads.len() {
-            ret.threads[idx] = ThreadStore::default();
+            diagnostic_hook: None,
+            pending_limit: i64::max_value(),
+            pending_policy: PendingPolicy::Reclaim,
+            max_reclaim_per_release: i64::max_value(),
+            max_foreign_reclaim_per_release: i64::max_value(),
+            reclaim_cursor: util::AtomicU64::new(0),
+            frame_mode: false,
+            retire_started: util::AtomicU64::new(0),
+            retire_finished: util::AtomicU64::new(0),
         }
-        ret
     }
 
     /// Alloc `HazardEpoch` in heap. Usage is the same as `new_in_stack`.
@@ -148,6 +266,20 @@ impl HazardEpoch {
     #[inline]
     unsafe fn destroy(&mut self) {
         self.retire();
+        stats::gauge_add(stats::HAZARD_THREAD_COUNT, -self.atomic_load_thread_count());
+        for slot in self.threads.iter() {
+            let ts = slot.swap(ptr::null_mut(), Ordering::SeqCst);
+            if !ts.is_null() {
+                drop(Box::from_raw(ts));
+            }
+        }
+    }
+
+    #[inline]
+    unsafe fn on_reclaimed(&mut self, retire_count: i64) {
+        util::sync_fetch_and_add_relaxed(self.hazard_waiting_count.as_mut_ptr(), -retire_count);
+        stats::gauge_add(stats::HAZARD_WAITING_COUNT, -retire_count);
+        stats::counter_add(stats::HAZARD_RECLAIMED_TOTAL, retire_count);
     }
 
     /// Reclaim all shared objects waiting to be reclaimed. It will be called when dropping `HazardEpoch`.
@@ -160,29 +292,307 @@ impl HazardEpoch {
     ///
     /// let mut h = HazardEpoch::new_in_heap(64, 200000);
     /// let node = Box::into_raw(Box::new(BaseHazardNode::default()));
-    /// unsafe { h.add_node(node); }
+    /// unsafe { h.try_add_node(node).unwrap(); }
     /// unsafe { h.retire(); }
     /// ```
     ///
     pub unsafe fn retire(&mut self) {
-        let mut ts = ptr::null_mut::<ThreadStore>();
-        let ret = self.get_thread_store(&mut ts);
-        if ret != error::Status::Success {
-            warn!("get_thread_store fail, ret={}", ret);
-            return;
-        }
-        let min_version = self.get_min_version(true);
-        let retire_count = (*ts).retire(min_version, &mut *ts);
-        sync_fetch_and_add(self.hazard_waiting_count.as_mut_ptr(), -retire_count);
+        self.flush_all();
+    }
+
+    #[inline]
+    fn begin_retire(&self) -> u64 {
+        self.retire_started.fetch_add(1, Ordering::SeqCst)
+    }
+
+    #[inline]
+    fn end_retire(&self) -> u64 {
+        self.retire_finished.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Reclaim at most `limit` objects tagged older than `version` in thread store `target`, moving
+    /// the others to `receiver`, which is the one of current thread. Return the number of
+    /// reclaimed objects.
+    unsafe fn retire_thread_store(
+        &mut self,
+        target: *mut ThreadStore,
+        version: u64,
+        receiver: *mut ThreadStore,
+        limit: i64,
+    ) -> i64 {
+        self.begin_retire();
+        let retire_count = (*target).retire(version, &mut *receiver, limit);
+        self.end_retire();
+        self.on_reclaimed(retire_count);
+        retire_count
+    }
 
-        let mut iter = self.atomic_load_thread_list();
+    /// Reclaim at most `limit` objects tagged older than `version` in all thread stores, moving the
+    /// others to `ts`. Stores of other threads are no longer visited once `foreign_limit` objects of
+    /// them are reclaimed. Return the number of reclaimed objects.
+    ///
+    /// The budget is checked between stores, because objects left in a store taken apart would be
+    /// moved to `ts`. Stores are visited from `reclaim_cursor` to the end of `thread_list` and then
+    /// from the head. The cursor is left where the budget runs out, or moved by one after a full
+    /// pass, so that the next reclaiming starts with stores skipped this time.
+    unsafe fn reclaim(
+        &mut self,
+        ts: *mut ThreadStore,
+        version: u64,
+        limit: i64,
+        foreign_limit: i64,
+    ) -> i64 {
+        let mut total = (*ts).retire(version, &mut *ts, limit);
+        self.on_reclaimed(total);
+
+        let head = self.thread_list.load(Ordering::SeqCst);
+        let mut len = 0;
+        let mut iter = head;
         while !iter.is_null() {
+            len += 1;
+            iter = (*iter).next();
+        }
+        // The cursor is only a hint where to start, any value is valid, so it's relaxed.
+        let start = self.reclaim_cursor.load(util::ORDER_RELAXED) % cmp::max(len, 1);
+        iter = head;
+        for _ in 0..start {
+            iter = (*iter).next();
+        }
+        let mut foreign = 0;
+        for step in 0..len {
+            if iter.is_null() {
+                iter = head;
+            }
+            if limit <= total || foreign_limit <= foreign {
+                self.reclaim_cursor.store((start + step) % len, util::ORDER_RELAXED);
+                return total;
+            }
             if iter != ts {
-                let retire_count = (*iter).retire(min_version, &mut *ts);
-                sync_fetch_and_add(self.hazard_waiting_count.as_mut_ptr(), -retire_count);
+                let retire_count = (*iter).retire(version, &mut *ts, limit - total);
+                self.on_reclaimed(retire_count);
+                total += retire_count;
+                foreign += retire_count;
             }
             iter = (*iter).next();
         }
+        self.reclaim_cursor.store(start + 1, util::ORDER_RELAXED);
+        total
+    }
+
+    /// Advance the global version and return the new one, which marks a boundary like a committed
+    /// transaction or batch: objects added before are tagged older than it, and those added after
+    /// are not. Pass it to `flush_before` later to reclaim objects retired before the boundary.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # // Objects are reclaimed right away in single thread mode.
+    /// # #[cfg(not(feature = "single_thread"))]
+    /// # fn main() {
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// let node = Box::into_raw(Box::new(BaseHazardNode::default()));
+    /// unsafe { h.try_add_node(node).unwrap(); }
+    /// let boundary = h.advance();
+    /// let node = Box::into_raw(Box::new(BaseHazardNode::default()));
+    /// unsafe { h.try_add_node(node).unwrap(); }
+    /// assert_eq!(unsafe { h.flush_before(boundary) }, 1);
+    /// assert_eq!(h.atomic_load_hazard_waiting_count(), 1);
+    /// # }
+    /// # #[cfg(feature = "single_thread")]
+    /// # fn main() {}
+    /// ```
+    ///
+    #[inline]
+    pub fn advance(&mut self) -> u64 {
+        unsafe { sync_add_and_fetch(self.version.as_mut_ptr(), 1) }
+    }
+
+    /// Reclaim objects tagged older than `version`, which is usually returned by `advance`, as long
+    /// as no handle holds them. Objects retired after `version` are kept even if reclaimable, so
+    /// reclaiming follows boundaries of the caller instead of thresholds of `release`. Return the
+    /// number of reclaimed objects.
+    pub unsafe fn flush_before(&mut self, version: u64) -> i64 {
+        let ts = match self.get_thread_store() {
+            Ok(ts) => ts,
+            Err(e) => {
+                diagnose!(hook: self.diagnostic_hook, "get_thread_store fail, {}", e);
+                return 0;
+            }
+        };
+        let min_version = self.get_min_version(true);
+        self.begin_retire();
+        let retire_count = self.reclaim(
+            ts,
+            cmp::min(version, min_version),
+            i64::max_value(),
+            i64::max_value(),
+        );
+        self.end_retire();
+        retire_count
+    }
+
+    /// Reclaim objects waiting in the thread store of thread `tid`, which helps a thread gone idle
+    /// with a backlog no threshold will trip on. Objects still held by a handle are moved to current
+    /// thread. Return the number of reclaimed objects, 0 if thread `tid` has never registered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # // Objects are reclaimed right away in single thread mode.
+    /// # #[cfg(not(feature = "single_thread"))]
+    /// # fn main() {
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+    /// use rs_lockfree::util;
+    /// use std::thread;
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// let addr = &mut *h as *mut HazardEpoch as usize;
+    /// let tid = thread::spawn(move || {
+    ///     let h = unsafe { &mut *(addr as *mut HazardEpoch) };
+    ///     let node = Box::into_raw(Box::new(BaseHazardNode::default()));
+    ///     unsafe { h.try_add_node(node).unwrap(); }
+    ///     util::get_thread_id() as u16
+    /// }).join().unwrap();
+    /// assert_eq!(unsafe { h.flush_thread(tid) }, 1);
+    /// assert_eq!(h.atomic_load_hazard_waiting_count(), 0);
+    /// # }
+    /// # #[cfg(feature = "single_thread")]
+    /// # fn main() {}
+    /// ```
+    ///
+    pub unsafe fn flush_thread(&mut self, tid: u16) -> i64 {
+        let ts = match self.get_thread_store() {
+            Ok(ts) => ts,
+            Err(e) => {
+                diagnose!(hook: self.diagnostic_hook, "get_thread_store fail, {}", e);
+                return 0;
+            }
+        };
+        let target = match self.threads.get(tid as usize) {
+            Some(slot) => slot.load(Ordering::SeqCst),
+            None => ptr::null_mut(),
+        };
+        if target.is_null() || 0 == (*target).get_hazard_waiting_count() {
+            return 0;
+        }
+        self.advance_version();
+        let min_version = self.get_min_version(true);
+        self.retire_thread_store(target, min_version, ts, i64::max_value())
+    }
+
+    /// Reclaim objects waiting in the thread stores of all threads, like `retire`, moving those
+    /// still held by a handle to current thread. Return the number of reclaimed objects.
+    pub unsafe fn flush_all(&mut self) -> i64 {
+        self.inner_flush_all(i64::max_value(), i64::max_value())
+    }
+
+    unsafe fn inner_flush_all(&mut self, limit: i64, foreign_limit: i64) -> i64 {
+        let ts = match self.get_thread_store() {
+            Ok(ts) => ts,
+            Err(e) => {
+                diagnose!(hook: self.diagnostic_hook, "get_thread_store fail, {}", e);
+                return 0;
+            }
+        };
+        // Objects tagged with current version become reclaimable once no handle holds it.
+        self.advance_version();
+        let min_version = self.get_min_version(true);
+        self.begin_retire();
+        let retire_count = self.reclaim(ts, min_version, limit, foreign_limit);
+        self.end_retire();
+        retire_count
+    }
+
+    /// Try reclaiming objects tagged at or before `version`, and return true if none is left. It
+    /// fails if a handle still holds them, or if another thread was reclaiming meanwhile, which
+    /// may have taken objects out of thread stores.
+    unsafe fn try_wait_reclaimed(&mut self, version: u64) -> bool {
+        let ts = match self.get_thread_store() {
+            Ok(ts) => ts,
+            Err(e) => {
+                diagnose!(hook: self.diagnostic_hook, "get_thread_store fail, {}", e);
+                return false;
+            }
+        };
+        self.advance_version();
+        let min_version = self.get_min_version(true);
+        let started = self.begin_retire();
+        let quiet = started == self.retire_finished.load(Ordering::SeqCst);
+        if version < min_version {
+            self.reclaim(ts, min_version, i64::max_value(), i64::max_value());
+        }
+        let finished = self.end_retire();
+        quiet && version < min_version && started == finished
+            && started + 1 == self.retire_started.load(Ordering::SeqCst)
+    }
+
+    /// Block until all objects tagged at or before `version`, which is usually returned by
+    /// `advance`, have been reclaimed, helping to reclaim them, so shutdown sequences and tests can
+    /// make sure no garbage remains. Objects still held by a handle are waited for. It waits by
+    /// `util::WaitPolicy`, and keeps waiting while other threads keep reclaiming. With feature
+    /// `debug-poison`, reclaimed objects may still be in quarantine.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # // Objects are reclaimed right away in single thread mode.
+    /// # #[cfg(not(feature = "single_thread"))]
+    /// # fn main() {
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// let node = Box::into_raw(Box::new(BaseHazardNode::default()));
+    /// unsafe { h.try_add_node(node).unwrap(); }
+    /// let boundary = h.advance();
+    /// unsafe { h.wait_until_reclaimed(boundary); }
+    /// assert_eq!(h.atomic_load_hazard_waiting_count(), 0);
+    /// # }
+    /// # #[cfg(feature = "single_thread")]
+    /// # fn main() {}
+    /// ```
+    ///
+    pub unsafe fn wait_until_reclaimed(&mut self, version: u64) {
+        let mut backoff = util::Backoff::new();
+        while !self.try_wait_reclaimed(version) {
+            backoff.snooze();
+        }
+    }
+
+    /// Return a future which is ready once all objects tagged at or before `version` have been
+    /// reclaimed, like `wait_until_reclaimed`. Each poll tries once, and wakes the task again if
+    /// not ready, so it takes turns with other tasks of the executor instead of blocking.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # // Objects are reclaimed right away in single thread mode.
+    /// # #[cfg(not(feature = "single_thread"))]
+    /// # fn main() {
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+    /// use std::future::Future;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll, Waker};
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// let node = Box::into_raw(Box::new(BaseHazardNode::default()));
+    /// unsafe { h.try_add_node(node).unwrap(); }
+    /// let boundary = h.advance();
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// let mut reclaimed = unsafe { h.wait_until_reclaimed_async(boundary) };
+    /// assert_eq!(Pin::new(&mut reclaimed).poll(&mut cx), Poll::Ready(()));
+    /// assert_eq!(h.atomic_load_hazard_waiting_count(), 0);
+    /// # }
+    /// # #[cfg(feature = "single_thread")]
+    /// # fn main() {}
+    /// ```
+    ///
+    pub unsafe fn wait_until_reclaimed_async<'a>(&'a mut self, version: u64) -> ReclaimFuture<'a> {
+        ReclaimFuture {
+            epoch: self,
+            version,
+        }
     }
 
     /// Reclaim all shared objects waiting to be reclaimed. `node` can be any type as long as it implements
@@ -220,35 +630,108 @@ impl HazardEpoch {
     ///     cnt: &cnt,
     ///     v: 2333,
     /// }));
-    /// unsafe { h.add_node(node); }
+    /// unsafe { h.try_add_node(node).unwrap(); }
     /// drop(h);
     /// assert_eq!(*cnt.borrow(), 10);
     /// ```
     ///
     #[inline]
-    pub unsafe fn add_node<T>(&mut self, node: *mut T) -> error::Status
+    pub unsafe fn try_add_node<T>(&mut self, node: *mut T) -> Result<(), HazardError>
     where
         T: HazardNodeT,
     {
-        let mut ts = ptr::null_mut::<ThreadStore>();
-        let mut ret;
         if node.is_null() {
-            warn!("node is null");
-            ret = error::Status::InvalidParam;
-        } else if error::Status::Success != {
-            ret = self.get_thread_store(&mut ts);
-            ret
-        } {
-            warn!("get_thread_store fail, ret={}", ret);
-        } else if error::Status::Success != {
-            ret = (*ts).add_node(sync_add_and_fetch(self.version.as_mut_ptr(), 1), node);
-            ret
-        } {
-            warn!("add_node fail, ret={}", ret);
+            diagnose!(hook: self.diagnostic_hook, "node is null");
+            return Err(HazardError::InvalidParam { op: "add_node" });
+        }
+        let ts = self.get_thread_store().map_err(|e| {
+            diagnose!(hook: self.diagnostic_hook, "get_thread_store fail, {}", e);
+            e
+        })?;
+        (*ts).add_node(self.atomic_load_version(), node);
+        if VERSION_ADVANCE_BATCH <= (*ts).inc_unadvanced_count() {
+            (*ts).reset_unadvanced_count();
+            self.advance_version();
+        }
+        util::sync_fetch_and_add_relaxed(self.hazard_waiting_count.as_mut_ptr(), 1);
+        stats::gauge_add(stats::HAZARD_WAITING_COUNT, 1);
+        if cfg!(single_thread) && u64::max_value() == (*ts).version() {
+            // Not accessed by anyone, reclaim it right away.
+            self.retire();
+        } else if self.pending_limit <= self.atomic_load_hazard_waiting_count() {
+            self.retire();
+        }
+        Ok(())
+    }
+
+    /// Limit the number of objects waiting to be reclaimed to `limit`, None for no limit(default).
+    /// Reaching it, `try_add_node` reclaims synchronously, and `check_pending` also returns
+    /// `HazardError::PendingLimit` if `policy` is `PendingPolicy::Reject`, so a stalled reader
+    /// can't make waiting objects grow without bound.
+    #[inline]
+    pub fn set_pending_limit(&mut self, limit: Option<i64>, policy: PendingPolicy) {
+        self.pending_limit = limit.unwrap_or(i64::max_value());
+        self.pending_policy = policy;
+    }
+
+    /// Reclaim synchronously if objects waiting to be reclaimed reach the limit set by
+    /// `set_pending_limit`, and return `HazardError::PendingLimit` if they still do and the policy
+    /// is `PendingPolicy::Reject`. Call it before unlinking a shared object, because an unlinked
+    /// object must be added anyway. `push` of `LockFreeQueue` and `LockFreeStack` calls it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch, PendingPolicy};
+    /// use rs_lockfree::error::HazardError;
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// h.set_pending_limit(Some(1), PendingPolicy::Reject);
+    /// let handle = h.try_acquire().unwrap();
+    /// let node = Box::into_raw(Box::new(BaseHazardNode::default()));
+    /// unsafe { h.try_add_node(node).unwrap(); }
+    /// // Held handle keeps the node from being reclaimed.
+    /// assert_eq!(
+    ///     unsafe { h.check_pending() },
+    ///     Err(HazardError::PendingLimit { pending: 1, limit: 1 })
+    /// );
+    /// unsafe { h.release(handle); }
+    /// assert!(unsafe { h.check_pending() }.is_ok());
+    /// ```
+    ///
+    pub unsafe fn check_pending(&mut self) -> Result<(), HazardError> {
+        if self.pending_limit > self.atomic_load_hazard_waiting_count() {
+            return Ok(());
+        }
+        self.retire();
+        let pending = self.atomic_load_hazard_waiting_count();
+        if PendingPolicy::Reject == self.pending_policy && self.pending_limit <= pending {
+            diagnose!(
+                hook: self.diagnostic_hook,
+                "{} objects are waiting to be reclaimed, reaching limit {}",
+                pending,
+                self.pending_limit
+            );
+            Err(HazardError::PendingLimit {
+                pending,
+                limit: self.pending_limit,
+            })
         } else {
-            sync_fetch_and_add(self.hazard_waiting_count.as_mut_ptr(), 1);
+            Ok(())
+        }
+    }
+
+    /// Same as `try_add_node`, but return `Status`.
+    #[deprecated(since = "0.1.2", note = "use `try_add_node` instead")]
+    #[inline]
+    pub unsafe fn add_node<T>(&mut self, node: *mut T) -> error::Status
+    where
+        T: HazardNodeT,
+    {
+        match self.try_add_node(node) {
+            Ok(()) => error::Status::Success,
+            Err(e) => e.into(),
         }
-        ret
     }
 
     #[inline]
@@ -256,121 +739,588 @@ impl HazardEpoch {
         unsafe { intrinsics::atomic_load(self.version.as_ptr()) }
     }
 
-    /// Before accessing a shared object, call method `acquire` to get the `handle` of this operation.
+    #[inline]
+    unsafe fn advance_version(&mut self) {
+        sync_add_and_fetch(self.version.as_mut_ptr(), 1);
+    }
+
+    /// Before accessing a shared object, call method `try_acquire` to get the `handle` of this
+    /// operation. Return `HazardError::Busy` if current thread hasn't released its last handle.
     ///
     /// # Examples
     ///
     /// ```
     /// use rs_lockfree::hazard_epoch::HazardEpoch;
     /// use rs_lockfree::hazard_epoch::BaseHazardNode;
-    /// use rs_lockfree::error::Status;
+    /// use rs_lockfree::error::Error;
+    ///
+    /// fn read(h: &mut HazardEpoch, node: *mut BaseHazardNode) -> Result<(), Error> {
+    ///     let handle = h.try_acquire()?;
+    ///     let _o = unsafe { &(*node) };
+    ///     unsafe { h.release(handle); }
+    ///     Ok(())
+    /// }
     ///
     /// let mut h = HazardEpoch::default_new_in_heap();
     /// let node = Box::into_raw(Box::new(BaseHazardNode::default()));
-    /// let mut handle = 0;
-    /// assert_eq!(h.acquire(&mut handle), Status::Success);
-    /// let _o = unsafe { &(*node) };
-    /// unsafe { h.release(handle); }
+    /// assert!(read(&mut h, node).is_ok());
+    /// unsafe { h.try_add_node(node).unwrap(); }
     /// ```
     ///
+    pub fn try_acquire(&self) -> Result<u64, HazardError> {
+        let ts = match unsafe { self.get_thread_store() } {
+            Ok(ts) => unsafe { &mut *ts },
+            Err(e) => {
+                diagnose!(hook: self.diagnostic_hook, "get_thread_store fail, {}", e);
+                return Err(e);
+            }
+        };
+        // Counted before loading the version, so that no recheck of the version is needed. An
+        // update of the minimum version finding no handle held loaded the global version before
+        // current load, and one walking thread stores without seeing current handle loaded it
+        // before the handle is published.
+        let tid = ts.tid();
+        unsafe { self.add_handle_count(tid, 1) };
+        let mut version_handle = VersionHandle::new(0);
+        if let Err(e) = ts.acquire(self.atomic_load_version(), &mut version_handle) {
+            unsafe { self.add_handle_count(tid, -1) };
+            diagnose!(hook: self.diagnostic_hook, "thread store acquire fail, {}", e);
+            return Err(e);
+        }
+        Ok(version_handle.ver_u64())
+    }
+
+    /// Same as `try_acquire`, but write the handle to `handle` and return `Status`.
+    #[deprecated(since = "0.1.2", note = "use `try_acquire` instead")]
     pub fn acquire(&mut self, handle: &mut u64) -> error::Status {
-        let mut ts = ptr::null_mut::<ThreadStore>();
-        let mut ret;
-        if error::Status::Success != {
-            ret = unsafe { self.get_thread_store(&mut ts) };
-            ret
-        } {
-            warn!("get_thread_store fail, ret={}", ret);
-        } else {
-            let ts = unsafe { &mut *ts };
-            loop {
-                let version = self.atomic_load_version();
-                let mut version_handle = VersionHandle::new(0);
-                if error::Status::Success != {
-                    ret = ts.acquire(version, &mut version_handle);
-                    ret
-                } {
-                    warn!("thread store acquire fail, ret={}", ret);
-                    break;
-                } else if version != self.atomic_load_version() {
-                    ts.release(&version_handle);
-                } else {
-                    *handle = version_handle.ver_u64();
-                    break;
-                }
+        match self.try_acquire() {
+            Ok(h) => {
+                *handle = h;
+                error::Status::Success
             }
+            Err(e) => e.into(),
         }
-        ret
+    }
+
+    /// Acquire a handle of current thread and return `Cursor` holding it, which protects shared
+    /// objects loaded through it until dropped. Return error like `try_acquire`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch, HazardNodeT};
+    /// use rs_lockfree::util::AtomicRawPtr;
+    /// use std::sync::atomic::Ordering;
+    ///
+    /// struct Node {
+    ///     base: BaseHazardNode,
+    ///     key: i32,
+    ///     next: AtomicRawPtr<Node>,
+    /// }
+    ///
+    /// impl HazardNodeT for Node {
+    ///     fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+    ///         &self.base as *const _ as *mut BaseHazardNode
+    ///     }
+    /// }
+    ///
+    /// impl Drop for Node {
+    ///     fn drop(&mut self) {}
+    /// }
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// let head = AtomicRawPtr::default();
+    /// for key in (0..4).rev() {
+    ///     let next = head.load(Ordering::SeqCst);
+    ///     let node = Box::new(Node {
+    ///         base: BaseHazardNode::default(),
+    ///         key,
+    ///         next: AtomicRawPtr::new(next),
+    ///     });
+    ///     head.store(Box::into_raw(node), Ordering::SeqCst);
+    /// }
+    /// {
+    ///     let cursor = h.cursor().unwrap();
+    ///     assert_eq!(cursor.protect(&head).map(|n| n.key), Some(0));
+    ///     // Unlink the node of key 2.
+    ///     let (link, node) = cursor.find(&head, |n| &n.next, |n| n.key >= 2);
+    ///     let node = node.unwrap();
+    ///     let next = node.next.load(Ordering::SeqCst);
+    ///     let (old, ok) = link.cas(node as *const _ as *mut _, next, Ordering::SeqCst);
+    ///     assert!(ok);
+    ///     unsafe { cursor.retire(old).unwrap() };
+    ///     // Still readable until the cursor is dropped.
+    ///     assert_eq!(node.key, 2);
+    ///     assert_eq!(cursor.find(&head, |n| &n.next, |n| n.key >= 2).1.map(|n| n.key), Some(3));
+    /// }
+    /// let mut node = head.load(Ordering::SeqCst);
+    /// while !node.is_null() {
+    ///     let next = unsafe { (*node).next.load(Ordering::SeqCst) };
+    ///     unsafe { h.try_add_node(node).unwrap() };
+    ///     node = next;
+    /// }
+    /// ```
+    ///
+    pub fn cursor<'a>(&'a mut self) -> Result<Cursor<'a>, HazardError> {
+        let handle = self.try_acquire()?;
+        Ok(Cursor {
+            hazard_epoch: self,
+            handle,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Register current thread and call `f`, then clean up current thread when `f` returns or
+    /// panics: a handle left held by `f` is released, and objects added by current thread are
+    /// reclaimed unless a handle of another thread protects them. Return the result of `f`, or
+    /// error like `try_acquire` if current thread can't register.
+    ///
+    /// A short-lived worker thread which exits in the middle of an access, like by a panic, would
+    /// otherwise hold back reclamation of all threads forever. Thread IDs are never reused, so the
+    /// thread keeps its slot, but objects still protected when it exits are reclaimed by
+    /// `flush_all` or `retire` of other threads later. A handle held on entry is left to its owner.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(not(feature = "single_thread"))]
+    /// # fn main() {
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+    /// use std::thread;
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// let addr_h = &mut *h as *mut HazardEpoch as usize;
+    /// let ret = thread::spawn(move || {
+    ///     let h = unsafe { &mut *(addr_h as *mut HazardEpoch) };
+    ///     h.scope(|h| {
+    ///         let _handle = h.try_acquire().unwrap();
+    ///         panic!("not released");
+    ///     })
+    /// }).join();
+    /// assert!(ret.is_err());
+    /// // The handle of the worker is released, so it doesn't hold back reclamation.
+    /// let node = Box::into_raw(Box::new(BaseHazardNode::default()));
+    /// unsafe { h.try_add_node(node).unwrap() };
+    /// assert_eq!(unsafe { h.flush_all() }, 1);
+    /// # }
+    /// # #[cfg(feature = "single_thread")]
+    /// # fn main() {}
+    /// ```
+    ///
+    pub fn scope<R, F>(&mut self, f: F) -> Result<R, HazardError>
+    where
+        F: FnOnce(&mut HazardEpoch) -> R,
+    {
+        let ts = match unsafe { self.get_thread_store() } {
+            Ok(ts) => ts,
+            Err(e) => {
+                diagnose!(hook: self.diagnostic_hook, "get_thread_store fail, {}", e);
+                return Err(e);
+            }
+        };
+        let _exit = ScopeExit {
+            hazard_epoch: self,
+            ts,
+            held: u64::max_value() != unsafe { (*ts).version() },
+        };
+        Ok(f(self))
+    }
+
+    #[inline]
+    unsafe fn add_handle_count(&self, tid: u16, delta: i64) {
+        self.handle_counts[tid as usize % HANDLE_SHARD_COUNT].fetch_add(delta, Ordering::SeqCst);
+    }
+
+    /// Return true if no handle is held, by reading shards of handle count. A shared object
+    /// unlinked before it returns true can't be reached by any thread, so it may be freed or reused
+    /// right away instead of being added.
+    #[inline]
+    pub fn no_handle_held(&self) -> bool {
+        self.handle_counts
+            .iter()
+            .all(|shard| 0 == shard.load(Ordering::SeqCst))
     }
 
     /// Atomic load count of thread
     #[inline]
     fn atomic_load_thread_count(&self) -> i64 {
-        unsafe { intrinsics::atomic_load(&self.thread_count) }
+        self.thread_count.load(Ordering::SeqCst)
     }
 
     /// After accessing a shared object, call method `release` to trigger reclaiming. Usage is the
-    /// same as `acquire`.
+    /// same as `try_acquire`. While only one thread has ever registered, all objects it added are
+    /// reclaimed right away.
     #[inline]
     pub unsafe fn release(&mut self, handle: u64) {
-        let version_handle = VersionHandle::new(handle);
-        if MAX_THREAD_COUNT > version_handle.tid() as usize {
-            let ts = self.threads
-                .as_mut_ptr()
-                .offset(version_handle.tid() as isize);
-            (*ts).release(&version_handle);
-            if self.thread_waiting_threshold < (*ts).get_hazard_waiting_count() {
+        let ts = self.release_handle(handle);
+        if !ts.is_null() {
+            if self.frame_mode {
+                // Reclaimed by `end_frame` only.
+            } else if cfg!(single_thread) {
+                // No other thread may hold a handle, so reclaim all once current one is released.
+                if 0 < self.atomic_load_hazard_waiting_count() {
+                    self.inner_flush_all(
+                        self.max_reclaim_per_release,
+                        self.max_foreign_reclaim_per_release,
+                    );
+                }
+            } else if 1 == self.atomic_load_thread_count() {
+                // Only current thread has ever registered. Its waiting objects were unlinked before
+                // this check, and a thread registering after it can't reach them, so all are
+                // reclaimed without scanning. Once another thread registers, the full protocol is
+                // used for good. A new version is advanced each time, because retiring a thread
+                // store skips the version it retired by last time.
+                if 0 < (*ts).get_hazard_waiting_count() {
+                    let version = self.advance();
+                    self.retire_thread_store(ts, version, ts, self.max_reclaim_per_release);
+                }
+            } else if self.thread_waiting_threshold < (*ts).get_hazard_waiting_count() {
                 let min_version = self.get_min_version(false);
-                let retire_count = (*ts).retire(min_version, &mut *ts);
-                sync_fetch_and_add(self.hazard_waiting_count.as_mut_ptr(), -retire_count);
+                self.retire_thread_store(ts, min_version, ts, self.max_reclaim_per_release);
             } else if self.atomic_load_thread_count() * self.thread_waiting_threshold
                 < self.atomic_load_hazard_waiting_count()
             {
-                self.retire();
+                self.inner_flush_all(
+                    self.max_reclaim_per_release,
+                    self.max_foreign_reclaim_per_release,
+                );
+            }
+        }
+    }
+
+    /// Same as `release`, but never reclaim, so that a handle acquired through `&self`, like by
+    /// `Debug` of containers, is released without `&mut self`. Objects waiting are reclaimed by
+    /// later `release` or `retire`.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be acquired by current thread and not released before.
+    #[inline]
+    pub unsafe fn release_no_reclaim(&self, handle: u64) {
+        self.release_handle(handle);
+    }
+
+    /// Release `handle` and return the thread store holding it, null if there's none.
+    unsafe fn release_handle(&self, handle: u64) -> *mut ThreadStore {
+        let version_handle = VersionHandle::new(handle);
+        let ts = match self.threads.get(version_handle.tid() as usize) {
+            Some(slot) => slot.load(Ordering::SeqCst),
+            None => ptr::null_mut(),
+        };
+        if !ts.is_null() {
+            let held = u64::max_value() != (*ts).version();
+            if !(*ts).release(&version_handle) {
+                diagnose!(
+                    hook: self.diagnostic_hook,
+                    "invalid handle seq={}, tid={}",
+                    version_handle.seq(),
+                    version_handle.tid()
+                );
+            } else if held {
+                self.add_handle_count(version_handle.tid(), -1);
             }
         }
+        ts
+    }
+
+    /// Turn on or off frame mode, in which `release` never reclaims, and each thread reclaims its
+    /// objects by calling `end_frame` at the end of its frames, like a render or game loop. Off by
+    /// default.
+    #[inline]
+    pub fn set_frame_mode(&mut self, enabled: bool) {
+        self.frame_mode = enabled;
+    }
+
+    /// Limit the number of objects reclaimed by a single `release` to `limit`, None for no
+    /// limit(default). Objects left over are reclaimed by later releases, so the work of a large
+    /// backlog is spread across many operations instead of stalling an unlucky one. `limit` must
+    /// be positive. Explicit `retire`, `flush_*` and `end_frame` are not limited.
+    #[inline]
+    pub fn set_max_reclaim_per_release(&mut self, limit: Option<i64>) {
+        if let Some(limit) = limit {
+            assert!(0 < limit, "limit must be positive");
+        }
+        self.max_reclaim_per_release = limit.unwrap_or(i64::max_value());
+    }
+
+    /// Limit the objects from thread stores of other threads reclaimed by a single `release`, None
+    /// for no limit(default). Once all threads are over the threshold, a releasing thread reclaims
+    /// its own objects and then stores of others until `limit` objects of them are reclaimed, and
+    /// the next releasing thread goes on from the store where it stopped, so no thread pays for the
+    /// garbage of everyone. Stores are not split, so the last one may exceed `limit`. `limit` must
+    /// be positive. Explicit `retire` and `flush_*` are not limited.
+    #[inline]
+    pub fn set_max_foreign_reclaim_per_release(&mut self, limit: Option<i64>) {
+        if let Some(limit) = limit {
+            assert!(0 < limit, "limit must be positive");
+        }
+        self.max_foreign_reclaim_per_release = limit.unwrap_or(i64::max_value());
+    }
+
+    /// Mark the end of current frame of current thread, and reclaim objects it retired during the
+    /// frame before, so objects retired during frame N are reclaimed at the start of frame N+2, at a
+    /// known point in the loop. Objects still held by a handle wait for a later frame. Return the
+    /// number of reclaimed objects.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # // Objects are reclaimed right away in single thread mode.
+    /// # #[cfg(not(feature = "single_thread"))]
+    /// # fn main() {
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// h.set_frame_mode(true);
+    /// for frame in 0..3 {
+    ///     let handle = h.try_acquire().unwrap();
+    ///     let node = Box::into_raw(Box::new(BaseHazardNode::default()));
+    ///     unsafe {
+    ///         h.try_add_node(node).unwrap();
+    ///         h.release(handle);
+    ///     }
+    ///     // The node of frame 0 is reclaimed at the end of frame 1.
+    ///     assert_eq!(unsafe { h.end_frame() }, if 0 == frame { 0 } else { 1 });
+    /// }
+    /// # }
+    /// # #[cfg(feature = "single_thread")]
+    /// # fn main() {}
+    /// ```
+    ///
+    pub unsafe fn end_frame(&mut self) -> i64 {
+        let ts = match self.get_thread_store() {
+            Ok(ts) => ts,
+            Err(e) => {
+                diagnose!(hook: self.diagnostic_hook, "get_thread_store fail, {}", e);
+                return 0;
+            }
+        };
+        let boundary = self.advance();
+        let last = (*ts).swap_frame_boundary(boundary);
+        if 0 == last || 0 == (*ts).get_hazard_waiting_count() {
+            return 0;
+        }
+        let min_version = self.get_min_version(true);
+        self.retire_thread_store(ts, cmp::min(last, min_version), ts, i64::max_value())
+    }
+
+    /// Set the hook receiving diagnostics of current `HazardEpoch`, None to use the process-wide
+    /// one. See module `diagnostics`.
+    #[inline]
+    pub fn set_diagnostic_hook(&mut self, hook: Option<DiagnosticHook>) {
+        self.diagnostic_hook = hook;
+    }
+
+    /// Check internal invariants: the thread list is acyclic and matches the thread count, waiting
+    /// lists are acyclic and match their counters, held handles match their shard counts, and no
+    /// version is newer than the global one.
+    /// Only available with feature `debug-assert-invariants`.
+    ///
+    /// It walks lists without protection, so no other thread may use current `HazardEpoch` at the
+    /// same time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "debug-assert-invariants")]
+    /// # fn main() {
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// let node = Box::into_raw(Box::new(BaseHazardNode::default()));
+    /// unsafe {
+    ///     h.try_add_node(node).unwrap();
+    ///     assert!(h.verify().is_ok());
+    /// }
+    /// # }
+    /// # #[cfg(not(feature = "debug-assert-invariants"))]
+    /// # fn main() {}
+    /// ```
+    ///
+    #[cfg(feature = "debug-assert-invariants")]
+    pub unsafe fn verify(&self) -> Result<(), InvariantError> {
+        let global = self.atomic_load_version();
+        let min_version = self.curr_min_version();
+        if min_version > global {
+            return Err(InvariantError::VersionAhead {
+                what: "cached minimum version",
+                version: min_version,
+                global,
+            });
+        }
+        let head = self.thread_list.load(Ordering::SeqCst);
+        let (thread_count, _) = util::verify_list(head, |ts| (*ts).next(), "thread list")?;
+        let found = self.atomic_load_thread_count();
+        if thread_count != found {
+            return Err(InvariantError::CountMismatch {
+                counter: "thread count",
+                expected: thread_count,
+                found,
+            });
+        }
+        let mut iter = head;
+        while !iter.is_null() {
+            if !(*iter).is_enabled() {
+                return Err(InvariantError::BadNode {
+                    what: "disabled thread store in thread list",
+                });
+            }
+            iter = (*iter).next();
+        }
+        let mut waiting_count = 0;
+        let mut handle_count = 0;
+        for slot in self.threads.iter() {
+            let ts = slot.load(Ordering::SeqCst);
+            if !ts.is_null() {
+                waiting_count += (*ts).verify(global)?;
+                handle_count += (u64::max_value() != (*ts).version()) as i64;
+            }
+        }
+        let found = self.handle_counts
+            .iter()
+            .map(|shard| shard.load(Ordering::SeqCst))
+            .sum();
+        if handle_count != found {
+            return Err(InvariantError::CountMismatch {
+                counter: "held handle count",
+                expected: handle_count,
+                found,
+            });
+        }
+        let found = self.atomic_load_hazard_waiting_count();
+        if waiting_count != found {
+            return Err(InvariantError::CountMismatch {
+                counter: "hazard waiting count",
+                expected: waiting_count,
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// Return current interval(microsecond) to update minimum version cache, which is adapted to
+    /// retire pressure and never exceeds `min_version_cache_time_us` given to the constructor.
+    #[inline]
+    pub fn min_version_cache_time_us(&self) -> i64 {
+        unsafe { self.cache_time_us() }
     }
 
     /// Atomic load count of shared objects waiting to be reclaimed.
+    ///
+    /// The count only triggers reclaiming, and objects are protected by versions, so it's updated
+    /// and loaded relaxed. It's exact once the threads updating it are joined.
     #[inline]
     pub fn atomic_load_hazard_waiting_count(&self) -> i64 {
-        unsafe { intrinsics::atomic_load(self.hazard_waiting_count.as_ptr()) }
+        unsafe { util::atomic_load_relaxed(self.hazard_waiting_count.as_ptr()) }
+    }
+
+    /// Return the handles held by registered threads, oldest version first, so a stuck reader
+    /// blocking reclamation can be identified by another thread, like a monitoring one. It only
+    /// reads, and each entry is a snapshot which may be out of date once returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::hazard_epoch::HazardEpoch;
+    /// use rs_lockfree::util;
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// let handle = h.try_acquire().unwrap();
+    /// let active = h.active_handles();
+    /// assert_eq!(active.len(), 1);
+    /// assert_eq!(active[0].tid as i64, util::get_thread_id());
+    /// unsafe { h.release(handle) };
+    /// assert!(h.active_handles().is_empty());
+    /// ```
+    ///
+    pub fn active_handles(&self) -> Vec<ActiveHandle> {
+        let now = util::get_cur_microseconds_time();
+        let mut ret = Vec::new();
+        let mut iter = self.thread_list.load(Ordering::SeqCst);
+        while !iter.is_null() {
+            unsafe {
+                let version = (*iter).version();
+                let acquired_at = (*iter).acquired_at();
+                // Skip it if released or acquired again meanwhile.
+                if u64::max_value() != version && version == (*iter).version() {
+                    ret.push(ActiveHandle {
+                        tid: (*iter).tid(),
+                        version,
+                        held_us: cmp::max(now - acquired_at, 0),
+                    });
+                }
+                iter = (*iter).next();
+            }
+        }
+        ret.sort_by_key(|h| (h.version, -h.held_us));
+        ret
+    }
+
+    /// Return the histogram of delays(microsecond) from adding shared objects to destroying them,
+    /// over all `HazardEpoch` in current process. Only available with feature `reclaim-delay`.
+    ///
+    /// Its percentiles tell how far reclamation lags behind. A long tail usually means some thread
+    /// holds a handle for long, like a stalled reader, which keeps all later objects waiting.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "reclaim-delay")]
+    /// # fn main() {
+    /// use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+    ///
+    /// let mut h = HazardEpoch::default_new_in_heap();
+    /// let count = HazardEpoch::reclaim_delay().count();
+    /// unsafe {
+    ///     h.try_add_node(Box::into_raw(Box::new(BaseHazardNode::default())))
+    ///         .unwrap();
+    ///     h.flush_all();
+    /// }
+    /// let delays = HazardEpoch::reclaim_delay();
+    /// assert!(delays.count() > count);
+    /// assert!(delays.min() <= delays.percentile(99.0));
+    /// assert!(delays.percentile(99.0) <= delays.max());
+    /// # }
+    /// # #[cfg(not(feature = "reclaim-delay"))]
+    /// # fn main() {}
+    /// ```
+    ///
+    #[cfg(feature = "reclaim-delay")]
+    pub fn reclaim_delay() -> &'static util::Histogram {
+        stats::reclaim_delay()
     }
 
     #[inline]
-    unsafe fn get_thread_store(&mut self, ts: &mut *mut ThreadStore) -> error::Status {
-        let mut ret = error::Status::Success;
-        let tn = util::get_thread_id() as u16;
-        if MAX_THREAD_COUNT <= tn as usize {
-            warn!("thread number overflow, tn={}", tn);
-            ret = error::Status::ThreadNumOverflow;
+    unsafe fn get_thread_store(&self) -> Result<*mut ThreadStore, HazardError> {
+        let tid = util::get_thread_id();
+        if MAX_THREAD_COUNT <= tid as usize {
+            diagnose!(hook: self.diagnostic_hook, "thread number overflow, tid={}", tid);
+            Err(HazardError::ThreadNumOverflow {
+                tid,
+                max_thread_count: MAX_THREAD_COUNT,
+            })
         } else {
-            *ts = self.threads.as_mut_ptr().offset(tn as isize);
-            let ts_obj = &mut **ts;
-            // different thread use different thread store.
-            if !ts_obj.is_enabled() {
+            let tn = tid as u16;
+            let mut ts = self.threads[tn as usize].load(Ordering::SeqCst);
+            // different thread use different thread store, which is allocated when it registers.
+            if ts.is_null() {
                 // CAS can be used directly here, no ABA problem.
                 // Atomicity of thread_count is not necessary.
+                ts = Box::into_raw(Box::new(ThreadStore::default()));
+                (*ts).set_enabled(tn);
 
-                self.thread_lock.lock();
+                self.thread_lock.raw_lock();
 
-                ts_obj.set_enabled(tn);
-                ts_obj.set_next(self.atomic_load_thread_list());
-                intrinsics::atomic_store(
-                    &mut self.thread_list as *mut _ as *mut usize,
-                    *ts as usize,
-                );
-                sync_fetch_and_add(&mut self.thread_count, 1);
+                (*ts).set_next(self.thread_list.load(Ordering::SeqCst));
+                self.thread_list.store(ts, Ordering::SeqCst);
+                self.threads[tn as usize].store(ts, Ordering::SeqCst);
+                self.thread_count.fetch_add(1, Ordering::SeqCst);
+                stats::gauge_add(stats::HAZARD_THREAD_COUNT, 1);
 
-                self.thread_lock.unlock();
+                self.thread_lock.raw_unlock();
             }
+            Ok(ts)
         }
-        ret
-    }
-
-    #[inline]
-    unsafe fn atomic_load_thread_list(&self) -> *mut ThreadStore {
-        util::atomic_load_raw_ptr(&self.thread_list)
     }
 
     unsafe fn get_min_version(&mut self, force_flush: bool) -> u64 {
@@ -379,12 +1329,17 @@ impl HazardEpoch {
             ret = self.curr_min_version();
             ret
         }
-            && self.curr_min_version_timestamp() + self.min_version_cache_time_us
+            && self.curr_min_version_timestamp() + self.cache_time_us()
                 > util::get_cur_microseconds_time()
         {
         } else {
+            self.adapt_cache_time();
             ret = self.atomic_load_version();
-            let mut iter = self.atomic_load_thread_list();
+            let mut iter = if self.no_handle_held() {
+                ptr::null_mut()
+            } else {
+                self.thread_list.load(Ordering::SeqCst)
+            };
             while !iter.is_null() {
                 let ts_min_version = (*iter).version();
                 if ret > ts_min_version {
@@ -399,6 +1354,118 @@ impl HazardEpoch {
     }
 }
 
+/// Clean up current thread at the exit of `HazardEpoch::scope`.
+struct ScopeExit {
+    hazard_epoch: *mut HazardEpoch,
+    ts: *mut ThreadStore,
+    /// Whether a handle was held on entry.
+    held: bool,
+}
+
+impl Drop for ScopeExit {
+    fn drop(&mut self) {
+        unsafe {
+            let hazard_epoch = &mut *self.hazard_epoch;
+            let tid = util::get_thread_id() as u16;
+            if !self.held && (*self.ts).release_held() {
+                if !thread::panicking() {
+                    diagnose!(hook: hazard_epoch.diagnostic_hook, "handle left held, tid={}", tid);
+                }
+                hazard_epoch.add_handle_count(tid, -1);
+            }
+            hazard_epoch.flush_thread(tid);
+        }
+    }
+}
+
+/// Traversal of linked shared objects under a handle of `HazardEpoch`, returned by
+/// `HazardEpoch::cursor`, and the handle is released when dropped.
+///
+/// Shared objects are tagged with the version when added, so the handle protects every object
+/// added after it's acquired, instead of one object per hazard pointer. An object loaded from a
+/// link through the cursor was reachable after acquiring, so it's only added later and stays valid
+/// until the cursor is dropped. Hops therefore need no revalidation, and references returned by
+/// the cursor live as long as the cursor.
+pub struct Cursor<'a> {
+    hazard_epoch: *mut HazardEpoch,
+    handle: u64,
+    _marker: PhantomData<&'a mut HazardEpoch>,
+}
+
+impl<'a> Cursor<'a> {
+    /// Return the handle held by current cursor.
+    #[inline]
+    pub fn handle(&self) -> u64 {
+        self.handle
+    }
+
+    /// Load the object `src` links to, None if null.
+    #[inline]
+    pub fn protect<'c, T: 'c>(&'c self, src: &util::AtomicRawPtr<T>) -> Option<&'c T> {
+        unsafe { src.load(Ordering::SeqCst).as_ref() }
+    }
+
+    /// Walk from link `head` through links returned by `next`, until the first object `pred`
+    /// returns true for. Return the link to it and the object, or the last link and None if no
+    /// object matches, so that the caller can insert or unlink at that position by CAS on the link.
+    pub fn find<'c, T, N, P>(
+        &'c self,
+        head: &'c util::AtomicRawPtr<T>,
+        next: N,
+        mut pred: P,
+    ) -> (&'c util::AtomicRawPtr<T>, Option<&'c T>)
+    where
+        N: Fn(&'c T) -> &'c util::AtomicRawPtr<T>,
+        P: FnMut(&'c T) -> bool,
+    {
+        let mut link = head;
+        while let Some(node) = self.protect(link) {
+            if pred(node) {
+                return (link, Some(node));
+            }
+            link = next(node);
+        }
+        (link, None)
+    }
+
+    /// Add `node` unlinked by current thread to `HazardEpoch` like `HazardEpoch::try_add_node`.
+    /// It stays readable through current cursor until dropped.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be allocated by `Box`, unlinked, and not added before.
+    #[inline]
+    pub unsafe fn retire<T: HazardNodeT>(&self, node: *mut T) -> Result<(), HazardError> {
+        (*self.hazard_epoch).try_add_node(node)
+    }
+}
+
+impl<'a> Drop for Cursor<'a> {
+    fn drop(&mut self) {
+        unsafe { (*self.hazard_epoch).release(self.handle) }
+    }
+}
+
+/// Future returned by `HazardEpoch::wait_until_reclaimed_async`.
+pub struct ReclaimFuture<'a> {
+    epoch: &'a mut HazardEpoch,
+    version: u64,
+}
+
+impl<'a> Future for ReclaimFuture<'a> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        if unsafe { this.epoch.try_wait_reclaimed(this.version) } {
+            Poll::Ready(())
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
 impl Drop for HazardEpoch {
     fn drop(&mut self) {
         unsafe {
@@ -1,10 +1,18 @@
 use std::ptr;
 use util;
-use error;
+use error::HazardError;
+#[cfg(feature = "debug-assert-invariants")]
+use error::InvariantError;
 use std;
+use std::cmp;
 use std::intrinsics;
-use std::{mem, raw};
+use std::sync::atomic::Ordering;
+use std::mem;
 use util::WrappedAlign64Type;
+#[cfg(feature = "debug-poison")]
+use quarantine;
+#[cfg(feature = "reclaim-delay")]
+use stats;
 use util::sync_fetch_and_add;
 
 struct SeqVersion {
@@ -74,7 +82,7 @@ impl VersionHandle {
     }
 
     #[inline]
-    fn seq(&self) -> u32 {
+    pub fn seq(&self) -> u32 {
         unsafe { self.data.tid_seq.seq }
     }
 
@@ -122,17 +130,29 @@ pub trait HazardNodeT: Drop {
 /// [`HazardNodeT`]: trait.HazardNodeT.html
 ///
 pub struct BaseHazardNode {
-    trait_obj: raw::TraitObject,
+    trait_obj: Option<*mut (HazardNodeT + 'static)>,
     next: *mut BaseHazardNode,
     version: u64,
+    /// Last node of the run with the same version starting from current one, valid if current
+    /// node is the head of a run in a waiting list.
+    run_last: *mut BaseHazardNode,
+    /// Number of nodes in the run starting from current one.
+    run_len: i64,
+    /// Time when current node is added to `HazardEpoch`.
+    #[cfg(feature = "reclaim-delay")]
+    added_at: i64,
 }
 
 impl Default for BaseHazardNode {
     fn default() -> Self {
         BaseHazardNode {
-            trait_obj: unsafe { mem::zeroed() },
+            trait_obj: None,
             next: ptr::null_mut(),
             version: std::u64::MAX,
+            run_last: ptr::null_mut(),
+            run_len: 0,
+            #[cfg(feature = "reclaim-delay")]
+            added_at: 0,
         }
     }
 }
@@ -170,22 +190,37 @@ impl BaseHazardNode {
     }
 
     #[inline]
-    fn set_tait_obj(&mut self, trait_obj: raw::TraitObject) {
-        self.trait_obj = trait_obj;
+    fn set_trait_obj(&mut self, trait_obj: *mut (HazardNodeT + 'static)) {
+        self.trait_obj = Some(trait_obj);
     }
 
     #[inline]
-    fn trait_obj(&self) -> raw::TraitObject {
-        self.trait_obj
+    fn trait_obj(&self) -> *mut (HazardNodeT + 'static) {
+        self.trait_obj.expect("node is not added to HazardEpoch")
     }
 }
 
+/// Waiting list of a thread is made of runs, each of which holds nodes with the same version and
+/// is reclaimed or kept as a whole, so reclaiming skips a kept run in O(1) and only walks freed
+/// nodes. Only the owner thread pushes to its list, and it remembers the run it pushed last, so
+/// that a new node extends the run without reading nodes which may be taken by other threads.
 pub struct ThreadStore {
     enabled: bool,
     tid: u16,
     last_retire_version: u64,
+    unadvanced_count: u32,
+    /// Global version at the last end of frame of current thread, 0 before the first one.
+    frame_boundary: u64,
+    /// Node pushed last by `add_node`, null if the list is changed by moving nodes in.
+    last_added: *mut BaseHazardNode,
+    /// Run of `last_added`.
+    last_run_last: *mut BaseHazardNode,
+    last_run_len: i64,
+    last_run_version: u64,
     curr_seq_version: WrappedAlign64Type<SeqVersion>,
-    hazard_waiting_list: WrappedAlign64Type<*mut BaseHazardNode>,
+    /// Time(microsecond) current thread acquired its handle last, for `HazardEpoch::active_handles`.
+    acquired_at: util::AtomicI64,
+    hazard_waiting_list: WrappedAlign64Type<util::AtomicRawPtr<BaseHazardNode>>,
     hazard_waiting_count: WrappedAlign64Type<i64>,
     next: WrappedAlign64Type<*mut ThreadStore>,
 }
@@ -202,8 +237,15 @@ impl ThreadStore {
             enabled: false,
             tid: 0,
             last_retire_version: 0,
+            unadvanced_count: 0,
+            frame_boundary: 0,
+            last_added: ptr::null_mut(),
+            last_run_last: ptr::null_mut(),
+            last_run_len: 0,
+            last_run_version: 0,
             curr_seq_version: Default::default(),
-            hazard_waiting_list: WrappedAlign64Type(ptr::null_mut()),
+            acquired_at: util::AtomicI64::new(0),
+            hazard_waiting_list: Default::default(),
             hazard_waiting_count: Default::default(),
             next: WrappedAlign64Type(ptr::null_mut()),
         }
@@ -221,10 +263,16 @@ impl ThreadStore {
     }
 
     #[inline]
-    fn tid(&self) -> u16 {
+    pub fn tid(&self) -> u16 {
         self.tid
     }
 
+    /// Return the time(microsecond) current thread acquired its handle last.
+    #[inline]
+    pub fn acquired_at(&self) -> i64 {
+        self.acquired_at.load(Ordering::SeqCst)
+    }
+
     #[inline]
     pub fn set_next(&mut self, next: *mut ThreadStore) {
         self.next = WrappedAlign64Type(next);
@@ -247,60 +295,100 @@ impl ThreadStore {
 
     #[inline]
     fn curr_version(&self) -> u64 {
-        self.curr_seq_version.version
+        // Read by other threads to compute the minimum version.
+        unsafe { intrinsics::atomic_load(&self.curr_seq_version.version) }
     }
 
     #[inline]
     fn set_curr_version(&mut self, version: u64) {
-        self.curr_seq_version.version = version;
+        unsafe { intrinsics::atomic_store(&mut self.curr_seq_version.version, version) }
     }
 
     #[inline]
-    pub fn acquire(&mut self, version: u64, handle: &mut VersionHandle) -> error::Status {
+    pub fn acquire(&mut self, version: u64, handle: &mut VersionHandle) -> Result<(), HazardError> {
         assert_eq!(self.tid(), util::get_thread_id() as u16);
-        let mut ret = error::Status::Success;
         if std::u64::MAX != self.curr_version() {
-            warn!(
-                "current thread has already assigned a version handle, seq={}",
-                self.curr_seq()
-            );
-            ret = error::Status::Busy;
+            Err(HazardError::Busy {
+                tid: util::get_thread_id(),
+            })
         } else {
+            // Stored before the version, so whoever sees the version sees the time too.
+            self.acquired_at
+                .store(util::get_cur_microseconds_time(), Ordering::SeqCst);
             self.set_curr_version(version);
             handle.set_tid(self.tid());
             handle.set_high_bits(0);
             handle.set_seq(self.curr_seq());
+            Ok(())
         }
-        ret
     }
 
-    pub fn release(&mut self, handle: &VersionHandle) {
+    /// Return false if `handle` is invalid.
+    pub fn release(&mut self, handle: &VersionHandle) -> bool {
         assert_eq!(self.tid(), util::get_thread_id() as u16);
         if self.tid() != handle.tid() && self.curr_seq() != handle.seq() {
-            warn!("invalid handle seq={}, tid={}", handle.seq(), handle.tid());
+            false
+        } else {
+            self.set_curr_version(std::u64::MAX);
+            self.inc_curr_seq();
+            true
+        }
+    }
+
+    /// Release the handle held by current thread whatever its sequence is. Return false if none is
+    /// held.
+    pub fn release_held(&mut self) -> bool {
+        assert_eq!(self.tid(), util::get_thread_id() as u16);
+        if std::u64::MAX == self.curr_version() {
+            false
         } else {
             self.set_curr_version(std::u64::MAX);
             self.inc_curr_seq();
+            true
         }
     }
 
-    pub unsafe fn add_node<T>(&mut self, version: u64, node: *mut T) -> error::Status
+    pub unsafe fn add_node<T>(&mut self, version: u64, node: *mut T)
     where
         T: HazardNodeT,
     {
         assert_eq!(self.tid(), util::get_thread_id() as u16);
-        let ret = error::Status::Success;
         let base = (*node).get_base_hazard_node();
 
-        (*base).set_tait_obj(mem::transmute::<_, raw::TraitObject>(
-            &mut *node as &mut HazardNodeT,
+        // Only the lifetime is erased, the fat pointer keeps provenance of `node`.
+        (*base).set_trait_obj(mem::transmute::<_, *mut (HazardNodeT + 'static)>(
+            node as *mut HazardNodeT,
         ));
 
         (*base).set_version(version);
+        #[cfg(feature = "reclaim-delay")]
+        {
+            (*base).added_at = stats::reclaim_delay_start();
+        }
 
-        self.inner_add_nodes(base, base, 1);
-
-        ret
+        let mut curr = self.hazard_waiting_list.load(Ordering::SeqCst);
+        loop {
+            // Head is still `last_added` only if no other thread has taken the list since, so the
+            // remembered run is intact.
+            if !curr.is_null() && curr == self.last_added && version == self.last_run_version {
+                (*base).run_last = self.last_run_last;
+                (*base).run_len = self.last_run_len + 1;
+            } else {
+                (*base).run_last = base;
+                (*base).run_len = 1;
+            }
+            (*base).set_next(curr);
+            let (tmp, ok) = self.hazard_waiting_list.cas(curr, base, Ordering::SeqCst);
+            if ok {
+                break;
+            }
+            curr = tmp;
+        }
+        self.last_added = base;
+        self.last_run_last = (*base).run_last;
+        self.last_run_len = (*base).run_len;
+        self.last_run_version = version;
+        sync_fetch_and_add(self.hazard_waiting_count.as_mut_ptr(), 1);
     }
 
     #[inline]
@@ -308,12 +396,33 @@ impl ThreadStore {
         unsafe { intrinsics::atomic_load(self.hazard_waiting_count.as_ptr()) }
     }
 
+    /// Count a node added since the global version was last advanced by current thread, and
+    /// return the number of such nodes.
+    #[inline]
+    pub fn inc_unadvanced_count(&mut self) -> u32 {
+        self.unadvanced_count += 1;
+        self.unadvanced_count
+    }
+
+    #[inline]
+    pub fn reset_unadvanced_count(&mut self) {
+        self.unadvanced_count = 0;
+    }
+
+    /// Record `boundary` as the end of current frame, and return the one of last frame.
     #[inline]
-    unsafe fn atomic_load_hazard_waiting_list(&self) -> *mut BaseHazardNode {
-        util::atomic_load_raw_ptr(self.hazard_waiting_list.as_ptr())
+    pub fn swap_frame_boundary(&mut self, boundary: u64) -> u64 {
+        mem::replace(&mut self.frame_boundary, boundary)
     }
 
-    pub unsafe fn retire(&mut self, version: u64, node_receiver: &mut ThreadStore) -> i64 {
+    /// Reclaim at most `limit` nodes tagged older than `version`, and move the others to
+    /// `node_receiver`. Return the number of reclaimed nodes.
+    pub unsafe fn retire(
+        &mut self,
+        version: u64,
+        node_receiver: &mut ThreadStore,
+        limit: i64,
+    ) -> i64 {
         assert!(
             self as *const _ != node_receiver as *const _
                 || self.tid() == util::get_thread_id() as u16
@@ -321,39 +430,55 @@ impl ThreadStore {
         if self.last_retire_version == version {
             return 0;
         }
-        self.last_retire_version = version;
-        let mut curr = self.atomic_load_hazard_waiting_list();
-        let mut old = curr;
-        while !{
-            let (tmp, ok) = self.atomic_cxchg_hazard_waiting_list(old, ptr::null_mut());
-            curr = tmp;
-            ok
-        } {
-            old = curr;
-        }
+        let curr = self.hazard_waiting_list.swap(ptr::null_mut(), Ordering::SeqCst);
+        yield_point!(RETIRE_SWAP);
         let mut list_retire = ptr::null_mut();
         let mut move_count = 0i64;
         let mut retire_count = 0i64;
-        let mut pseudo_head = BaseHazardNode::default();
-        pseudo_head.set_next(curr);
-        let mut iter = &mut pseudo_head as *mut BaseHazardNode;
-        while !(*iter).next().is_null() {
-            if (*(*iter).next()).version() <= version {
-                retire_count += 1;
-                let tmp = (*iter).next();
-                (*iter).set_next((*(*iter).next()).next());
-
-                (*tmp).set_next(list_retire);
-                list_retire = tmp;
-            } else {
-                move_count += 1;
-                iter = (*iter).next();
+        let mut move_list_head = ptr::null_mut();
+        let mut move_list_tail: *mut BaseHazardNode = ptr::null_mut();
+        let mut truncated = false;
+        let mut run = curr;
+        while !run.is_null() {
+            let run_last = (*run).run_last;
+            let run_len = (*run).run_len;
+            let next_run = (*run_last).next();
+            let mut kept = run;
+            let mut kept_len = run_len;
+            if (*run).version() < version {
+                let n = cmp::min(run_len, limit - retire_count);
+                if 0 < n {
+                    // Reclaim the first `n` nodes, and keep the rest as a run of its own.
+                    let mut last = run;
+                    for _ in 1..n {
+                        last = (*last).next();
+                    }
+                    kept = (*last).next();
+                    kept_len = run_len - n;
+                    if 0 < kept_len {
+                        (*kept).run_last = run_last;
+                        (*kept).run_len = kept_len;
+                    }
+                    retire_count += n;
+                    (*last).next = list_retire;
+                    list_retire = run;
+                }
+                truncated |= 0 < kept_len;
+            }
+            if 0 < kept_len {
+                move_count += kept_len;
+                if move_list_tail.is_null() {
+                    move_list_head = kept;
+                } else {
+                    (*move_list_tail).set_next(kept);
+                }
+                move_list_tail = run_last;
             }
+            run = next_run;
         }
-        let mut move_list_tail = ptr::null_mut();
-        let move_list_head = pseudo_head.next();
-        if !move_list_head.is_null() {
-            move_list_tail = iter;
+        // Nodes left by `limit` are reclaimed by a later call with the same version.
+        if !truncated {
+            self.last_retire_version = version;
         }
         node_receiver.inner_add_nodes(move_list_head, move_list_tail, move_count);
         sync_fetch_and_add(
@@ -369,9 +494,12 @@ impl ThreadStore {
     }
 
     unsafe fn retire_hazard_node(node_retire: *mut BaseHazardNode) {
-        let trait_obj = (*node_retire).trait_obj();
-        let obj = mem::transmute::<raw::TraitObject, &mut HazardNodeT>(trait_obj);
-        Box::from_raw(obj as *mut HazardNodeT);
+        #[cfg(feature = "reclaim-delay")]
+        stats::reclaim_delay_record((*node_retire).added_at);
+        #[cfg(feature = "debug-poison")]
+        quarantine::retire((*node_retire).trait_obj());
+        #[cfg(not(feature = "debug-poison"))]
+        drop(Box::from_raw((*node_retire).trait_obj()));
     }
 
     #[inline]
@@ -379,14 +507,6 @@ impl ThreadStore {
         self.curr_version()
     }
 
-    unsafe fn atomic_cxchg_hazard_waiting_list(
-        &mut self,
-        old: *mut BaseHazardNode,
-        src: *mut BaseHazardNode,
-    ) -> (*mut BaseHazardNode, bool) {
-        util::atomic_cxchg_raw_ptr(self.hazard_waiting_list.as_mut_ptr(), old, src)
-    }
-
     unsafe fn inner_add_nodes(
         &mut self,
         head: *mut BaseHazardNode,
@@ -395,11 +515,12 @@ impl ThreadStore {
     ) {
         assert_eq!(self.tid(), util::get_thread_id() as u16);
         if 0 < count {
-            let mut curr = self.atomic_load_hazard_waiting_list();
+            self.last_added = ptr::null_mut();
+            let mut curr = self.hazard_waiting_list.load(Ordering::SeqCst);
             let mut old = curr;
             (*tail).set_next(curr);
             while !{
-                let (tmp, ok) = self.atomic_cxchg_hazard_waiting_list(old, head);
+                let (tmp, ok) = self.hazard_waiting_list.cas(old, head, Ordering::SeqCst);
                 curr = tmp;
                 ok
             } {
@@ -410,10 +531,73 @@ impl ThreadStore {
         }
     }
 
+    /// Check the waiting list against `hazard_waiting_count` and global version `global`, and
+    /// return the number of waiting nodes.
+    #[cfg(feature = "debug-assert-invariants")]
+    pub unsafe fn verify(&self, global: u64) -> Result<i64, InvariantError> {
+        let version = self.curr_version();
+        if std::u64::MAX != version && version > global {
+            return Err(InvariantError::VersionAhead {
+                what: "handle of thread store",
+                version,
+                global,
+            });
+        }
+        let head = self.hazard_waiting_list.load(Ordering::SeqCst);
+        let (count, _) = util::verify_list(head, |n| (*n).next(), "hazard waiting list")?;
+        let mut iter = head;
+        while !iter.is_null() {
+            if (*iter).version() > global {
+                return Err(InvariantError::VersionAhead {
+                    what: "waiting node",
+                    version: (*iter).version(),
+                    global,
+                });
+            }
+            if (*iter).trait_obj.is_none() {
+                return Err(InvariantError::BadNode {
+                    what: "waiting node without owner",
+                });
+            }
+            iter = (*iter).next();
+        }
+        let mut run = head;
+        while !run.is_null() {
+            let mut len = 1;
+            let mut iter = run;
+            while iter != (*run).run_last {
+                iter = (*iter).next();
+                if iter.is_null() || (*iter).version() != (*run).version() {
+                    return Err(InvariantError::BadNode {
+                        what: "run of waiting nodes with different versions",
+                    });
+                }
+                len += 1;
+            }
+            if len != (*run).run_len {
+                return Err(InvariantError::CountMismatch {
+                    counter: "length of waiting run",
+                    expected: len,
+                    found: (*run).run_len,
+                });
+            }
+            run = (*iter).next();
+        }
+        let found = self.get_hazard_waiting_count();
+        if count != found {
+            return Err(InvariantError::CountMismatch {
+                counter: "waiting count of thread store",
+                expected: count,
+                found,
+            });
+        }
+        Ok(count)
+    }
+
     unsafe fn destroy(&mut self) {
-        while !self.hazard_waiting_list.is_null() {
-            let node_retire = *self.hazard_waiting_list;
-            self.hazard_waiting_list = WrappedAlign64Type((*node_retire).next());
+        while !self.hazard_waiting_list.load(Ordering::SeqCst).is_null() {
+            let node_retire = self.hazard_waiting_list.load(Ordering::SeqCst);
+            self.hazard_waiting_list.store((*node_retire).next(), Ordering::SeqCst);
             Self::retire_hazard_node(node_retire);
         }
     }
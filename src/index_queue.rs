@@ -0,0 +1,302 @@
+//! Definition and implementations of `IndexQueue`
+//!
+use error::{PushError, QueueError};
+use traits::ConcurrentQueue;
+use util;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Index of no slot.
+const NIL: u32 = u32::max_value();
+
+/// Link to slot `index`, packed with a count which is increased on every update of the link.
+#[inline]
+fn pack(index: u32, count: u32) -> u64 {
+    u64::from(count) << 32 | u64::from(index)
+}
+
+#[inline]
+fn index(link: u64) -> u32 {
+    link as u32
+}
+
+#[inline]
+fn count(link: u64) -> u32 {
+    (link >> 32) as u32
+}
+
+struct Slot<T> {
+    /// Next slot in the queue or the free list.
+    next: util::AtomicU64,
+    /// Parties which haven't done with current slot, the dequeuer reading its value and the one
+    /// unlinking it. The last one frees it.
+    refs: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Bounded LockFree queue whose nodes are slots of a slab allocated at construction, linked by
+/// 32-bit indices instead of pointers.
+///
+/// It's the queue of Michael & Scott with counted links: each link packs a slot index with a
+/// count increased on every update, so a CAS on a link which has been recycled in between fails,
+/// which defeats the `ABA problem` without `HazardEpoch`. Free slots form a stack linked the same
+/// way. Slots refer to each other by index, so the slab doesn't depend on its address, and a link
+/// takes 8 bytes even on 32-bit targets.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::index_queue::IndexQueue;
+///
+/// let queue = IndexQueue::with_capacity(2);
+/// assert!(queue.try_push(1).is_ok());
+/// assert!(queue.try_push(2).is_ok());
+/// assert_eq!(queue.try_push(3).unwrap_err().value, 3);
+/// assert_eq!(queue.pop(), Some(1));
+/// assert!(queue.try_push(3).is_ok());
+/// assert_eq!(queue.pop(), Some(2));
+/// assert_eq!(queue.pop(), Some(3));
+/// assert_eq!(queue.pop(), None);
+/// ```
+///
+pub struct IndexQueue<T> {
+    head: util::WrappedAlign64Type<util::AtomicU64>,
+    tail: util::WrappedAlign64Type<util::AtomicU64>,
+    free: util::WrappedAlign64Type<util::AtomicU64>,
+    slots: Box<[Slot<T>]>,
+}
+
+unsafe impl<T: Send> Send for IndexQueue<T> {}
+
+unsafe impl<T: Send> Sync for IndexQueue<T> {}
+
+impl<T> IndexQueue<T> {
+    /// Return empty IndexQueue holding at most `capacity` elements, which must be positive and
+    /// less than `u32::max_value()`. One more slot is allocated as the dummy head.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(
+            0 < capacity && capacity < NIL as usize,
+            "capacity must be in [1, {})",
+            NIL
+        );
+        let slots: Vec<Slot<T>> = (0..capacity + 1)
+            .map(|i| Slot {
+                // Slot 0 is the dummy head, and the others are free.
+                next: util::AtomicU64::new(if 0 == i || capacity == i {
+                    pack(NIL, 0)
+                } else {
+                    pack(i as u32 + 1, 0)
+                }),
+                refs: AtomicU8::new(1),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        IndexQueue {
+            head: util::WrappedAlign64Type(util::AtomicU64::new(pack(0, 0))),
+            tail: util::WrappedAlign64Type(util::AtomicU64::new(pack(0, 0))),
+            free: util::WrappedAlign64Type(util::AtomicU64::new(pack(1, 0))),
+            slots: slots.into_boxed_slice(),
+        }
+    }
+
+    /// Return the maximum number of elements.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    #[inline]
+    fn slot(&self, i: u32) -> &Slot<T> {
+        &self.slots[i as usize]
+    }
+
+    /// Take a slot from the free list, None if there is none.
+    fn alloc(&self) -> Option<u32> {
+        let mut backoff = util::Backoff::new();
+        let mut free = self.free.load(Ordering::SeqCst);
+        loop {
+            let i = index(free);
+            if NIL == i {
+                return None;
+            }
+            // Slot `i` may have been taken already, then the count makes the CAS fail.
+            let next = self.slot(i).next.load(Ordering::SeqCst);
+            let (cur, ok) = self.free.cas(
+                free,
+                pack(index(next), count(free).wrapping_add(1)),
+                Ordering::SeqCst,
+            );
+            if ok {
+                return Some(i);
+            }
+            free = cur;
+            backoff.spin();
+        }
+    }
+
+    /// Put slot `i` back to the free list.
+    fn dealloc(&self, i: u32) {
+        let slot = self.slot(i);
+        let mut backoff = util::Backoff::new();
+        let mut free = self.free.load(Ordering::SeqCst);
+        loop {
+            let old = slot.next.load(Ordering::SeqCst);
+            slot.next
+                .store(pack(index(free), count(old).wrapping_add(1)), Ordering::SeqCst);
+            let (cur, ok) = self.free
+                .cas(free, pack(i, count(free).wrapping_add(1)), Ordering::SeqCst);
+            if ok {
+                return;
+            }
+            free = cur;
+            backoff.spin();
+        }
+    }
+
+    /// Drop a reference of slot `i`, and free it if it's the last one.
+    #[inline]
+    fn unref(&self, i: u32) {
+        if 1 == self.slot(i).refs.fetch_sub(1, Ordering::SeqCst) {
+            self.dealloc(i);
+        }
+    }
+
+    /// Push an element to the end of current queue. Return `QueueError::Full` with the element if
+    /// all slots are in use.
+    pub fn try_push(&self, v: T) -> Result<(), PushError<T>> {
+        let i = match self.alloc() {
+            Some(i) => i,
+            None => {
+                return Err(PushError {
+                    error: QueueError::Full { op: "try_push" },
+                    value: v,
+                })
+            }
+        };
+        let slot = self.slot(i);
+        unsafe { ptr::write((*slot.value.get()).as_mut_ptr(), v) };
+        slot.refs.store(2, Ordering::SeqCst);
+        let old = slot.next.load(Ordering::SeqCst);
+        slot.next
+            .store(pack(NIL, count(old).wrapping_add(1)), Ordering::SeqCst);
+
+        let mut backoff = util::Backoff::new();
+        loop {
+            let tail = self.tail.load(Ordering::SeqCst);
+            let next = self.slot(index(tail)).next.load(Ordering::SeqCst);
+            if tail == self.tail.load(Ordering::SeqCst) {
+                if NIL == index(next) {
+                    let (_, ok) = self.slot(index(tail)).next.cas(
+                        next,
+                        pack(i, count(next).wrapping_add(1)),
+                        Ordering::SeqCst,
+                    );
+                    if ok {
+                        self.tail
+                            .cas(tail, pack(i, count(tail).wrapping_add(1)), Ordering::SeqCst);
+                        return Ok(());
+                    }
+                } else {
+                    // Help the push which has linked but not moved the tail yet.
+                    self.tail.cas(
+                        tail,
+                        pack(index(next), count(tail).wrapping_add(1)),
+                        Ordering::SeqCst,
+                    );
+                }
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Pop the element at the head of current queue.
+    pub fn pop(&self) -> Option<T> {
+        let mut backoff = util::Backoff::new();
+        loop {
+            let head = self.head.load(Ordering::SeqCst);
+            let tail = self.tail.load(Ordering::SeqCst);
+            let next = self.slot(index(head)).next.load(Ordering::SeqCst);
+            if head == self.head.load(Ordering::SeqCst) {
+                if index(head) == index(tail) {
+                    if NIL == index(next) {
+                        return None;
+                    }
+                    self.tail.cas(
+                        tail,
+                        pack(index(next), count(tail).wrapping_add(1)),
+                        Ordering::SeqCst,
+                    );
+                } else {
+                    let n = index(next);
+                    let (_, ok) = self.head.cas(
+                        head,
+                        pack(n, count(head).wrapping_add(1)),
+                        Ordering::SeqCst,
+                    );
+                    if ok {
+                        // Slot `n` becomes the dummy head, and is kept until both its value is
+                        // read and it's unlinked.
+                        let v = unsafe { ptr::read((*self.slot(n).value.get()).as_ptr()) };
+                        self.unref(n);
+                        self.unref(index(head));
+                        return Some(v);
+                    }
+                }
+            }
+            backoff.spin();
+        }
+    }
+}
+
+impl<T> ConcurrentQueue<T> for IndexQueue<T> {
+    fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        IndexQueue::try_push(self, v)
+    }
+
+    fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
+        Ok(self.pop())
+    }
+}
+
+impl<T> Drop for IndexQueue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use index_queue::IndexQueue;
+        let queue = IndexQueue::with_capacity(4);
+        assert_eq!(queue.capacity(), 4);
+        assert_eq!(queue.pop(), None);
+        for lap in 0..3 {
+            for i in 0..4 {
+                assert!(queue.try_push(lap * 4 + i).is_ok());
+            }
+            assert_eq!(queue.try_push(100).unwrap_err().value, 100);
+            for i in 0..4 {
+                assert_eq!(queue.pop(), Some(lap * 4 + i));
+            }
+            assert_eq!(queue.pop(), None);
+        }
+    }
+
+    #[test]
+    fn test_drop() {
+        use index_queue::IndexQueue;
+        use std::rc::Rc;
+        let v = Rc::new(0);
+        {
+            let queue = IndexQueue::with_capacity(4);
+            queue.try_push(v.clone()).ok().unwrap();
+            queue.try_push(v.clone()).ok().unwrap();
+            assert_eq!(queue.pop().map(|v| Rc::strong_count(&v)), Some(3));
+            assert_eq!(Rc::strong_count(&v), 2);
+        }
+        assert_eq!(Rc::strong_count(&v), 1);
+    }
+}
@@ -0,0 +1,296 @@
+//! Definition and implementations of `IntrusiveQueue`
+//!
+use error::{PushError, QueueError};
+use util;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Link embedded in a node of `IntrusiveQueue`.
+pub struct Link<T> {
+    next: util::AtomicRawPtr<Link<T>>,
+    owner: util::AtomicRawPtr<T>,
+    linked: AtomicBool,
+}
+
+unsafe impl<T> Send for Link<T> {}
+
+unsafe impl<T> Sync for Link<T> {}
+
+impl<T> Default for Link<T> {
+    fn default() -> Self {
+        Link::new()
+    }
+}
+
+impl<T> Link<T> {
+    /// Return unlinked Link.
+    pub const fn new() -> Self {
+        Link {
+            next: util::AtomicRawPtr::new(ptr::null_mut()),
+            owner: util::AtomicRawPtr::new(ptr::null_mut()),
+            linked: AtomicBool::new(false),
+        }
+    }
+
+    /// Return true if the node of current link is in a queue.
+    #[inline]
+    pub fn is_linked(&self) -> bool {
+        self.linked.load(Ordering::SeqCst)
+    }
+}
+
+/// Node of `IntrusiveQueue`, which embeds a `Link` of itself.
+pub trait IntrusiveNodeT: Sized {
+    /// Return the link embedded in current node.
+    fn get_link(&self) -> &Link<Self>;
+}
+
+/// Intrusive LockFree queue with multiple producers and a single consumer, whose nodes are
+/// provided by the caller, so `push` and `pop` never allocate.
+///
+/// It's the algorithm of Dmitry Vyukov: `push` swaps the head and then links the old one to the
+/// node, and the consumer follows links from the tail, passing a stub link held by the queue when
+/// the queue runs empty. Nodes are borrowed for `'a`, which the queue can't outlive, and the queue
+/// never frees them, so unlinking needs no `HazardEpoch`. Like `LockFreeQueue`, a pop may return
+/// nothing while a push is between swapping the head and linking.
+///
+/// `new` is `const`, but current queue can't be moved once a node is pushed.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::intrusive_queue::{IntrusiveNodeT, IntrusiveQueue, Link};
+///
+/// struct Job {
+///     link: Link<Job>,
+///     id: u32,
+/// }
+///
+/// impl IntrusiveNodeT for Job {
+///     fn get_link(&self) -> &Link<Job> {
+///         &self.link
+///     }
+/// }
+///
+/// static JOBS: [Job; 2] = [Job { link: Link::new(), id: 1 }, Job { link: Link::new(), id: 2 }];
+/// static QUEUE: IntrusiveQueue<'static, Job> = IntrusiveQueue::new();
+///
+/// QUEUE.try_push(&JOBS[0]).unwrap();
+/// QUEUE.try_push(&JOBS[1]).unwrap();
+/// // A node can't be pushed again until popped.
+/// assert!(QUEUE.try_push(&JOBS[0]).is_err());
+/// unsafe {
+///     assert_eq!(QUEUE.pop().map(|j| j.id), Some(1));
+///     assert_eq!(QUEUE.pop().map(|j| j.id), Some(2));
+///     assert!(QUEUE.pop().is_none());
+/// }
+/// ```
+///
+pub struct IntrusiveQueue<'a, T: 'a> {
+    /// Last pushed link, null for the stub.
+    head: util::WrappedAlign64Type<util::AtomicRawPtr<Link<T>>>,
+    /// Link before the next node to pop, only accessed by the consumer, null for the stub.
+    tail: util::WrappedAlign64Type<UnsafeCell<*mut Link<T>>>,
+    stub: Link<T>,
+    marker: PhantomData<&'a T>,
+}
+
+unsafe impl<'a, T: Sync> Send for IntrusiveQueue<'a, T> {}
+
+unsafe impl<'a, T: Sync> Sync for IntrusiveQueue<'a, T> {}
+
+impl<'a, T: IntrusiveNodeT> Default for IntrusiveQueue<'a, T> {
+    fn default() -> Self {
+        IntrusiveQueue::new()
+    }
+}
+
+impl<'a, T: IntrusiveNodeT> IntrusiveQueue<'a, T> {
+    /// Return empty IntrusiveQueue.
+    pub const fn new() -> Self {
+        IntrusiveQueue {
+            head: util::WrappedAlign64Type(util::AtomicRawPtr::new(ptr::null_mut())),
+            tail: util::WrappedAlign64Type(UnsafeCell::new(ptr::null_mut())),
+            stub: Link::new(),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn stub(&self) -> *mut Link<T> {
+        &self.stub as *const _ as *mut _
+    }
+
+    #[inline]
+    fn or_stub(&self, link: *mut Link<T>) -> *mut Link<T> {
+        if link.is_null() {
+            self.stub()
+        } else {
+            link
+        }
+    }
+
+    unsafe fn push_link(&self, link: *mut Link<T>) {
+        (*link).next.store(ptr::null_mut(), Ordering::SeqCst);
+        let prev = self.or_stub(self.head.swap(link, Ordering::SeqCst));
+        // Until linked, the consumer can't reach `link` or nodes pushed after it.
+        (*prev).next.store(link, Ordering::SeqCst);
+    }
+
+    /// Push `node` to the end of current queue. Return `QueueError::Linked` with the node if it's
+    /// already in a queue.
+    pub fn try_push(&self, node: &'a T) -> Result<(), PushError<&'a T>> {
+        let link = node.get_link();
+        if link.linked.swap(true, Ordering::SeqCst) {
+            return Err(PushError {
+                error: QueueError::Linked { op: "try_push" },
+                value: node,
+            });
+        }
+        link.owner.store(node as *const T as *mut T, Ordering::SeqCst);
+        unsafe { self.push_link(link as *const _ as *mut _) };
+        Ok(())
+    }
+
+    /// Pop the node at the head of current queue, which can be pushed again since then.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread may pop at a time.
+    pub unsafe fn pop(&self) -> Option<&'a T> {
+        let stub = self.stub();
+        let tail_ptr = self.tail.0.get();
+        let mut tail = self.or_stub(*tail_ptr);
+        let mut next = (*tail).next.load(Ordering::SeqCst);
+        if tail == stub {
+            if next.is_null() {
+                return None;
+            }
+            *tail_ptr = next;
+            tail = next;
+            next = (*next).next.load(Ordering::SeqCst);
+        }
+        if next.is_null() {
+            if tail != self.or_stub(self.head.load(Ordering::SeqCst)) {
+                // A producer has swapped the head, but not linked `tail` to its node yet.
+                return None;
+            }
+            // `tail` is the last node, put the stub after it to take it out.
+            self.push_link(stub);
+            next = (*tail).next.load(Ordering::SeqCst);
+            if next.is_null() {
+                return None;
+            }
+        }
+        *tail_ptr = next;
+        let node = &*(*tail).owner.load(Ordering::SeqCst);
+        (*tail).linked.store(false, Ordering::SeqCst);
+        Some(node)
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use intrusive_queue::{IntrusiveNodeT, IntrusiveQueue, Link};
+
+        struct Node {
+            link: Link<Node>,
+            v: usize,
+        }
+
+        impl IntrusiveNodeT for Node {
+            fn get_link(&self) -> &Link<Node> {
+                &self.link
+            }
+        }
+
+        let nodes: Vec<Node> = (0..4)
+            .map(|v| Node {
+                link: Link::new(),
+                v,
+            })
+            .collect();
+        let queue = IntrusiveQueue::new();
+        unsafe {
+            assert!(queue.pop().is_none());
+            for lap in 0..3 {
+                for node in nodes.iter() {
+                    queue.try_push(node).unwrap();
+                    assert!(node.get_link().is_linked());
+                }
+                assert!(queue.try_push(&nodes[lap]).is_err());
+                for i in 0..4 {
+                    let node = queue.pop().unwrap();
+                    assert_eq!(node.v, i);
+                    assert!(!node.get_link().is_linked());
+                }
+                assert!(queue.pop().is_none());
+                // Pushing one node at a time passes the stub each time.
+                queue.try_push(&nodes[lap]).unwrap();
+                assert_eq!(queue.pop().unwrap().v, lap);
+                assert!(queue.pop().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn test_mpsc() {
+        use intrusive_queue::{IntrusiveNodeT, IntrusiveQueue, Link};
+        use std::thread;
+
+        struct Node {
+            link: Link<Node>,
+            producer: usize,
+            seq: usize,
+        }
+
+        impl IntrusiveNodeT for Node {
+            fn get_link(&self) -> &Link<Node> {
+                &self.link
+            }
+        }
+
+        let producer_num = 3;
+        let test_num = 10000;
+        let nodes: &'static Vec<Node> = Box::leak(Box::new(
+            (0..producer_num * test_num)
+                .map(|i| Node {
+                    link: Link::new(),
+                    producer: i / test_num,
+                    seq: i % test_num,
+                })
+                .collect(),
+        ));
+        let queue: &'static IntrusiveQueue<Node> = Box::leak(Box::new(IntrusiveQueue::new()));
+        let producers: Vec<_> = (0..producer_num)
+            .map(|id| {
+                thread::spawn(move || {
+                    for node in nodes[id * test_num..(id + 1) * test_num].iter() {
+                        queue.try_push(node).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        // Nodes of each producer are popped in order.
+        let mut next = vec![0; producer_num];
+        let mut cnt = 0;
+        while cnt < producer_num * test_num {
+            match unsafe { queue.pop() } {
+                Some(node) => {
+                    assert_eq!(node.seq, next[node.producer]);
+                    next[node.producer] += 1;
+                    cnt += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        for t in producers {
+            t.join().unwrap();
+        }
+        assert!(unsafe { queue.pop() }.is_none());
+    }
+}
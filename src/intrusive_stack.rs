@@ -0,0 +1,282 @@
+//! Definition and implementations of `IntrusiveStack`
+//!
+//! Nodes of `IntrusiveStack` are provided by the caller and may be pushed again right after
+//! popped, so the top pointer is packed with a version tag and both are updated by 128-bit CAS,
+//! like `TaggedLockFreeStack`. It's only available on `x86_64` and `aarch64`.
+//!
+use error::{PushError, QueueError};
+use traits::ConcurrentStack;
+use util;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Link embedded in a node of `IntrusiveStack`.
+pub struct Link<T> {
+    next: util::AtomicRawPtr<Link<T>>,
+    owner: util::AtomicRawPtr<T>,
+    linked: AtomicBool,
+}
+
+unsafe impl<T> Send for Link<T> {}
+
+unsafe impl<T> Sync for Link<T> {}
+
+impl<T> Default for Link<T> {
+    fn default() -> Self {
+        Link::new()
+    }
+}
+
+impl<T> Link<T> {
+    /// Return unlinked Link.
+    pub const fn new() -> Self {
+        Link {
+            next: util::AtomicRawPtr::new(ptr::null_mut()),
+            owner: util::AtomicRawPtr::new(ptr::null_mut()),
+            linked: AtomicBool::new(false),
+        }
+    }
+
+    /// Return true if the node of current link is in a stack.
+    #[inline]
+    pub fn is_linked(&self) -> bool {
+        self.linked.load(Ordering::SeqCst)
+    }
+}
+
+/// Node of `IntrusiveStack`, which embeds a `Link` of itself.
+pub trait IntrusiveNodeT: Sized {
+    /// Return the link embedded in current node.
+    fn get_link(&self) -> &Link<Self>;
+}
+
+/// Top link of a stack and its version tag, which are compared and swapped together.
+#[repr(C, align(16))]
+struct TaggedTop<T> {
+    ptr: *mut Link<T>,
+    tag: u64,
+}
+
+impl<T> Copy for TaggedTop<T> {}
+
+impl<T> Clone for TaggedTop<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Default for TaggedTop<T> {
+    fn default() -> Self {
+        TaggedTop {
+            ptr: ptr::null_mut(),
+            tag: 0,
+        }
+    }
+}
+
+#[inline]
+unsafe fn atomic_cxchg_tagged_top<T>(
+    dst: *mut TaggedTop<T>,
+    old: TaggedTop<T>,
+    src: TaggedTop<T>,
+) -> (TaggedTop<T>, bool) {
+    let (v, ok) = util::atomic_cxchg_u128(
+        dst as *mut u128,
+        mem::transmute::<_, u128>(old),
+        mem::transmute::<_, u128>(src),
+    );
+    (mem::transmute::<u128, _>(v), ok)
+}
+
+/// Atomic load a `TaggedTop` by a CAS which never changes it.
+#[inline]
+unsafe fn atomic_load_tagged_top<T>(src: *mut TaggedTop<T>) -> TaggedTop<T> {
+    atomic_cxchg_tagged_top(src, TaggedTop::default(), TaggedTop::default()).0
+}
+
+/// Intrusive LockFree stack, whose nodes are provided by the caller, so `push` and `pop` never
+/// allocate, for free lists and object recycling where values already live in stable memory.
+///
+/// Nodes are borrowed for `'a`, which the stack can't outlive, so a node read after it's popped
+/// by another thread is still valid memory, and the tag of the top makes the CAS fail if so.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::intrusive_stack::{IntrusiveNodeT, IntrusiveStack, Link};
+///
+/// struct Buffer {
+///     link: Link<Buffer>,
+///     id: u32,
+/// }
+///
+/// impl IntrusiveNodeT for Buffer {
+///     fn get_link(&self) -> &Link<Buffer> {
+///         &self.link
+///     }
+/// }
+///
+/// let buffers: Vec<Buffer> = (0..2).map(|id| Buffer { link: Link::new(), id }).collect();
+/// let free_list = IntrusiveStack::new();
+/// for b in buffers.iter() {
+///     free_list.push(b);
+/// }
+/// let b = free_list.pop().unwrap();
+/// assert_eq!(b.id, 1);
+/// // Recycle it.
+/// free_list.push(b);
+/// assert_eq!(free_list.pop().map(|b| b.id), Some(1));
+/// assert_eq!(free_list.pop().map(|b| b.id), Some(0));
+/// assert!(free_list.pop().is_none());
+/// ```
+///
+pub struct IntrusiveStack<'a, T: 'a> {
+    top: util::WrappedAlign64Type<UnsafeCell<TaggedTop<T>>>,
+    marker: PhantomData<&'a T>,
+}
+
+unsafe impl<'a, T: Sync> Send for IntrusiveStack<'a, T> {}
+
+unsafe impl<'a, T: Sync> Sync for IntrusiveStack<'a, T> {}
+
+impl<'a, T: IntrusiveNodeT> Default for IntrusiveStack<'a, T> {
+    fn default() -> Self {
+        IntrusiveStack::new()
+    }
+}
+
+impl<'a, T: IntrusiveNodeT> IntrusiveStack<'a, T> {
+    /// Return empty IntrusiveStack. Panic if current CPU doesn't support 128-bit CAS.
+    pub fn new() -> Self {
+        assert!(
+            util::has_cxchg_u128(),
+            "128-bit CAS is not supported by current CPU"
+        );
+        IntrusiveStack {
+            top: util::WrappedAlign64Type(UnsafeCell::new(TaggedTop::default())),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn top(&self) -> *mut TaggedTop<T> {
+        self.top.0.get()
+    }
+
+    /// Push `node` to the top of current stack. Panic if `try_push` fails.
+    pub fn push(&self, node: &'a T) {
+        if let Err(e) = self.try_push(node) {
+            panic!("push fail, {}", e);
+        }
+    }
+
+    /// Push `node` to the top of current stack. Return `QueueError::Linked` with the node if it's
+    /// already in a stack.
+    pub fn try_push(&self, node: &'a T) -> Result<(), PushError<&'a T>> {
+        let link = node.get_link();
+        if link.linked.swap(true, Ordering::SeqCst) {
+            return Err(PushError {
+                error: QueueError::Linked { op: "try_push" },
+                value: node,
+            });
+        }
+        link.owner.store(node as *const T as *mut T, Ordering::SeqCst);
+        let link = link as *const _ as *mut Link<T>;
+        unsafe {
+            let mut old = atomic_load_tagged_top(self.top());
+            let mut backoff = util::Backoff::new();
+            loop {
+                (*link).next.store(old.ptr, Ordering::SeqCst);
+                let new = TaggedTop {
+                    ptr: link,
+                    tag: old.tag.wrapping_add(1),
+                };
+                let (cur, ok) = atomic_cxchg_tagged_top(self.top(), old, new);
+                if ok {
+                    break;
+                }
+                old = cur;
+                backoff.spin();
+            }
+        }
+        Ok(())
+    }
+
+    /// Pop the node at the top of current stack, which can be pushed again since then.
+    pub fn pop(&self) -> Option<&'a T> {
+        unsafe {
+            let mut old = atomic_load_tagged_top(self.top());
+            let mut backoff = util::Backoff::new();
+            while !old.ptr.is_null() {
+                // `old.ptr` may have been popped and pushed again by others, but it's still
+                // valid for `'a`, and the tag makes sure the CAS fails if so.
+                let new = TaggedTop {
+                    ptr: (*old.ptr).next.load(Ordering::SeqCst),
+                    tag: old.tag.wrapping_add(1),
+                };
+                let (cur, ok) = atomic_cxchg_tagged_top(self.top(), old, new);
+                if ok {
+                    let node = &*(*old.ptr).owner.load(Ordering::SeqCst);
+                    (*old.ptr).linked.store(false, Ordering::SeqCst);
+                    return Some(node);
+                }
+                old = cur;
+                backoff.spin();
+            }
+            None
+        }
+    }
+}
+
+impl<'a, T: IntrusiveNodeT> ConcurrentStack<&'a T> for IntrusiveStack<'a, T> {
+    fn try_push(&mut self, node: &'a T) -> Result<(), PushError<&'a T>> {
+        IntrusiveStack::try_push(self, node)
+    }
+
+    fn try_pop(&mut self) -> Result<Option<&'a T>, QueueError> {
+        Ok(self.pop())
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use intrusive_stack::{IntrusiveNodeT, IntrusiveStack, Link};
+
+        struct Node {
+            link: Link<Node>,
+            v: usize,
+        }
+
+        impl IntrusiveNodeT for Node {
+            fn get_link(&self) -> &Link<Node> {
+                &self.link
+            }
+        }
+
+        let nodes: Vec<Node> = (0..4)
+            .map(|v| Node {
+                link: Link::new(),
+                v,
+            })
+            .collect();
+        let stack = IntrusiveStack::new();
+        assert!(stack.pop().is_none());
+        for _ in 0..3 {
+            for node in nodes.iter() {
+                stack.push(node);
+                assert!(node.get_link().is_linked());
+            }
+            assert!(stack.try_push(&nodes[0]).is_err());
+            for i in (0..4).rev() {
+                let node = stack.pop().unwrap();
+                assert_eq!(node.v, i);
+                assert!(!node.get_link().is_linked());
+            }
+            assert!(stack.pop().is_none());
+        }
+    }
+}
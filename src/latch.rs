@@ -0,0 +1,173 @@
+//! Definition and implementations of `Latch`
+//!
+use util;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::AtomicBool;
+use std::thread::{self, Thread};
+
+/// Node of a thread parked in `Latch::wait`, in its own stack.
+struct Waiter {
+    thread: Thread,
+    notified: AtomicBool,
+    next: *mut Waiter,
+}
+
+/// Countdown latch, or countdown event, released once `count_down` has been called as many times
+/// as the initial count, like waiting for N workers to finish a phase. It can't be reset.
+///
+/// `wait` spins by `util::Backoff` first, then pushes a node in its own stack onto a list of
+/// waiters by CAS and parks. The call reaching zero swaps the list with a sentinel, so later
+/// waiters return at once, and unparks every waiter on it. No lock is taken, unlike `Mutex` and
+/// `Condvar`. Counting down acquires and releases the count, so all writes before `count_down` in
+/// every thread are visible after `wait` returns.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::latch::Latch;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let latch = Arc::new(Latch::new(3));
+/// let workers: Vec<_> = (0..3)
+///     .map(|_| {
+///         let latch = latch.clone();
+///         thread::spawn(move || latch.count_down())
+///     })
+///     .collect();
+/// latch.wait();
+/// assert_eq!(latch.count(), 0);
+/// for t in workers {
+///     t.join().unwrap();
+/// }
+/// ```
+///
+pub struct Latch {
+    count: util::WrappedAlign64Type<util::AtomicI64>,
+    /// Waiters parked, or the sentinel `released` once the count reaches zero.
+    waiters: util::WrappedAlign64Type<util::AtomicRawPtr<Waiter>>,
+}
+
+impl Latch {
+    /// Return Latch waiting for `count` calls of `count_down`. It's released already if `count` is
+    /// 0.
+    pub fn new(count: usize) -> Latch {
+        let waiters = if 0 == count {
+            released()
+        } else {
+            ptr::null_mut()
+        };
+        Latch {
+            count: util::WrappedAlign64Type(util::AtomicI64::new(count as i64)),
+            waiters: util::WrappedAlign64Type(util::AtomicRawPtr::new(waiters)),
+        }
+    }
+
+    /// Return the number of `count_down` calls still needed, which may be out of date once
+    /// returned.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count.load(util::ORDER_ACQUIRE) as usize
+    }
+
+    /// Decrease the count by one, and wake up all waiters if it reaches zero. It does nothing once
+    /// current latch is released.
+    pub fn count_down(&self) {
+        let mut cur = self.count.load(util::ORDER_RELAXED);
+        let mut backoff = util::Backoff::new();
+        loop {
+            if 0 == cur {
+                return;
+            }
+            // Release the writes of current thread to waiters, and acquire those of earlier calls
+            // for the one reaching zero, which wakes them up.
+            let (prev, ok) = self.count.cas(cur, cur - 1, util::ORDER_ACQ_REL);
+            if ok {
+                break;
+            }
+            cur = prev;
+            backoff.spin();
+        }
+        if 1 == cur {
+            unsafe { self.wake_all() };
+        }
+    }
+
+    /// Return true if current latch is released, without waiting.
+    #[inline]
+    pub fn try_wait(&self) -> bool {
+        0 == self.count.load(util::ORDER_ACQUIRE)
+    }
+
+    /// Block until current latch is released, spinning for a while before parking.
+    pub fn wait(&self) {
+        let mut backoff = util::Backoff::new();
+        while !backoff.is_completed() {
+            if self.try_wait() {
+                return;
+            }
+            backoff.snooze();
+        }
+        let mut waiter = Waiter {
+            thread: thread::current(),
+            notified: AtomicBool::new(false),
+            next: ptr::null_mut(),
+        };
+        // Only accessed through `node` from now on, which is shared with the waking thread.
+        let node = &mut waiter as *mut Waiter;
+        let mut head = self.waiters.load(util::ORDER_ACQUIRE);
+        loop {
+            if head == released() {
+                return;
+            }
+            unsafe { (*node).next = head };
+            let (prev, ok) = self.waiters.cas(head, node, util::ORDER_ACQ_REL);
+            if ok {
+                break;
+            }
+            head = prev;
+        }
+        // Unparking may come before parking, or a park may return spuriously, so the flag decides.
+        while !unsafe { (*node).notified.load(util::ORDER_ACQUIRE) } {
+            thread::park();
+        }
+    }
+
+    unsafe fn wake_all(&self) {
+        let mut node = self.waiters.swap(released(), util::ORDER_ACQ_REL);
+        while !node.is_null() {
+            // The waiter returns and frees its node once notified, so read it all before.
+            let next = (*node).next;
+            let thread = (*node).thread.clone();
+            (*node).notified.store(true, util::ORDER_RELEASE);
+            thread.unpark();
+            node = next;
+        }
+    }
+}
+
+/// Sentinel of the waiter list of a released latch, which is never the address of a node.
+#[inline]
+fn released() -> *mut Waiter {
+    NonNull::dangling().as_ptr()
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use latch::Latch;
+        let latch = Latch::new(2);
+        assert!(!latch.try_wait());
+        latch.count_down();
+        assert_eq!(latch.count(), 1);
+        latch.count_down();
+        assert!(latch.try_wait());
+        latch.wait();
+        latch.count_down();
+        assert_eq!(latch.count(), 0);
+
+        let latch = Latch::new(0);
+        assert!(latch.try_wait());
+        latch.wait();
+    }
+}
@@ -13,23 +13,111 @@
 //! We provide `HazardEpoch`, a practical implementation of `Hazard Pointers`, which make further
 //! improvement and provide an easier way for usage.
 //! `LockFreeQueue` and `LockFreeStack`, implemented based on `HazardEpoch`, contain a few simple
-//! methods like `push`, `pop`.
+//! methods like `push`, `pop`. `TaggedLockFreeStack` trades portability for lower per-op overhead
+//! by defeating `ABA problem` with double-width CAS instead of `HazardEpoch`. `ArtMap`, an adaptive
+//! radix tree keyed by byte strings, provides ordered prefix lookups for in-memory index use cases.
+//! For data which doesn't fit lock-free structures, user mode locks `SpinLock`, `TicketLock`,
+//! `SpinRwLock`, `BravoRwLock` and `SeqLock` are provided.
 //!
 #![feature(core_intrinsics)]
-#![feature(raw)]
 #![allow(dead_code)]
 
+/// Report an unexpected condition by `diagnostics::emit`, with the hook after `hook:` if given.
+macro_rules! diagnose {
+    (hook: $hook:expr, $($arg:tt)+) => {
+        $crate::diagnostics::emit(
+            $hook,
+            &$crate::diagnostics::Diagnostic {
+                target: module_path!(),
+                args: format_args!($($arg)+),
+            },
+        )
+    };
+    ($($arg:tt)+) => {
+        diagnose!(hook: None, $($arg)+)
+    };
+}
+
+/// Reach yield point `test_hooks::$name` with feature `test-hooks`, or do nothing.
+macro_rules! yield_point {
+    ($name:ident) => {
+        #[cfg(feature = "test-hooks")]
+        $crate::test_hooks::reach($crate::test_hooks::$name);
+    };
+}
+
 mod hazard_pointer;
+#[cfg(feature = "debug-poison")]
+mod quarantine;
+pub mod diagnostics;
+mod stats;
 pub mod util;
+pub mod numa;
+pub mod per_thread;
 pub mod error;
+pub mod traits;
 pub mod hazard_epoch;
+pub mod epoch;
+pub mod arc_swap;
 pub mod spin_lock;
+pub mod ticket_lock;
 pub mod spin_rwlock;
+pub mod bravo_rwlock;
+pub mod seq_lock;
+pub mod latch;
 pub mod lockfree_queue;
+pub mod multi_queue;
 pub mod lockfree_stack;
+pub mod array_queue;
+pub mod index_queue;
+pub mod overwrite_ring;
+pub mod byte_ring;
+pub mod slot_ring;
+pub mod broadcast_ring;
+pub mod intrusive_queue;
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub mod intrusive_stack;
+pub mod channel;
+pub mod tagged_stack;
+pub mod art_map;
+pub mod flat_combining;
+pub mod fixed_hash_map;
+pub mod once_map;
+pub mod gen_arena;
+pub mod count_min;
+pub mod union_find;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "rayon")]
+pub mod par_drain;
+#[cfg(feature = "test-hooks")]
+pub mod test_hooks;
 
+#[cfg(feature = "log")]
 #[macro_use]
 extern crate log;
 
 #[macro_use]
 extern crate cfg_if;
+
+#[cfg(feature = "lock_api")]
+extern crate lock_api;
+
+#[cfg(feature = "metrics")]
+#[macro_use]
+extern crate metrics;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+#[cfg(feature = "affinity")]
+extern crate core_affinity;
+
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+#[cfg(all(test, feature = "serde"))]
+extern crate bincode;
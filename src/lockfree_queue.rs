@@ -1,16 +1,51 @@
 //! Definition and implementations of `LockFreeQueue`
 //!
-use hazard_epoch::HazardEpoch;
+use error::{PushError, QueueError};
+#[cfg(feature = "debug-assert-invariants")]
+use error::InvariantError;
+use hazard_epoch::{HazardEpoch, PendingPolicy};
 use hazard_pointer::{BaseHazardNode, HazardNodeT};
 use util;
+use stats;
+use traits::ConcurrentQueue;
+use std::fmt;
+use std::iter;
+use std::mem::{self, MaybeUninit};
 use std::ptr;
+use std::sync::atomic::{AtomicU8, Ordering};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "serde")]
+use serde::de::Error as DeError;
+#[cfg(feature = "serde")]
+use serde::ser::Error as SerError;
+#[cfg(feature = "rayon")]
+use par_drain::ParDrain;
 
 type FIFONodePtr<T> = *mut FIFONode<T>;
 
+/// Element popped by `pop_node`, with the node to reuse if any.
+type PoppedNode<T> = Option<(T, Option<NodeBox<T>>)>;
+
+/// Element of a node is ready to be taken.
+const ELEM_READY: u8 = 0;
+/// Element of a node is being checked by `retain`.
+const ELEM_CHECKING: u8 = 1;
+/// Element of a node has been popped or removed.
+const ELEM_TAKEN: u8 = 2;
+/// Element of a node is being formatted by `Debug`.
+const ELEM_READING: u8 = 3;
+
+/// Maximum number of elements shown by `Debug`.
+const DEBUG_ELEMENTS: usize = 16;
+
 struct FIFONode<T> {
     value: Option<T>,
     base: BaseHazardNode,
-    next: FIFONodePtr<T>,
+    /// Stored once by the push linking the next node, and loaded by others walking the queue.
+    next: util::AtomicRawPtr<FIFONode<T>>,
+    /// State of the element, so that pop and `retain` never take it both.
+    state: AtomicU8,
 }
 
 impl<T> HazardNodeT for FIFONode<T> {
@@ -28,27 +63,106 @@ impl<T> Default for FIFONode<T> {
         FIFONode {
             value: None,
             base: BaseHazardNode::default(),
-            next: ptr::null_mut(),
+            next: util::AtomicRawPtr::default(),
+            state: AtomicU8::new(ELEM_TAKEN),
         }
     }
 }
 
 impl<T> FIFONode<T> {
+    /// Acquire pairs with the release in `set_next`, so the element of the next node written by
+    /// its push is visible before the node is dereferenced. Nothing else is ordered by the link,
+    /// because reclamation is guarded by the `SeqCst` loads of head and tail under a handle.
     fn next(&self) -> FIFONodePtr<T> {
-        self.next
+        self.next.load(util::ORDER_ACQUIRE)
     }
 
-    fn set_next(&mut self, next: FIFONodePtr<T>) {
-        self.next = next;
+    fn set_next(&self, next: FIFONodePtr<T>) {
+        self.next.store(next, util::ORDER_RELEASE);
     }
 
     fn new(value: T) -> Self {
         FIFONode {
             value: Some(value),
             base: BaseHazardNode::default(),
-            next: ptr::null_mut(),
+            next: util::AtomicRawPtr::default(),
+            state: AtomicU8::new(ELEM_READY),
         }
     }
+
+    // State transitions form a lock around the element: claiming it by CAS acquires what was
+    // written by whoever set it ready, and setting it ready or taken releases what was done to it.
+    // So acquire and release are enough, there is no store followed by a load of another location.
+
+    fn is_taken(&self) -> bool {
+        ELEM_TAKEN == self.state.load(util::ORDER_ACQUIRE)
+    }
+
+    /// Take the element, waiting for `retain` checking it or `Debug` reading it. Return None if
+    /// it's taken already.
+    fn take(&mut self) -> Option<T> {
+        let mut backoff = util::Backoff::new();
+        loop {
+            match self.state.compare_exchange(
+                ELEM_READY,
+                ELEM_TAKEN,
+                util::ORDER_ACQ_REL,
+                util::ORDER_ACQUIRE,
+            ) {
+                Ok(_) => return self.value.take(),
+                Err(ELEM_CHECKING) | Err(ELEM_READING) => backoff.snooze(),
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Claim the element for reading, waiting for others checking or reading it, and return a
+    /// guard releasing it. Return None if it's taken already.
+    fn read<'a>(&'a self) -> Option<Checking<'a>> {
+        let mut backoff = util::Backoff::new();
+        loop {
+            match self.state.compare_exchange(
+                ELEM_READY,
+                ELEM_READING,
+                util::ORDER_ACQUIRE,
+                util::ORDER_ACQUIRE,
+            ) {
+                Ok(_) => return Some(Checking(&self.state)),
+                Err(ELEM_TAKEN) => return None,
+                Err(_) => backoff.snooze(),
+            }
+        }
+    }
+}
+
+/// Set the element being checked by `retain` or read by `Debug` ready again when dropped, even if
+/// the check panics.
+struct Checking<'a>(&'a AtomicU8);
+
+impl<'a> Drop for Checking<'a> {
+    fn drop(&mut self) {
+        self.0.store(ELEM_READY, util::ORDER_RELEASE);
+    }
+}
+
+/// Allocation of a queue node, returned by `LockFreeQueue::pop_node` and reused by
+/// `LockFreeQueue::push_with_node`, so a producer and a consumer passing it around don't touch the
+/// allocator.
+pub struct NodeBox<T>(Box<FIFONode<T>>);
+
+unsafe impl<T: Send> Send for NodeBox<T> {}
+
+impl<T> Default for NodeBox<T> {
+    fn default() -> Self {
+        NodeBox::new()
+    }
+}
+
+impl<T> NodeBox<T> {
+    /// Allocate an empty node, for example to prime a producer before the first pop.
+    pub fn new() -> Self {
+        NodeBox(Box::default())
+    }
 }
 
 /// LockFree queue, implemented based on `HazardEpoch`
@@ -72,26 +186,18 @@ impl<T> FIFONode<T> {
 ///
 pub struct LockFreeQueue<T> {
     hazard_epoch: HazardEpoch,
-    head: util::WrappedAlign64Type<FIFONodePtr<T>>,
-    tail: util::WrappedAlign64Type<FIFONodePtr<T>>,
+    head: util::WrappedAlign64Type<util::AtomicRawPtr<FIFONode<T>>>,
+    tail: util::WrappedAlign64Type<util::AtomicRawPtr<FIFONode<T>>>,
 }
 
 impl<T> LockFreeQueue<T> {
-    unsafe fn atomic_load_head(&self) -> FIFONodePtr<T> {
-        util::atomic_load_raw_ptr(self.head.as_ptr())
-    }
-
-    unsafe fn atomic_load_tail(&self) -> FIFONodePtr<T> {
-        util::atomic_load_raw_ptr(self.tail.as_ptr())
-    }
-
     /// Return LockFreeQueue in stack with default setting of HazardEpoch
     pub unsafe fn default_new_in_stack() -> LockFreeQueue<T> {
         let head = Box::into_raw(Box::new(FIFONode::<T>::default()));
         LockFreeQueue {
             hazard_epoch: HazardEpoch::default_new_in_stack(),
-            head: util::WrappedAlign64Type(head),
-            tail: util::WrappedAlign64Type(head),
+            head: util::WrappedAlign64Type(util::AtomicRawPtr::new(head)),
+            tail: util::WrappedAlign64Type(util::AtomicRawPtr::new(head)),
         }
     }
 
@@ -100,121 +206,887 @@ impl<T> LockFreeQueue<T> {
         unsafe { Box::new(Self::default_new_in_stack()) }
     }
 
-    /// Push an element to the end of current queue
+    /// Limit the number of popped nodes waiting to be reclaimed, see
+    /// `HazardEpoch::set_pending_limit`. Pushing checks the limit.
+    pub fn set_pending_limit(&mut self, limit: Option<i64>, policy: PendingPolicy) {
+        self.hazard_epoch.set_pending_limit(limit, policy);
+    }
+
+    /// Push an element to the end of current queue. Panic if `try_push` fails.
     pub fn push(&mut self, v: T) {
+        if let Err(e) = self.try_push(v) {
+            panic!("push fail, {}", e);
+        }
+    }
+
+    /// Push an element to the end of current queue. Return `QueueError::AllocFailed` if memory
+    /// of node can't be allocated, or `QueueError::Hazard` if `HazardEpoch` fails, including
+    /// `HazardError::PendingLimit` under `PendingPolicy::Reject`. The element is given back in
+    /// both cases.
+    pub fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
         unsafe { self.inner_push(v) }
     }
 
-    unsafe fn inner_push(&mut self, v: T) {
-        let node = Box::into_raw(Box::new(FIFONode::new(v)));
-        let mut handle = 0_u64;
-        self.hazard_epoch.acquire(&mut handle);
-        let mut cur = self.atomic_load_tail();
+    unsafe fn inner_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        if let Err(e) = self.hazard_epoch.check_pending() {
+            return Err(PushError {
+                error: e.into(),
+                value: v,
+            });
+        }
+        let node = match util::try_box_into_raw(FIFONode::new(v)) {
+            Ok(node) => node,
+            Err(mut node) => {
+                return Err(PushError {
+                    error: QueueError::AllocFailed { op: "push" },
+                    value: node.value.take().unwrap(),
+                })
+            }
+        };
+        self.link_node(node)
+    }
+
+    /// Push an element to the end of current queue in `node`, instead of allocating one. Panic if
+    /// `try_push_with_node` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::lockfree_queue::{LockFreeQueue, NodeBox};
+    ///
+    /// let mut queue = LockFreeQueue::default_new_in_heap();
+    /// let mut node = NodeBox::new();
+    /// for i in 0..10 {
+    ///     queue.push_with_node(i, node);
+    ///     let (v, recycled) = queue.pop_node().unwrap();
+    ///     assert_eq!(v, i);
+    ///     // No handle is held by others here, so the node is always recycled.
+    ///     node = recycled.unwrap();
+    /// }
+    /// ```
+    ///
+    pub fn push_with_node(&mut self, v: T, node: NodeBox<T>) {
+        if let Err(e) = self.try_push_with_node(v, node) {
+            panic!("push_with_node fail, {}", e);
+        }
+    }
+
+    /// Same as `push_with_node`, but return error like `try_push` except `QueueError::AllocFailed`.
+    /// The element is given back, and `node` is freed.
+    pub fn try_push_with_node(&mut self, v: T, node: NodeBox<T>) -> Result<(), PushError<T>> {
+        unsafe { self.inner_push_with_node(v, node) }
+    }
+
+    unsafe fn inner_push_with_node(&mut self, v: T, node: NodeBox<T>) -> Result<(), PushError<T>> {
+        if let Err(e) = self.hazard_epoch.check_pending() {
+            return Err(PushError {
+                error: e.into(),
+                value: v,
+            });
+        }
+        let mut node = node.0;
+        *node = FIFONode::new(v);
+        self.link_node(Box::into_raw(node))
+    }
+
+    /// Link `node` holding an element to the end of current queue.
+    unsafe fn link_node(&mut self, node: FIFONodePtr<T>) -> Result<(), PushError<T>> {
+        let handle = match self.hazard_epoch.try_acquire() {
+            Ok(handle) => handle,
+            Err(e) => {
+                return Err(PushError {
+                    error: e.into(),
+                    value: Box::from_raw(node).value.take().unwrap(),
+                })
+            }
+        };
+        let mut cur = self.tail.load(Ordering::SeqCst);
         let mut old = cur;
+        let mut backoff = util::Backoff::new();
         while !{
-            let (tmp, b) = util::atomic_cxchg_raw_ptr(self.tail.as_mut_ptr(), old, node);
+            let (tmp, b) = self.tail.cas(old, node, Ordering::SeqCst);
             cur = tmp;
             b
         } {
             old = cur;
+            backoff.spin();
         }
+        // Until `cur` links to `node`, `node` and elements pushed after it are unreachable by pop.
+        yield_point!(QUEUE_TAIL_LINK);
         (*cur).set_next(node);
         self.hazard_epoch.release(handle);
+        stats::gauge_add(stats::QUEUE_DEPTH, 1);
+        Ok(())
     }
 
-    /// Pop the element at the head of current queue
+    /// Pop the element at the head of current queue. Panic if `try_pop` fails.
     pub fn pop(&mut self) -> Option<T> {
+        match self.try_pop() {
+            Ok(ret) => ret,
+            Err(e) => panic!("pop fail, {}", e),
+        }
+    }
+
+    /// Same as `pop`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    pub fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
         unsafe { self.inner_pop() }
     }
 
-    unsafe fn inner_pop(&mut self) -> Option<T> {
+    unsafe fn inner_pop(&mut self) -> Result<Option<T>, QueueError> {
         let mut ret = None;
-        let mut handle = 0_u64;
-        self.hazard_epoch.acquire(&mut handle);
-        let mut cur = self.atomic_load_head();
-        let mut old = cur;
-        let mut node = (*cur).next();
-        while !node.is_null() && !{
-            let (tmp, b) = util::atomic_cxchg_raw_ptr(self.head.as_mut_ptr(), old, node);
-            cur = tmp;
-            b
-        } {
-            old = cur;
-            node = (*cur).next();
-        }
-        if !node.is_null() {
-            ret = (*node).value.take();
-            assert!(ret.is_some());
-            self.hazard_epoch.add_node(cur);
+        let handle = self.hazard_epoch.try_acquire()?;
+        let mut backoff = util::Backoff::new();
+        loop {
+            let mut cur = self.head.load(Ordering::SeqCst);
+            let mut old = cur;
+            let mut node = (*cur).next();
+            while !node.is_null() && !{
+                let (tmp, b) = self.head.cas(old, node, Ordering::SeqCst);
+                cur = tmp;
+                b
+            } {
+                old = cur;
+                node = (*cur).next();
+                backoff.spin();
+            }
+            if node.is_null() {
+                break;
+            }
+            self.hazard_epoch.try_add_node(cur).unwrap();
+            // Elements removed by `retain` are skipped, and their nodes unlinked.
+            ret = (*node).take();
+            if ret.is_some() {
+                stats::gauge_add(stats::QUEUE_DEPTH, -1);
+                break;
+            }
         }
         self.hazard_epoch.release(handle);
-        ret
+        Ok(ret)
+    }
+
+    /// Pop the element at the head of current queue, with the allocation of the node it unlinked
+    /// if it can be reused by `push_with_node` right away. Panic if `try_pop_node` fails.
+    pub fn pop_node(&mut self) -> Option<(T, Option<NodeBox<T>>)> {
+        match self.try_pop_node() {
+            Ok(ret) => ret,
+            Err(e) => panic!("pop_node fail, {}", e),
+        }
+    }
+
+    /// Same as `pop_node`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    ///
+    /// Popping unlinks the node before the one holding the element, which other threads may still
+    /// read. It's returned if no handle of `HazardEpoch` is held once current pop releases its
+    /// own, because then no thread can reach it any more, and is left to `HazardEpoch` otherwise.
+    /// So a producer and a consumer mostly pass nodes between each other, and only fall back to
+    /// the allocator while others are inside the queue.
+    pub fn try_pop_node(&mut self) -> Result<PoppedNode<T>, QueueError> {
+        unsafe { self.inner_pop_node() }
+    }
+
+    unsafe fn inner_pop_node(&mut self) -> Result<PoppedNode<T>, QueueError> {
+        let mut ret = None;
+        let mut unlinked: FIFONodePtr<T> = ptr::null_mut();
+        {
+            // Released at the end of the block, before checking handles of other threads.
+            let cursor = self.hazard_epoch.cursor()?;
+            let mut backoff = util::Backoff::new();
+            loop {
+                let mut cur = self.head.load(Ordering::SeqCst);
+                let mut old = cur;
+                let mut node = (*cur).next();
+                while !node.is_null() && !{
+                    let (tmp, b) = self.head.cas(old, node, Ordering::SeqCst);
+                    cur = tmp;
+                    b
+                } {
+                    old = cur;
+                    node = (*cur).next();
+                    backoff.spin();
+                }
+                if node.is_null() {
+                    break;
+                }
+                // Elements removed by `retain` are skipped, and nodes unlinked before the last one
+                // are left to `HazardEpoch`.
+                if !unlinked.is_null() {
+                    cursor.retire(unlinked)?;
+                }
+                unlinked = cur;
+                ret = (*node).take();
+                if ret.is_some() {
+                    stats::gauge_add(stats::QUEUE_DEPTH, -1);
+                    break;
+                }
+            }
+        }
+        if unlinked.is_null() {
+            return Ok(None);
+        }
+        // Handles acquired from now on can only load a head after `unlinked`.
+        let recycled = if self.hazard_epoch.no_handle_held() {
+            Some(NodeBox(Box::from_raw(unlinked)))
+        } else {
+            self.hazard_epoch.try_add_node(unlinked)?;
+            None
+        };
+        Ok(ret.map(|v| (v, recycled)))
+    }
+
+    /// Pop the element at the head of current queue only if `f` returns true on it. Return None if
+    /// current queue is empty or `f` returns false, in which case the element stays at the head.
+    /// Panic if `try_pop_if` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::lockfree_queue::LockFreeQueue;
+    ///
+    /// let mut queue = LockFreeQueue::default_new_in_heap();
+    /// // Deadlines of work items, in order.
+    /// queue.push(10);
+    /// queue.push(20);
+    /// let now = 15;
+    /// assert_eq!(queue.pop_if(|&deadline| deadline <= now), Some(10));
+    /// assert_eq!(queue.pop_if(|&deadline| deadline <= now), None);
+    /// assert_eq!(queue.pop(), Some(20));
+    /// ```
+    ///
+    pub fn pop_if<F: FnMut(&T) -> bool>(&mut self, f: F) -> Option<T> {
+        match self.try_pop_if(f) {
+            Ok(ret) => ret,
+            Err(e) => panic!("pop_if fail, {}", e),
+        }
+    }
+
+    /// Same as `pop_if`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    ///
+    /// The first element is claimed by CAS before `f` checks it, like `retain`, so a pop reaching
+    /// it waits for `f`, and if `f` returns true it's taken in place, whose node is unlinked by a
+    /// later pop. No other element can be popped in between, unlike popping and pushing back.
+    pub fn try_pop_if<F: FnMut(&T) -> bool>(&mut self, f: F) -> Result<Option<T>, QueueError> {
+        unsafe { self.inner_pop_if(f) }
+    }
+
+    unsafe fn inner_pop_if<F: FnMut(&T) -> bool>(
+        &mut self,
+        mut f: F,
+    ) -> Result<Option<T>, QueueError> {
+        let mut ret = None;
+        // Released when dropped, even if `f` panics.
+        let cursor = self.hazard_epoch.cursor()?;
+        let mut backoff = util::Backoff::new();
+        loop {
+            let head = self.head.load(Ordering::SeqCst);
+            let node = (*head).next();
+            if node.is_null() {
+                break;
+            }
+            match (*node).state.compare_exchange(
+                ELEM_READY,
+                ELEM_CHECKING,
+                util::ORDER_ACQUIRE,
+                util::ORDER_ACQUIRE,
+            ) {
+                Ok(_) => {
+                    let checking = Checking(&(*node).state);
+                    if f((*node).value.as_ref().unwrap()) {
+                        mem::forget(checking);
+                        ret = (*node).value.take();
+                        (*node).state.store(ELEM_TAKEN, util::ORDER_RELEASE);
+                        stats::gauge_add(stats::QUEUE_DEPTH, -1);
+                    }
+                    break;
+                }
+                // Checked by `retain` or read by `Debug`, which releases it soon.
+                Err(ELEM_CHECKING) | Err(ELEM_READING) => backoff.snooze(),
+                // Taken already, unlink it like pop to reach the next one.
+                Err(_) => {
+                    if self.head.cas(head, node, Ordering::SeqCst).1 {
+                        cursor.retire(head)?;
+                    }
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Detach the first `n` elements of current queue, or all if fewer, into a new queue in heap
+    /// keeping their order. Panic if `try_split_off` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::lockfree_queue::LockFreeQueue;
+    ///
+    /// let mut queue = LockFreeQueue::default_new_in_heap();
+    /// for i in 0..10 {
+    ///     queue.push(i);
+    /// }
+    /// let mut front = queue.split_off(3);
+    /// assert_eq!(front.pop(), Some(0));
+    /// assert_eq!(queue.pop(), Some(3));
+    /// let mut half = queue.steal_half();
+    /// for i in 4..7 {
+    ///     assert_eq!(half.pop(), Some(i));
+    /// }
+    /// assert!(half.pop().is_none());
+    /// assert_eq!(queue.pop(), Some(7));
+    /// ```
+    ///
+    pub fn split_off(&mut self, n: usize) -> Box<LockFreeQueue<T>> {
+        match self.try_split_off(n) {
+            Ok(ret) => ret,
+            Err(e) => panic!("split_off fail, {}", e),
+        }
+    }
+
+    /// Same as `split_off`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    ///
+    /// The head is moved past `n` nodes by one CAS, which takes their elements like a pop, so
+    /// shards balance load without popping and pushing each element through shared ends. Elements
+    /// are moved into new nodes, because other threads may still read the detached ones, so it
+    /// costs O(n).
+    pub fn try_split_off(&mut self, n: usize) -> Result<Box<LockFreeQueue<T>>, QueueError> {
+        unsafe { self.inner_split_off(Some(n)) }
+    }
+
+    /// Detach the first half of the elements, rounded up, like `split_off`. Panic if
+    /// `try_steal_half` fails.
+    pub fn steal_half(&mut self) -> Box<LockFreeQueue<T>> {
+        match self.try_steal_half() {
+            Ok(ret) => ret,
+            Err(e) => panic!("steal_half fail, {}", e),
+        }
+    }
+
+    /// Same as `steal_half`, but return `QueueError::Hazard` if `HazardEpoch` fails. Elements are
+    /// counted by walking the whole queue, so it costs O(len).
+    pub fn try_steal_half(&mut self) -> Result<Box<LockFreeQueue<T>>, QueueError> {
+        unsafe { self.inner_split_off(None) }
+    }
+
+    /// Move `link`, the head of a queue, past `n` elements, or half of them if None, by one CAS,
+    /// and return the old head and the new one. Elements after the old head up to the new one are
+    /// to be taken. It only borrows the head, so that a cursor of `hazard_epoch` can be held.
+    unsafe fn detach(
+        link: &util::AtomicRawPtr<FIFONode<T>>,
+        n: Option<usize>,
+    ) -> (FIFONodePtr<T>, FIFONodePtr<T>) {
+        let mut backoff = util::Backoff::new();
+        loop {
+            let head = link.load(Ordering::SeqCst);
+            let n = match n {
+                Some(n) => n,
+                None => {
+                    let mut len = 0usize;
+                    let mut iter = (*head).next();
+                    while !iter.is_null() {
+                        len += !(*iter).is_taken() as usize;
+                        iter = (*iter).next();
+                    }
+                    len.div_ceil(2)
+                }
+            };
+            // Nodes of removed elements are passed without counting.
+            let mut last = head;
+            let mut cnt = 0;
+            while cnt < n {
+                let next = (*last).next();
+                if next.is_null() {
+                    break;
+                }
+                cnt += !(*next).is_taken() as usize;
+                last = next;
+            }
+            if last == head || link.cas(head, last, Ordering::SeqCst).1 {
+                return (head, last);
+            }
+            backoff.spin();
+        }
+    }
+
+    unsafe fn inner_split_off(&mut self, n: Option<usize>) -> Result<Box<Self>, QueueError> {
+        let cursor = self.hazard_epoch.cursor()?;
+        let (first, last) = Self::detach(&self.head, n);
+        let ret = Self::default_new_in_heap();
+        let mut tail = ret.tail.load(Ordering::SeqCst);
+        let mut iter = first;
+        while iter != last {
+            let next = (*iter).next();
+            if let Some(v) = (*next).take() {
+                let node = Box::into_raw(Box::new(FIFONode::new(v)));
+                (*tail).set_next(node);
+                tail = node;
+            }
+            cursor.retire(iter)?;
+            iter = next;
+        }
+        ret.tail.store(tail, Ordering::SeqCst);
+        Ok(ret)
+    }
+
+    /// Pop up to `out.len()` elements into `out` in FIFO order, and return the number of them,
+    /// which are initialized in `out[..n]`. Return 0 if current queue is empty. Panic if
+    /// `try_pop_many` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::lockfree_queue::LockFreeQueue;
+    /// use std::mem::MaybeUninit;
+    ///
+    /// let mut queue = LockFreeQueue::default_new_in_heap();
+    /// for i in 0..5 {
+    ///     queue.push(i);
+    /// }
+    /// let mut buf = [MaybeUninit::uninit(); 4];
+    /// let n = queue.pop_many(&mut buf);
+    /// let popped: Vec<i32> = buf[..n].iter().map(|v| unsafe { v.assume_init() }).collect();
+    /// assert_eq!(popped, vec![0, 1, 2, 3]);
+    /// assert_eq!(queue.pop_many(&mut buf), 1);
+    /// assert_eq!(queue.pop_many(&mut buf), 0);
+    /// ```
+    ///
+    pub fn pop_many(&mut self, out: &mut [MaybeUninit<T>]) -> usize {
+        match self.try_pop_many(out) {
+            Ok(ret) => ret,
+            Err(e) => panic!("pop_many fail, {}", e),
+        }
+    }
+
+    /// Same as `pop_many`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    ///
+    /// The head is moved past a batch of elements by one CAS like `split_off`, instead of one CAS
+    /// per element, and they are moved into `out` without wrapping each in `Option`, for batch
+    /// consumers. Another batch is taken if `retain` removed some of the detached elements
+    /// meanwhile, until `out` is full or current queue is empty.
+    pub fn try_pop_many(&mut self, out: &mut [MaybeUninit<T>]) -> Result<usize, QueueError> {
+        unsafe { self.inner_pop_many(out) }
+    }
+
+    unsafe fn inner_pop_many(&mut self, out: &mut [MaybeUninit<T>]) -> Result<usize, QueueError> {
+        let cursor = self.hazard_epoch.cursor()?;
+        let mut cnt = 0;
+        while cnt < out.len() {
+            let (first, last) = Self::detach(&self.head, Some(out.len() - cnt));
+            if first == last {
+                break;
+            }
+            let mut iter = first;
+            while iter != last {
+                let next = (*iter).next();
+                if let Some(v) = (*next).take() {
+                    out[cnt] = MaybeUninit::new(v);
+                    cnt += 1;
+                }
+                cursor.retire(iter)?;
+                iter = next;
+            }
+        }
+        stats::gauge_add(stats::QUEUE_DEPTH, -(cnt as i64));
+        Ok(cnt)
+    }
+
+    /// Drop all elements of current queue, and return the number of them. Panic if `try_clear`
+    /// fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::lockfree_queue::LockFreeQueue;
+    ///
+    /// let mut queue = LockFreeQueue::default_new_in_heap();
+    /// for i in 0..5 {
+    ///     queue.push(i);
+    /// }
+    /// assert_eq!(queue.clear(), 5);
+    /// assert!(queue.pop().is_none());
+    /// queue.push(5);
+    /// assert_eq!(queue.pop(), Some(5));
+    /// ```
+    ///
+    pub fn clear(&mut self) -> usize {
+        match self.try_clear() {
+            Ok(ret) => ret,
+            Err(e) => panic!("clear fail, {}", e),
+        }
+    }
+
+    /// Same as `clear`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    ///
+    /// Unlike `destroy`, it can be called at the same time as pushes and pops. The head is moved
+    /// past all linked elements by one CAS like `split_off`, and their nodes are retired through
+    /// `HazardEpoch`, so threads still reading them are safe. Elements being pushed at the same
+    /// time may be left in current queue.
+    pub fn try_clear(&mut self) -> Result<usize, QueueError> {
+        unsafe { self.inner_drain(drop) }
+    }
+
+    /// Detach all linked elements by one CAS, and call `f` with each of them in FIFO order.
+    /// Return the number of them.
+    unsafe fn inner_drain<F: FnMut(T)>(&mut self, mut f: F) -> Result<usize, QueueError> {
+        // Released when dropped, even if `f` panics.
+        let cursor = self.hazard_epoch.cursor()?;
+        let (first, last) = Self::detach(&self.head, Some(usize::max_value()));
+        let mut cnt = 0;
+        let mut iter = first;
+        while iter != last {
+            let next = (*iter).next();
+            if let Some(v) = (*next).take() {
+                f(v);
+                cnt += 1;
+            }
+            cursor.retire(iter)?;
+            iter = next;
+        }
+        stats::gauge_add(stats::QUEUE_DEPTH, -(cnt as i64));
+        Ok(cnt)
+    }
+
+    /// Remove elements `f` returns false for, and return the number of removed ones. Panic if
+    /// `try_retain` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::lockfree_queue::LockFreeQueue;
+    ///
+    /// let mut queue = LockFreeQueue::default_new_in_heap();
+    /// for i in 0..10 {
+    ///     queue.push(i);
+    /// }
+    /// assert_eq!(queue.retain(|v| v % 3 != 0), 4);
+    /// for i in [1, 2, 4, 5, 7, 8].iter() {
+    ///     assert_eq!(queue.pop(), Some(*i));
+    /// }
+    /// assert!(queue.pop().is_none());
+    /// ```
+    ///
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, f: F) -> usize {
+        match self.try_retain(f) {
+            Ok(ret) => ret,
+            Err(e) => panic!("retain fail, {}", e),
+        }
+    }
+
+    /// Same as `retain`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    ///
+    /// It walks the queue at the same time as pushes and pops, like cancelling queued work items.
+    /// Each element is claimed by CAS before `f` checks it, so a pop reaching it waits for `f`,
+    /// and an element popped or checked by another `retain` meanwhile is skipped. Removed elements
+    /// are dropped at once, and their nodes are unlinked by later pops, which skip them. Elements
+    /// pushed at the same time may not be checked.
+    pub fn try_retain<F: FnMut(&T) -> bool>(&mut self, f: F) -> Result<usize, QueueError> {
+        unsafe { self.inner_retain(f) }
+    }
+
+    unsafe fn inner_retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) -> Result<usize, QueueError> {
+        // Released when dropped, even if `f` panics.
+        let _cursor = self.hazard_epoch.cursor()?;
+        let mut cnt = 0;
+        let mut iter = (*self.head.load(Ordering::SeqCst)).next();
+        while !iter.is_null() {
+            let mut backoff = util::Backoff::new();
+            let claimed = loop {
+                match (*iter).state.compare_exchange(
+                    ELEM_READY,
+                    ELEM_CHECKING,
+                    util::ORDER_ACQUIRE,
+                    util::ORDER_ACQUIRE,
+                ) {
+                    Ok(_) => break true,
+                    // `Debug` releases it soon.
+                    Err(ELEM_READING) => backoff.snooze(),
+                    Err(_) => break false,
+                }
+            };
+            if claimed {
+                let checking = Checking(&(*iter).state);
+                if !f((*iter).value.as_ref().unwrap()) {
+                    mem::forget(checking);
+                    let v = (*iter).value.take();
+                    (*iter).state.store(ELEM_TAKEN, util::ORDER_RELEASE);
+                    drop(v);
+                    cnt += 1;
+                }
+            }
+            iter = (*iter).next();
+        }
+        stats::gauge_add(stats::QUEUE_DEPTH, -(cnt as i64));
+        Ok(cnt)
     }
 
     pub unsafe fn destroy(&mut self) {
-        let mut head = *self.head;
+        let mut head = self.head.load(Ordering::SeqCst);
+        let mut depth = 0;
         while !head.is_null() {
-            head = Box::from_raw(head).next;
+            let node = Box::from_raw(head);
+            depth += node.value.is_some() as i64;
+            head = node.next.load(Ordering::SeqCst);
         }
-        self.head = util::WrappedAlign64Type(ptr::null_mut());
-        self.tail = util::WrappedAlign64Type(ptr::null_mut());
+        stats::gauge_add(stats::QUEUE_DEPTH, -depth);
+        self.head.store(ptr::null_mut(), Ordering::SeqCst);
+        self.tail.store(ptr::null_mut(), Ordering::SeqCst);
     }
-}
 
-impl<T> Drop for LockFreeQueue<T> {
-    fn drop(&mut self) {
-        unsafe {
-            self.destroy();
+    /// Check internal invariants: nodes from head are acyclic and end at tail, only the head node
+    /// and nodes of removed elements have no element, and `HazardEpoch::verify` passes. Only available with feature
+    /// `debug-assert-invariants`. No other thread may use current queue at the same time.
+    #[cfg(feature = "debug-assert-invariants")]
+    pub unsafe fn verify(&self) -> Result<(), InvariantError> {
+        let head = self.head.load(Ordering::SeqCst);
+        if head.is_null() {
+            return Err(InvariantError::BadNode { what: "null head" });
+        }
+        let (_, last) = util::verify_list(head, |n| (*n).next(), "queue")?;
+        if last != self.tail.load(Ordering::SeqCst) {
+            return Err(InvariantError::BadNode {
+                what: "tail which is not the last node",
+            });
+        }
+        if (*head).value.is_some() {
+            return Err(InvariantError::BadNode {
+                what: "head node with element",
+            });
+        }
+        let mut iter = (*head).next();
+        while !iter.is_null() {
+            if (*iter).value.is_none() != (*iter).is_taken() {
+                return Err(InvariantError::BadNode {
+                    what: "queue node without element",
+                });
+            }
+            iter = (*iter).next();
         }
+        self.hazard_epoch.verify()
     }
 }
 
-mod test {
-    use std::cell::RefCell;
+#[cfg(feature = "serde")]
+impl<T> LockFreeQueue<T> {
+    /// Serialize elements as a sequence in FIFO order without removing them, protected by
+    /// `HazardEpoch`. It's safe to call while other threads use current queue: each element is
+    /// claimed by CAS like `Debug` does, and held until serializing finishes, so pops and `retain`
+    /// reaching it wait. Elements pushed at the same time may be left out.
+    pub fn serialize_snapshot<S>(&mut self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        let _cursor = self.hazard_epoch.cursor().map_err(S::Error::custom)?;
+        // Claim all elements first, so that the sequence has a known length.
+        let mut elements = vec![];
+        let mut node = unsafe { (*self.head.load(Ordering::SeqCst)).next() };
+        while !node.is_null() {
+            let node_ref = unsafe { &*node };
+            // Nodes of removed elements are skipped.
+            if let Some(reading) = node_ref.read() {
+                elements.push((reading, node_ref.value.as_ref().unwrap()));
+            }
+            node = node_ref.next();
+        }
+        serializer.collect_seq(elements.iter().map(|&(_, v)| v))
+    }
 
-    struct Node<'a, T> {
-        cnt: &'a RefCell<i32>,
-        v: T,
+    /// Pop all elements and serialize them as a sequence in FIFO order. Elements pushed at the
+    /// same time may be left in current queue.
+    pub fn serialize_drain<S>(&mut self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        // Pop all first, so that the sequence has a known length.
+        let elements: Vec<T> = iter::from_fn(|| self.pop()).collect();
+        serializer.collect_seq(&elements)
     }
 
-    impl<'a, T> Drop for Node<'a, T> {
-        fn drop(&mut self) {
-            *self.cnt.borrow_mut() += 1;
+    /// Return LockFreeQueue in heap holding elements of a sequence serialized by
+    /// `serialize_snapshot` or `serialize_drain`, in the same order. It can be used by
+    /// `#[serde(deserialize_with = "LockFreeQueue::deserialize_in_heap")]`.
+    pub fn deserialize_in_heap<'de, D>(deserializer: D) -> Result<Box<Self>, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        let mut ret = Self::default_new_in_heap();
+        for v in values {
+            ret.try_push(v).map_err(|e| D::Error::custom(e.error))?;
         }
+        Ok(ret)
     }
+}
 
-    #[test]
-    fn test_base() {
-        use lockfree_queue::LockFreeQueue;
-        let mut queue = unsafe { LockFreeQueue::default_new_in_stack() };
-        assert!(queue.pop().is_none());
-        queue.push(1);
-        assert_eq!(queue.pop().unwrap(), 1);
-        let test_num = 100;
-        for i in 0..test_num {
-            queue.push(i);
+#[cfg(feature = "rayon")]
+impl<T: Send> LockFreeQueue<T> {
+    /// Return a parallel iterator of `rayon` over all elements, which are detached by current
+    /// thread at once. FIFO order is not kept. Panic if `try_par_drain` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rayon;
+    /// extern crate rs_lockfree;
+    ///
+    /// use rayon::prelude::*;
+    /// use rs_lockfree::lockfree_queue::LockFreeQueue;
+    ///
+    /// # fn main() {
+    /// let mut queue = LockFreeQueue::default_new_in_heap();
+    /// for i in 0..100 {
+    ///     queue.push(i);
+    /// }
+    /// let drain = queue.par_drain();
+    /// assert!(queue.pop().is_none());
+    /// // Workers never use `HazardEpoch`, so the pool can be of any size.
+    /// let pool = rayon::ThreadPoolBuilder::new().num_threads(32).build().unwrap();
+    /// assert_eq!(pool.install(move || drain.sum::<i32>()), 4950);
+    /// # }
+    /// ```
+    ///
+    pub fn par_drain(&mut self) -> ParDrain<T> {
+        match self.try_par_drain() {
+            Ok(ret) => ret,
+            Err(e) => panic!("par_drain fail, {}", e),
         }
-        for i in 0..test_num {
-            assert_eq!(queue.pop().unwrap(), i);
+    }
+
+    /// Same as `par_drain`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    pub fn try_par_drain(&mut self) -> Result<ParDrain<T>, QueueError> {
+        let mut elements = vec![];
+        unsafe { self.inner_drain(|v| elements.push(v))? };
+        Ok(ParDrain::new(elements))
+    }
+}
+
+impl<T> ConcurrentQueue<T> for LockFreeQueue<T> {
+    fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        LockFreeQueue::try_push(self, v)
+    }
+
+    fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
+        LockFreeQueue::try_pop(self)
+    }
+}
+
+/// Elements of nodes after `head` until `tail`, or until a node not linked yet.
+struct DebugElements<T> {
+    head: FIFONodePtr<T>,
+    tail: FIFONodePtr<T>,
+    len: usize,
+}
+
+impl<T> DebugElements<T> {
+    unsafe fn nodes(&self) -> impl Iterator<Item = FIFONodePtr<T>> {
+        let (mut iter, tail) = (self.head, self.tail);
+        iter::from_fn(move || {
+            if iter == tail || iter.is_null() {
+                return None;
+            }
+            iter = (*iter).next();
+            if iter.is_null() {
+                None
+            } else {
+                Some(iter)
+            }
+        })
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DebugElements<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut list = f.debug_list();
+        let mut cnt = 0;
+        for node in unsafe { self.nodes() } {
+            if cnt == DEBUG_ELEMENTS {
+                break;
+            }
+            if let Some(_reading) = unsafe { (*node).read() } {
+                list.entry(unsafe { (*node).value.as_ref().unwrap() });
+                cnt += 1;
+            }
+        }
+        if cnt < self.len {
+            list.finish_non_exhaustive()
+        } else {
+            list.finish()
+        }
+    }
+}
+
+/// Show the number of elements and the first 16 of them, protected by `HazardEpoch`, like
+/// `LockFreeQueue { len: 2, elements: [0, 1] }`, with `..` closing `elements` if any is left out.
+/// It's safe to call while other threads use current queue: each element is claimed by CAS while
+/// it's formatted, so a pop reaching it waits, and elements pushed after formatting starts are left
+/// out. Elements may be popped between counting and formatting, so `len` is only a hint.
+impl<T: fmt::Debug> fmt::Debug for LockFreeQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let handle = match self.hazard_epoch.try_acquire() {
+            Ok(handle) => handle,
+            Err(e) => return write!(f, "LockFreeQueue {{ error: {} }}", e),
+        };
+        let head = self.head.load(Ordering::SeqCst);
+        let mut elements = DebugElements {
+            head,
+            tail: self.tail.load(Ordering::SeqCst),
+            len: 0,
+        };
+        elements.len = unsafe { elements.nodes() }
+            .filter(|node| unsafe { !(**node).is_taken() })
+            .count();
+        let ret = f.debug_struct("LockFreeQueue")
+            .field("len", &elements.len)
+            .field("elements", &elements)
+            .finish();
+        // Reclaiming needs `&mut HazardEpoch`, so it's left to later operations.
+        unsafe { self.hazard_epoch.release_no_reclaim(handle) };
+        ret
+    }
+}
+
+impl<T> Drop for LockFreeQueue<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy();
         }
     }
+}
 
+mod test {
     #[test]
-    fn test_memory_leak() {
+    #[cfg(feature = "debug-assert-invariants")]
+    fn test_verify() {
+        use error::InvariantError;
         use lockfree_queue::LockFreeQueue;
-        let cnt = RefCell::new(0);
-        let mut queue = unsafe { LockFreeQueue::default_new_in_stack() };
-        let test_num = 100;
-        for i in 0..test_num {
-            queue.push(Node { cnt: &cnt, v: i });
-        }
+        use std::ptr;
+        use std::sync::atomic::Ordering;
+
+        let mut queue = LockFreeQueue::default_new_in_heap();
         unsafe {
-            assert!((**queue.head).value.is_none());
-        }
-        assert_eq!(*cnt.borrow(), 0);
-        for i in 0..test_num {
-            assert_eq!(queue.pop().unwrap().v, i);
+            assert_eq!(queue.verify(), Ok(()));
+            for i in 0..3 {
+                queue.push(i);
+            }
+            queue.pop();
+            assert_eq!(queue.verify(), Ok(()));
+            assert_eq!(queue.retain(|v| 1 != *v), 1);
+            assert_eq!(queue.verify(), Ok(()));
+
+            let tail = queue.tail.load(Ordering::SeqCst);
+            let head = queue.head.load(Ordering::SeqCst);
+            queue.tail.store(head, Ordering::SeqCst);
+            assert_eq!(
+                queue.verify(),
+                Err(InvariantError::BadNode {
+                    what: "tail which is not the last node"
+                })
+            );
+            queue.tail.store(tail, Ordering::SeqCst);
+            (*tail).set_next(head);
+            assert_eq!(queue.verify(), Err(InvariantError::Cyclic { list: "queue" }));
+            (*tail).set_next(ptr::null_mut());
+            assert_eq!(queue.verify(), Ok(()));
         }
-        assert_eq!(*cnt.borrow(), test_num);
     }
 }
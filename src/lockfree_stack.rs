@@ -1,16 +1,45 @@
 //! Definition and implementations of `LockFreeStack`
 //!
-use hazard_epoch::HazardEpoch;
+use error::{PushError, QueueError};
+#[cfg(feature = "debug-assert-invariants")]
+use error::InvariantError;
+use hazard_epoch::{HazardEpoch, PendingPolicy};
 use hazard_pointer::{BaseHazardNode, HazardNodeT};
 use util;
+use stats;
+use traits::ConcurrentStack;
+use std::fmt;
+use std::iter;
+use std::mem::MaybeUninit;
 use std::ptr;
+use std::sync::atomic::{AtomicU8, Ordering};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "serde")]
+use serde::de::Error as DeError;
+#[cfg(feature = "serde")]
+use serde::ser::Error as SerError;
+#[cfg(feature = "rayon")]
+use par_drain::ParDrain;
 
 type LIFONodePtr<T> = *mut LIFONode<T>;
 
+/// Element of a node is ready to be taken.
+const ELEM_READY: u8 = 0;
+/// Element of a node is being formatted by `Debug`.
+const ELEM_READING: u8 = 1;
+/// Element of a node has been popped.
+const ELEM_TAKEN: u8 = 2;
+
+/// Maximum number of elements shown by `Debug`.
+const DEBUG_ELEMENTS: usize = 16;
+
 struct LIFONode<T> {
     value: Option<T>,
     base: BaseHazardNode,
     next: LIFONodePtr<T>,
+    /// State of the element, so that pop waits for `Debug` reading it.
+    state: AtomicU8,
 }
 
 impl<T> HazardNodeT for LIFONode<T> {
@@ -29,6 +58,7 @@ impl<T> Default for LIFONode<T> {
             value: None,
             base: BaseHazardNode::default(),
             next: ptr::null_mut(),
+            state: AtomicU8::new(ELEM_TAKEN),
         }
     }
 }
@@ -47,10 +77,55 @@ impl<T> LIFONode<T> {
             value: Some(value),
             base: BaseHazardNode::default(),
             next: ptr::null_mut(),
+            state: AtomicU8::new(ELEM_READY),
+        }
+    }
+
+    /// Take the element, waiting for `Debug` reading it. Return None if it's taken already.
+    fn take(&mut self) -> Option<T> {
+        let mut backoff = util::Backoff::new();
+        loop {
+            match self.state.compare_exchange(
+                ELEM_READY,
+                ELEM_TAKEN,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return self.value.take(),
+                Err(ELEM_READING) => backoff.snooze(),
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Claim the element for reading, waiting for others reading it, and return a guard releasing
+    /// it. Return None if it's taken already.
+    fn read<'a>(&'a self) -> Option<Reading<'a>> {
+        let mut backoff = util::Backoff::new();
+        loop {
+            match self.state.compare_exchange(
+                ELEM_READY,
+                ELEM_READING,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return Some(Reading(&self.state)),
+                Err(ELEM_TAKEN) => return None,
+                Err(_) => backoff.snooze(),
+            }
         }
     }
 }
 
+/// Set the element read by `Debug` ready again when dropped, even if formatting panics.
+struct Reading<'a>(&'a AtomicU8);
+
+impl<'a> Drop for Reading<'a> {
+    fn drop(&mut self) {
+        self.0.store(ELEM_READY, Ordering::SeqCst);
+    }
+}
+
 /// LockFree stack, implemented based on `HazardEpoch`
 ///
 /// # Examples
@@ -72,19 +147,15 @@ impl<T> LIFONode<T> {
 ///
 pub struct LockFreeStack<T> {
     hazard_epoch: HazardEpoch,
-    top: util::WrappedAlign64Type<LIFONodePtr<T>>,
+    top: util::WrappedAlign64Type<util::AtomicRawPtr<LIFONode<T>>>,
 }
 
 impl<T> LockFreeStack<T> {
-    unsafe fn atomic_load_top(&self) -> LIFONodePtr<T> {
-        util::atomic_load_raw_ptr(self.top.as_ptr())
-    }
-
     /// Return LockFreeStack in stack with default setting of HazardEpoch
     pub unsafe fn default_new_in_stack() -> LockFreeStack<T> {
         LockFreeStack {
             hazard_epoch: HazardEpoch::default_new_in_stack(),
-            top: util::WrappedAlign64Type(ptr::null_mut()),
+            top: util::WrappedAlign64Type(util::AtomicRawPtr::default()),
         }
     }
 
@@ -93,62 +164,508 @@ impl<T> LockFreeStack<T> {
         unsafe { Box::new(Self::default_new_in_stack()) }
     }
 
-    /// Push an element to the top of current stack
+    /// Limit the number of popped nodes waiting to be reclaimed, see
+    /// `HazardEpoch::set_pending_limit`. Pushing checks the limit.
+    pub fn set_pending_limit(&mut self, limit: Option<i64>, policy: PendingPolicy) {
+        self.hazard_epoch.set_pending_limit(limit, policy);
+    }
+
+    /// Push an element to the top of current stack. Panic if `try_push` fails.
     pub fn push(&mut self, v: T) {
+        if let Err(e) = self.try_push(v) {
+            panic!("push fail, {}", e);
+        }
+    }
+
+    /// Push an element to the top of current stack. Return `QueueError::AllocFailed` if memory
+    /// of node can't be allocated, or `QueueError::Hazard` if `HazardEpoch` fails, including
+    /// `HazardError::PendingLimit` under `PendingPolicy::Reject`. The element is given back in
+    /// both cases.
+    pub fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
         unsafe { self.inner_push(v) }
     }
 
-    unsafe fn inner_push(&mut self, v: T) {
-        let node = Box::into_raw(Box::new(LIFONode::new(v)));
-        let mut handle = 0_u64;
-        self.hazard_epoch.acquire(&mut handle);
-        let mut cur = self.atomic_load_top();
+    unsafe fn inner_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        if let Err(e) = self.hazard_epoch.check_pending() {
+            return Err(PushError {
+                error: e.into(),
+                value: v,
+            });
+        }
+        let node = match util::try_box_into_raw(LIFONode::new(v)) {
+            Ok(node) => node,
+            Err(mut node) => {
+                return Err(PushError {
+                    error: QueueError::AllocFailed { op: "push" },
+                    value: node.value.take().unwrap(),
+                })
+            }
+        };
+        let handle = match self.hazard_epoch.try_acquire() {
+            Ok(handle) => handle,
+            Err(e) => {
+                return Err(PushError {
+                    error: e.into(),
+                    value: Box::from_raw(node).value.take().unwrap(),
+                })
+            }
+        };
+        let mut cur = self.top.load(Ordering::SeqCst);
         let mut old = cur;
         (*node).set_next(old);
+        let mut backoff = util::Backoff::new();
         while !{
-            let (tmp, b) = util::atomic_cxchg_raw_ptr(self.top.as_mut_ptr(), old, node);
+            let (tmp, b) = self.top.cas(old, node, Ordering::SeqCst);
             cur = tmp;
             b
         } {
             old = cur;
             (*node).set_next(old);
+            backoff.spin();
         }
         self.hazard_epoch.release(handle);
+        stats::gauge_add(stats::STACK_DEPTH, 1);
+        Ok(())
     }
 
-    /// Pop the element at the top of current queue
+    /// Pop the element at the top of current queue. Panic if `try_pop` fails.
     pub fn pop(&mut self) -> Option<T> {
+        match self.try_pop() {
+            Ok(ret) => ret,
+            Err(e) => panic!("pop fail, {}", e),
+        }
+    }
+
+    /// Same as `pop`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    pub fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
         unsafe { self.inner_pop() }
     }
 
-    unsafe fn inner_pop(&mut self) -> Option<T> {
+    unsafe fn inner_pop(&mut self) -> Result<Option<T>, QueueError> {
         let mut ret = None;
-        let mut handle = 0_u64;
-        self.hazard_epoch.acquire(&mut handle);
-        let mut cur = self.atomic_load_top();
+        let handle = self.hazard_epoch.try_acquire()?;
+        let mut cur = self.top.load(Ordering::SeqCst);
         let mut old = cur;
+        let mut backoff = util::Backoff::new();
         while !cur.is_null() && !{
-            let (tmp, b) = util::atomic_cxchg_raw_ptr(self.top.as_mut_ptr(), old, (*cur).next());
+            let (tmp, b) = self.top.cas(old, (*cur).next(), Ordering::SeqCst);
             cur = tmp;
             b
         } {
             old = cur;
+            backoff.spin();
         }
         if !cur.is_null() {
-            ret = (*cur).value.take();
+            ret = (*cur).take();
             assert!(ret.is_some());
-            self.hazard_epoch.add_node(cur);
+            self.hazard_epoch.try_add_node(cur).unwrap();
+            stats::gauge_add(stats::STACK_DEPTH, -1);
         }
         self.hazard_epoch.release(handle);
-        ret
+        Ok(ret)
+    }
+
+    /// Pop the element at the top of current stack only if `f` returns true on it. Return None if
+    /// current stack is empty or `f` returns false, in which case the element stays on top. Panic
+    /// if `try_pop_if` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::lockfree_stack::LockFreeStack;
+    ///
+    /// let mut stack = LockFreeStack::default_new_in_heap();
+    /// stack.push(3);
+    /// stack.push(8);
+    /// // Only take work fitting in a budget of 5.
+    /// assert_eq!(stack.pop_if(|&cost| cost <= 5), None);
+    /// assert_eq!(stack.pop(), Some(8));
+    /// assert_eq!(stack.pop_if(|&cost| cost <= 5), Some(3));
+    /// ```
+    ///
+    pub fn pop_if<F: FnMut(&T) -> bool>(&mut self, f: F) -> Option<T> {
+        match self.try_pop_if(f) {
+            Ok(ret) => ret,
+            Err(e) => panic!("pop_if fail, {}", e),
+        }
+    }
+
+    /// Same as `pop_if`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    ///
+    /// The top is read under the handle and removed by a CAS from it, so no other element can be
+    /// popped in between, unlike popping and pushing back. `f` is called again on the new top
+    /// each time the top changes before the CAS.
+    pub fn try_pop_if<F: FnMut(&T) -> bool>(&mut self, f: F) -> Result<Option<T>, QueueError> {
+        unsafe { self.inner_pop_if(f) }
+    }
+
+    unsafe fn inner_pop_if<F: FnMut(&T) -> bool>(
+        &mut self,
+        mut f: F,
+    ) -> Result<Option<T>, QueueError> {
+        let mut ret = None;
+        // Released when dropped, even if `f` panics.
+        let cursor = self.hazard_epoch.cursor()?;
+        let mut backoff = util::Backoff::new();
+        loop {
+            let cur = self.top.load(Ordering::SeqCst);
+            if cur.is_null() {
+                break;
+            }
+            // Reading keeps a concurrent pop from taking the element until `f` returns.
+            let pass = match (*cur).read() {
+                Some(_reading) => f((*cur).value.as_ref().unwrap()),
+                None => {
+                    // Popped meanwhile, check the new top.
+                    backoff.spin();
+                    continue;
+                }
+            };
+            if !pass {
+                break;
+            }
+            if self.top.cas(cur, (*cur).next(), Ordering::SeqCst).1 {
+                ret = (*cur).take();
+                assert!(ret.is_some());
+                cursor.retire(cur)?;
+                stats::gauge_add(stats::STACK_DEPTH, -1);
+                break;
+            }
+            backoff.spin();
+        }
+        Ok(ret)
+    }
+
+    /// Pop up to `out.len()` elements into `out` from the top, and return the number of them,
+    /// which are initialized in `out[..n]`. Return 0 if current stack is empty. Panic if
+    /// `try_pop_many` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::lockfree_stack::LockFreeStack;
+    /// use std::mem::MaybeUninit;
+    ///
+    /// let mut stack = LockFreeStack::default_new_in_heap();
+    /// for i in 0..5 {
+    ///     stack.push(i);
+    /// }
+    /// let mut buf = [MaybeUninit::uninit(); 4];
+    /// let n = stack.pop_many(&mut buf);
+    /// let popped: Vec<i32> = buf[..n].iter().map(|v| unsafe { v.assume_init() }).collect();
+    /// assert_eq!(popped, vec![4, 3, 2, 1]);
+    /// assert_eq!(stack.pop_many(&mut buf), 1);
+    /// assert_eq!(stack.pop_many(&mut buf), 0);
+    /// ```
+    ///
+    pub fn pop_many(&mut self, out: &mut [MaybeUninit<T>]) -> usize {
+        match self.try_pop_many(out) {
+            Ok(ret) => ret,
+            Err(e) => panic!("pop_many fail, {}", e),
+        }
+    }
+
+    /// Same as `pop_many`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    ///
+    /// The top is moved past a batch of elements by one CAS, instead of one CAS per element, and
+    /// they are moved into `out` without wrapping each in `Option`, for batch consumers.
+    pub fn try_pop_many(&mut self, out: &mut [MaybeUninit<T>]) -> Result<usize, QueueError> {
+        unsafe { self.inner_pop_many(out) }
+    }
+
+    unsafe fn inner_pop_many(&mut self, out: &mut [MaybeUninit<T>]) -> Result<usize, QueueError> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        let cursor = self.hazard_epoch.cursor()?;
+        let mut backoff = util::Backoff::new();
+        // Nodes held are never reused, so the CAS fails if any of them has been popped.
+        let (first, end) = loop {
+            let top = self.top.load(Ordering::SeqCst);
+            let mut end = top;
+            let mut cnt = 0;
+            while !end.is_null() && cnt < out.len() {
+                end = (*end).next();
+                cnt += 1;
+            }
+            if top.is_null() || self.top.cas(top, end, Ordering::SeqCst).1 {
+                break (top, end);
+            }
+            backoff.spin();
+        };
+        let mut cnt = 0;
+        let mut iter = first;
+        while iter != end {
+            let next = (*iter).next();
+            let v = (*iter).take();
+            out[cnt] = MaybeUninit::new(v.unwrap());
+            cnt += 1;
+            cursor.retire(iter)?;
+            iter = next;
+        }
+        stats::gauge_add(stats::STACK_DEPTH, -(cnt as i64));
+        Ok(cnt)
+    }
+
+    /// Drop all elements of current stack, and return the number of them. Panic if `try_clear`
+    /// fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::lockfree_stack::LockFreeStack;
+    ///
+    /// let mut stack = LockFreeStack::default_new_in_heap();
+    /// for i in 0..5 {
+    ///     stack.push(i);
+    /// }
+    /// assert_eq!(stack.clear(), 5);
+    /// assert!(stack.pop().is_none());
+    /// stack.push(5);
+    /// assert_eq!(stack.pop(), Some(5));
+    /// ```
+    ///
+    pub fn clear(&mut self) -> usize {
+        match self.try_clear() {
+            Ok(ret) => ret,
+            Err(e) => panic!("clear fail, {}", e),
+        }
+    }
+
+    /// Same as `clear`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    ///
+    /// Unlike `destroy`, it can be called at the same time as pushes and pops. The top is swapped
+    /// with null, which detaches all elements at once, and their nodes are retired through
+    /// `HazardEpoch`, so threads still reading them are safe.
+    pub fn try_clear(&mut self) -> Result<usize, QueueError> {
+        unsafe { self.inner_drain(drop) }
+    }
+
+    /// Detach all elements by one swap, and call `f` with each of them from top to bottom. Return
+    /// the number of them.
+    unsafe fn inner_drain<F: FnMut(T)>(&mut self, mut f: F) -> Result<usize, QueueError> {
+        // Released when dropped, even if `f` panics.
+        let cursor = self.hazard_epoch.cursor()?;
+        let mut iter = self.top.swap(ptr::null_mut(), Ordering::SeqCst);
+        let mut cnt = 0;
+        while !iter.is_null() {
+            let next = (*iter).next();
+            if let Some(v) = (*iter).take() {
+                f(v);
+                cnt += 1;
+            }
+            cursor.retire(iter)?;
+            iter = next;
+        }
+        stats::gauge_add(stats::STACK_DEPTH, -(cnt as i64));
+        Ok(cnt)
     }
 
     pub unsafe fn destroy(&mut self) {
-        let mut head = *self.top;
+        let mut head = self.top.load(Ordering::SeqCst);
+        let mut depth = 0;
         while !head.is_null() {
-            head = Box::from_raw(head).next;
+            let node = Box::from_raw(head);
+            depth += node.value.is_some() as i64;
+            head = node.next;
+        }
+        stats::gauge_add(stats::STACK_DEPTH, -depth);
+        self.top.store(ptr::null_mut(), Ordering::SeqCst);
+    }
+
+    /// Check internal invariants: nodes from top are acyclic and all hold elements, and
+    /// `HazardEpoch::verify` passes. Only available with feature `debug-assert-invariants`. No
+    /// other thread may use current stack at the same time.
+    #[cfg(feature = "debug-assert-invariants")]
+    pub unsafe fn verify(&self) -> Result<(), InvariantError> {
+        let top = self.top.load(Ordering::SeqCst);
+        util::verify_list(top, |n| (*n).next(), "stack")?;
+        let mut iter = top;
+        while !iter.is_null() {
+            if (*iter).value.is_none() {
+                return Err(InvariantError::BadNode {
+                    what: "stack node without element",
+                });
+            }
+            iter = (*iter).next();
+        }
+        self.hazard_epoch.verify()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T> LockFreeStack<T> {
+    /// Serialize elements as a sequence from top to bottom without removing them, protected by
+    /// `HazardEpoch`. It's safe to call while other threads use current stack: each element is
+    /// claimed by CAS like `Debug` does, and held until serializing finishes, so pops reaching it
+    /// wait. Elements pushed at the same time may be left out.
+    pub fn serialize_snapshot<S>(&mut self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        let _cursor = self.hazard_epoch.cursor().map_err(S::Error::custom)?;
+        // Claim all elements first, so that the sequence has a known length.
+        let mut elements = vec![];
+        let mut node = self.top.load(Ordering::SeqCst);
+        while !node.is_null() {
+            let node_ref = unsafe { &*node };
+            if let Some(reading) = node_ref.read() {
+                elements.push((reading, node_ref.value.as_ref().unwrap()));
+            }
+            node = node_ref.next();
+        }
+        serializer.collect_seq(elements.iter().map(|&(_, v)| v))
+    }
+
+    /// Pop all elements and serialize them as a sequence from top to bottom. Elements pushed at
+    /// the same time may be left in current stack.
+    pub fn serialize_drain<S>(&mut self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        // Pop all first, so that the sequence has a known length.
+        let elements: Vec<T> = iter::from_fn(|| self.pop()).collect();
+        serializer.collect_seq(&elements)
+    }
+
+    /// Return LockFreeStack in heap holding elements of a sequence serialized by
+    /// `serialize_snapshot` or `serialize_drain`, with the first one at the top. It can be used by
+    /// `#[serde(deserialize_with = "LockFreeStack::deserialize_in_heap")]`.
+    pub fn deserialize_in_heap<'de, D>(deserializer: D) -> Result<Box<Self>, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        let mut ret = Self::default_new_in_heap();
+        for v in values.into_iter().rev() {
+            ret.try_push(v).map_err(|e| D::Error::custom(e.error))?;
         }
-        self.top = util::WrappedAlign64Type(ptr::null_mut());
+        Ok(ret)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send> LockFreeStack<T> {
+    /// Return a parallel iterator of `rayon` over all elements, which are detached by current
+    /// thread at once. LIFO order is not kept. Panic if `try_par_drain` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate rayon;
+    /// extern crate rs_lockfree;
+    ///
+    /// use rayon::prelude::*;
+    /// use rs_lockfree::lockfree_stack::LockFreeStack;
+    ///
+    /// # fn main() {
+    /// let mut stack = LockFreeStack::default_new_in_heap();
+    /// for i in 0..100 {
+    ///     stack.push(i);
+    /// }
+    /// let drain = stack.par_drain();
+    /// assert!(stack.pop().is_none());
+    /// // Workers never use `HazardEpoch`, so the pool can be of any size.
+    /// let pool = rayon::ThreadPoolBuilder::new().num_threads(32).build().unwrap();
+    /// assert_eq!(pool.install(move || drain.sum::<i32>()), 4950);
+    /// # }
+    /// ```
+    ///
+    pub fn par_drain(&mut self) -> ParDrain<T> {
+        match self.try_par_drain() {
+            Ok(ret) => ret,
+            Err(e) => panic!("par_drain fail, {}", e),
+        }
+    }
+
+    /// Same as `par_drain`, but return `QueueError::Hazard` if `HazardEpoch` fails.
+    pub fn try_par_drain(&mut self) -> Result<ParDrain<T>, QueueError> {
+        let mut elements = vec![];
+        unsafe { self.inner_drain(|v| elements.push(v))? };
+        Ok(ParDrain::new(elements))
+    }
+}
+
+impl<T> ConcurrentStack<T> for LockFreeStack<T> {
+    fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        LockFreeStack::try_push(self, v)
+    }
+
+    fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
+        LockFreeStack::try_pop(self)
+    }
+}
+
+/// Elements of nodes from `top` to the bottom.
+struct DebugElements<T> {
+    top: LIFONodePtr<T>,
+    len: usize,
+}
+
+impl<T> DebugElements<T> {
+    unsafe fn nodes(&self) -> impl Iterator<Item = LIFONodePtr<T>> {
+        let mut iter = self.top;
+        iter::from_fn(move || {
+            if iter.is_null() {
+                return None;
+            }
+            let node = iter;
+            iter = (*iter).next();
+            Some(node)
+        })
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for DebugElements<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut list = f.debug_list();
+        let mut cnt = 0;
+        for node in unsafe { self.nodes() } {
+            if cnt == DEBUG_ELEMENTS {
+                break;
+            }
+            if let Some(_reading) = unsafe { (*node).read() } {
+                list.entry(unsafe { (*node).value.as_ref().unwrap() });
+                cnt += 1;
+            }
+        }
+        if cnt < self.len {
+            list.finish_non_exhaustive()
+        } else {
+            list.finish()
+        }
+    }
+}
+
+/// Show the number of elements and the first 16 of them from the top, protected by `HazardEpoch`,
+/// like `LockFreeStack { len: 2, elements: [1, 0] }`, with `..` closing `elements` if any is left
+/// out. It's safe to call while other threads use current stack: each element is claimed by CAS
+/// while it's formatted, so a pop taking it waits, and elements pushed after formatting starts are
+/// left out. Elements may be popped between counting and formatting, so `len` is only a hint.
+impl<T: fmt::Debug> fmt::Debug for LockFreeStack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let handle = match self.hazard_epoch.try_acquire() {
+            Ok(handle) => handle,
+            Err(e) => return write!(f, "LockFreeStack {{ error: {} }}", e),
+        };
+        let mut elements = DebugElements {
+            top: self.top.load(Ordering::SeqCst),
+            len: 0,
+        };
+        // Popped nodes still link to the nodes under them, which are skipped as well if popped.
+        elements.len = unsafe { elements.nodes() }
+            .filter(|node| unsafe { ELEM_TAKEN != (**node).state.load(Ordering::SeqCst) })
+            .count();
+        let ret = f.debug_struct("LockFreeStack")
+            .field("len", &elements.len)
+            .field("elements", &elements)
+            .finish();
+        // Reclaiming needs `&mut HazardEpoch`, so it's left to later operations.
+        unsafe { self.hazard_epoch.release_no_reclaim(handle) };
+        ret
     }
 }
 
@@ -190,6 +707,15 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_try_push_pop() {
+        use lockfree_stack::LockFreeStack;
+        let mut q = LockFreeStack::default_new_in_heap();
+        assert_eq!(q.try_pop(), Ok(None));
+        assert!(q.try_push(1).is_ok());
+        assert_eq!(q.try_pop(), Ok(Some(1)));
+    }
+
     #[test]
     fn test_memory_leak() {
         use lockfree_stack::LockFreeStack;
@@ -205,4 +731,66 @@ mod test {
         }
         assert_eq!(*cnt.borrow(), test_num);
     }
+
+    #[test]
+    #[cfg(feature = "debug-assert-invariants")]
+    fn test_verify() {
+        use error::InvariantError;
+        use lockfree_stack::LockFreeStack;
+        use std::ptr;
+        use std::sync::atomic::Ordering;
+
+        let mut stack = LockFreeStack::default_new_in_heap();
+        unsafe {
+            assert_eq!(stack.verify(), Ok(()));
+            for i in 0..3 {
+                stack.push(i);
+            }
+            stack.pop();
+            assert_eq!(stack.verify(), Ok(()));
+
+            let top = stack.top.load(Ordering::SeqCst);
+            let bottom = (*top).next();
+            (*bottom).set_next(top);
+            assert_eq!(stack.verify(), Err(InvariantError::Cyclic { list: "stack" }));
+            (*bottom).set_next(ptr::null_mut());
+            assert_eq!(stack.verify(), Ok(()));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        use bincode;
+        use lockfree_stack::LockFreeStack;
+        use serde_json;
+        let mut stack = LockFreeStack::default_new_in_heap();
+        for i in 0..4 {
+            stack.push(i);
+        }
+        let mut buf = vec![];
+        stack.serialize_snapshot(&mut serde_json::Serializer::new(&mut buf)).unwrap();
+        assert_eq!(buf, b"[3,2,1,0]");
+        // Bincode needs the length of a sequence ahead.
+        let mut bin = vec![];
+        stack.serialize_snapshot(&mut bincode::Serializer::new(&mut bin, bincode::options()))
+            .unwrap();
+        let mut de = bincode::Deserializer::from_slice(&bin, bincode::options());
+        let mut copy = LockFreeStack::<i32>::deserialize_in_heap(&mut de).unwrap();
+        assert_eq!(
+            (0..5).map(|_| copy.pop()).collect::<Vec<_>>(),
+            [Some(3), Some(2), Some(1), Some(0), None]
+        );
+        assert_eq!(stack.pop(), Some(3));
+        let mut buf = vec![];
+        stack.serialize_drain(&mut serde_json::Serializer::new(&mut buf)).unwrap();
+        assert_eq!(buf, b"[2,1,0]");
+        assert_eq!(stack.pop(), None);
+        let mut de = serde_json::Deserializer::from_slice(&buf);
+        let mut stack = LockFreeStack::<i32>::deserialize_in_heap(&mut de).unwrap();
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), Some(0));
+        assert_eq!(stack.pop(), None);
+    }
 }
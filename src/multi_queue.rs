@@ -0,0 +1,105 @@
+//! Definition and implementations of `MultiQueue`
+//!
+use error::{PushError, QueueError};
+use lockfree_queue::LockFreeQueue;
+use numa;
+use traits::ConcurrentQueue;
+
+/// Relaxed FIFO queue of `LockFreeQueue` shards, for workloads which tolerate relaxed ordering
+/// and need throughput to scale with threads.
+///
+/// A thread pushes to and pops from its own shard, picked by `numa::shard_index`, so threads
+/// mostly touch different cache lines. A thread finding its shard empty steals from the other
+/// shards in turn. Elements pushed by one thread are popped in order from its shard, but there's
+/// no order across shards.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::multi_queue::MultiQueue;
+///
+/// let mut queue = MultiQueue::with_shards(4);
+/// for i in 0..10 {
+///     queue.push(i);
+/// }
+/// let mut popped: Vec<_> = (0..10).map(|_| queue.pop().unwrap()).collect();
+/// popped.sort();
+/// assert_eq!(popped, (0..10).collect::<Vec<_>>());
+/// assert!(queue.pop().is_none());
+/// ```
+///
+pub struct MultiQueue<T> {
+    shards: Vec<LockFreeQueue<T>>,
+}
+
+impl<T> MultiQueue<T> {
+    /// Return empty MultiQueue in heap of `shard_count` shards, which must be positive. Threads
+    /// share shards if there are more threads than shards.
+    pub fn with_shards(shard_count: usize) -> Box<MultiQueue<T>> {
+        assert!(0 < shard_count, "shard_count must be positive");
+        // Shards are not moved once the vector is built.
+        Box::new(MultiQueue {
+            shards: (0..shard_count)
+                .map(|_| unsafe { LockFreeQueue::default_new_in_stack() })
+                .collect(),
+        })
+    }
+
+    /// Return the number of shards.
+    #[inline]
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    #[inline]
+    fn local_shard(&self) -> usize {
+        numa::shard_index(self.shards.len())
+    }
+
+    /// Push an element to the shard of current thread. Panic if `try_push` fails.
+    pub fn push(&mut self, v: T) {
+        if let Err(e) = self.try_push(v) {
+            panic!("push fail, {}", e);
+        }
+    }
+
+    /// Push an element to the shard of current thread. Return error like
+    /// `LockFreeQueue::try_push`, which gives the element back.
+    pub fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        let local = self.local_shard();
+        self.shards[local].try_push(v)
+    }
+
+    /// Pop an element from the shard of current thread, or steal one from other shards if it's
+    /// empty. Panic if `try_pop` fails.
+    pub fn pop(&mut self) -> Option<T> {
+        match self.try_pop() {
+            Ok(ret) => ret,
+            Err(e) => panic!("pop fail, {}", e),
+        }
+    }
+
+    /// Same as `pop`, but return `QueueError::Hazard` if `HazardEpoch` of a shard fails. Return
+    /// None if all shards were found empty one after another, which may miss elements pushed to a
+    /// shard after it's checked.
+    pub fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
+        let shard_count = self.shards.len();
+        let local = self.local_shard();
+        for i in 0..shard_count {
+            if let Some(v) = self.shards[(local + i) % shard_count].try_pop()? {
+                return Ok(Some(v));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<T> ConcurrentQueue<T> for MultiQueue<T> {
+    fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        MultiQueue::try_push(self, v)
+    }
+
+    fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
+        MultiQueue::try_pop(self)
+    }
+}
@@ -0,0 +1,291 @@
+//! Definition and implementations of NUMA-aware helpers
+//!
+//! Topology is read from `/sys/devices/system/node` once. On other systems, or if it can't be
+//! read, the machine is treated as a single node, so that containers sharded by node still work.
+//!
+use hazard_epoch::MAX_THREAD_COUNT;
+use util;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Once, ONCE_INIT};
+
+/// Node of a thread not recorded yet.
+const UNKNOWN_NODE: usize = !0;
+
+/// Parse cpu list like `0-3,8,10-11`.
+fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut ret = vec![];
+    for range in list.trim().split(',').filter(|s| !s.is_empty()) {
+        let mut bounds = range.splitn(2, '-').map(|s| s.trim().parse::<usize>());
+        match (bounds.next(), bounds.next()) {
+            (Some(Ok(lo)), None) => ret.push(lo),
+            (Some(Ok(lo)), Some(Ok(hi))) => ret.extend(lo..hi + 1),
+            _ => diagnose!("invalid cpu list {:?}", list),
+        }
+    }
+    ret
+}
+
+/// NUMA topology of current machine.
+pub struct NumaTopology {
+    node_count: usize,
+    cpu_to_node: Vec<usize>,
+    thread_nodes: Vec<AtomicUsize>,
+}
+
+impl NumaTopology {
+    fn new(nodes: Vec<(usize, Vec<usize>)>) -> NumaTopology {
+        let node_count = nodes.iter().map(|&(node, _)| node + 1).max().unwrap_or(1);
+        let cpu_count = nodes
+            .iter()
+            .flat_map(|&(_, ref cpus)| cpus.iter().map(|&cpu| cpu + 1))
+            .max()
+            .unwrap_or(0);
+        let mut cpu_to_node = vec![0; cpu_count];
+        for &(node, ref cpus) in nodes.iter() {
+            for &cpu in cpus.iter() {
+                cpu_to_node[cpu] = node;
+            }
+        }
+        let mut thread_nodes = Vec::with_capacity(MAX_THREAD_COUNT);
+        for _ in 0..MAX_THREAD_COUNT {
+            thread_nodes.push(AtomicUsize::new(UNKNOWN_NODE));
+        }
+        NumaTopology {
+            node_count,
+            cpu_to_node,
+            thread_nodes,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn load() -> NumaTopology {
+        let mut nodes = vec![];
+        if let Ok(entries) = fs::read_dir("/sys/devices/system/node") {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if !name.starts_with("node") {
+                    continue;
+                }
+                if let Ok(node) = name[4..].parse::<usize>() {
+                    if let Ok(list) = fs::read_to_string(entry.path().join("cpulist")) {
+                        nodes.push((node, parse_cpu_list(&list)));
+                    }
+                }
+            }
+        }
+        NumaTopology::new(nodes)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn load() -> NumaTopology {
+        NumaTopology::new(vec![])
+    }
+
+    /// Return the number of nodes, at least 1.
+    #[inline]
+    pub fn node_count(&self) -> usize {
+        self.node_count
+    }
+
+    /// Return the number of cpus listed by nodes, which is one more than the largest cpu id, 0 if
+    /// unknown.
+    #[inline]
+    pub fn cpu_count(&self) -> usize {
+        self.cpu_to_node.len()
+    }
+
+    /// Return the node of `cpu`, 0 if unknown.
+    #[inline]
+    pub fn cpu_node(&self, cpu: usize) -> usize {
+        self.cpu_to_node.get(cpu).cloned().unwrap_or(0)
+    }
+
+    /// Return cpus of `node` in ascending order, empty if unknown.
+    pub fn node_cpus(&self, node: usize) -> Vec<usize> {
+        if self.cpu_to_node.is_empty() {
+            return vec![];
+        }
+        (0..self.cpu_to_node.len())
+            .filter(|&cpu| self.cpu_to_node[cpu] == node)
+            .collect()
+    }
+
+    /// Return the node which thread `tid` was running on when it called `current_node` or
+    /// `shard_index` first time. None if not recorded.
+    pub fn thread_node(&self, tid: i64) -> Option<usize> {
+        if 0 > tid || MAX_THREAD_COUNT <= tid as usize {
+            return None;
+        }
+        match self.thread_nodes[tid as usize].load(util::ORDER_RELAXED) {
+            UNKNOWN_NODE => None,
+            node => Some(node),
+        }
+    }
+
+    /// Return the node of current thread. It's recorded at the first call, so that a thread keeps
+    /// using the same node even if migrated by the scheduler later. Pin threads to cpus to make it
+    /// accurate.
+    pub fn current_node(&self) -> usize {
+        let tid = util::get_thread_id();
+        if let Some(node) = self.thread_node(tid) {
+            return node;
+        }
+        let node = current_cpu().map_or(0, |cpu| self.cpu_node(cpu));
+        if MAX_THREAD_COUNT > tid as usize {
+            self.thread_nodes[tid as usize].store(node, util::ORDER_RELAXED);
+        }
+        node
+    }
+
+    /// Return index of the shard for current thread among `shard_count` shards, which are split
+    /// evenly into nodes, so that threads of one node share the shards of that node.
+    pub fn shard_index(&self, shard_count: usize) -> usize {
+        assert!(0 < shard_count, "shard_count must be positive");
+        let tid = util::get_thread_id() as usize;
+        if shard_count < self.node_count {
+            return self.current_node() % shard_count;
+        }
+        let per_node = shard_count / self.node_count;
+        self.current_node() * per_node + tid % per_node
+    }
+}
+
+static NUMA_TOPOLOGY_INIT: Once = ONCE_INIT;
+static mut NUMA_TOPOLOGY: *const NumaTopology = 0 as *const NumaTopology;
+
+/// Return the NUMA topology of current machine.
+pub fn topology() -> &'static NumaTopology {
+    unsafe {
+        NUMA_TOPOLOGY_INIT.call_once(|| {
+            NUMA_TOPOLOGY = Box::into_raw(Box::new(NumaTopology::load()));
+        });
+        &*NUMA_TOPOLOGY
+    }
+}
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn sched_getcpu() -> i32;
+}
+
+/// Return the cpu which current thread is running on. None if unknown.
+#[cfg(target_os = "linux")]
+pub fn current_cpu() -> Option<usize> {
+    let cpu = unsafe { sched_getcpu() };
+    if 0 > cpu {
+        None
+    } else {
+        Some(cpu as usize)
+    }
+}
+
+/// Return the cpu which current thread is running on. None if unknown.
+#[cfg(not(target_os = "linux"))]
+pub fn current_cpu() -> Option<usize> {
+    None
+}
+
+/// Return the node of current thread, see `NumaTopology::current_node`.
+#[inline]
+pub fn current_node() -> usize {
+    topology().current_node()
+}
+
+/// Return index of the shard for current thread, see `NumaTopology::shard_index`.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::numa;
+///
+/// let shards: Vec<_> = (0..4 * numa::topology().node_count()).map(|_| vec![1]).collect();
+/// let idx = numa::shard_index(shards.len());
+/// assert_eq!(shards[idx], vec![1]);
+/// ```
+///
+#[inline]
+pub fn shard_index(shard_count: usize) -> usize {
+    topology().shard_index(shard_count)
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod mbind {
+    extern "C" {
+        fn syscall(num: i64, ...) -> i64;
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const SYS_MBIND: i64 = 237;
+    #[cfg(target_arch = "aarch64")]
+    const SYS_MBIND: i64 = 235;
+    const MPOL_PREFERRED: i64 = 1;
+
+    pub unsafe fn prefer_node(addr: *mut u8, len: usize, node: usize) -> bool {
+        let mut mask = vec![0u64; node / 64 + 1];
+        mask[node / 64] = 1 << (node % 64);
+        0 == syscall(
+            SYS_MBIND,
+            addr,
+            len,
+            MPOL_PREFERRED,
+            mask.as_ptr(),
+            mask.len() as u64 * 64 + 1,
+            0i64,
+        )
+    }
+}
+
+/// Hint the kernel to place pages of `[addr, addr + len)` on `node`, e.g. for segments of a queue
+/// mostly accessed by threads of one node. `addr` must be page aligned. Return false if the hint
+/// is not supported or rejected, which is harmless because pages are placed by first touch anyway.
+pub unsafe fn hint_node_local(addr: *mut u8, len: usize, node: usize) -> bool {
+    cfg_if! {
+        if #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))] {
+            mbind::prefer_node(addr, len, node)
+        } else {
+            let _ = (addr, len, node);
+            false
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_parse_cpu_list() {
+        use numa::parse_cpu_list;
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpu_list(""), Vec::<usize>::new());
+    }
+
+    // `sched_getcpu` is not supported by Miri.
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_base() {
+        use hazard_epoch::MAX_THREAD_COUNT;
+        use numa::{self, NumaTopology};
+        use util;
+        let topology = NumaTopology::new(vec![(0, vec![0, 1]), (1, vec![2, 3])]);
+        assert_eq!(topology.node_count(), 2);
+        assert_eq!(topology.cpu_node(3), 1);
+        assert_eq!(topology.cpu_count(), 4);
+        assert_eq!(topology.cpu_node(100), 0);
+        assert_eq!(topology.node_cpus(1), vec![2, 3]);
+        assert!(topology.node_cpus(2).is_empty());
+        assert!(NumaTopology::new(vec![]).node_cpus(0).is_empty());
+        let node = topology.current_node();
+        // Thread ids are never reused, so test threads may run out of `MAX_THREAD_COUNT`.
+        let tid = util::get_thread_id();
+        if MAX_THREAD_COUNT > tid as usize {
+            assert_eq!(topology.thread_node(tid), Some(node));
+        }
+        for shard_count in 1..10 {
+            assert!(topology.shard_index(shard_count) < shard_count);
+        }
+        assert_eq!(topology.shard_index(8) / 4, node);
+
+        let topology = numa::topology();
+        assert!(numa::current_node() < topology.node_count());
+        assert!(numa::shard_index(3) < 3);
+    }
+}
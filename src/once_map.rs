@@ -0,0 +1,237 @@
+//! Definition and implementations of `OnceMap`
+//!
+use util;
+use std::borrow::Borrow;
+use std::cell::UnsafeCell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem::{self, MaybeUninit};
+use std::ptr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Value of an entry is being computed by the thread which inserted it.
+const VALUE_PENDING: u8 = 0;
+/// Value of an entry is ready.
+const VALUE_READY: u8 = 1;
+/// Computing the value of an entry panicked.
+const VALUE_POISONED: u8 = 2;
+
+struct Entry<K, V> {
+    hash: u64,
+    key: K,
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<V>>,
+    /// Next entry of the same bucket, set before the entry is published.
+    next: *mut Entry<K, V>,
+}
+
+impl<K, V> Entry<K, V> {
+    /// Wait until the value is computed, and return it. Panic if computing it panicked.
+    fn wait_value(&self) -> &V {
+        let mut backoff = util::Backoff::new();
+        loop {
+            match self.state.load(Ordering::SeqCst) {
+                VALUE_READY => return unsafe { &*(*self.value.get()).as_ptr() },
+                VALUE_POISONED => panic!("computing value of OnceMap panicked"),
+                _ => backoff.snooze(),
+            }
+        }
+    }
+}
+
+/// Poison the entry when dropped, unless its value is computed.
+struct Computing<'a>(&'a AtomicU8);
+
+impl<'a> Drop for Computing<'a> {
+    fn drop(&mut self) {
+        self.0.store(VALUE_POISONED, Ordering::SeqCst);
+    }
+}
+
+/// Concurrent map computing the value of each key exactly once, for caching results which are
+/// costly to compute, like parsed configs or compiled regexes.
+///
+/// Each bucket is a list of entries, and a new entry is put at the front by CAS. Entries are never
+/// removed until the map is dropped, so values are returned by reference without `HazardEpoch`.
+/// The thread whose entry is put computes the value, and other threads finding the entry wait for
+/// it by spinning. The number of buckets is fixed, so lists grow longer if far more keys than it
+/// are inserted.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::once_map::OnceMap;
+///
+/// let map = OnceMap::with_capacity(16);
+/// assert_eq!(map.get("a"), None);
+/// assert_eq!(map.get_or_init("a".to_string(), || 1), &1);
+/// // Computed already, so the closure is not called.
+/// assert_eq!(map.get_or_init("a".to_string(), || 2), &1);
+/// assert_eq!(map.get("a"), Some(&1));
+/// ```
+///
+pub struct OnceMap<K, V> {
+    buckets: Box<[util::AtomicRawPtr<Entry<K, V>>]>,
+    mask: usize,
+}
+
+unsafe impl<K: Send, V: Send> Send for OnceMap<K, V> {}
+
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for OnceMap<K, V> {}
+
+impl<K, V> OnceMap<K, V>
+where
+    K: Hash + Eq,
+{
+    /// Return empty OnceMap with at least `capacity` buckets, which is rounded up to a power of
+    /// two no less than 1.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+        OnceMap {
+            buckets: (0..capacity)
+                .map(|_| util::AtomicRawPtr::default())
+                .collect::<Vec<_>>()
+                .into_boxed_slice(),
+            mask: capacity - 1,
+        }
+    }
+
+    /// Return the number of buckets.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    fn hash<Q: Hash + ?Sized>(key: &Q) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Return the entry of `key` in the list from `iter` until `end`.
+    fn find<Q>(
+        &self,
+        mut iter: *mut Entry<K, V>,
+        end: *mut Entry<K, V>,
+        hash: u64,
+        key: &Q,
+    ) -> Option<&Entry<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        while iter != end {
+            let entry = unsafe { &*iter };
+            if entry.hash == hash && entry.key.borrow() == key {
+                return Some(entry);
+            }
+            iter = entry.next;
+        }
+        None
+    }
+
+    /// Return the value of `key`, None if not inserted, waiting if it's being computed. Panic if
+    /// computing it panicked.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = Self::hash(key);
+        let head = self.buckets[hash as usize & self.mask].load(Ordering::SeqCst);
+        self.find(head, ptr::null_mut(), hash, key)
+            .map(|entry| entry.wait_value())
+    }
+
+    /// Return the value of `key`, calling `init` to compute it if not inserted. `init` is called
+    /// at most once for each key, even if threads race to insert it, and threads losing the race
+    /// wait for the value. Panic if computing it panicked, and the key stays poisoned.
+    pub fn get_or_init<F>(&self, key: K, init: F) -> &V
+    where
+        F: FnOnce() -> V,
+    {
+        let hash = Self::hash(&key);
+        let bucket = &self.buckets[hash as usize & self.mask];
+        let mut head = bucket.load(Ordering::SeqCst);
+        if let Some(entry) = self.find(head, ptr::null_mut(), hash, &key) {
+            return entry.wait_value();
+        }
+        let entry = Box::into_raw(Box::new(Entry {
+            hash,
+            key,
+            state: AtomicU8::new(VALUE_PENDING),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            next: head,
+        }));
+        let mut backoff = util::Backoff::new();
+        loop {
+            let (cur, ok) = bucket.cas(head, entry, Ordering::SeqCst);
+            if ok {
+                break;
+            }
+            // Only entries put before the old head are new.
+            if let Some(found) = self.find(cur, head, hash, unsafe { &(*entry).key }) {
+                unsafe { drop(Box::from_raw(entry)) };
+                return found.wait_value();
+            }
+            head = cur;
+            unsafe { (*entry).next = head };
+            backoff.spin();
+        }
+        let entry = unsafe { &*entry };
+        let computing = Computing(&entry.state);
+        let value = init();
+        unsafe { ptr::write((*entry.value.get()).as_mut_ptr(), value) };
+        mem::forget(computing);
+        entry.state.store(VALUE_READY, Ordering::SeqCst);
+        unsafe { &*(*entry.value.get()).as_ptr() }
+    }
+}
+
+impl<K, V> Drop for OnceMap<K, V> {
+    fn drop(&mut self) {
+        for bucket in self.buckets.iter() {
+            let mut iter = bucket.swap(ptr::null_mut(), Ordering::SeqCst);
+            while !iter.is_null() {
+                let entry = unsafe { Box::from_raw(iter) };
+                if VALUE_READY == entry.state.load(Ordering::SeqCst) {
+                    unsafe { ptr::drop_in_place((*entry.value.get()).as_mut_ptr()) };
+                }
+                iter = entry.next;
+            }
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use once_map::OnceMap;
+        use std::cell::Cell;
+        use std::rc::Rc;
+        let map = OnceMap::with_capacity(3);
+        assert_eq!(map.capacity(), 4);
+        let calls = Cell::new(0);
+        for _ in 0..2 {
+            for i in 0..100 {
+                let v = map.get_or_init(i, || {
+                    calls.set(calls.get() + 1);
+                    i * 2
+                });
+                assert_eq!(*v, i * 2);
+            }
+        }
+        assert_eq!(calls.get(), 100);
+        assert_eq!(map.get(&7), Some(&14));
+        assert_eq!(map.get(&100), None);
+
+        // Values are dropped with the map.
+        let rc = Rc::new(());
+        let map = OnceMap::with_capacity(0);
+        map.get_or_init("a", || rc.clone());
+        map.get_or_init("b", || rc.clone());
+        assert_eq!(Rc::strong_count(&rc), 3);
+        drop(map);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+}
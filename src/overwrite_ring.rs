@@ -0,0 +1,239 @@
+//! Definition and implementations of `OverwriteRing`
+//!
+use error::{PushError, QueueError};
+use hazard_epoch::HazardEpoch;
+use hazard_pointer::{BaseHazardNode, HazardNodeT};
+use traits::ConcurrentQueue;
+use util;
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+struct RingNode<T> {
+    value: Option<T>,
+    base: BaseHazardNode,
+    /// Position of the element, slot `seq % capacity` holds it.
+    seq: u64,
+}
+
+impl<T> HazardNodeT for RingNode<T> {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut _
+    }
+}
+
+impl<T> Drop for RingNode<T> {
+    fn drop(&mut self) {}
+}
+
+/// Bounded LockFree ring, implemented based on `HazardEpoch`, whose `push` never finds it full,
+/// but overwrites the oldest unread element, for metrics or trace buffers where losing old data is
+/// better than blocking producers.
+///
+/// Each slot holds a node tagged with the position of its element. A producer takes the next
+/// position, and replaces the node in its slot if older, and a consumer takes the node at the head
+/// position by swapping in null. Replaced nodes are added to `HazardEpoch`, so their elements are
+/// dropped once no other thread reads them. A producer finding a newer node in its slot drops its
+/// own element, which is also counted as overwritten.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::overwrite_ring::OverwriteRing;
+///
+/// let mut ring = OverwriteRing::with_capacity(2);
+/// for i in 0..5 {
+///     ring.push(i);
+/// }
+/// assert_eq!(ring.overwritten_count(), 3);
+/// assert_eq!(ring.pop(), Some(3));
+/// assert_eq!(ring.pop(), Some(4));
+/// assert_eq!(ring.pop(), None);
+/// ```
+///
+pub struct OverwriteRing<T> {
+    hazard_epoch: HazardEpoch,
+    /// Position of the next element to pop.
+    head: util::WrappedAlign64Type<util::AtomicU64>,
+    /// Position of the next element to push.
+    tail: util::WrappedAlign64Type<util::AtomicU64>,
+    overwritten: util::WrappedAlign64Type<util::AtomicU64>,
+    slots: Box<[util::AtomicRawPtr<RingNode<T>>]>,
+}
+
+impl<T> OverwriteRing<T> {
+    /// Return empty OverwriteRing in heap holding at most `capacity` elements, which must be
+    /// positive, with default setting of HazardEpoch.
+    pub fn with_capacity(capacity: usize) -> Box<OverwriteRing<T>> {
+        assert!(0 < capacity, "capacity must be positive");
+        Box::new(OverwriteRing {
+            hazard_epoch: unsafe { HazardEpoch::default_new_in_stack() },
+            head: util::WrappedAlign64Type(util::AtomicU64::new(0)),
+            tail: util::WrappedAlign64Type(util::AtomicU64::new(0)),
+            overwritten: util::WrappedAlign64Type(util::AtomicU64::new(0)),
+            slots: (0..capacity)
+                .map(|_| util::AtomicRawPtr::default())
+                .collect::<Vec<_>>()
+                .into_boxed_slice(),
+        })
+    }
+
+    /// Return the maximum number of elements.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Return the number of elements overwritten before being popped.
+    #[inline]
+    pub fn overwritten_count(&self) -> u64 {
+        self.overwritten.load(Ordering::SeqCst)
+    }
+
+    /// Return the slot of position `seq` in `slots`. It only borrows the slots, so that a cursor
+    /// of `hazard_epoch` can be held meanwhile.
+    #[inline]
+    fn slot(
+        slots: &[util::AtomicRawPtr<RingNode<T>>],
+        seq: u64,
+    ) -> &util::AtomicRawPtr<RingNode<T>> {
+        &slots[(seq % slots.len() as u64) as usize]
+    }
+
+    /// Push an element to the end of current ring, overwriting the oldest one if full. Panic if
+    /// `try_push` fails.
+    pub fn push(&mut self, v: T) {
+        if let Err(e) = self.try_push(v) {
+            panic!("push fail, {}", e);
+        }
+    }
+
+    /// Push an element to the end of current ring, overwriting the oldest one if full. Return
+    /// `QueueError::AllocFailed` if memory of node can't be allocated, or `QueueError::Hazard` if
+    /// `HazardEpoch` fails. The element is given back in both cases, except that if the element is
+    /// pushed but the overwritten one can't be retired, the overwritten one is given back instead.
+    pub fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        unsafe { self.inner_push(v) }
+    }
+
+    unsafe fn inner_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        let node = match util::try_box_into_raw(RingNode {
+            value: Some(v),
+            base: BaseHazardNode::default(),
+            seq: 0,
+        }) {
+            Ok(node) => node,
+            Err(mut node) => {
+                return Err(PushError {
+                    error: QueueError::AllocFailed { op: "push" },
+                    value: node.value.take().unwrap(),
+                })
+            }
+        };
+        let cursor = match self.hazard_epoch.cursor() {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                return Err(PushError {
+                    error: e.into(),
+                    value: Box::from_raw(node).value.take().unwrap(),
+                })
+            }
+        };
+        let seq = self.tail.fetch_add(1, Ordering::SeqCst);
+        (*node).seq = seq;
+        let slot = Self::slot(&self.slots, seq);
+        let mut cur = slot.load(Ordering::SeqCst);
+        let mut backoff = util::Backoff::new();
+        loop {
+            if !cur.is_null() && (*cur).seq > seq {
+                // A later producer of the same slot got there first.
+                self.overwritten.fetch_add(1, Ordering::SeqCst);
+                drop(Box::from_raw(node));
+                break;
+            }
+            let (tmp, ok) = slot.cas(cur, node, Ordering::SeqCst);
+            if ok {
+                if !cur.is_null() {
+                    self.overwritten.fetch_add(1, Ordering::SeqCst);
+                    if let Err(e) = cursor.retire(cur) {
+                        // Unlinked by current push, so no other thread takes its element.
+                        return Err(PushError {
+                            error: e.into(),
+                            value: (*cur).value.take().unwrap(),
+                        });
+                    }
+                }
+                break;
+            }
+            cur = tmp;
+            backoff.spin();
+        }
+        Ok(())
+    }
+
+    /// Pop the oldest element of current ring. Panic if `try_pop` fails.
+    pub fn pop(&mut self) -> Option<T> {
+        match self.try_pop() {
+            Ok(ret) => ret,
+            Err(e) => panic!("pop fail, {}", e),
+        }
+    }
+
+    /// Same as `pop`, but return `QueueError::Hazard` if `HazardEpoch` fails. Like
+    /// `LockFreeQueue`, it may return nothing while the push of the oldest element is in progress.
+    pub fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
+        unsafe { self.inner_pop() }
+    }
+
+    unsafe fn inner_pop(&mut self) -> Result<Option<T>, QueueError> {
+        let capacity = self.slots.len() as u64;
+        let cursor = self.hazard_epoch.cursor()?;
+        let mut backoff = util::Backoff::new();
+        let ret = loop {
+            let head = self.head.load(Ordering::SeqCst);
+            let tail = self.tail.load(Ordering::SeqCst);
+            if head >= tail {
+                break None;
+            }
+            if tail - head > capacity {
+                // Elements before `tail - capacity` have been overwritten.
+                self.head.cas(head, tail - capacity, Ordering::SeqCst);
+                continue;
+            }
+            let slot = Self::slot(&self.slots, head);
+            let node = slot.load(Ordering::SeqCst);
+            if node.is_null() || (*node).seq < head {
+                break None;
+            }
+            let (_, ok) = self.head.cas(head, head + 1, Ordering::SeqCst);
+            if ok && (*node).seq == head && slot.cas(node, ptr::null_mut(), Ordering::SeqCst).1 {
+                let ret = (*node).value.take();
+                cursor.retire(node)?;
+                break ret;
+            }
+            // Claimed by another consumer, or overwritten by a producer.
+            backoff.spin();
+        };
+        Ok(ret)
+    }
+}
+
+impl<T> ConcurrentQueue<T> for OverwriteRing<T> {
+    fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
+        OverwriteRing::try_push(self, v)
+    }
+
+    fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
+        OverwriteRing::try_pop(self)
+    }
+}
+
+impl<T> Drop for OverwriteRing<T> {
+    fn drop(&mut self) {
+        for slot in self.slots.iter() {
+            let node = slot.swap(ptr::null_mut(), Ordering::SeqCst);
+            if !node.is_null() {
+                unsafe { drop(Box::from_raw(node)) };
+            }
+        }
+    }
+}
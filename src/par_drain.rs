@@ -0,0 +1,50 @@
+//! Definition and implementations of `ParDrain`, a `rayon` parallel iterator draining
+//! `LockFreeQueue` or `LockFreeStack`
+//!
+use rayon::iter::plumbing::UnindexedConsumer;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Parallel iterator returned by `par_drain` of `LockFreeQueue` and `LockFreeStack`.
+///
+/// The calling thread detaches all elements from the container by one CAS, like `clear`, and
+/// moves them out of their nodes, which are retired there. Workers of `rayon` then split the
+/// detached elements between them, so they never touch `HazardEpoch` or take its thread ids, and
+/// the pool may have any number of threads. Elements pushed after `par_drain` returns are left in
+/// the container.
+pub struct ParDrain<T> {
+    elements: Vec<T>,
+}
+
+impl<T> ParDrain<T> {
+    /// Return ParDrain yielding `elements`, which are detached from a container already.
+    pub fn new(elements: Vec<T>) -> Self {
+        ParDrain { elements }
+    }
+
+    /// Return the number of elements detached.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Return true if no element is detached.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+}
+
+impl<T: Send> ParallelIterator for ParDrain<T> {
+    type Item = T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<T>,
+    {
+        self.elements.into_par_iter().drive_unindexed(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
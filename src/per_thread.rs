@@ -0,0 +1,232 @@
+//! Definition and implementations of per-thread and per-cpu storage
+//!
+//! Each slot is padded to a cache line, so that threads updating their own slots don't contend,
+//! and the owner of the storage aggregates all slots by iterating them, like sharded counters.
+//!
+use error::HazardError;
+use hazard_epoch::MAX_THREAD_COUNT;
+use numa;
+use util;
+use std::cmp;
+use std::thread;
+
+/// Storage of one padded slot for each thread id below `MAX_THREAD_COUNT`.
+///
+/// Thread ids are never reused, so a slot is only touched by its thread through `get`, and by
+/// others through `get_of` or `iter`. Slots are shared, so `T` is usually atomic or locked, like
+/// `util::AtomicU64`.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::per_thread::PerThreadShard;
+/// use rs_lockfree::util;
+/// use std::sync::Arc;
+/// use std::sync::atomic::Ordering;
+/// use std::thread;
+///
+/// let counters = Arc::new(PerThreadShard::<util::AtomicU64>::new());
+/// let workers: Vec<_> = (0..2)
+///     .map(|_| {
+///         let counters = counters.clone();
+///         thread::spawn(move || {
+///             for _ in 0..100 {
+///                 counters.get().fetch_add(1, Ordering::Relaxed);
+///             }
+///         })
+///     })
+///     .collect();
+/// for t in workers {
+///     t.join().unwrap();
+/// }
+/// let sum: u64 = counters.iter().map(|c| c.load(Ordering::Relaxed)).sum();
+/// assert_eq!(sum, 200);
+/// ```
+///
+pub struct PerThreadShard<T> {
+    slots: Box<[util::WrappedAlign64Type<T>]>,
+}
+
+impl<T: Default> Default for PerThreadShard<T> {
+    fn default() -> Self {
+        PerThreadShard::new()
+    }
+}
+
+impl<T: Default> PerThreadShard<T> {
+    /// Return PerThreadShard whose slots are `T::default()`.
+    pub fn new() -> Self {
+        PerThreadShard::with_init(T::default)
+    }
+}
+
+impl<T> PerThreadShard<T> {
+    /// Return PerThreadShard whose slots are built by `init`.
+    pub fn with_init<F>(init: F) -> Self
+    where
+        F: FnMut() -> T,
+    {
+        PerThreadShard {
+            slots: new_slots(MAX_THREAD_COUNT, init),
+        }
+    }
+
+    /// Return the slot of current thread. Panic if `try_get` fails.
+    #[inline]
+    pub fn get(&self) -> &T {
+        match self.try_get() {
+            Ok(ret) => ret,
+            Err(e) => panic!("get fail, {}", e),
+        }
+    }
+
+    /// Return the slot of current thread. Return `HazardError::ThreadNumOverflow` if its thread id
+    /// is not less than `MAX_THREAD_COUNT`.
+    #[inline]
+    pub fn try_get(&self) -> Result<&T, HazardError> {
+        let tid = util::get_thread_id();
+        self.get_of(tid as usize)
+            .ok_or(HazardError::ThreadNumOverflow {
+                tid,
+                max_thread_count: MAX_THREAD_COUNT,
+            })
+    }
+
+    /// Return the slot of thread `tid`, or None if there is no slot for it.
+    #[inline]
+    pub fn get_of(&self, tid: usize) -> Option<&T> {
+        self.slots.get(tid).map(|slot| &**slot)
+    }
+
+    /// Return the number of slots, which is `MAX_THREAD_COUNT`.
+    #[inline]
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Iterate slots of all threads in order of thread id, including threads not started yet.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().map(|slot| &**slot)
+    }
+
+    /// Iterate slots of all threads mutably, for example to reset them.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().map(|slot| &mut **slot)
+    }
+}
+
+/// Storage of one padded slot for each cpu.
+///
+/// A thread uses the slot of the cpu it's running on, told by `numa::current_cpu`, or picked by
+/// its thread id where the cpu is unknown. Threads may be migrated or preempted, so a slot may be
+/// touched by several threads at once, and `T` must be safe to share. Compared with
+/// `PerThreadShard`, there are fewer slots to aggregate if threads outnumber cpus.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::per_thread::PerCpu;
+/// use rs_lockfree::util;
+/// use std::sync::atomic::Ordering;
+///
+/// let counters = PerCpu::<util::AtomicU64>::new();
+/// counters.get().fetch_add(3, Ordering::Relaxed);
+/// assert!(counters.slot_count() > 0);
+/// assert_eq!(counters.iter().map(|c| c.load(Ordering::Relaxed)).sum::<u64>(), 3);
+/// ```
+///
+pub struct PerCpu<T> {
+    slots: Box<[util::WrappedAlign64Type<T>]>,
+}
+
+impl<T: Default> Default for PerCpu<T> {
+    fn default() -> Self {
+        PerCpu::new()
+    }
+}
+
+impl<T: Default> PerCpu<T> {
+    /// Return PerCpu whose slots are `T::default()`.
+    pub fn new() -> Self {
+        PerCpu::with_init(T::default)
+    }
+}
+
+impl<T> PerCpu<T> {
+    /// Return PerCpu whose slots are built by `init`. There is a slot for each cpu listed by
+    /// `numa::topology`, and at least one for each thread `std` expects to run in parallel.
+    pub fn with_init<F>(init: F) -> Self
+    where
+        F: FnMut() -> T,
+    {
+        let parallelism = thread::available_parallelism().map_or(1, |n| n.get());
+        PerCpu {
+            slots: new_slots(cmp::max(numa::topology().cpu_count(), parallelism), init),
+        }
+    }
+
+    /// Return the slot of the cpu which current thread is running on.
+    #[inline]
+    pub fn get(&self) -> &T {
+        let idx = numa::current_cpu().unwrap_or_else(|| util::get_thread_id() as usize);
+        &self.slots[idx % self.slots.len()]
+    }
+
+    /// Return the slot of `cpu`, or None if there is no slot for it.
+    #[inline]
+    pub fn get_of(&self, cpu: usize) -> Option<&T> {
+        self.slots.get(cpu).map(|slot| &**slot)
+    }
+
+    /// Return the number of slots.
+    #[inline]
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Iterate slots of all cpus in order of cpu id.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().map(|slot| &**slot)
+    }
+
+    /// Iterate slots of all cpus mutably, for example to reset them.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().map(|slot| &mut **slot)
+    }
+}
+
+fn new_slots<T, F>(count: usize, mut init: F) -> Box<[util::WrappedAlign64Type<T>]>
+where
+    F: FnMut() -> T,
+{
+    (0..count)
+        .map(|_| util::WrappedAlign64Type(init()))
+        .collect::<Vec<_>>()
+        .into_boxed_slice()
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use hazard_epoch::MAX_THREAD_COUNT;
+        use per_thread::{PerCpu, PerThreadShard};
+
+        let mut shard = PerThreadShard::<u64>::new();
+        assert_eq!(shard.slot_count(), MAX_THREAD_COUNT);
+        for (tid, v) in shard.iter_mut().enumerate() {
+            *v = tid as u64;
+        }
+        assert_eq!(shard.get_of(1), Some(&1));
+        assert_eq!(shard.get_of(MAX_THREAD_COUNT), None);
+        let sum: u64 = shard.iter().sum();
+        assert_eq!(sum, (MAX_THREAD_COUNT * (MAX_THREAD_COUNT - 1) / 2) as u64);
+        let a = shard.get_of(0).unwrap() as *const u64 as usize;
+        let b = shard.get_of(1).unwrap() as *const u64 as usize;
+        assert_eq!(b - a, 64);
+
+        let cpus = PerCpu::with_init(|| 1);
+        assert!(0 < cpus.slot_count());
+        assert_eq!(cpus.iter().count(), cpus.slot_count());
+        assert_eq!(cpus.get_of(cpus.slot_count()), None);
+    }
+}
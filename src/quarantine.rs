@@ -0,0 +1,128 @@
+//! Poisoning and quarantine of reclaimed shared objects, enabled by feature `debug-poison`
+//!
+//! Instead of being freed right away, a reclaimed object is dropped, filled with `POISON` and
+//! held in a quarantine list for `quarantine_period`, then freed by later reclaiming or
+//! `flush_quarantine`. Accessing it after reclaiming reads the pattern, and following a pointer
+//! read from it crashes at a non-canonical address like `0xdededededededede`, instead of silently
+//! reading reused memory.
+//!
+use hazard_pointer::HazardNodeT;
+use std::alloc::{self, Layout};
+use std::collections::VecDeque;
+use std::ptr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use util;
+
+/// Byte filling reclaimed objects.
+pub const POISON: u8 = 0xde;
+
+struct Entry {
+    addr: usize,
+    layout: Layout,
+    retire_time_us: i64,
+}
+
+static PERIOD_US: AtomicI64 = AtomicI64::new(100_000);
+static QUARANTINE: Mutex<VecDeque<Entry>> = Mutex::new(VecDeque::new());
+
+fn lock_quarantine() -> MutexGuard<'static, VecDeque<Entry>> {
+    QUARANTINE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Set how long reclaimed objects are held before freed, 100ms by default.
+pub fn set_quarantine_period(period: Duration) {
+    PERIOD_US.store(period.as_micros() as i64, Ordering::SeqCst);
+}
+
+/// Return how long reclaimed objects are held before freed.
+pub fn quarantine_period() -> Duration {
+    Duration::from_micros(PERIOD_US.load(Ordering::SeqCst) as u64)
+}
+
+/// Return the number of reclaimed objects held in quarantine.
+pub fn quarantined_count() -> usize {
+    lock_quarantine().len()
+}
+
+/// Free all reclaimed objects held in quarantine, no matter how long they have been held.
+pub fn flush_quarantine() {
+    let entries: Vec<Entry> = lock_quarantine().drain(..).collect();
+    for entry in entries {
+        unsafe { alloc::dealloc(entry.addr as *mut u8, entry.layout) };
+    }
+}
+
+/// Drop `obj` allocated by `Box`, poison its memory and put it in quarantine, then free objects
+/// held longer than `quarantine_period`.
+pub unsafe fn retire(obj: *mut (HazardNodeT + 'static)) {
+    let layout = Layout::for_value(&*obj);
+    ptr::drop_in_place(obj);
+    let addr = obj as *mut u8;
+    ptr::write_bytes(addr, POISON, layout.size());
+
+    let now = util::get_cur_microseconds_time();
+    let deadline = now - PERIOD_US.load(Ordering::SeqCst);
+    let mut expired = Vec::new();
+    {
+        let mut quarantine = lock_quarantine();
+        quarantine.push_back(Entry {
+            addr: addr as usize,
+            layout,
+            retire_time_us: now,
+        });
+        while quarantine
+            .front()
+            .map_or(false, |e| e.retire_time_us <= deadline)
+        {
+            expired.push(quarantine.pop_front().unwrap());
+        }
+    }
+    for entry in expired {
+        alloc::dealloc(entry.addr as *mut u8, entry.layout);
+    }
+}
+
+mod test {
+    #[test]
+    fn test_poison() {
+        use hazard_epoch::{BaseHazardNode, HazardNodeT};
+        use quarantine::{self, POISON};
+        use std::mem;
+        use std::slice;
+        use std::time::Duration;
+
+        struct Node {
+            base: BaseHazardNode,
+            v: u64,
+        }
+
+        impl HazardNodeT for Node {
+            fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+                &self.base as *const _ as *mut _
+            }
+        }
+
+        impl Drop for Node {
+            fn drop(&mut self) {
+                assert_eq!(self.v, 7);
+            }
+        }
+
+        quarantine::set_quarantine_period(Duration::from_secs(3600));
+        let node = Box::into_raw(Box::new(Node {
+            base: BaseHazardNode::default(),
+            v: 7,
+        }));
+        unsafe {
+            quarantine::retire(node as *mut HazardNodeT);
+            // Still allocated, but every byte is poisoned.
+            let bytes = slice::from_raw_parts(node as *const u8, mem::size_of::<Node>());
+            assert!(bytes.iter().all(|&b| POISON == b));
+        }
+        assert!(0 < quarantine::quarantined_count());
+        quarantine::set_quarantine_period(Duration::from_millis(100));
+        quarantine::flush_quarantine();
+    }
+}
@@ -0,0 +1,143 @@
+//! Definition and implementations of `SeqLock`
+//!
+use util;
+use std::cell::UnsafeCell;
+use std::intrinsics;
+use std::ptr;
+
+/// User mode SeqLock for small `Copy` data. Writers make the sequence odd, update data in place and
+/// make it even again; readers never write shared memory, they just retry if the sequence is odd
+/// or changed during reading. It complements `SpinRWLock` for read-dominated data where even read
+/// CAS is too expensive. Both reading and writing take `&self`, so it can be shared by threads
+/// directly, like in `Arc` or `static` items.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::seq_lock::SeqLock;
+///
+/// let lock = SeqLock::new((1, 2));
+/// assert_eq!(lock.read(), (1, 2));
+/// lock.write((3, 4));
+/// assert_eq!(lock.read(), (3, 4));
+/// assert_eq!(lock.seq(), 2);
+/// ```
+///
+pub struct SeqLock<T: Copy> {
+    seq: util::WrappedAlign64Type<u64>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    /// Return SeqLock which protects `data`. It's `const`, so that SeqLock can be put in `static`
+    /// items.
+    pub const fn new(data: T) -> Self {
+        SeqLock {
+            seq: util::WrappedAlign64Type(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Return current sequence, which is odd while writing.
+    #[inline]
+    pub fn seq(&self) -> u64 {
+        unsafe { intrinsics::atomic_load(self.seq.as_ptr()) }
+    }
+
+    /// Keep trying to read until a consistent snapshot is got.
+    pub fn read(&self) -> T {
+        let mut backoff = util::Backoff::new();
+        loop {
+            if let Some(v) = self.try_read() {
+                return v;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Return a snapshot if no writer interferes with current reading, else None.
+    #[inline]
+    pub fn try_read(&self) -> Option<T> {
+        unsafe {
+            let seq = self.seq();
+            if 0 != seq & 1 {
+                return None;
+            }
+            let v = util::racy_read(self.data.get());
+            if seq == self.seq() {
+                Some(v)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Replace data by `v`.
+    #[inline]
+    pub fn write(&self, v: T) {
+        self.update(|data| *data = v);
+    }
+
+    /// Modify data in place. Writers are serialized by the sequence. If `f` panics, data is left
+    /// unchanged and the sequence is still made even.
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut T),
+    {
+        unsafe {
+            let unlock = Unlock {
+                seq: self.seq.as_mut_ptr(),
+                next: self.lock() + 2,
+            };
+            let mut v = ptr::read_volatile(self.data.get());
+            f(&mut v);
+            util::racy_write(self.data.get(), v);
+            drop(unlock);
+        }
+    }
+
+    /// Make sequence odd and return the even one before.
+    unsafe fn lock(&self) -> u64 {
+        let mut backoff = util::Backoff::new();
+        loop {
+            let seq = self.seq();
+            if 0 == seq & 1
+                && intrinsics::atomic_cxchg(self.seq.as_mut_ptr(), seq, seq + 1).1
+            {
+                return seq;
+            }
+            backoff.snooze();
+        }
+    }
+}
+
+/// Make the sequence even again when dropped, even if the writer panics.
+struct Unlock {
+    seq: *mut u64,
+    next: u64,
+}
+
+impl Drop for Unlock {
+    fn drop(&mut self) {
+        unsafe { intrinsics::atomic_store(self.seq, self.next) }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_seq_lock() {
+        use seq_lock::SeqLock;
+        let lock = SeqLock::new([0_u64; 4]);
+        assert_eq!(lock.seq(), 0);
+        lock.update(|v| v[1] = 1);
+        assert_eq!(lock.try_read(), Some([0, 1, 0, 0]));
+        assert_eq!(lock.seq(), 2);
+        unsafe {
+            lock.lock();
+        }
+        assert!(lock.try_read().is_none());
+        assert_eq!(lock.seq(), 3);
+    }
+}
@@ -0,0 +1,242 @@
+//! Definition and implementations of `SlotRing`
+//!
+use error::QueueError;
+use util;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// State of a slot which can be allocated.
+const SLOT_FREE: u8 = 0;
+/// State of a slot owned by a `SlotGuard`.
+const SLOT_HELD: u8 = 1;
+/// State of a slot whose index is handed out by `SlotGuard::into_index`, until it's claimed.
+const SLOT_SENT: u8 = 2;
+
+struct Slot<T> {
+    state: AtomicU8,
+    message: UnsafeCell<T>,
+}
+
+/// LockFree allocator of fixed-size message slots, which are allocated in ring order.
+///
+/// All messages are constructed when current ring is, and live in its slots until it's dropped,
+/// so a producer allocates a slot, overwrites the message in place, and enqueues just the index of
+/// the slot to a queue of `usize`, like `ArrayQueue` or `IndexQueue`. That saves allocating both a
+/// boxed message and a queue node per message, and buffers inside messages keep their capacity
+/// across reuse. The consumer claims the slot by its index, and the slot is freed once the guard
+/// is dropped.
+///
+/// Each allocation takes the slot at a shared cursor, and moves the cursor on, skipping slots
+/// which are still held, so slots are reused in the order they are allocated when consumers
+/// complete in order, and producers rarely contend on the same slot.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::array_queue::ArrayQueue;
+/// use rs_lockfree::slot_ring::SlotRing;
+///
+/// let ring: SlotRing<Vec<u8>> = SlotRing::with_capacity(4);
+/// let queue: ArrayQueue<usize, 4> = ArrayQueue::new();
+///
+/// let mut slot = ring.alloc();
+/// slot.clear();
+/// slot.extend_from_slice(b"hello");
+/// queue.try_push(slot.into_index()).unwrap();
+///
+/// let slot = ring.claim(queue.pop().unwrap()).unwrap();
+/// assert_eq!(&slot[..], b"hello");
+/// drop(slot);
+/// assert_eq!(ring.free_count(), 4);
+/// ```
+///
+pub struct SlotRing<T> {
+    cursor: util::WrappedAlign64Type<AtomicUsize>,
+    slots: Box<[Slot<T>]>,
+}
+
+unsafe impl<T: Send> Send for SlotRing<T> {}
+
+unsafe impl<T: Send> Sync for SlotRing<T> {}
+
+impl<T: Default> SlotRing<T> {
+    /// Return SlotRing of `capacity` slots holding default messages. `capacity` must be positive.
+    pub fn with_capacity(capacity: usize) -> Self {
+        SlotRing::from_fn(capacity, |_| T::default())
+    }
+}
+
+impl<T> SlotRing<T> {
+    /// Return SlotRing of `capacity` slots, whose messages are made by calling `f` with the slot
+    /// index. `capacity` must be positive.
+    pub fn from_fn<F: FnMut(usize) -> T>(capacity: usize, mut f: F) -> Self {
+        assert!(0 < capacity, "capacity must be positive");
+        let slots: Vec<Slot<T>> = (0..capacity)
+            .map(|i| Slot {
+                state: AtomicU8::new(SLOT_FREE),
+                message: UnsafeCell::new(f(i)),
+            })
+            .collect();
+        SlotRing {
+            cursor: util::WrappedAlign64Type(AtomicUsize::new(0)),
+            slots: slots.into_boxed_slice(),
+        }
+    }
+
+    /// Return the number of slots.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Return the number of free slots, which may be out of date once returned.
+    pub fn free_count(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| SLOT_FREE == slot.state.load(Ordering::SeqCst))
+            .count()
+    }
+
+    /// Allocate a free slot. Return `QueueError::Full` if all slots are held or sent.
+    pub fn try_alloc<'a>(&'a self) -> Result<SlotGuard<'a, T>, QueueError> {
+        for _ in 0..self.capacity() {
+            let index = self.cursor.fetch_add(1, util::ORDER_RELAXED) % self.capacity();
+            if self.transit(index, SLOT_FREE) {
+                return Ok(SlotGuard::new(self, index));
+            }
+        }
+        Err(QueueError::Full { op: "try_alloc" })
+    }
+
+    /// Keep trying to allocate a free slot until success, waiting for consumers to free one.
+    pub fn alloc<'a>(&'a self) -> SlotGuard<'a, T> {
+        let mut backoff = util::Backoff::new();
+        loop {
+            if let Ok(guard) = self.try_alloc() {
+                return guard;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Take the slot `index` handed out by `SlotGuard::into_index`. Return None if it's out of
+    /// range or not sent, like when it has been claimed already.
+    ///
+    /// The index doesn't tell which allocation it comes from, so a stale index claims the message
+    /// sent from the slot later, if any.
+    pub fn claim<'a>(&'a self, index: usize) -> Option<SlotGuard<'a, T>> {
+        if index < self.capacity() && self.transit(index, SLOT_SENT) {
+            Some(SlotGuard::new(self, index))
+        } else {
+            None
+        }
+    }
+
+    /// Make slot `index` held if it's in state `from`.
+    #[inline]
+    fn transit(&self, index: usize, from: u8) -> bool {
+        let state = &self.slots[index].state;
+        from == state.load(util::ORDER_RELAXED)
+            && state
+                .compare_exchange(from, SLOT_HELD, util::ORDER_ACQUIRE, util::ORDER_RELAXED)
+                .is_ok()
+    }
+}
+
+/// Guard of a slot allocated from `SlotRing` or claimed by its index, which derefs to the message.
+/// The slot is freed when the guard is dropped.
+pub struct SlotGuard<'a, T: 'a> {
+    ring: &'a SlotRing<T>,
+    index: usize,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T> SlotGuard<'a, T> {
+    #[inline]
+    fn new(ring: &'a SlotRing<T>, index: usize) -> Self {
+        SlotGuard {
+            ring,
+            index,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return the index of current slot.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Hand out current slot without freeing it, and return its index, which is sent to the
+    /// consumer to claim the slot by `SlotRing::claim`.
+    pub fn into_index(self) -> usize {
+        let index = self.index;
+        self.ring.slots[index]
+            .state
+            .store(SLOT_SENT, util::ORDER_RELEASE);
+        mem::forget(self);
+        index
+    }
+}
+
+impl<'a, T> Deref for SlotGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.ring.slots[self.index].message.get() }
+    }
+}
+
+impl<'a, T> DerefMut for SlotGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.ring.slots[self.index].message.get() }
+    }
+}
+
+impl<'a, T> Drop for SlotGuard<'a, T> {
+    fn drop(&mut self) {
+        self.ring.slots[self.index]
+            .state
+            .store(SLOT_FREE, util::ORDER_RELEASE);
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use error::QueueError;
+        use slot_ring::SlotRing;
+        let ring = SlotRing::from_fn(3, |i| i * 10);
+        assert_eq!(ring.capacity(), 3);
+        let a = ring.alloc();
+        let mut b = ring.try_alloc().unwrap();
+        *b += 1;
+        let b = b.into_index();
+        assert_eq!((a.index(), b), (0, 1));
+        assert!(ring.claim(b + 5).is_none());
+        assert!(ring.claim(a.index()).is_none());
+        let c = ring.alloc();
+        assert_eq!(c.index(), 2);
+        assert_eq!(
+            ring.try_alloc().err(),
+            Some(QueueError::Full { op: "try_alloc" })
+        );
+        assert_eq!(ring.free_count(), 0);
+
+        // Freed slots are allocated again in ring order.
+        drop(a);
+        let b = ring.claim(b).unwrap();
+        assert_eq!(*b, 11);
+        assert!(ring.claim(b.index()).is_none());
+        drop(b);
+        assert_eq!(ring.free_count(), 2);
+        assert_eq!(ring.alloc().index(), 0);
+        assert_eq!(ring.alloc().index(), 1);
+        drop(c);
+        assert_eq!(*ring.alloc(), 20);
+        assert_eq!(ring.free_count(), 3);
+    }
+}
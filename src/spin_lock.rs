@@ -1,114 +1,712 @@
 //! Definition and implementations of `SpinLock`
 //!
+use error::LockError;
 use util;
-use std::intrinsics;
-use std::ptr;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicI8, Ordering};
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+use std::thread;
+use std::time::{Duration, Instant};
 
-/// User mode SpinLock
-pub struct SpinLock {
-    atomic: i8,
+/// Owner of an unlocked SpinLock, which is not a valid thread id.
+#[cfg(feature = "debug_lock")]
+const NO_OWNER: i64 = -1;
+
+/// User mode SpinLock, which protects data of type `T` like `std::sync::Mutex`. `SpinLock<()>`
+/// can be used as a bare lock by `raw_lock` and `raw_unlock`.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::spin_lock::SpinLock;
+///
+/// let lock = SpinLock::new(vec![1]);
+/// lock.lock().unwrap().push(2);
+/// assert_eq!(*lock.lock().unwrap(), vec![1, 2]);
+/// {
+///     let _guard = lock.lock().unwrap();
+///     assert!(lock.try_lock().is_err());
+/// }
+/// assert_eq!(lock.into_inner().unwrap(), vec![1, 2]);
+/// ```
+///
+/// Like `std::sync::Mutex`, current lock is poisoned if a thread panics while holding the guard,
+/// then lockers get `PoisonError` which still contains the guard, so they can decide whether the
+/// protected invariants are broken. Call `clear_poison` after the data is repaired.
+///
+/// With feature `debug_lock`, the owner thread is recorded, and relocking by the owner or
+/// unlocking by another thread panics instead of hanging forever or corrupting data.
+///
+/// With feature `lock-stats`, spins, failed CAS and wait time of lockers are counted, and returned
+/// by `contention_stats`, to find out which lock is contended.
+///
+pub struct SpinLock<T: ?Sized = ()> {
+    atomic: AtomicI8,
+    poisoned: AtomicBool,
+    #[cfg(feature = "debug_lock")]
+    owner: util::AtomicI64,
+    wait_policy: Option<util::WaitPolicy>,
+    contention: util::ContentionCounters,
+    data: UnsafeCell<T>,
 }
 
-impl Default for SpinLock {
+unsafe impl<T: ?Sized + Send> Send for SpinLock<T> {}
+
+unsafe impl<T: ?Sized + Send> Sync for SpinLock<T> {}
+
+impl<T: Default> Default for SpinLock<T> {
     fn default() -> Self {
-        SpinLock { atomic: 0 }
+        SpinLock::new(T::default())
     }
 }
 
-impl SpinLock {
-    /// Keep trying to lock until success.
-    pub fn lock(&mut self) {
-        while self.is_locked() || !unsafe { self.inner_lock() } {
-            util::pause();
+impl<T> SpinLock<T> {
+    /// Return SpinLock which protects `data`. It's `const`, so that SpinLock can be put in `static`
+    /// items.
+    #[inline]
+    pub const fn new(data: T) -> Self {
+        SpinLock {
+            atomic: AtomicI8::new(0),
+            poisoned: AtomicBool::new(false),
+            #[cfg(feature = "debug_lock")]
+            owner: util::AtomicI64::new(NO_OWNER),
+            wait_policy: None,
+            contention: util::ContentionCounters::new(),
+            data: UnsafeCell::new(data),
         }
     }
 
-    /// Keep trying to lock until success, then return SpinLockGuard.
+    /// Return SpinLock which protects `data`, and waits by `policy` instead of the process-wide
+    /// `WaitPolicy`.
+    #[inline]
+    pub const fn with_wait_policy(data: T, policy: util::WaitPolicy) -> Self {
+        SpinLock {
+            atomic: AtomicI8::new(0),
+            poisoned: AtomicBool::new(false),
+            #[cfg(feature = "debug_lock")]
+            owner: util::AtomicI64::new(NO_OWNER),
+            wait_policy: Some(policy),
+            contention: util::ContentionCounters::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consume current lock and return the protected data, which is wrapped in `PoisonError` if
+    /// current lock is poisoned.
+    #[inline]
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.is_poisoned();
+        let data = self.data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+impl<T: ?Sized> SpinLock<T> {
+    /// Set `WaitPolicy` of current lock, None for the process-wide one.
+    #[inline]
+    pub fn set_wait_policy(&mut self, policy: Option<util::WaitPolicy>) {
+        self.wait_policy = policy;
+    }
+
+    #[inline]
+    fn backoff(&self) -> util::Backoff {
+        self.wait_policy.map_or_else(util::Backoff::new, util::Backoff::with_policy)
+    }
+
+    /// Return spins, failed CAS and wait time of lockers since current lock was made. Only
+    /// blocking lock methods, like `lock` and `try_lock_for`, are counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "lock-stats")]
+    /// # fn main() {
+    /// use rs_lockfree::spin_lock::SpinLock;
+    ///
+    /// let lock = SpinLock::new(0);
+    /// *lock.lock().unwrap() += 1;
+    /// // Uncontended locking doesn't wait.
+    /// assert_eq!(lock.contention_stats().spins, 0);
+    /// # }
+    /// # #[cfg(not(feature = "lock-stats"))]
+    /// # fn main() {}
+    /// ```
+    #[cfg(feature = "lock-stats")]
+    #[inline]
+    pub fn contention_stats(&self) -> util::ContentionStats {
+        self.contention.load()
+    }
+
+    /// Keep trying to lock until success, then return SpinLockGuard which derefs to the data.
+    /// Return `PoisonError` with the guard if current lock is poisoned.
     #[inline]
-    pub unsafe fn lock_guard(&mut self) -> SpinLockGuard {
-        self.lock();
+    pub fn lock(&self) -> LockResult<SpinLockGuard<T>> {
+        self.raw_lock();
         SpinLockGuard::new(self)
     }
 
-    /// Unlock if is locked, else panic.
+    /// Return SpinLockGuard if lock successfully, `TryLockError::WouldBlock` if current lock is
+    /// held by others.
+    #[inline]
+    pub fn try_lock(&self) -> TryLockResult<SpinLockGuard<T>> {
+        if self.raw_try_lock() {
+            Ok(SpinLockGuard::new(self)?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// Keep trying to lock until success or `timeout` elapses. Return `LockError::Timeout` if
+    /// failed, or `LockError::Poisoned` if current lock is poisoned.
+    #[inline]
+    pub fn try_lock_for(&self, timeout: Duration) -> Result<SpinLockGuard<T>, LockError> {
+        let start = Instant::now();
+        self.lock_until("try_lock_for", start, start + timeout)
+    }
+
+    /// Keep trying to lock until success or `deadline` is reached. Return `LockError::Timeout` if
+    /// failed, or `LockError::Poisoned` if current lock is poisoned.
+    #[inline]
+    pub fn try_lock_until(&self, deadline: Instant) -> Result<SpinLockGuard<T>, LockError> {
+        self.lock_until("try_lock_until", Instant::now(), deadline)
+    }
+
+    fn lock_until(
+        &self,
+        op: &'static str,
+        start: Instant,
+        deadline: Instant,
+    ) -> Result<SpinLockGuard<T>, LockError> {
+        let mut backoff = self.backoff();
+        let mut probe = util::ContentionProbe::new();
+        let ret = loop {
+            match self.try_lock() {
+                Ok(guard) => break Ok(guard),
+                Err(TryLockError::Poisoned(_)) => break Err(LockError::Poisoned { op }),
+                Err(TryLockError::WouldBlock) => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break Err(LockError::Timeout {
+                    op,
+                    timeout: now - start,
+                });
+            }
+            probe.spin();
+            backoff.snooze();
+        };
+        probe.finish(&self.contention);
+        ret
+    }
+
+    /// Same as `lock`, the returned SpinLockGuard borrows current lock, so it can't outlive it.
+    #[inline]
+    pub fn lock_guard(&self) -> LockResult<SpinLockGuard<T>> {
+        self.lock()
+    }
+
+    /// Return mutable reference of the data. No locking is needed because of `&mut self`.
+    #[inline]
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let poisoned = self.is_poisoned();
+        let data = unsafe { &mut *self.data.get() };
+        if poisoned {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Return true if a thread panicked while holding current lock.
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::SeqCst)
+    }
+
+    /// Clear the poisoned state, after the protected data is repaired.
+    #[inline]
+    pub fn clear_poison(&self) {
+        self.poisoned.store(false, Ordering::SeqCst)
+    }
+
+    /// Keep trying to lock until success, without guard. Backs off exponentially under contention
+    /// and waits by `WaitPolicy` after spinning for a while.
+    pub fn raw_lock(&self) {
+        self.check_relock();
+        let mut backoff = self.backoff();
+        let mut probe = util::ContentionProbe::new();
+        loop {
+            if !self.is_locked() {
+                if unsafe { self.inner_lock() } {
+                    break;
+                }
+                probe.fail_cas();
+            }
+            probe.spin();
+            backoff.snooze();
+        }
+        probe.finish(&self.contention);
+        self.set_owner();
+    }
+
+    /// Unlock if is locked, else panic. Caller must hold the lock by `raw_lock` or `raw_try_lock`.
+    #[inline]
+    pub unsafe fn raw_unlock(&self) {
+        self.check_and_clear_owner();
+        assert!(self.is_locked() && self.inner_unlock());
+    }
+
+    /// Return true if lock successfully, without guard.
     #[inline]
-    pub fn unlock(&mut self) {
-        assert!(self.is_locked() && unsafe { self.inner_unlock() });
+    pub fn raw_try_lock(&self) -> bool {
+        if !self.is_locked() && unsafe { self.inner_lock() } {
+            self.set_owner();
+            true
+        } else {
+            false
+        }
+    }
+
+    #[cfg(feature = "debug_lock")]
+    fn check_relock(&self) {
+        let tid = util::get_thread_id();
+        if tid == self.owner.load(Ordering::SeqCst) {
+            panic!("SpinLock is relocked by its owner thread {}, which would deadlock", tid);
+        }
+    }
+
+    #[cfg(feature = "debug_lock")]
+    #[inline]
+    fn set_owner(&self) {
+        self.owner.store(util::get_thread_id(), Ordering::SeqCst)
+    }
+
+    #[cfg(feature = "debug_lock")]
+    fn check_and_clear_owner(&self) {
+        let tid = util::get_thread_id();
+        let owner = self.owner.load(Ordering::SeqCst);
+        if tid != owner {
+            panic!("SpinLock is unlocked by thread {}, but owned by thread {}", tid, owner);
+        }
+        self.owner.store(NO_OWNER, Ordering::SeqCst)
     }
 
+    #[cfg(not(feature = "debug_lock"))]
     #[inline]
-    unsafe fn inner_unlock(&mut self) -> bool {
-        intrinsics::atomic_cxchg(&mut self.atomic, 1, 0).1
+    fn check_relock(&self) {}
+
+    #[cfg(not(feature = "debug_lock"))]
+    #[inline]
+    fn set_owner(&self) {}
+
+    #[cfg(not(feature = "debug_lock"))]
+    #[inline]
+    fn check_and_clear_owner(&self) {}
+
+    #[inline]
+    unsafe fn inner_unlock(&self) -> bool {
+        self.atomic
+            .compare_exchange(1, 0, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
     }
 
     #[inline]
-    unsafe fn inner_lock(&mut self) -> bool {
-        intrinsics::atomic_cxchg(&mut self.atomic, 0, 1).1
+    unsafe fn inner_lock(&self) -> bool {
+        self.atomic
+            .compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
     }
 
     /// Return true if locked.
     #[inline]
     pub fn is_locked(&self) -> bool {
-        unsafe { 0 != intrinsics::atomic_load(&self.atomic) }
+        0 != self.atomic.load(Ordering::SeqCst)
+    }
+}
+
+cfg_if! {
+    if #[cfg(feature = "lock_api")] {
+        /// `lock_api::Mutex` based on `SpinLock`, which provides `const` construction and mapped
+        /// guards.
+        pub type Mutex<T> = lock_api::Mutex<SpinLock, T>;
+
+        /// Guard of `Mutex`.
+        pub type MutexGuard<'a, T> = lock_api::MutexGuard<'a, SpinLock, T>;
+
+        unsafe impl lock_api::RawMutex for SpinLock {
+            const INIT: SpinLock = SpinLock::new(());
+
+            // Owner must unlock by itself when it's tracked.
+            #[cfg(feature = "debug_lock")]
+            type GuardMarker = lock_api::GuardNoSend;
+            #[cfg(not(feature = "debug_lock"))]
+            type GuardMarker = lock_api::GuardSend;
+
+            #[inline]
+            fn lock(&self) {
+                self.raw_lock();
+            }
+
+            #[inline]
+            fn try_lock(&self) -> bool {
+                self.raw_try_lock()
+            }
+
+            #[inline]
+            unsafe fn unlock(&self) {
+                self.raw_unlock();
+            }
+
+            #[inline]
+            fn is_locked(&self) -> bool {
+                SpinLock::is_locked(self)
+            }
+        }
+    }
+}
+
+/// Guard of SpinLock, which derefs to the protected data and unlocks it when dropped. Current lock
+/// is poisoned if the guard is dropped by panic. With feature `debug_lock`, it can't be sent to
+/// another thread, because only the owner may unlock.
+pub struct SpinLockGuard<'a, T: ?Sized + 'a> {
+    lock: &'a SpinLock<T>,
+    panicking: bool,
+    /// Opt out of the auto traits, which would follow `SpinLock` instead of `T`.
+    marker: PhantomData<*const ()>,
+}
+
+#[cfg(not(feature = "debug_lock"))]
+unsafe impl<'a, T: ?Sized + Send> Send for SpinLockGuard<'a, T> {}
+
+unsafe impl<'a, T: ?Sized + Sync> Sync for SpinLockGuard<'a, T> {}
+
+impl<'a, T: ?Sized> SpinLockGuard<'a, T> {
+    /// Lock must be held.
+    #[inline]
+    fn new(lock: &'a SpinLock<T>) -> LockResult<Self> {
+        let guard = SpinLockGuard {
+            lock,
+            panicking: thread::panicking(),
+            marker: PhantomData,
+        };
+        if lock.is_poisoned() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
     }
 
-    /// Return true if lock successfully.
+    /// Make a guard of a part of the protected data, like a field, so that it can be handed out
+    /// without exposing the whole data. Current lock is held until the returned guard is dropped.
+    /// It's an associated function, so it doesn't shadow methods of `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::spin_lock::{SpinLock, SpinLockGuard};
+    ///
+    /// let lock = SpinLock::new((String::from("stats"), 1));
+    /// let mut count = SpinLockGuard::map(lock.lock().unwrap(), |v| &mut v.1);
+    /// *count += 1;
+    /// assert!(lock.try_lock().is_err());
+    /// drop(count);
+    /// assert_eq!(lock.lock().unwrap().1, 2);
+    /// ```
     #[inline]
-    pub fn try_lock(&mut self) -> bool {
-        !self.is_locked() && unsafe { self.inner_lock() }
+    pub fn map<U: ?Sized, F>(guard: Self, f: F) -> MappedSpinLockGuard<'a, T, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *guard.lock.data.get() }) as *mut U;
+        MappedSpinLockGuard::new(guard.into_raw(), data)
+    }
+
+    /// Same as `map`, but return the original guard if `f` returns None.
+    #[inline]
+    pub fn try_map<U: ?Sized, F>(guard: Self, f: F) -> Result<MappedSpinLockGuard<'a, T, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(unsafe { &mut *guard.lock.data.get() }) {
+            Some(data) => {
+                let data = data as *mut U;
+                Ok(MappedSpinLockGuard::new(guard.into_raw(), data))
+            }
+            None => Err(guard),
+        }
+    }
+
+    /// Take the lock and panicking state out of current guard without unlocking.
+    #[inline]
+    fn into_raw(self) -> (&'a SpinLock<T>, bool) {
+        let raw = (self.lock, self.panicking);
+        mem::forget(self);
+        raw
     }
 }
 
-/// Guard of SpinLock, unlock it when dropped.
-pub struct SpinLockGuard {
-    spin_lock: *mut SpinLock,
+impl<'a, T: ?Sized> Deref for SpinLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
 }
 
-impl Default for SpinLockGuard {
-    fn default() -> Self {
-        SpinLockGuard {
-            spin_lock: ptr::null_mut(),
+impl<'a, T: ?Sized> DerefMut for SpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for SpinLockGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.panicking && thread::panicking() {
+                self.lock.poisoned.store(true, Ordering::SeqCst);
+            }
+            self.lock.raw_unlock();
         }
     }
 }
 
-impl SpinLockGuard {
+/// Guard of a part of the data protected by SpinLock, made by `SpinLockGuard::map`. It derefs to
+/// the part only, and unlocks current lock or poisons it when dropped like `SpinLockGuard`.
+pub struct MappedSpinLockGuard<'a, T: ?Sized + 'a, U: ?Sized + 'a> {
+    lock: &'a SpinLock<T>,
+    panicking: bool,
+    data: *mut U,
+    marker: PhantomData<&'a mut U>,
+}
+
+unsafe impl<'a, T: ?Sized + Send, U: ?Sized + Sync> Sync for MappedSpinLockGuard<'a, T, U> {}
+
+impl<'a, T: ?Sized, U: ?Sized> MappedSpinLockGuard<'a, T, U> {
     #[inline]
-    unsafe fn destroy(&mut self) {
-        if !self.spin_lock.is_null() {
-            (*self.spin_lock).unlock();
-            self.spin_lock = ptr::null_mut();
+    fn new((lock, panicking): (&'a SpinLock<T>, bool), data: *mut U) -> Self {
+        MappedSpinLockGuard {
+            lock,
+            panicking,
+            data,
+            marker: PhantomData,
         }
     }
 
+    /// Make a guard of a part of current part, see `SpinLockGuard::map`.
     #[inline]
-    fn new(spin_lock: *mut SpinLock) -> Self {
-        SpinLockGuard { spin_lock }
+    pub fn map<V: ?Sized, F>(guard: Self, f: F) -> MappedSpinLockGuard<'a, T, V>
+    where
+        F: FnOnce(&mut U) -> &mut V,
+    {
+        let data = f(unsafe { &mut *guard.data }) as *mut V;
+        MappedSpinLockGuard::new(guard.into_raw(), data)
+    }
+
+    /// Same as `map`, but return the original guard if `f` returns None.
+    #[inline]
+    pub fn try_map<V: ?Sized, F>(guard: Self, f: F) -> Result<MappedSpinLockGuard<'a, T, V>, Self>
+    where
+        F: FnOnce(&mut U) -> Option<&mut V>,
+    {
+        match f(unsafe { &mut *guard.data }) {
+            Some(data) => {
+                let data = data as *mut V;
+                Ok(MappedSpinLockGuard::new(guard.into_raw(), data))
+            }
+            None => Err(guard),
+        }
+    }
+
+    #[inline]
+    fn into_raw(self) -> (&'a SpinLock<T>, bool) {
+        let raw = (self.lock, self.panicking);
+        mem::forget(self);
+        raw
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized> Deref for MappedSpinLockGuard<'a, T, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized> DerefMut for MappedSpinLockGuard<'a, T, U> {
+    fn deref_mut(&mut self) -> &mut U {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized, U: ?Sized + fmt::Debug> fmt::Debug for MappedSpinLockGuard<'a, T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
     }
 }
 
-impl Drop for SpinLockGuard {
+impl<'a, T: ?Sized, U: ?Sized> Drop for MappedSpinLockGuard<'a, T, U> {
     fn drop(&mut self) {
         unsafe {
-            self.destroy();
+            if !self.panicking && thread::panicking() {
+                self.lock.poisoned.store(true, Ordering::SeqCst);
+            }
+            self.lock.raw_unlock();
         }
     }
 }
 
 mod test {
+    #[test]
+    fn test_static() {
+        use spin_lock::SpinLock;
+        use util::WaitPolicy;
+        static LOCK: SpinLock<u64> = SpinLock::new(0);
+        static YIELD_LOCK: SpinLock<u64> = SpinLock::with_wait_policy(0, WaitPolicy::Yield);
+        *LOCK.lock().unwrap() += 1;
+        *YIELD_LOCK.lock().unwrap() += 2;
+        assert_eq!(*LOCK.lock().unwrap(), 1);
+        assert_eq!(*YIELD_LOCK.lock().unwrap(), 2);
+    }
+
     #[test]
     fn test_spin_lock() {
         use spin_lock::SpinLock;
-        let mut lock = SpinLock::default();
-        lock.lock();
+        let lock: SpinLock = SpinLock::default();
+        lock.raw_lock();
         assert!(lock.is_locked());
-        lock.unlock();
+        unsafe {
+            lock.raw_unlock();
+        }
         assert!(!lock.is_locked());
 
-        unsafe {
-            let _lock_guard = lock.lock_guard();
+        {
+            let _lock_guard = lock.lock_guard().unwrap();
             assert!(lock.is_locked());
+            assert!(lock.try_lock().is_err());
+        }
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    fn test_spin_lock_data() {
+        use spin_lock::SpinLock;
+        let mut lock = SpinLock::new(0);
+        {
+            let mut guard = lock.lock().unwrap();
+            *guard += 1;
+            assert!(lock.is_locked());
+            assert!(lock.try_lock().is_err());
+        }
+        assert!(!lock.is_locked());
+        *lock.try_lock().unwrap() += 1;
+        *lock.get_mut().unwrap() += 1;
+        assert_eq!(lock.into_inner().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_poison() {
+        use error::Status;
+        use spin_lock::SpinLock;
+        use std::sync::{Arc, TryLockError};
+        use std::thread;
+        use std::time::Duration;
+        let lock = Arc::new(SpinLock::new(0));
+        let lock2 = lock.clone();
+        let ret = thread::spawn(move || {
+            let mut guard = lock2.lock().unwrap();
+            *guard += 1;
+            panic!("poison current lock");
+        }).join();
+        assert!(ret.is_err());
+        assert!(!lock.is_locked());
+        assert!(lock.is_poisoned());
+        assert_eq!(*lock.lock().unwrap_err().into_inner(), 1);
+        match lock.try_lock() {
+            Err(TryLockError::Poisoned(_)) => {}
+            _ => panic!("lock should be poisoned"),
+        }
+        let err = lock.try_lock_for(Duration::from_millis(1)).unwrap_err();
+        assert_eq!(Status::from(err), Status::Poisoned);
+        lock.clear_poison();
+        assert_eq!(*lock.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_try_lock_for() {
+        use error::LockError;
+        use spin_lock::SpinLock;
+        use std::time::{Duration, Instant};
+        let lock = SpinLock::new(1);
+        {
+            let _guard = lock.lock().unwrap();
+            match lock.try_lock_for(Duration::from_millis(10)) {
+                Err(LockError::Timeout { op, timeout }) => {
+                    assert_eq!(op, "try_lock_for");
+                    assert!(timeout >= Duration::from_millis(10));
+                }
+                _ => panic!("lock should time out"),
+            }
+            assert!(lock.try_lock_until(Instant::now()).is_err());
         }
+        assert_eq!(*lock.try_lock_for(Duration::from_millis(10)).unwrap(), 1);
         assert!(!lock.is_locked());
     }
+
+    #[cfg(feature = "debug_lock")]
+    #[test]
+    #[should_panic(expected = "relocked by its owner")]
+    fn test_debug_relock() {
+        use spin_lock::SpinLock;
+        let lock = SpinLock::new(0);
+        let _guard = lock.lock().unwrap();
+        let _guard2 = lock.lock().unwrap();
+    }
+
+    #[cfg(feature = "debug_lock")]
+    #[test]
+    fn test_debug_unlock_by_non_owner() {
+        use spin_lock::SpinLock;
+        use std::sync::Arc;
+        use std::thread;
+        let lock: Arc<SpinLock> = Arc::new(SpinLock::default());
+        lock.raw_lock();
+        let lock2 = lock.clone();
+        let ret = thread::spawn(move || unsafe { lock2.raw_unlock() }).join();
+        assert!(ret.is_err());
+        assert!(lock.is_locked());
+        unsafe {
+            lock.raw_unlock();
+        }
+    }
+
+    #[cfg(feature = "lock_api")]
+    #[test]
+    fn test_lock_api() {
+        use lock_api::{MutexGuard, RawMutex};
+        use spin_lock::{Mutex, SpinLock};
+        static MUTEX: Mutex<u64> = Mutex::const_new(<SpinLock as RawMutex>::INIT, 0);
+        *MUTEX.lock() += 1;
+        {
+            let guard = MUTEX.lock();
+            assert!(MUTEX.try_lock().is_none());
+            let mapped = MutexGuard::map(guard, |v| v);
+            assert_eq!(*mapped, 1);
+        }
+        assert!(!MUTEX.is_locked());
+    }
 }
@@ -1,12 +1,22 @@
 //! Definition and implementations of `SpinRWLock`
 //!
-use std::ptr;
+use error::LockError;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
 use std::intrinsics;
+use std::sync::atomic::Ordering;
 use util;
 
 const MAX_REF_CNT: u64 = 0x00ffffff;
 
-#[repr(C)]
+/// Writer owner of SpinRWLock not locked for writing, which is not a valid thread id.
+const NO_OWNER: i64 = -1;
+
+// `u64` is only 4-byte aligned on 32-bit x86, but atomic access needs natural alignment.
+#[repr(C, align(8))]
 #[derive(Copy, Clone)]
 union AtomicLockData {
     v: u64,
@@ -88,7 +98,7 @@ impl AtomicInfo {
         }
     }
 
-    pub fn new(v: u64) -> Self {
+    pub const fn new(v: u64) -> Self {
         AtomicInfo {
             data: AtomicLockData { v },
         }
@@ -101,26 +111,51 @@ impl Default for AtomicInfo {
     }
 }
 
-/// User mode SpinRWLock
+/// User mode SpinRWLock. Write lock must be released by the thread which locked it, which is
+/// checked in debug builds or with feature `debug_lock`.
+///
+/// With feature `lock-stats`, spins, failed CAS and wait time of readers and writers are counted,
+/// and returned by `contention_stats`.
 pub struct SpinRWLock {
     atomic_info: AtomicInfo,
-    w_owner: i64,
+    w_owner: util::AtomicI64,
+    w_version: util::AtomicU64,
+    contention: util::ContentionCounters,
 }
 
 impl SpinRWLock {
+    /// Return unlocked SpinRWLock. It's `const`, so that SpinRWLock can be put in `static` items.
+    #[inline]
+    pub const fn new() -> Self {
+        SpinRWLock {
+            atomic_info: AtomicInfo::new(0),
+            w_owner: util::AtomicI64::new(NO_OWNER),
+            w_version: util::AtomicU64::new(0),
+            contention: util::ContentionCounters::new(),
+        }
+    }
+
+    /// Return spins, failed CAS and wait time of readers and writers since current lock was made.
+    /// Only blocking lock methods, like `rlock`, `lock` and `try_lock_for`, are counted.
+    #[cfg(feature = "lock-stats")]
+    #[inline]
+    pub fn contention_stats(&self) -> util::ContentionStats {
+        self.contention.load()
+    }
+
     #[inline]
     fn atomic_info(&self) -> AtomicInfo {
-        // TODO: whether atomic_load is needed or not?
-        self.atomic_info
+        AtomicInfo::new(unsafe { intrinsics::atomic_load(self.atomic_info.v_ref()) })
     }
 
     #[inline]
-    fn atomic_cxchg_atomic_v(&mut self, old_v: u64, new_v: u64) -> bool {
-        unsafe { intrinsics::atomic_cxchg(self.atomic_info.v_mut(), old_v, new_v).1 }
+    fn atomic_cxchg_atomic_v(&self, old_v: u64, new_v: u64) -> bool {
+        let v = self.atomic_info.v_ref() as *const u64 as *mut u64;
+        unsafe { intrinsics::atomic_cxchg(v, old_v, new_v).1 }
     }
 
     #[inline]
-    pub fn try_rlock(&mut self) -> bool {
+    pub fn try_rlock(&self) -> bool {
         let mut ret = false;
         let old_v = self.atomic_info();
         let mut new_v = old_v;
@@ -133,21 +168,28 @@ impl SpinRWLock {
         ret
     }
 
-    pub fn rlock(&mut self) {
+    pub fn rlock(&self) {
+        let mut backoff = util::Backoff::new();
+        let mut probe = util::ContentionProbe::new();
         loop {
             let old_v = self.atomic_info();
             let mut new_v = old_v;
             new_v.add_r_ref_cnt(1);
             if 0 == old_v.w_pending() && 0 == old_v.w_lock_flag() && MAX_REF_CNT > old_v.r_ref_cnt()
-                && self.atomic_cxchg_atomic_v(old_v.v(), new_v.v())
             {
-                break;
+                if self.atomic_cxchg_atomic_v(old_v.v(), new_v.v()) {
+                    break;
+                }
+                probe.fail_cas();
             }
-            util::pause();
+            probe.spin();
+            backoff.snooze();
         }
+        probe.finish(&self.contention);
     }
 
-    pub unsafe fn unrlock(&mut self) {
+    pub unsafe fn unrlock(&self) {
+        let mut backoff = util::Backoff::new();
         loop {
             let old_v = self.atomic_info();
             let mut new_v = old_v;
@@ -158,13 +200,13 @@ impl SpinRWLock {
             } else if self.atomic_cxchg_atomic_v(old_v.v(), new_v.v()) {
                 break;
             } else {
-                util::pause();
+                backoff.spin();
             }
         }
     }
 
     #[inline]
-    pub fn try_lock(&mut self) -> bool {
+    pub fn try_lock(&self) -> bool {
         let mut ret = false;
         let old_v = self.atomic_info();
         let mut new_v = old_v;
@@ -173,12 +215,15 @@ impl SpinRWLock {
         if 0 == old_v.w_lock_flag() && 0 == old_v.r_ref_cnt()
             && self.atomic_cxchg_atomic_v(old_v.v(), new_v.v())
         {
+            self.set_w_owner(util::get_thread_id());
             ret = true;
         }
         ret
     }
 
-    pub fn lock(&mut self) {
+    pub fn lock(&self) {
+        let mut backoff = util::Backoff::new();
+        let mut probe = util::ContentionProbe::new();
         loop {
             let old_v = self.atomic_info();
             let mut new_v = old_v;
@@ -192,16 +237,23 @@ impl SpinRWLock {
             }
             if self.atomic_cxchg_atomic_v(old_v.v(), new_v.v()) {
                 if !pending {
-                    self.w_owner = util::get_thread_id();
+                    self.set_w_owner(util::get_thread_id());
                     assert_eq!(new_v.w_pending(), 0);
                     break;
                 }
+            } else {
+                probe.fail_cas();
             }
-            util::pause();
+            probe.spin();
+            backoff.snooze();
         }
+        probe.finish(&self.contention);
     }
 
-    pub unsafe fn unlock(&mut self) {
+    pub unsafe fn unlock(&self) {
+        self.check_and_clear_w_owner();
+        self.inc_w_version();
+        let mut backoff = util::Backoff::new();
         loop {
             let old_v = self.atomic_info();
             let mut new_v = old_v;
@@ -215,84 +267,793 @@ impl SpinRWLock {
             } else if self.atomic_cxchg_atomic_v(old_v.v(), new_v.v()) {
                 break;
             } else {
-                util::pause();
+                backoff.spin();
             }
         }
     }
 
-    pub unsafe fn rlock_guard(&mut self) -> RLockGuard {
+    /// Convert the held write lock into a read lock atomically, so that no other writer can lock
+    /// in between. Panic if not locked for writing.
+    pub unsafe fn downgrade(&self) {
+        self.check_and_clear_w_owner();
+        self.inc_w_version();
+        let mut backoff = util::Backoff::new();
+        loop {
+            let old_v = self.atomic_info();
+            let mut new_v = old_v;
+            new_v.set_w_lock_flag(0);
+            new_v.set_r_ref_cnt(1);
+            if 0 == old_v.w_lock_flag() || 0 != old_v.r_ref_cnt() {
+                panic!(
+                    "can't downgrade w_lock_flag {} r_ref_cnt {}",
+                    old_v.w_lock_flag(),
+                    old_v.r_ref_cnt()
+                );
+            } else if self.atomic_cxchg_atomic_v(old_v.v(), new_v.v()) {
+                break;
+            } else {
+                backoff.spin();
+            }
+        }
+    }
+
+    /// Keep trying to lock for reading until success or `timeout` elapses. Return
+    /// `LockError::Timeout` if failed.
+    pub fn try_rlock_for(&self, timeout: Duration) -> Result<RLockGuard, LockError> {
+        if self.retry_until(Instant::now() + timeout, || self.try_rlock()) {
+            Ok(RLockGuard { lock: self })
+        } else {
+            Err(LockError::Timeout {
+                op: "try_rlock_for",
+                timeout,
+            })
+        }
+    }
+
+    /// Keep trying to lock for writing until success or `timeout` elapses. Return
+    /// `LockError::Timeout` if failed. Unlike `lock`, pending flag is not set while waiting, so
+    /// readers are not blocked by a writer which may give up.
+    pub fn try_lock_for(&self, timeout: Duration) -> Result<WLockGuard, LockError> {
+        if self.retry_until(Instant::now() + timeout, || self.try_lock()) {
+            Ok(WLockGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            Err(LockError::Timeout {
+                op: "try_lock_for",
+                timeout,
+            })
+        }
+    }
+
+    /// Return a stamp for optimistic reading if not locked for writing. Data read after it is
+    /// consistent only if `validate_stamp` returns true.
+    #[inline]
+    pub fn read_stamp(&self) -> Option<u64> {
+        let version = self.w_version();
+        if 0 == self.atomic_info().w_lock_flag() {
+            Some(version)
+        } else {
+            None
+        }
+    }
+
+    /// Return true if no writer has locked since `stamp` was got.
+    #[inline]
+    pub fn validate_stamp(&self, stamp: u64) -> bool {
+        unsafe {
+            intrinsics::atomic_fence_acq();
+        }
+        0 == self.atomic_info().w_lock_flag() && stamp == self.w_version()
+    }
+
+    /// Return the number of readers holding current lock. Like the other state accessors, it's a
+    /// snapshot which may be out of date once returned, good for deciding to skip or defer work
+    /// instead of blocking, but not for correctness.
+    #[inline]
+    pub fn reader_count(&self) -> u64 {
+        self.atomic_info().r_ref_cnt()
+    }
+
+    /// Return true if a writer holds current lock.
+    #[inline]
+    pub fn is_write_locked(&self) -> bool {
+        0 != self.atomic_info().w_lock_flag()
+    }
+
+    /// Return true if a writer blocked in `lock` is waiting, which blocks new readers.
+    #[inline]
+    pub fn has_pending_writer(&self) -> bool {
+        0 != self.atomic_info().w_pending()
+    }
+
+    #[inline]
+    fn w_owner(&self) -> i64 {
+        self.w_owner.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    fn set_w_owner(&self, owner: i64) {
+        self.w_owner.store(owner, Ordering::SeqCst)
+    }
+
+    /// Panic if current thread doesn't own the write lock, then clear the owner. If not locked
+    /// for writing, leave it to the caller to report. Write guards are `!Send`, so only raw
+    /// `unlock` by another thread reaches the panic.
+    #[inline]
+    fn check_and_clear_w_owner(&self) {
+        let owner = self.w_owner();
+        if NO_OWNER == owner {
+            return;
+        }
+        if cfg!(any(debug_assertions, feature = "debug_lock")) {
+            let tid = util::get_thread_id();
+            if tid != owner {
+                panic!("SpinRWLock is unlocked by thread {}, but owned by thread {}", tid, owner);
+            }
+        }
+        self.set_w_owner(NO_OWNER);
+    }
+
+    #[inline]
+    fn w_version(&self) -> u64 {
+        self.w_version.load(Ordering::SeqCst)
+    }
+
+    /// Writers bump the version before releasing, so optimistic readers can detect them.
+    #[inline]
+    fn inc_w_version(&self) {
+        self.w_version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn retry_until<F>(&self, deadline: Instant, mut f: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        let mut backoff = util::Backoff::new();
+        let mut probe = util::ContentionProbe::new();
+        let ret = loop {
+            if f() {
+                break true;
+            }
+            if Instant::now() >= deadline {
+                break false;
+            }
+            probe.spin();
+            backoff.snooze();
+        };
+        probe.finish(&self.contention);
+        ret
+    }
+
+    /// Keep trying to lock for reading until success, then return RLockGuard.
+    #[inline]
+    pub fn rlock_guard(&self) -> RLockGuard {
         self.rlock();
-        RLockGuard::new(self)
+        RLockGuard { lock: self }
     }
 
-    pub unsafe fn wlock_guard(&mut self) -> WLockGuard {
+    /// Keep trying to lock for writing until success, then return WLockGuard.
+    #[inline]
+    pub fn wlock_guard(&self) -> WLockGuard {
         self.lock();
-        WLockGuard::new(self)
+        WLockGuard {
+            lock: self,
+            marker: PhantomData,
+        }
     }
 }
 
 impl Default for SpinRWLock {
     fn default() -> Self {
-        SpinRWLock {
-            atomic_info: Default::default(),
-            w_owner: 0,
+        SpinRWLock::new()
+    }
+}
+
+cfg_if! {
+    if #[cfg(feature = "lock_api")] {
+        /// `lock_api::RwLock` based on `SpinRWLock`, which provides `const` construction and mapped
+        /// guards.
+        pub type RwLock<T> = lock_api::RwLock<SpinRWLock, T>;
+
+        /// Read guard of `RwLock`.
+        pub type RwLockReadGuard<'a, T> = lock_api::RwLockReadGuard<'a, SpinRWLock, T>;
+
+        /// Write guard of `RwLock`.
+        pub type RwLockWriteGuard<'a, T> = lock_api::RwLockWriteGuard<'a, SpinRWLock, T>;
+
+        unsafe impl lock_api::RawRwLock for SpinRWLock {
+            const INIT: SpinRWLock = SpinRWLock::new();
+
+            // Owner must unlock by itself.
+            type GuardMarker = lock_api::GuardNoSend;
+
+            #[inline]
+            fn lock_shared(&self) {
+                self.rlock();
+            }
+
+            #[inline]
+            fn try_lock_shared(&self) -> bool {
+                self.try_rlock()
+            }
+
+            #[inline]
+            unsafe fn unlock_shared(&self) {
+                self.unrlock();
+            }
+
+            #[inline]
+            fn lock_exclusive(&self) {
+                self.lock();
+            }
+
+            #[inline]
+            fn try_lock_exclusive(&self) -> bool {
+                self.try_lock()
+            }
+
+            #[inline]
+            unsafe fn unlock_exclusive(&self) {
+                self.unlock();
+            }
         }
     }
 }
 
-/// Guard of RLock, unlock it when dropped.
-pub struct RLockGuard {
-    lock: *mut SpinRWLock,
+/// Reader-writer lock based on `SpinRWLock`, which protects data of type `T` like
+/// `std::sync::RwLock`. Multiple readers or one writer can hold it at the same time, and pending
+/// writers block new readers, so writers won't be starved.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::spin_rwlock::SpinRwLock;
+///
+/// let lock = SpinRwLock::new(vec![1]);
+/// {
+///     let r1 = lock.read();
+///     let r2 = lock.read();
+///     assert_eq!(*r1, *r2);
+///     assert!(lock.try_write().is_none());
+/// }
+/// lock.write().push(2);
+/// assert_eq!(lock.into_inner(), vec![1, 2]);
+/// ```
+///
+pub struct SpinRwLock<T: ?Sized> {
+    lock: SpinRWLock,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for SpinRwLock<T> {}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for SpinRwLock<T> {}
+
+impl<T: Default> Default for SpinRwLock<T> {
+    fn default() -> Self {
+        SpinRwLock::new(T::default())
+    }
 }
 
-impl RLockGuard {
-    unsafe fn destroy(&mut self) {
-        if !self.lock.is_null() {
-            (*self.lock).unrlock();
-            self.lock = ptr::null_mut();
+impl<T> SpinRwLock<T> {
+    /// Return SpinRwLock which protects `data`. It's `const`, so that SpinRwLock can be put in
+    /// `static` items.
+    #[inline]
+    pub const fn new(data: T) -> Self {
+        SpinRwLock {
+            lock: SpinRWLock::new(),
+            data: UnsafeCell::new(data),
         }
     }
 
-    pub fn new(lock: *mut SpinRWLock) -> Self {
-        RLockGuard { lock }
+    /// Consume current lock and return the protected data.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
     }
 }
 
-impl Default for RLockGuard {
-    fn default() -> Self {
-        RLockGuard {
-            lock: ptr::null_mut(),
+impl<T: ?Sized> SpinRwLock<T> {
+    /// Keep trying to lock for reading until success, then return guard which derefs to `&T`.
+    #[inline]
+    pub fn read(&self) -> SpinRwLockReadGuard<T> {
+        self.lock.rlock();
+        SpinRwLockReadGuard { lock: self }
+    }
+
+    /// Return read guard if lock for reading successfully.
+    #[inline]
+    pub fn try_read(&self) -> Option<SpinRwLockReadGuard<T>> {
+        if self.lock.try_rlock() {
+            Some(SpinRwLockReadGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    /// Keep trying to lock for writing until success, then return guard which derefs to `&mut T`.
+    #[inline]
+    pub fn write(&self) -> SpinRwLockWriteGuard<T> {
+        self.lock.lock();
+        SpinRwLockWriteGuard {
+            lock: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return write guard if lock for writing successfully.
+    #[inline]
+    pub fn try_write(&self) -> Option<SpinRwLockWriteGuard<T>> {
+        if self.lock.try_lock() {
+            Some(SpinRwLockWriteGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Keep trying to lock for reading until success or `timeout` elapses. Return
+    /// `LockError::Timeout` if failed.
+    pub fn try_read_for(&self, timeout: Duration) -> Result<SpinRwLockReadGuard<T>, LockError> {
+        let guard = self.lock.try_rlock_for(timeout)?;
+        mem::forget(guard);
+        Ok(SpinRwLockReadGuard { lock: self })
+    }
+
+    /// Keep trying to lock for writing until success or `timeout` elapses. Return
+    /// `LockError::Timeout` if failed.
+    pub fn try_write_for(&self, timeout: Duration) -> Result<SpinRwLockWriteGuard<T>, LockError> {
+        let guard = self.lock.try_lock_for(timeout)?;
+        mem::forget(guard);
+        Ok(SpinRwLockWriteGuard {
+            lock: self,
+            marker: PhantomData,
+        })
+    }
+
+    /// Return spins, failed CAS and wait time of readers and writers since current lock was made,
+    /// like `SpinRWLock::contention_stats`.
+    #[cfg(feature = "lock-stats")]
+    #[inline]
+    pub fn contention_stats(&self) -> util::ContentionStats {
+        self.lock.contention_stats()
+    }
+
+    /// Return the number of readers holding current lock, like `SpinRWLock::reader_count`.
+    #[inline]
+    pub fn reader_count(&self) -> u64 {
+        self.lock.reader_count()
+    }
+
+    /// Return true if a writer holds current lock.
+    #[inline]
+    pub fn is_write_locked(&self) -> bool {
+        self.lock.is_write_locked()
+    }
+
+    /// Return true if a writer blocked in `write` is waiting, which blocks new readers.
+    #[inline]
+    pub fn has_pending_writer(&self) -> bool {
+        self.lock.has_pending_writer()
+    }
+
+    /// Read a copy of the data without touching the reader count, and call `f` on it if no writer
+    /// interferes, like `SeqLock`. Fall back to locking for reading if validation fails, so `f` is
+    /// called exactly once, on consistent data. This removes the read-side CAS for read-dominated
+    /// data.
+    pub fn try_optimistic_read<R, F>(&self, f: F) -> R
+    where
+        T: Copy,
+        F: FnOnce(&T) -> R,
+    {
+        if let Some(stamp) = self.lock.read_stamp() {
+            let data = unsafe { util::racy_read(self.data.get()) };
+            if self.lock.validate_stamp(stamp) {
+                return f(&data);
+            }
         }
+        f(&*self.read())
     }
+
+    /// Return mutable reference of the data. No locking is needed because of `&mut self`.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+}
+
+/// Read guard of SpinRwLock, which derefs to `&T` and unlocks it when dropped.
+pub struct SpinRwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a SpinRwLock<T>,
 }
 
-/// Guard of WLock, unlock it when dropped.
-pub struct WLockGuard {
-    lock: *mut SpinRWLock,
+impl<'a, T: ?Sized> Deref for SpinRwLockReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
 }
 
-impl WLockGuard {
-    unsafe fn destroy(&mut self) {
-        if !self.lock.is_null() {
-            (*self.lock).unlock();
-            self.lock = ptr::null_mut();
+impl<'a, T: ?Sized> SpinRwLockReadGuard<'a, T> {
+    /// Make a read guard of a part of the protected data, like a field, so that it can be handed
+    /// out without exposing the whole data. It's an associated function, so it doesn't shadow
+    /// methods of `T`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::spin_rwlock::{SpinRwLock, SpinRwLockReadGuard};
+    ///
+    /// let lock = SpinRwLock::new((String::from("name"), vec![1, 2]));
+    /// let items = SpinRwLockReadGuard::map(lock.read(), |v| &v.1[..]);
+    /// assert_eq!(*items, [1, 2]);
+    /// assert!(lock.try_write().is_none());
+    /// ```
+    #[inline]
+    pub fn map<U: ?Sized, F>(guard: Self, f: F) -> MappedSpinRwLockReadGuard<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        let data = f(unsafe { &*guard.lock.data.get() }) as *const U;
+        MappedSpinRwLockReadGuard::new(guard.into_raw(), data)
+    }
+
+    /// Same as `map`, but return the original guard if `f` returns None.
+    #[inline]
+    pub fn try_map<U: ?Sized, F>(
+        guard: Self,
+        f: F,
+    ) -> Result<MappedSpinRwLockReadGuard<'a, U>, Self>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        match f(unsafe { &*guard.lock.data.get() }) {
+            Some(data) => {
+                let data = data as *const U;
+                Ok(MappedSpinRwLockReadGuard::new(guard.into_raw(), data))
+            }
+            None => Err(guard),
         }
     }
 
-    pub fn new(lock: *mut SpinRWLock) -> Self {
-        WLockGuard { lock }
+    #[inline]
+    fn into_raw(self) -> &'a SpinRWLock {
+        let lock = &self.lock.lock;
+        mem::forget(self);
+        lock
     }
 }
 
-impl Default for WLockGuard {
-    fn default() -> Self {
-        WLockGuard {
-            lock: ptr::null_mut(),
+impl<'a, T: ?Sized> Drop for SpinRwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.lock.unrlock();
+        }
+    }
+}
+
+/// Write guard of SpinRwLock, which derefs to `&mut T` and unlocks it when dropped. It can't be
+/// sent to another thread, because unlocking by a thread other than the owner panics in debug
+/// builds or with feature `debug_lock`.
+///
+/// ```compile_fail
+/// use rs_lockfree::spin_rwlock::SpinRwLock;
+/// use std::thread;
+///
+/// static LOCK: SpinRwLock<i32> = SpinRwLock::new(0);
+/// let guard = LOCK.write();
+/// thread::spawn(move || drop(guard));
+/// ```
+pub struct SpinRwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a SpinRwLock<T>,
+    /// Opt out of the auto traits, which would follow `SpinRwLock` instead of `T`.
+    marker: PhantomData<*const ()>,
+}
+
+unsafe impl<'a, T: ?Sized + Sync> Sync for SpinRwLockWriteGuard<'a, T> {}
+
+impl<'a, T: ?Sized> Deref for SpinRwLockWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for SpinRwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> SpinRwLockWriteGuard<'a, T> {
+    /// Convert current write guard into a read guard atomically, see `WLockGuard::downgrade`.
+    pub fn downgrade(self) -> SpinRwLockReadGuard<'a, T> {
+        let lock = self.lock;
+        mem::forget(self);
+        unsafe {
+            lock.lock.downgrade();
+        }
+        SpinRwLockReadGuard { lock }
+    }
+
+    /// Make a write guard of a part of the protected data, see `SpinRwLockReadGuard::map`.
+    #[inline]
+    pub fn map<U: ?Sized, F>(guard: Self, f: F) -> MappedSpinRwLockWriteGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *guard.lock.data.get() }) as *mut U;
+        MappedSpinRwLockWriteGuard::new(guard.into_raw(), data)
+    }
+
+    /// Same as `map`, but return the original guard if `f` returns None.
+    #[inline]
+    pub fn try_map<U: ?Sized, F>(
+        guard: Self,
+        f: F,
+    ) -> Result<MappedSpinRwLockWriteGuard<'a, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(unsafe { &mut *guard.lock.data.get() }) {
+            Some(data) => {
+                let data = data as *mut U;
+                Ok(MappedSpinRwLockWriteGuard::new(guard.into_raw(), data))
+            }
+            None => Err(guard),
+        }
+    }
+
+    #[inline]
+    fn into_raw(self) -> &'a SpinRWLock {
+        let lock = &self.lock.lock;
+        mem::forget(self);
+        lock
+    }
+}
+
+impl<'a, T: ?Sized> Drop for SpinRwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.lock.unlock();
+        }
+    }
+}
+
+/// Read guard of a part of the data protected by SpinRwLock, made by `SpinRwLockReadGuard::map`.
+/// It derefs to `&U` and unlocks the lock when dropped.
+pub struct MappedSpinRwLockReadGuard<'a, U: ?Sized + 'a> {
+    lock: &'a SpinRWLock,
+    data: *const U,
+    marker: PhantomData<&'a U>,
+}
+
+unsafe impl<'a, U: ?Sized + Sync> Sync for MappedSpinRwLockReadGuard<'a, U> {}
+
+impl<'a, U: ?Sized> MappedSpinRwLockReadGuard<'a, U> {
+    #[inline]
+    fn new(lock: &'a SpinRWLock, data: *const U) -> Self {
+        MappedSpinRwLockReadGuard {
+            lock,
+            data,
+            marker: PhantomData,
+        }
+    }
+
+    /// Make a read guard of a part of current part, see `SpinRwLockReadGuard::map`.
+    #[inline]
+    pub fn map<V: ?Sized, F>(guard: Self, f: F) -> MappedSpinRwLockReadGuard<'a, V>
+    where
+        F: FnOnce(&U) -> &V,
+    {
+        let data = f(unsafe { &*guard.data }) as *const V;
+        MappedSpinRwLockReadGuard::new(guard.into_raw(), data)
+    }
+
+    /// Same as `map`, but return the original guard if `f` returns None.
+    #[inline]
+    pub fn try_map<V: ?Sized, F>(
+        guard: Self,
+        f: F,
+    ) -> Result<MappedSpinRwLockReadGuard<'a, V>, Self>
+    where
+        F: FnOnce(&U) -> Option<&V>,
+    {
+        match f(unsafe { &*guard.data }) {
+            Some(data) => {
+                let data = data as *const V;
+                Ok(MappedSpinRwLockReadGuard::new(guard.into_raw(), data))
+            }
+            None => Err(guard),
+        }
+    }
+
+    #[inline]
+    fn into_raw(self) -> &'a SpinRWLock {
+        let lock = self.lock;
+        mem::forget(self);
+        lock
+    }
+}
+
+impl<'a, U: ?Sized> Deref for MappedSpinRwLockReadGuard<'a, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, U: ?Sized> Drop for MappedSpinRwLockReadGuard<'a, U> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.unrlock();
+        }
+    }
+}
+
+/// Write guard of a part of the data protected by SpinRwLock, made by
+/// `SpinRwLockWriteGuard::map`. It derefs to `&mut U` and unlocks the lock when dropped. It can't
+/// be sent to another thread, like `SpinRwLockWriteGuard`.
+///
+/// ```compile_fail
+/// use rs_lockfree::spin_rwlock::{SpinRwLock, SpinRwLockWriteGuard};
+/// use std::thread;
+///
+/// static LOCK: SpinRwLock<(i32, i32)> = SpinRwLock::new((0, 0));
+/// let guard = SpinRwLockWriteGuard::map(LOCK.write(), |v| &mut v.0);
+/// thread::spawn(move || drop(guard));
+/// ```
+pub struct MappedSpinRwLockWriteGuard<'a, U: ?Sized + 'a> {
+    lock: &'a SpinRWLock,
+    /// Raw pointer, which also keeps current guard `!Send`.
+    data: *mut U,
+    marker: PhantomData<&'a mut U>,
+}
+
+unsafe impl<'a, U: ?Sized + Sync> Sync for MappedSpinRwLockWriteGuard<'a, U> {}
+
+impl<'a, U: ?Sized> MappedSpinRwLockWriteGuard<'a, U> {
+    #[inline]
+    fn new(lock: &'a SpinRWLock, data: *mut U) -> Self {
+        MappedSpinRwLockWriteGuard {
+            lock,
+            data,
+            marker: PhantomData,
+        }
+    }
+
+    /// Make a write guard of a part of current part, see `SpinRwLockWriteGuard::map`.
+    #[inline]
+    pub fn map<V: ?Sized, F>(guard: Self, f: F) -> MappedSpinRwLockWriteGuard<'a, V>
+    where
+        F: FnOnce(&mut U) -> &mut V,
+    {
+        let data = f(unsafe { &mut *guard.data }) as *mut V;
+        MappedSpinRwLockWriteGuard::new(guard.into_raw(), data)
+    }
+
+    /// Same as `map`, but return the original guard if `f` returns None.
+    #[inline]
+    pub fn try_map<V: ?Sized, F>(
+        guard: Self,
+        f: F,
+    ) -> Result<MappedSpinRwLockWriteGuard<'a, V>, Self>
+    where
+        F: FnOnce(&mut U) -> Option<&mut V>,
+    {
+        match f(unsafe { &mut *guard.data }) {
+            Some(data) => {
+                let data = data as *mut V;
+                Ok(MappedSpinRwLockWriteGuard::new(guard.into_raw(), data))
+            }
+            None => Err(guard),
+        }
+    }
+
+    /// Convert current write guard into a read guard of the same part atomically, see
+    /// `WLockGuard::downgrade`.
+    pub fn downgrade(guard: Self) -> MappedSpinRwLockReadGuard<'a, U> {
+        let data = guard.data as *const U;
+        let lock = guard.into_raw();
+        unsafe {
+            lock.downgrade();
+        }
+        MappedSpinRwLockReadGuard::new(lock, data)
+    }
+
+    #[inline]
+    fn into_raw(self) -> &'a SpinRWLock {
+        let lock = self.lock;
+        mem::forget(self);
+        lock
+    }
+}
+
+impl<'a, U: ?Sized> Deref for MappedSpinRwLockWriteGuard<'a, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, U: ?Sized> DerefMut for MappedSpinRwLockWriteGuard<'a, U> {
+    fn deref_mut(&mut self) -> &mut U {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, U: ?Sized> Drop for MappedSpinRwLockWriteGuard<'a, U> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.unlock();
+        }
+    }
+}
+
+/// Guard of RLock, which borrows SpinRWLock and unlocks it when dropped.
+pub struct RLockGuard<'a> {
+    lock: &'a SpinRWLock,
+}
+
+impl<'a> Drop for RLockGuard<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.unrlock();
+        }
+    }
+}
+
+/// Guard of WLock, which borrows SpinRWLock and unlocks it when dropped. It can't be sent to
+/// another thread, like `SpinRwLockWriteGuard`.
+pub struct WLockGuard<'a> {
+    lock: &'a SpinRWLock,
+    /// Opt out of `Send`, only the owner may unlock.
+    marker: PhantomData<*const ()>,
+}
+
+unsafe impl<'a> Sync for WLockGuard<'a> {}
+
+impl<'a> WLockGuard<'a> {
+    /// Convert current write guard into a read guard atomically, so that other writers can't
+    /// sneak in before reading the published change.
+    pub fn downgrade(self) -> RLockGuard<'a> {
+        let lock = self.lock;
+        mem::forget(self);
+        unsafe {
+            lock.downgrade();
+        }
+        RLockGuard { lock }
+    }
+}
+
+impl<'a> Drop for WLockGuard<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.unlock();
         }
     }
 }
 
 mod test {
+    #[test]
+    fn test_static() {
+        use spin_rwlock::{SpinRWLock, SpinRwLock};
+        static RAW_LOCK: SpinRWLock = SpinRWLock::new();
+        static LOCK: SpinRwLock<u64> = SpinRwLock::new(0);
+        assert!(RAW_LOCK.try_rlock());
+        unsafe { RAW_LOCK.unrlock() };
+        *LOCK.write() += 1;
+        assert_eq!(*LOCK.read(), 1);
+    }
+
     #[test]
     fn test_rwlock() {
         use spin_rwlock::SpinRWLock;
@@ -323,4 +1084,147 @@ mod test {
         assert_eq!(lock.atomic_info.w_lock_flag(), 0);
         assert_eq!(lock.atomic_info.r_ref_cnt(), 0);
     }
+
+    #[test]
+    fn test_lock_guard() {
+        use spin_rwlock::SpinRWLock;
+        let lock = SpinRWLock::default();
+        {
+            let _r1 = lock.rlock_guard();
+            let _r2 = lock.rlock_guard();
+            assert_eq!(lock.atomic_info().r_ref_cnt(), 2);
+            assert!(!lock.try_lock());
+        }
+        assert_eq!(lock.atomic_info().r_ref_cnt(), 0);
+        {
+            let _w = lock.wlock_guard();
+            assert_eq!(lock.atomic_info().w_lock_flag(), 1);
+            assert!(!lock.try_rlock());
+        }
+        assert_eq!(lock.atomic_info().w_lock_flag(), 0);
+        {
+            let r = lock.wlock_guard().downgrade();
+            assert_eq!(lock.atomic_info().w_lock_flag(), 0);
+            assert_eq!(lock.atomic_info().r_ref_cnt(), 1);
+            assert!(!lock.try_lock());
+            let _r2 = lock.rlock_guard();
+            drop(r);
+        }
+        assert_eq!(lock.atomic_info().r_ref_cnt(), 0);
+        assert!(lock.try_lock());
+    }
+
+    #[test]
+    fn test_w_owner() {
+        use spin_rwlock::{SpinRWLock, NO_OWNER};
+        use std::sync::Arc;
+        use std::thread;
+        use util;
+        let lock = Arc::new(SpinRWLock::default());
+        assert_eq!(lock.w_owner(), NO_OWNER);
+        lock.lock();
+        assert_eq!(lock.w_owner(), util::get_thread_id());
+        if cfg!(debug_assertions) {
+            let lock2 = lock.clone();
+            let ret = thread::spawn(move || unsafe { lock2.unlock() }).join();
+            assert!(ret.is_err());
+            assert_eq!(lock.atomic_info().w_lock_flag(), 1);
+        }
+        unsafe {
+            lock.unlock();
+        }
+        assert_eq!(lock.w_owner(), NO_OWNER);
+        assert!(lock.try_lock());
+        assert_eq!(lock.w_owner(), util::get_thread_id());
+        unsafe {
+            lock.downgrade();
+        }
+        assert_eq!(lock.w_owner(), NO_OWNER);
+    }
+
+    #[test]
+    fn test_optimistic_read() {
+        use spin_rwlock::SpinRwLock;
+        let lock = SpinRwLock::new((1, 2));
+        let stamp = lock.lock.read_stamp().unwrap();
+        assert!(lock.lock.validate_stamp(stamp));
+        assert_eq!(lock.try_optimistic_read(|v| v.0 + v.1), 3);
+        {
+            let mut w = lock.write();
+            assert!(lock.lock.read_stamp().is_none());
+            assert!(!lock.lock.validate_stamp(stamp));
+            w.0 = 3;
+        }
+        assert!(!lock.lock.validate_stamp(stamp));
+        assert_eq!(lock.try_optimistic_read(|v| *v), (3, 2));
+        let _r = lock.read();
+        assert_eq!(lock.try_optimistic_read(|v| v.0), 3);
+    }
+
+    #[test]
+    fn test_try_lock_for() {
+        use error::Status;
+        use spin_rwlock::SpinRwLock;
+        use std::time::Duration;
+        let lock = SpinRwLock::new(1);
+        let timeout = Duration::from_millis(10);
+        {
+            let _w = lock.write();
+            assert_eq!(lock.try_read_for(timeout).err().map(Status::from), Some(Status::Timeout));
+            assert_eq!(lock.try_write_for(timeout).err().map(Status::from), Some(Status::Timeout));
+        }
+        {
+            let r = lock.try_read_for(timeout).unwrap();
+            assert_eq!(*r, 1);
+            assert_eq!(lock.try_write_for(timeout).err().map(Status::from), Some(Status::Timeout));
+        }
+        *lock.try_write_for(timeout).unwrap() += 1;
+        assert_eq!(*lock.read(), 2);
+    }
+
+    #[test]
+    fn test_spin_rw_lock() {
+        use spin_rwlock::SpinRwLock;
+        let mut lock = SpinRwLock::new(0);
+        {
+            let r1 = lock.read();
+            let r2 = lock.try_read().unwrap();
+            assert_eq!(*r1 + *r2, 0);
+            assert!(lock.try_write().is_none());
+        }
+        {
+            let mut w = lock.write();
+            *w += 1;
+            assert!(lock.try_read().is_none());
+            assert!(lock.try_write().is_none());
+        }
+        {
+            let mut w = lock.write();
+            *w += 1;
+            let r = w.downgrade();
+            assert_eq!(*r, 2);
+            assert!(lock.try_write().is_none());
+            assert!(lock.try_read().is_some());
+        }
+        *lock.get_mut() += 1;
+        assert_eq!(*lock.read(), 3);
+        assert_eq!(lock.into_inner(), 3);
+    }
+
+    #[cfg(feature = "lock_api")]
+    #[test]
+    fn test_lock_api() {
+        use lock_api::{RawRwLock, RwLockReadGuard};
+        use spin_rwlock::{RwLock, SpinRWLock};
+        static LOCK: RwLock<u64> = RwLock::const_new(<SpinRWLock as RawRwLock>::INIT, 0);
+        *LOCK.write() += 1;
+        {
+            let r1 = LOCK.read();
+            let r2 = LOCK.read();
+            assert!(LOCK.try_write().is_none());
+            let mapped = RwLockReadGuard::map(r1, |v| v);
+            assert_eq!(*mapped, *r2);
+        }
+        assert!(LOCK.try_write().is_some());
+    }
 }
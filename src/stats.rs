@@ -0,0 +1,101 @@
+//! Health of reclamation and containers published through the `metrics` facade
+//!
+//! With feature `metrics`, values are summed over all instances in current process and recorded by
+//! the recorder installed by user, such as `metrics-exporter-prometheus`. Without it, all functions
+//! are empty and optimized away.
+//!
+//! With feature `reclaim-delay`, the delay from adding each object to `HazardEpoch` to destroying
+//! it is also recorded in a process-wide `util::Histogram`, returned by `HazardEpoch::reclaim_delay`.
+//!
+#[cfg(feature = "reclaim-delay")]
+use std::ptr;
+#[cfg(feature = "reclaim-delay")]
+use std::sync::{Once, ONCE_INIT};
+#[cfg(feature = "reclaim-delay")]
+use util;
+
+/// Gauge of shared objects waiting to be reclaimed by `HazardEpoch`.
+pub const HAZARD_WAITING_COUNT: &str = "rs_lockfree_hazard_waiting_count";
+/// Counter of shared objects reclaimed by `HazardEpoch`.
+pub const HAZARD_RECLAIMED_TOTAL: &str = "rs_lockfree_hazard_reclaimed_total";
+/// Histogram of seconds from adding shared objects to `HazardEpoch` to destroying them, with
+/// feature `reclaim-delay`.
+pub const HAZARD_RECLAIM_DELAY_SECONDS: &str = "rs_lockfree_hazard_reclaim_delay_seconds";
+/// Gauge of threads registered to `HazardEpoch`.
+pub const HAZARD_THREAD_COUNT: &str = "rs_lockfree_hazard_thread_count";
+/// Gauge of elements in `LockFreeQueue`.
+pub const QUEUE_DEPTH: &str = "rs_lockfree_queue_depth";
+/// Gauge of elements in `LockFreeStack`.
+pub const STACK_DEPTH: &str = "rs_lockfree_stack_depth";
+
+cfg_if! {
+    if #[cfg(feature = "metrics")] {
+        /// Add `delta` to gauge `name`.
+        #[inline]
+        pub fn gauge_add(name: &'static str, delta: i64) {
+            if 0 < delta {
+                gauge!(name).increment(delta as f64);
+            } else if 0 > delta {
+                gauge!(name).decrement(-delta as f64);
+            }
+        }
+
+        /// Add `delta` to counter `name`.
+        #[inline]
+        pub fn counter_add(name: &'static str, delta: i64) {
+            if 0 < delta {
+                counter!(name).increment(delta as u64);
+            }
+        }
+
+        /// Record `v` in histogram `name`.
+        #[inline]
+        pub fn histogram_record(name: &'static str, v: f64) {
+            histogram!(name).record(v);
+        }
+    } else {
+        /// Add `delta` to gauge `name`.
+        #[inline]
+        pub fn gauge_add(_name: &'static str, _delta: i64) {}
+
+        /// Add `delta` to counter `name`.
+        #[inline]
+        pub fn counter_add(_name: &'static str, _delta: i64) {}
+
+        /// Record `v` in histogram `name`.
+        #[inline]
+        pub fn histogram_record(_name: &'static str, _v: f64) {}
+    }
+}
+
+cfg_if! {
+    if #[cfg(feature = "reclaim-delay")] {
+        static RECLAIM_DELAY_INIT: Once = ONCE_INIT;
+        static mut RECLAIM_DELAY: *const util::Histogram = ptr::null();
+
+        /// Return the histogram of delays(microsecond) from adding shared objects to `HazardEpoch`
+        /// to destroying them, over all instances in current process.
+        pub fn reclaim_delay() -> &'static util::Histogram {
+            unsafe {
+                RECLAIM_DELAY_INIT.call_once(|| {
+                    RECLAIM_DELAY = Box::into_raw(Box::new(util::Histogram::new()));
+                });
+                &*RECLAIM_DELAY
+            }
+        }
+
+        /// Return the time to remember when an object is added to `HazardEpoch`.
+        #[inline]
+        pub fn reclaim_delay_start() -> i64 {
+            util::get_cur_microseconds_time()
+        }
+
+        /// Record the delay of destroying an object added at `start`.
+        #[inline]
+        pub fn reclaim_delay_record(start: i64) {
+            let delay = (util::get_cur_microseconds_time() - start).max(0) as u64;
+            reclaim_delay().record(delay);
+            histogram_record(HAZARD_RECLAIM_DELAY_SECONDS, delay as f64 / 1e6);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Definition and implementations of `TaggedLockFreeStack`
+//!
+//! On `x86_64`, `TaggedLockFreeStack` packs a version tag with the top pointer and updates both by
+//! `cmpxchg16b`, which defeats the `ABA problem` without any `HazardEpoch` participation. Popped
+//! nodes are recycled by an internal free list instead of being released to the allocator, so a
+//! stale `next` read is always a read of valid memory and only causes a failed CAS. On other
+//! targets it falls back to the hazard based `LockFreeStack`.
+//!
+
+#[cfg(target_arch = "x86_64")]
+mod tagged_x86 {
+    use error::{PushError, QueueError};
+    use traits::ConcurrentStack;
+    use util;
+    use std::mem;
+    use std::ptr;
+
+    type TaggedNodePtr<T> = *mut TaggedNode<T>;
+
+    struct TaggedNode<T> {
+        value: Option<T>,
+        next: TaggedNodePtr<T>,
+    }
+
+    impl<T> TaggedNode<T> {
+        #[inline]
+        unsafe fn next(&self) -> TaggedNodePtr<T> {
+            util::atomic_load_raw_ptr(&self.next)
+        }
+
+        #[inline]
+        unsafe fn set_next(&mut self, next: TaggedNodePtr<T>) {
+            util::atomic_store_raw_ptr(&mut self.next, next);
+        }
+    }
+
+    /// Top pointer of a list and its version tag, which are compared and swapped together.
+    #[repr(C, align(16))]
+    struct TaggedTop<T> {
+        ptr: TaggedNodePtr<T>,
+        tag: u64,
+    }
+
+    impl<T> Copy for TaggedTop<T> {}
+
+    impl<T> Clone for TaggedTop<T> {
+        fn clone(&self) -> Self {
+            *self
+        }
+    }
+
+    impl<T> Default for TaggedTop<T> {
+        fn default() -> Self {
+            TaggedTop {
+                ptr: ptr::null_mut(),
+                tag: 0,
+            }
+        }
+    }
+
+    #[inline]
+    unsafe fn atomic_cxchg_tagged_top<T>(
+        dst: *mut TaggedTop<T>,
+        old: TaggedTop<T>,
+        src: TaggedTop<T>,
+    ) -> (TaggedTop<T>, bool) {
+        let (v, ok) = util::atomic_cxchg_u128(
+            dst as *mut u128,
+            mem::transmute::<_, u128>(old),
+            mem::transmute::<_, u128>(src),
+        );
+        (mem::transmute::<u128, _>(v), ok)
+    }
+
+    /// Atomic load a `TaggedTop` by a CAS which never changes it.
+    #[inline]
+    unsafe fn atomic_load_tagged_top<T>(src: *mut TaggedTop<T>) -> TaggedTop<T> {
+        atomic_cxchg_tagged_top(src, TaggedTop::default(), TaggedTop::default()).0
+    }
+
+    unsafe fn push_node<T>(top: *mut TaggedTop<T>, node: TaggedNodePtr<T>) {
+        let mut old = atomic_load_tagged_top(top);
+        let mut backoff = util::Backoff::new();
+        loop {
+            (*node).set_next(old.ptr);
+            let new = TaggedTop {
+                ptr: node,
+                tag: old.tag.wrapping_add(1),
+            };
+            let (cur, ok) = atomic_cxchg_tagged_top(top, old, new);
+            if ok {
+                break;
+            }
+            old = cur;
+            backoff.spin();
+        }
+    }
+
+    unsafe fn pop_node<T>(top: *mut TaggedTop<T>) -> TaggedNodePtr<T> {
+        let mut old = atomic_load_tagged_top(top);
+        let mut backoff = util::Backoff::new();
+        while !old.ptr.is_null() {
+            // `old.ptr` may have been popped and recycled by others, but its memory is still
+            // valid, and the tag makes sure the CAS fails if so.
+            let new = TaggedTop {
+                ptr: (*old.ptr).next(),
+                tag: old.tag.wrapping_add(1),
+            };
+            let (cur, ok) = atomic_cxchg_tagged_top(top, old, new);
+            if ok {
+                break;
+            }
+            old = cur;
+            backoff.spin();
+        }
+        old.ptr
+    }
+
+    unsafe fn destroy_list<T>(top: &mut TaggedTop<T>) {
+        let mut head = top.ptr;
+        while !head.is_null() {
+            head = Box::from_raw(head).next;
+        }
+        *top = TaggedTop::default();
+    }
+
+    /// LockFree stack, implemented based on double-width CAS of tagged pointer
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::tagged_stack::TaggedLockFreeStack;
+    /// let mut stack = unsafe { TaggedLockFreeStack::default_new_in_stack() };
+    /// assert!(stack.pop().is_none());
+    /// stack.push(1);
+    /// assert_eq!(stack.pop().unwrap(), 1);
+    /// let test_num = 100;
+    /// for i in 0..test_num {
+    ///     stack.push(i);
+    /// }
+    /// for i in 0..test_num {
+    ///     assert_eq!(stack.pop().unwrap(), test_num - i - 1);
+    /// }
+    /// ```
+    ///
+    pub struct TaggedLockFreeStack<T> {
+        top: util::WrappedAlign64Type<TaggedTop<T>>,
+        free_list: util::WrappedAlign64Type<TaggedTop<T>>,
+    }
+
+    impl<T> TaggedLockFreeStack<T> {
+        /// Return TaggedLockFreeStack in stack. Panic if current CPU doesn't support `cmpxchg16b`.
+        pub unsafe fn default_new_in_stack() -> TaggedLockFreeStack<T> {
+            assert!(util::has_cxchg_u128(), "cmpxchg16b is not supported by current CPU");
+            TaggedLockFreeStack {
+                top: util::WrappedAlign64Type(TaggedTop::default()),
+                free_list: util::WrappedAlign64Type(TaggedTop::default()),
+            }
+        }
+
+        /// Return TaggedLockFreeStack in heap
+        pub fn default_new_in_heap() -> Box<Self> {
+            unsafe { Box::new(Self::default_new_in_stack()) }
+        }
+
+        /// Push an element to the top of current stack
+        pub fn push(&mut self, v: T) {
+            unsafe {
+                let mut node = pop_node(self.free_list.as_mut_ptr());
+                if node.is_null() {
+                    node = Box::into_raw(Box::new(TaggedNode {
+                        value: None,
+                        next: ptr::null_mut(),
+                    }));
+                }
+                (*node).value = Some(v);
+                push_node(self.top.as_mut_ptr(), node);
+            }
+        }
+
+        /// Pop the element at the top of current stack
+        pub fn pop(&mut self) -> Option<T> {
+            unsafe {
+                let node = pop_node(self.top.as_mut_ptr());
+                if node.is_null() {
+                    return None;
+                }
+                let ret = (*node).value.take();
+                assert!(ret.is_some());
+                push_node(self.free_list.as_mut_ptr(), node);
+                ret
+            }
+        }
+
+        pub unsafe fn destroy(&mut self) {
+            destroy_list(self.top.get_mut());
+            destroy_list(self.free_list.get_mut());
+        }
+    }
+
+    impl<T> ConcurrentStack<T> for TaggedLockFreeStack<T> {
+        fn try_push(&mut self, v: T) -> Result<(), PushError<T>> {
+            self.push(v);
+            Ok(())
+        }
+
+        fn try_pop(&mut self) -> Result<Option<T>, QueueError> {
+            Ok(self.pop())
+        }
+    }
+
+    impl<T> Drop for TaggedLockFreeStack<T> {
+        fn drop(&mut self) {
+            unsafe {
+                self.destroy();
+            }
+        }
+    }
+
+    mod test {
+        use std::cell::RefCell;
+
+        struct Node<'a, T> {
+            cnt: &'a RefCell<i32>,
+            v: T,
+        }
+
+        impl<'a, T> Drop for Node<'a, T> {
+            fn drop(&mut self) {
+                *self.cnt.borrow_mut() += 1;
+            }
+        }
+
+        // `cmpxchg16b` is not supported by Miri.
+        #[test]
+        #[cfg_attr(miri, ignore)]
+        fn test_memory_leak() {
+            use tagged_stack::TaggedLockFreeStack;
+            let cnt = RefCell::new(0);
+            let test_num = 100;
+            {
+                let mut stack = unsafe { TaggedLockFreeStack::default_new_in_stack() };
+                for i in 0..test_num {
+                    stack.push(Node { cnt: &cnt, v: i });
+                }
+                assert_eq!(*cnt.borrow(), 0);
+                for i in 0..test_num / 2 {
+                    assert_eq!(stack.pop().unwrap().v, test_num - i - 1);
+                }
+                assert_eq!(*cnt.borrow(), test_num / 2);
+                // reuse a recycled node
+                stack.push(Node { cnt: &cnt, v: test_num });
+                assert_eq!(stack.pop().unwrap().v, test_num);
+            }
+            assert_eq!(*cnt.borrow(), test_num + 1);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub use self::tagged_x86::TaggedLockFreeStack;
+
+/// Fall back to the hazard based stack on targets without double-width CAS.
+#[cfg(not(target_arch = "x86_64"))]
+pub use lockfree_stack::LockFreeStack as TaggedLockFreeStack;
@@ -0,0 +1,163 @@
+//! Controllable yield points inside critical CAS sequences, for tests driving specific interleavings
+//!
+//! It's an internal module of feature `test-hooks`, which should only be enabled by tests. Each
+//! point is named by a constant below, and reached by every thread passing the code it marks. A
+//! test can `pause` a point, so that the next thread reaching it is parked there until `resume`,
+//! while other threads run into the window it's parked in. A point can also be given a delay by
+//! `set_delay`, which widens the window for every thread reaching it.
+//!
+//! Points are shared by the whole process, so tests using them should hold the guard of
+//! `exclusive`, and leave them clean by `reset`.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(feature = "test-hooks")]
+//! # fn main() {
+//! use rs_lockfree::lockfree_queue::LockFreeQueue;
+//! use rs_lockfree::test_hooks;
+//! use std::thread;
+//!
+//! let _guard = test_hooks::exclusive();
+//! let mut queue = LockFreeQueue::<u32>::default_new_in_heap();
+//! let p = &mut *queue as *mut LockFreeQueue<u32> as usize;
+//! test_hooks::pause(test_hooks::QUEUE_TAIL_LINK);
+//! let t = thread::spawn(move || unsafe { (*(p as *mut LockFreeQueue<u32>)).push(1) });
+//! test_hooks::wait_paused(test_hooks::QUEUE_TAIL_LINK);
+//! // The new node has taken the tail, but isn't linked to the queue yet.
+//! assert_eq!(queue.pop(), None);
+//! test_hooks::resume(test_hooks::QUEUE_TAIL_LINK);
+//! t.join().unwrap();
+//! assert_eq!(queue.pop(), Some(1));
+//! test_hooks::reset();
+//! # }
+//! # #[cfg(not(feature = "test-hooks"))]
+//! # fn main() {}
+//! ```
+//!
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+/// In `LockFreeQueue::push`, after the new node takes `tail` and before the old tail links to it.
+pub const QUEUE_TAIL_LINK: &str = "lockfree_queue::tail_link";
+/// In reclaiming of a thread store, after its waiting list is swapped out and before the nodes
+/// still in use are put back.
+pub const RETIRE_SWAP: &str = "hazard_pointer::retire_swap";
+
+struct Point {
+    name: &'static str,
+    armed: bool,
+    /// Thread parked at current point.
+    parked: Option<ThreadId>,
+    delay: Option<Duration>,
+    hits: usize,
+}
+
+static POINTS: Mutex<Vec<Point>> = Mutex::new(Vec::new());
+static CHANGED: Condvar = Condvar::new();
+static EXCLUSIVE: Mutex<()> = Mutex::new(());
+
+fn lock_points() -> MutexGuard<'static, Vec<Point>> {
+    // A failed assertion of another test must not break the following ones.
+    POINTS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn get_point<'a>(points: &'a mut Vec<Point>, name: &'static str) -> &'a mut Point {
+    match points.iter().position(|p| p.name == name) {
+        Some(i) => &mut points[i],
+        None => {
+            points.push(Point {
+                name,
+                armed: false,
+                parked: None,
+                delay: None,
+                hits: 0,
+            });
+            points.last_mut().unwrap()
+        }
+    }
+}
+
+/// Called at point `name` by the code it marks.
+pub fn reach(name: &'static str) {
+    let me = thread::current().id();
+    let mut points = lock_points();
+    let delay = {
+        let point = get_point(&mut points, name);
+        point.hits += 1;
+        if point.armed {
+            point.armed = false;
+            point.parked = Some(me);
+            CHANGED.notify_all();
+        }
+        point.delay
+    };
+    while Some(me) == get_point(&mut points, name).parked {
+        points = CHANGED.wait(points).unwrap_or_else(|e| e.into_inner());
+    }
+    drop(points);
+    if let Some(delay) = delay {
+        thread::sleep(delay);
+    }
+}
+
+/// Return a guard serializing tests which use points.
+pub fn exclusive() -> MutexGuard<'static, ()> {
+    EXCLUSIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Park the next thread reaching point `name` until `resume`.
+pub fn pause(name: &'static str) {
+    get_point(&mut lock_points(), name).armed = true;
+}
+
+/// Block until a thread is parked at point `name`.
+pub fn wait_paused(name: &'static str) {
+    let mut points = lock_points();
+    while get_point(&mut points, name).parked.is_none() {
+        points = CHANGED.wait(points).unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+/// Let the thread parked at point `name` go on.
+pub fn resume(name: &'static str) {
+    get_point(&mut lock_points(), name).parked = None;
+    CHANGED.notify_all();
+}
+
+/// Sleep `delay` at point `name` for every thread reaching it, None to stop.
+pub fn set_delay(name: &'static str, delay: Option<Duration>) {
+    get_point(&mut lock_points(), name).delay = delay;
+}
+
+/// Return how many times point `name` has been reached.
+pub fn hits(name: &'static str) -> usize {
+    get_point(&mut lock_points(), name).hits
+}
+
+/// Resume all parked threads and clear all points.
+pub fn reset() {
+    lock_points().clear();
+    CHANGED.notify_all();
+}
+
+mod test {
+    #[test]
+    fn test_pause_resume() {
+        use std::thread;
+        use test_hooks;
+
+        let name = "test_hooks::test";
+        let _guard = test_hooks::exclusive();
+        test_hooks::pause(name);
+        let t = thread::spawn(move || test_hooks::reach(name));
+        test_hooks::wait_paused(name);
+        test_hooks::reach(name);
+        assert_eq!(test_hooks::hits(name), 2);
+        test_hooks::resume(name);
+        t.join().unwrap();
+        test_hooks::reset();
+        assert_eq!(test_hooks::hits(name), 0);
+    }
+}
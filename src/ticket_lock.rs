@@ -0,0 +1,210 @@
+//! Definition and implementations of `TicketLock`
+//!
+use util;
+use std::cell::UnsafeCell;
+use std::intrinsics;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// User mode ticket lock, which protects data of type `T` like `SpinLock` but grants the lock in
+/// FIFO order. Each locker takes a ticket and waits until it's served, so no waiter can be starved
+/// by luckier threads, at the cost of a little more traffic on the serving counter.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::ticket_lock::TicketLock;
+///
+/// let lock = TicketLock::new(0);
+/// *lock.lock() += 1;
+/// {
+///     let _guard = lock.lock();
+///     assert!(lock.try_lock().is_none());
+/// }
+/// assert_eq!(lock.into_inner(), 1);
+/// ```
+///
+pub struct TicketLock<T: ?Sized = ()> {
+    next_ticket: util::WrappedAlign64Type<u64>,
+    now_serving: util::WrappedAlign64Type<u64>,
+    wait_policy: Option<util::WaitPolicy>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for TicketLock<T> {}
+
+unsafe impl<T: ?Sized + Send> Sync for TicketLock<T> {}
+
+impl<T: Default> Default for TicketLock<T> {
+    fn default() -> Self {
+        TicketLock::new(T::default())
+    }
+}
+
+impl<T> TicketLock<T> {
+    /// Return TicketLock which protects `data`. It's `const`, so that TicketLock can be put in
+    /// `static` items.
+    #[inline]
+    pub const fn new(data: T) -> Self {
+        TicketLock {
+            next_ticket: util::WrappedAlign64Type(0),
+            now_serving: util::WrappedAlign64Type(0),
+            wait_policy: None,
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Return TicketLock which protects `data`, and waits by `policy` instead of the process-wide
+    /// `WaitPolicy`.
+    #[inline]
+    pub const fn with_wait_policy(data: T, policy: util::WaitPolicy) -> Self {
+        TicketLock {
+            next_ticket: util::WrappedAlign64Type(0),
+            now_serving: util::WrappedAlign64Type(0),
+            wait_policy: Some(policy),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consume current lock and return the protected data.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> TicketLock<T> {
+    /// Set `WaitPolicy` of current lock, None for the process-wide one. `WaitPolicy::Spin` only
+    /// fits when every waiter has its own cpu, otherwise the waiter ahead may never be scheduled.
+    #[inline]
+    pub fn set_wait_policy(&mut self, policy: Option<util::WaitPolicy>) {
+        self.wait_policy = policy;
+    }
+
+    /// Take a ticket and wait until it's served, then return TicketLockGuard which derefs to the
+    /// data.
+    pub fn lock(&self) -> TicketLockGuard<T> {
+        let ticket = unsafe { util::sync_fetch_and_add(self.next_ticket.as_mut_ptr(), 1) };
+        // The waiter ahead may be descheduled, so yield after spinning for a while by default,
+        // otherwise all waiters behind would burn their time slices.
+        let mut backoff = self
+            .wait_policy
+            .map_or_else(util::Backoff::new, util::Backoff::with_policy);
+        while ticket != self.now_serving() {
+            backoff.snooze();
+        }
+        TicketLockGuard {
+            lock: self,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return TicketLockGuard if no one holds or waits for current lock.
+    pub fn try_lock(&self) -> Option<TicketLockGuard<T>> {
+        let serving = self.now_serving();
+        let ok = unsafe {
+            intrinsics::atomic_cxchg(self.next_ticket.as_mut_ptr(), serving, serving + 1).1
+        };
+        if ok {
+            Some(TicketLockGuard {
+                lock: self,
+                marker: PhantomData,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Return mutable reference of the data. No locking is needed because of `&mut self`.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    /// Return true if locked.
+    #[inline]
+    pub fn is_locked(&self) -> bool {
+        unsafe { intrinsics::atomic_load(self.next_ticket.as_ptr()) != self.now_serving() }
+    }
+
+    /// Return the number of threads holding or waiting for current lock.
+    #[inline]
+    pub fn queue_len(&self) -> u64 {
+        let serving = self.now_serving();
+        unsafe { intrinsics::atomic_load(self.next_ticket.as_ptr()).wrapping_sub(serving) }
+    }
+
+    #[inline]
+    fn now_serving(&self) -> u64 {
+        unsafe { intrinsics::atomic_load(self.now_serving.as_ptr()) }
+    }
+
+    /// Serve the next ticket. Only the holder writes `now_serving`, so no CAS is needed.
+    #[inline]
+    unsafe fn unlock(&self) {
+        let serving = self.now_serving();
+        intrinsics::atomic_store(self.now_serving.as_mut_ptr(), serving.wrapping_add(1));
+    }
+}
+
+/// Guard of TicketLock, which derefs to the protected data and unlocks it when dropped.
+pub struct TicketLockGuard<'a, T: ?Sized + 'a> {
+    lock: &'a TicketLock<T>,
+    /// Opt out of the auto traits, which would follow `TicketLock` instead of `T`.
+    marker: PhantomData<*const ()>,
+}
+
+unsafe impl<'a, T: ?Sized + Send> Send for TicketLockGuard<'a, T> {}
+
+unsafe impl<'a, T: ?Sized + Sync> Sync for TicketLockGuard<'a, T> {}
+
+impl<'a, T: ?Sized> Deref for TicketLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for TicketLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for TicketLockGuard<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock.unlock();
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_static() {
+        use ticket_lock::TicketLock;
+        static LOCK: TicketLock<u64> = TicketLock::new(0);
+        *LOCK.lock() += 1;
+        assert_eq!(*LOCK.lock(), 1);
+    }
+
+    #[test]
+    fn test_ticket_lock() {
+        use ticket_lock::TicketLock;
+        let mut lock = TicketLock::new(0);
+        assert!(!lock.is_locked());
+        {
+            let mut guard = lock.lock();
+            *guard += 1;
+            assert!(lock.is_locked());
+            assert_eq!(lock.queue_len(), 1);
+            assert!(lock.try_lock().is_none());
+        }
+        assert!(!lock.is_locked());
+        *lock.try_lock().unwrap() += 1;
+        assert_eq!(lock.queue_len(), 0);
+        *lock.get_mut() += 1;
+        assert_eq!(lock.into_inner(), 3);
+    }
+}
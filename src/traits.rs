@@ -0,0 +1,56 @@
+//! Traits implemented by queues and stacks of this crate
+//!
+//! Applications and benchmarks written against `ConcurrentQueue` or `ConcurrentStack` can swap
+//! implementations behind a generic parameter, like `LockFreeQueue` for an unbounded queue and
+//! `ArrayQueue` or `IndexQueue` for a bounded one. Methods take `&mut self` like `HazardEpoch`
+//! based containers, so types whose methods take `&self` implement them as well.
+//!
+//! `IntrusiveQueue` doesn't implement `ConcurrentQueue`, because only a single consumer may pop,
+//! and neither does `Channel`, whose ends are split into `Sender` and `Receiver`.
+//!
+//! # Examples
+//!
+//! ```
+//! use rs_lockfree::array_queue::ArrayQueue;
+//! use rs_lockfree::lockfree_queue::LockFreeQueue;
+//! use rs_lockfree::traits::ConcurrentQueue;
+//!
+//! fn round_trip<Q: ConcurrentQueue<i32>>(queue: &mut Q) -> Vec<i32> {
+//!     for i in 0..3 {
+//!         queue.try_push(i).unwrap();
+//!     }
+//!     let mut popped = vec![];
+//!     while let Some(v) = queue.try_pop().unwrap() {
+//!         popped.push(v);
+//!     }
+//!     popped
+//! }
+//!
+//! let mut unbounded = LockFreeQueue::default_new_in_heap();
+//! let mut bounded = ArrayQueue::<i32, 4>::new();
+//! assert_eq!(round_trip(&mut *unbounded), vec![0, 1, 2]);
+//! assert_eq!(round_trip(&mut bounded), vec![0, 1, 2]);
+//! ```
+//!
+use error::{PushError, QueueError};
+
+/// Queue popping elements in the order they are pushed, or in relaxed order for `MultiQueue`.
+pub trait ConcurrentQueue<T> {
+    /// Push an element to the tail. Return error with the element if it can't be pushed, like
+    /// `QueueError::Full` of bounded queues.
+    fn try_push(&mut self, v: T) -> Result<(), PushError<T>>;
+
+    /// Pop an element from the head, None if the queue is empty. Return error like
+    /// `QueueError::Hazard` if the queue can't be accessed.
+    fn try_pop(&mut self) -> Result<Option<T>, QueueError>;
+}
+
+/// Stack popping the element pushed last.
+pub trait ConcurrentStack<T> {
+    /// Push an element to the top. Return error with the element if it can't be pushed.
+    fn try_push(&mut self, v: T) -> Result<(), PushError<T>>;
+
+    /// Pop the element at the top, None if the stack is empty. Return error like
+    /// `QueueError::Hazard` if the stack can't be accessed.
+    fn try_pop(&mut self) -> Result<Option<T>, QueueError>;
+}
@@ -0,0 +1,169 @@
+//! Definition and implementations of `UnionFind`
+//!
+use util;
+use std::mem;
+use std::sync::atomic::Ordering;
+
+/// Parent of an element, packed with its rank, which are compared and swapped together.
+#[inline]
+fn pack(parent: u32, rank: u32) -> u64 {
+    u64::from(rank) << 32 | u64::from(parent)
+}
+
+#[inline]
+fn parent(word: u64) -> usize {
+    word as u32 as usize
+}
+
+#[inline]
+fn rank(word: u64) -> u32 {
+    (word >> 32) as u32
+}
+
+/// LockFree disjoint-set of elements `0..len`, for parallel graph algorithms like connected
+/// components and Kruskal's spanning trees.
+///
+/// Each element has one word holding its parent and rank. `find` halves paths by CAS on the
+/// words it passes, which only shortcuts a link, so it never changes the set of any element.
+/// `union` links the root ordered lower by (rank, index) under the other by CAS on the word of the
+/// linked root, which fails if it isn't a root with that rank anymore. Ranks only grow and a linked
+/// root never changes rank, so (rank, index) grows along every path and links never form a cycle.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::union_find::UnionFind;
+///
+/// let sets = UnionFind::new(4);
+/// assert!(sets.union(0, 1));
+/// assert!(sets.union(2, 3));
+/// assert!(!sets.union(1, 0));
+/// assert!(sets.same(0, 1));
+/// assert!(!sets.same(1, 2));
+/// assert!(sets.union(1, 3));
+/// assert_eq!(sets.find(0), sets.find(2));
+/// ```
+///
+pub struct UnionFind {
+    words: Vec<util::AtomicU64>,
+}
+
+impl UnionFind {
+    /// Return UnionFind of `len` elements, each in a set of its own. `len` must be less than
+    /// `u32::max_value()`.
+    pub fn new(len: usize) -> Self {
+        assert!(
+            len < u32::max_value() as usize,
+            "len must be less than {}",
+            u32::max_value()
+        );
+        UnionFind {
+            words: (0..len)
+                .map(|i| util::AtomicU64::new(pack(i as u32, 0)))
+                .collect(),
+        }
+    }
+
+    /// Return the number of elements.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Return true if there is no element.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Return the root of the set of `x`, which may be stale once returned if other threads are
+    /// merging. Panic if `x` is out of range.
+    pub fn find(&self, mut x: usize) -> usize {
+        loop {
+            let word = self.words[x].load(Ordering::SeqCst);
+            let p = parent(word);
+            if p == x {
+                return x;
+            }
+            let gp = parent(self.words[p].load(Ordering::SeqCst));
+            if gp != p {
+                // Path halving, it's fine to lose the race.
+                self.words[x].cas(word, pack(gp as u32, rank(word)), Ordering::SeqCst);
+            }
+            x = gp;
+        }
+    }
+
+    /// Merge the sets of `a` and `b`. Return false if they are already in the same set. Panic if
+    /// either is out of range.
+    pub fn union(&self, a: usize, b: usize) -> bool {
+        let mut backoff = util::Backoff::new();
+        loop {
+            let mut ra = self.find(a);
+            let mut rb = self.find(b);
+            if ra == rb {
+                return false;
+            }
+            let mut wa = self.words[ra].load(Ordering::SeqCst);
+            let mut wb = self.words[rb].load(Ordering::SeqCst);
+            if parent(wa) == ra && parent(wb) == rb {
+                // Link `ra` under `rb`.
+                if (rank(wa), ra) > (rank(wb), rb) {
+                    mem::swap(&mut ra, &mut rb);
+                    mem::swap(&mut wa, &mut wb);
+                }
+                let (_, ok) =
+                    self.words[ra].cas(wa, pack(rb as u32, rank(wa)), Ordering::SeqCst);
+                if ok {
+                    if rank(wa) == rank(wb) {
+                        // Fails if `rb` has changed, which is fine as ranks only guide balancing.
+                        self.words[rb].cas(wb, pack(rb as u32, rank(wb) + 1), Ordering::SeqCst);
+                    }
+                    return true;
+                }
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Return true if `a` and `b` are in the same set. Panic if either is out of range.
+    pub fn same(&self, a: usize, b: usize) -> bool {
+        loop {
+            let ra = self.find(a);
+            let rb = self.find(b);
+            if ra == rb {
+                return true;
+            }
+            // `ra` still being a root means they were in different sets when `rb` was found.
+            if parent(self.words[ra].load(Ordering::SeqCst)) == ra {
+                return false;
+            }
+        }
+    }
+}
+
+mod test {
+    #[test]
+    fn test_base() {
+        use union_find::UnionFind;
+        let sets = UnionFind::new(16);
+        assert_eq!(sets.len(), 16);
+        for i in 0..16 {
+            assert_eq!(sets.find(i), i);
+        }
+        // Merge odd and even elements separately.
+        for i in 2..16 {
+            assert!(sets.union(i - 2, i));
+        }
+        for i in 2..16 {
+            assert!(!sets.union(i, i - 2));
+            assert!(sets.same(i, i % 2));
+            assert!(!sets.same(i, 1 - i % 2));
+        }
+        assert!(sets.union(0, 15));
+        for i in 0..16 {
+            assert_eq!(sets.find(i), sets.find(0));
+        }
+        assert!(UnionFind::new(0).is_empty());
+    }
+}
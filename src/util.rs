@@ -1,8 +1,21 @@
 //! Utility of project
 extern crate time;
 
+#[cfg(feature = "debug-assert-invariants")]
+use error::InvariantError;
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
 use std::ops::{Deref, DerefMut};
+use std::ptr;
 use std::sync::atomic;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, MutexGuard, Once, PoisonError, ONCE_INIT};
+use std::thread;
+use std::time::Duration;
 
 /// Wrap struct into WrappedAlign64Type to make it 64bytes aligned.
 #[repr(align(64))]
@@ -60,15 +73,121 @@ impl<T> WrappedAlign64Type<T> {
 }
 
 /// Return current unix timestamp(microsecond).
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 pub fn get_cur_microseconds_time() -> i64 {
     (time::precise_time_ns() / 1_000) as i64
 }
 
-#[cfg(any(target_arch = "x86_64"))]
-mod atomic_x86 {
+/// Return a logical timestamp(microsecond), which increases by 1 for each call, because there is
+/// no clock without the help of JavaScript.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub fn get_cur_microseconds_time() -> i64 {
+    thread_local! {static NOW: Cell<i64> = Cell::new(0);};
+    NOW.with(|now| {
+        now.set(now.get() + 1);
+        now.get()
+    })
+}
+
+/// Move `v` into heap and return the raw pointer like `Box::into_raw(Box::new(v))`, but give `v`
+/// back instead of aborting if allocation fails. The pointer can be freed by `Box::from_raw`.
+pub fn try_box_into_raw<T>(v: T) -> Result<*mut T, T> {
+    let layout = Layout::new::<T>();
+    if 0 == layout.size() {
+        return Ok(Box::into_raw(Box::new(v)));
+    }
+    unsafe {
+        let ptr = alloc::alloc(layout) as *mut T;
+        if ptr.is_null() {
+            Err(v)
+        } else {
+            ptr::write(ptr, v);
+            Ok(ptr)
+        }
+    }
+}
+
+/// Walk list `list` from `head` by `next` until null, and return the number of nodes and the last
+/// one, or `InvariantError::Cyclic` if it links back to a walked node, found by Floyd's algorithm.
+#[cfg(feature = "debug-assert-invariants")]
+pub unsafe fn verify_list<N, F>(
+    head: *mut N,
+    next: F,
+    list: &'static str,
+) -> Result<(i64, *mut N), InvariantError>
+where
+    F: Fn(*mut N) -> *mut N,
+{
+    let mut len = 0;
+    let mut last = ptr::null_mut();
+    let mut cur = head;
+    let mut slow = head;
+    while !cur.is_null() {
+        last = cur;
+        cur = next(cur);
+        len += 1;
+        if 0 == len % 2 {
+            slow = next(slow);
+        }
+        if cur == slow && !cur.is_null() {
+            return Err(InvariantError::Cyclic { list });
+        }
+    }
+    Ok((len, last))
+}
+
+cfg_if! {
+    if #[cfg(feature = "tsan")] {
+        /// Read `*src` which may be written by `racy_write` concurrently, like data protected by a
+        /// sequence. The result may be torn, so it must be validated before use. Bytes are read by
+        /// acquire loads, which are understood by ThreadSanitizer.
+        pub unsafe fn racy_read<T>(src: *const T) -> T {
+            let mut ret = mem::MaybeUninit::<T>::uninit();
+            let dst = ret.as_mut_ptr() as *mut u8;
+            let src = src as *const atomic::AtomicU8;
+            for idx in 0..mem::size_of::<T>() {
+                *dst.add(idx) = (*src.add(idx)).load(ORDER_ACQUIRE);
+            }
+            ret.assume_init()
+        }
+
+        /// Write `v` to `*dst` which may be read by `racy_read` concurrently. The old value is not
+        /// dropped. Bytes are written by release stores, which are understood by ThreadSanitizer.
+        pub unsafe fn racy_write<T>(dst: *mut T, v: T) {
+            let src = &v as *const T as *const u8;
+            let dst = dst as *const atomic::AtomicU8;
+            for idx in 0..mem::size_of::<T>() {
+                (*dst.add(idx)).store(*src.add(idx), ORDER_RELEASE);
+            }
+            mem::forget(v);
+        }
+    } else {
+        /// Read `*src` which may be written by `racy_write` concurrently, like data protected by a
+        /// sequence. The result may be torn, so it must be validated before use. It's a volatile
+        /// read followed by an acquire fence.
+        #[inline]
+        pub unsafe fn racy_read<T>(src: *const T) -> T {
+            let ret = ptr::read_volatile(src);
+            atomic::fence(ORDER_ACQUIRE);
+            ret
+        }
+
+        /// Write `v` to `*dst` which may be read by `racy_read` concurrently. The old value is not
+        /// dropped. It's a volatile write followed by a release fence.
+        #[inline]
+        pub unsafe fn racy_write<T>(dst: *mut T, v: T) {
+            ptr::write_volatile(dst, v);
+            atomic::fence(ORDER_RELEASE);
+        }
+    }
+}
+
+/// Atomic helpers based on compiler intrinsics, which are lowered to `LOCK`-prefixed instructions
+/// on `x86_64`, and to exclusive or LSE instructions on `aarch64`.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+mod atomic_intrinsics {
     use std::ops::Add;
     use std::intrinsics;
-    use std::mem;
     use std::cell::Cell;
 
     /// Auto increase global thread id.
@@ -80,6 +199,7 @@ mod atomic_x86 {
         THREAD_ID.with(|tid| {
             if -1 == tid.get() {
                 tid.set(unsafe { sync_fetch_and_add(GLOBAL_THREAD_ID.get_mut(), 1) });
+                super::register_current_thread(tid.get());
             }
             tid.get()
         })
@@ -98,29 +218,1597 @@ mod atomic_x86 {
         intrinsics::atomic_xadd::<T>(dst, src)
     }
 
-    /// Atomic load raw pointer.
-    pub unsafe fn atomic_load_raw_ptr<T>(ptr: *const *mut T) -> *mut T {
-        intrinsics::atomic_load(ptr as *const usize) as *mut T
+    /// Like `sync_fetch_and_add`, but relaxed, for counters which order no other memory access.
+    #[inline]
+    pub unsafe fn sync_fetch_and_add_relaxed<T>(dst: *mut T, src: T) -> T {
+        if cfg!(feature = "seqcst-paranoid") {
+            intrinsics::atomic_xadd::<T>(dst, src)
+        } else {
+            intrinsics::atomic_xadd_relaxed::<T>(dst, src)
+        }
+    }
+
+    /// Relaxed atomic load, for counters and hints which order no other memory access.
+    #[inline]
+    pub unsafe fn atomic_load_relaxed<T>(src: *const T) -> T {
+        if cfg!(feature = "seqcst-paranoid") {
+            intrinsics::atomic_load(src)
+        } else {
+            intrinsics::atomic_load_relaxed(src)
+        }
+    }
+
+    /// Relaxed atomic store, for counters and hints which order no other memory access.
+    #[inline]
+    pub unsafe fn atomic_store_relaxed<T>(dst: *mut T, src: T) {
+        if cfg!(feature = "seqcst-paranoid") {
+            intrinsics::atomic_store(dst, src)
+        } else {
+            intrinsics::atomic_store_relaxed(dst, src)
+        }
+    }
+
+    /// Return true if `atomic_cxchg_u128` is supported by current CPU, which needs `cmpxchg16b` on
+    /// `x86_64`.
+    #[cfg(target_arch = "x86_64")]
+    pub fn has_cxchg_u128() -> bool {
+        is_x86_feature_detected!("cmpxchg16b")
+    }
+
+    /// Return true if `atomic_cxchg_u128` is supported by current CPU. Exclusive pair instructions,
+    /// or `CASP` of LSE, are always available on `aarch64`.
+    #[cfg(target_arch = "aarch64")]
+    pub fn has_cxchg_u128() -> bool {
+        true
+    }
+
+    /// Atomic CAS of 128 bits, for algorithms which update a pointer and a tag together. `dst` must
+    /// be 16 bytes aligned, and `has_cxchg_u128` must be checked in advance.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    pub unsafe fn atomic_cxchg_u128(dst: *mut u128, old: u128, src: u128) -> (u128, bool) {
+        #[inline]
+        #[target_feature(enable = "cmpxchg16b")]
+        unsafe fn cxchg(dst: *mut u128, old: u128, src: u128) -> (u128, bool) {
+            intrinsics::atomic_cxchg(dst, old, src)
+        }
+        debug_assert!(has_cxchg_u128(), "cmpxchg16b is not supported by current CPU");
+        debug_assert_eq!(0, dst as usize % 16, "atomic_cxchg_u128 on unaligned address");
+        cxchg(dst, old, src)
+    }
+
+    /// Atomic CAS of 128 bits, for algorithms which update a pointer and a tag together. `dst` must
+    /// be 16 bytes aligned.
+    #[cfg(target_arch = "aarch64")]
+    #[inline]
+    pub unsafe fn atomic_cxchg_u128(dst: *mut u128, old: u128, src: u128) -> (u128, bool) {
+        debug_assert_eq!(0, dst as usize % 16, "atomic_cxchg_u128 on unaligned address");
+        intrinsics::atomic_cxchg(dst, old, src)
+    }
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub use self::atomic_intrinsics::*;
+
+/// Portable atomic helpers based on `std::sync::atomic`, for targets other than `x86_64` and
+/// `aarch64`. Integers of 1, 2, 4 or 8 bytes are supported, whatever signedness they are.
+mod atomic_portable {
+    use std::cell::Cell;
+    use std::mem;
+    use std::ops::Add;
+    use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+    use super::ORDER_RELAXED;
+
+    /// Auto increase global thread id.
+    pub static GLOBAL_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
+
+    /// Return an unique ID for current thread.
+    pub fn get_thread_id() -> i64 {
+        thread_local! {static THREAD_ID: Cell<i64> = Cell::new(-1);};
+        THREAD_ID.with(|tid| {
+            if -1 == tid.get() {
+                tid.set(GLOBAL_THREAD_ID.fetch_add(1, Ordering::SeqCst) as i64);
+                super::register_current_thread(tid.get());
+            }
+            tid.get()
+        })
+    }
+
+    /// Like __sync_add_and_fetch in C.
+    pub unsafe fn sync_add_and_fetch<T>(dst: *mut T, src: T) -> T
+    where
+        T: Add<Output = T> + Copy,
+    {
+        sync_fetch_and_add(dst, src) + src
+    }
+
+    /// Like __sync_fetch_and_add in C. Addition wraps, so it's the same for signed integers.
+    /// `dst` must be aligned to its size, which plain `u64` fields are not on 32-bit targets, use
+    /// `util::AtomicU64` or `util::WrappedAlign64Type` for them.
+    pub unsafe fn sync_fetch_and_add<T>(dst: *mut T, src: T) -> T {
+        fetch_add(dst, src, Ordering::SeqCst)
+    }
+
+    /// Like `sync_fetch_and_add`, but relaxed, for counters which order no other memory access.
+    #[inline]
+    pub unsafe fn sync_fetch_and_add_relaxed<T>(dst: *mut T, src: T) -> T {
+        fetch_add(dst, src, ORDER_RELAXED)
+    }
+
+    /// Relaxed atomic load, for counters and hints which order no other memory access.
+    pub unsafe fn atomic_load_relaxed<T>(src: *const T) -> T {
+        macro_rules! load {
+            ($atomic:ty, $int:ty) => {{
+                let old = (*(src as *const $atomic)).load(ORDER_RELAXED);
+                mem::transmute_copy::<$int, T>(&old)
+            }};
+        }
+        match mem::size_of::<T>() {
+            1 => load!(AtomicU8, u8),
+            2 => load!(AtomicU16, u16),
+            4 => load!(AtomicU32, u32),
+            8 => load!(AtomicU64, u64),
+            n => panic!("atomic_load_relaxed doesn't support {} bytes", n),
+        }
+    }
+
+    /// Relaxed atomic store, for counters and hints which order no other memory access.
+    pub unsafe fn atomic_store_relaxed<T>(dst: *mut T, src: T) {
+        macro_rules! store {
+            ($atomic:ty, $int:ty) => {{
+                let v = mem::transmute_copy::<T, $int>(&src);
+                (*(dst as *const $atomic)).store(v, ORDER_RELAXED)
+            }};
+        }
+        match mem::size_of::<T>() {
+            1 => store!(AtomicU8, u8),
+            2 => store!(AtomicU16, u16),
+            4 => store!(AtomicU32, u32),
+            8 => store!(AtomicU64, u64),
+            n => panic!("atomic_store_relaxed doesn't support {} bytes", n),
+        }
+        mem::forget(src);
+    }
+
+    unsafe fn fetch_add<T>(dst: *mut T, src: T, order: Ordering) -> T {
+        macro_rules! fetch_add {
+            ($atomic:ty, $int:ty) => {{
+                debug_assert_eq!(
+                    0,
+                    dst as usize % mem::align_of::<$atomic>(),
+                    "sync_fetch_and_add on unaligned address"
+                );
+                let atomic = &*(dst as *const $atomic);
+                let old = atomic.fetch_add(mem::transmute_copy::<T, $int>(&src), order);
+                mem::transmute_copy::<$int, T>(&old)
+            }};
+        }
+        match mem::size_of::<T>() {
+            1 => fetch_add!(AtomicU8, u8),
+            2 => fetch_add!(AtomicU16, u16),
+            4 => fetch_add!(AtomicU32, u32),
+            8 => fetch_add!(AtomicU64, u64),
+            n => panic!("sync_fetch_and_add doesn't support {} bytes", n),
+        }
+    }
+
+    /// Return true if `atomic_cxchg_u128` is supported by current CPU.
+    pub fn has_cxchg_u128() -> bool {
+        false
+    }
+
+    /// Atomic CAS of 128 bits. Always panic because `has_cxchg_u128` is false on current target.
+    pub unsafe fn atomic_cxchg_u128(_dst: *mut u128, _old: u128, _src: u128) -> (u128, bool) {
+        panic!("128-bit CAS is not supported by current target")
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub use self::atomic_portable::*;
+
+// Raw pointers are accessed through `AtomicPtr` instead of casting to `usize`, so that their
+// provenance is kept, which is required by Miri.
+
+/// Atomic load raw pointer.
+pub unsafe fn atomic_load_raw_ptr<T>(ptr: *const *mut T) -> *mut T {
+    (*(ptr as *const AtomicPtr<T>)).load(Ordering::SeqCst)
+}
+
+/// Atomic store raw pointer.
+pub unsafe fn atomic_store_raw_ptr<T>(ptr: *mut *mut T, src: *mut T) {
+    (*(ptr as *const AtomicPtr<T>)).store(src, Ordering::SeqCst)
+}
+
+/// Atomic CAS raw pointer.
+pub unsafe fn atomic_cxchg_raw_ptr<T>(
+    ptr: *mut *mut T,
+    old: *mut T,
+    src: *mut T,
+) -> (*mut T, bool) {
+    match (*(ptr as *const AtomicPtr<T>)).compare_exchange(
+        old,
+        src,
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+    ) {
+        Ok(prev) => (prev, true),
+        Err(prev) => (prev, false),
+    }
+}
+
+// Orderings weaker than `SeqCst`, used on hot paths where the comment at each use shows that no
+// store and later load of different locations need to be ordered, which only `SeqCst` does. With
+// feature `seqcst-paranoid` they are all `SeqCst`, like orderings given to atomic wrappers here.
+
+/// Return `order`, or `SeqCst` with feature `seqcst-paranoid`, so that a suspected bug of memory
+/// ordering can be ruled in or out by rebuilding without patching.
+#[inline]
+pub const fn effective_order(order: Ordering) -> Ordering {
+    if cfg!(feature = "seqcst-paranoid") {
+        Ordering::SeqCst
+    } else {
+        order
+    }
+}
+
+/// Ordering of loads which must see all writes before the store they read from, like a pointer
+/// loaded before dereferencing it.
+pub const ORDER_ACQUIRE: Ordering = effective_order(Ordering::Acquire);
+/// Ordering of stores publishing all writes before them to `ORDER_ACQUIRE` loads.
+pub const ORDER_RELEASE: Ordering = effective_order(Ordering::Release);
+/// Ordering of read-modify-writes which both take over and publish data, like claiming a state.
+pub const ORDER_ACQ_REL: Ordering = effective_order(Ordering::AcqRel);
+/// Ordering of counters and hints which order no other memory access.
+pub const ORDER_RELAXED: Ordering = effective_order(Ordering::Relaxed);
+
+/// Return the strongest ordering for a failed CAS which is allowed with `order` on success.
+#[inline]
+fn cas_failure_ordering(order: Ordering) -> Ordering {
+    match order {
+        Ordering::Release | Ordering::Relaxed => Ordering::Relaxed,
+        Ordering::AcqRel | Ordering::Acquire => Ordering::Acquire,
+        _ => Ordering::SeqCst,
+    }
+}
+
+/// Raw pointer which is accessed atomically, replacing `atomic_load_raw_ptr`,
+/// `atomic_store_raw_ptr` and `atomic_cxchg_raw_ptr` on `*mut *mut T`.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::util::AtomicRawPtr;
+/// use std::ptr;
+/// use std::sync::atomic::Ordering;
+///
+/// let mut v = 1;
+/// let p = AtomicRawPtr::default();
+/// assert_eq!(p.cas(ptr::null_mut(), &mut v, Ordering::SeqCst), (ptr::null_mut(), true));
+/// assert_eq!(unsafe { *p.load(Ordering::SeqCst) }, 1);
+/// ```
+///
+pub struct AtomicRawPtr<T> {
+    ptr: AtomicPtr<T>,
+}
+
+impl<T> Default for AtomicRawPtr<T> {
+    fn default() -> Self {
+        AtomicRawPtr::new(ptr::null_mut())
+    }
+}
+
+impl<T> AtomicRawPtr<T> {
+    /// Return AtomicRawPtr holding `ptr`.
+    #[inline]
+    pub const fn new(ptr: *mut T) -> Self {
+        AtomicRawPtr {
+            ptr: AtomicPtr::new(ptr),
+        }
+    }
+
+    /// Load the pointer.
+    #[inline]
+    pub fn load(&self, order: Ordering) -> *mut T {
+        self.ptr.load(effective_order(order))
+    }
+
+    /// Store `ptr`.
+    #[inline]
+    pub fn store(&self, ptr: *mut T, order: Ordering) {
+        self.ptr.store(ptr, effective_order(order))
+    }
+
+    /// Store `ptr` and return the previous one.
+    #[inline]
+    pub fn swap(&self, ptr: *mut T, order: Ordering) -> *mut T {
+        self.ptr.swap(ptr, effective_order(order))
+    }
+
+    /// Store `src` if current pointer is `old`. Return the previous pointer and true if stored,
+    /// like `atomic_cxchg_raw_ptr`.
+    #[inline]
+    pub fn cas(&self, old: *mut T, src: *mut T, order: Ordering) -> (*mut T, bool) {
+        let order = effective_order(order);
+        match self
+            .ptr
+            .compare_exchange(old, src, order, cas_failure_ordering(order))
+        {
+            Ok(prev) => (prev, true),
+            Err(prev) => (prev, false),
+        }
+    }
+
+    /// Return mutable reference of the pointer. No atomic operation is needed because of
+    /// `&mut self`.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut *mut T {
+        self.ptr.get_mut()
+    }
+}
+
+macro_rules! atomic_int_wrapper {
+    ($(#[$attr:meta])* $name:ident, $atomic:ident, $int:ty) => {
+        $(#[$attr])*
+        #[derive(Default)]
+        pub struct $name {
+            v: atomic::$atomic,
+        }
+
+        impl $name {
+            /// Return atomic integer holding `v`.
+            #[inline]
+            pub const fn new(v: $int) -> Self {
+                $name {
+                    v: atomic::$atomic::new(v),
+                }
+            }
+
+            /// Load the value.
+            #[inline]
+            pub fn load(&self, order: Ordering) -> $int {
+                self.v.load(effective_order(order))
+            }
+
+            /// Store `v`.
+            #[inline]
+            pub fn store(&self, v: $int, order: Ordering) {
+                self.v.store(v, effective_order(order))
+            }
+
+            /// Store `src` if current value is `old`. Return the previous value and true if
+            /// stored.
+            #[inline]
+            pub fn cas(&self, old: $int, src: $int, order: Ordering) -> ($int, bool) {
+                let order = effective_order(order);
+                match self
+                    .v
+                    .compare_exchange(old, src, order, cas_failure_ordering(order))
+                {
+                    Ok(prev) => (prev, true),
+                    Err(prev) => (prev, false),
+                }
+            }
+
+            /// Add `v` with wrapping and return the previous value, like `sync_fetch_and_add`.
+            #[inline]
+            pub fn fetch_add(&self, v: $int, order: Ordering) -> $int {
+                self.v.fetch_add(v, effective_order(order))
+            }
+
+            /// Add `v` with wrapping and return the new value, like `sync_add_and_fetch`.
+            #[inline]
+            pub fn add_and_fetch(&self, v: $int, order: Ordering) -> $int {
+                self.v.fetch_add(v, effective_order(order)).wrapping_add(v)
+            }
+
+            /// Return mutable reference of the value. No atomic operation is needed because of
+            /// `&mut self`.
+            #[inline]
+            pub fn get_mut(&mut self) -> &mut $int {
+                self.v.get_mut()
+            }
+        }
+    };
+}
+
+atomic_int_wrapper!(
+    /// `i64` which is accessed atomically, replacing `sync_fetch_and_add` and intrinsics on
+    /// `*mut i64`.
+    AtomicI64,
+    AtomicI64,
+    i64
+);
+
+atomic_int_wrapper!(
+    /// `u64` which is accessed atomically, replacing `sync_fetch_and_add` and intrinsics on
+    /// `*mut u64`.
+    AtomicU64,
+    AtomicU64,
+    u64
+);
+
+/// Number of unused high bits of user space pointers, which have 48 significant bits on `x86_64`
+/// and `aarch64`.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const TAGGED_PTR_HIGH_BITS: u32 = 16;
+
+/// Number of unused high bits of user space pointers.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const TAGGED_PTR_HIGH_BITS: u32 = 0;
+
+/// Pointer of `T` with a small tag packed into its unused bits, which are the low bits guaranteed
+/// zero by the alignment of `T`, and the high bits beyond the address space. Pointer and tag fit in
+/// one `usize`, so they can be compared and swapped together by `AtomicTaggedPtr`, which makes the
+/// tag usable as a version against the `ABA problem` or as state flags of a node.
+///
+/// Tag is truncated to `tag_bits()` bits, so a version tag wraps around.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::util::TaggedPtr;
+///
+/// let mut v = 1u64;
+/// let p = TaggedPtr::new(&mut v, 5);
+/// assert_eq!(p.ptr(), &mut v as *mut u64);
+/// assert_eq!(p.tag(), 5);
+/// assert_eq!(p.with_tag(p.tag() + 1).tag(), 6);
+/// assert!(TaggedPtr::<u64>::null().ptr().is_null());
+/// ```
+///
+pub struct TaggedPtr<T> {
+    // Tag is added to the pointer by `wrapping_add` instead of packing an integer, so that it keeps
+    // the provenance of the pointer.
+    data: *mut u8,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for TaggedPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TaggedPtr<T> {}
+
+impl<T> PartialEq for TaggedPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+
+impl<T> Eq for TaggedPtr<T> {}
+
+impl<T> Default for TaggedPtr<T> {
+    fn default() -> Self {
+        TaggedPtr::null()
+    }
+}
+
+impl<T> fmt::Debug for TaggedPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaggedPtr")
+            .field("ptr", &self.ptr())
+            .field("tag", &self.tag())
+            .finish()
+    }
+}
+
+impl<T> TaggedPtr<T> {
+    /// Number of low bits which are zero because of the alignment of `T`.
+    #[inline]
+    fn low_bits() -> u32 {
+        mem::align_of::<T>().trailing_zeros()
+    }
+
+    #[inline]
+    fn low_mask() -> usize {
+        (1 << Self::low_bits()) - 1
+    }
+
+    #[inline]
+    fn high_mask() -> usize {
+        !(!0 >> TAGGED_PTR_HIGH_BITS)
+    }
+
+    /// Return number of bits available for tag.
+    #[inline]
+    pub fn tag_bits() -> u32 {
+        Self::low_bits() + TAGGED_PTR_HIGH_BITS
+    }
+
+    /// Return the maximum tag.
+    #[inline]
+    pub fn max_tag() -> usize {
+        if 0 == Self::tag_bits() {
+            0
+        } else {
+            !0 >> (mem::size_of::<usize>() as u32 * 8 - Self::tag_bits())
+        }
+    }
+
+    /// Return TaggedPtr of `ptr` and `tag`. `ptr` must be aligned for `T`, and tag is truncated.
+    #[inline]
+    pub fn new(ptr: *mut T, tag: usize) -> Self {
+        assert_eq!(
+            0,
+            ptr as usize & (Self::low_mask() | Self::high_mask()),
+            "unexpected pointer bits"
+        );
+        let tag = tag & Self::max_tag();
+        let low = tag & Self::low_mask();
+        // `checked_shl` handles targets without free high bits, where `tag >> low_bits` is zero.
+        let high = (tag >> Self::low_bits())
+            .checked_shl(mem::size_of::<usize>() as u32 * 8 - TAGGED_PTR_HIGH_BITS)
+            .unwrap_or(0);
+        TaggedPtr {
+            data: (ptr as *mut u8).wrapping_add(low | high),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Return null pointer with zero tag.
+    #[inline]
+    pub fn null() -> Self {
+        TaggedPtr {
+            data: ptr::null_mut(),
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn from_data(data: *mut u8) -> Self {
+        TaggedPtr {
+            data,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Return the pointer.
+    #[inline]
+    pub fn ptr(&self) -> *mut T {
+        let tag_bits = self.data as usize & (Self::low_mask() | Self::high_mask());
+        self.data.wrapping_sub(tag_bits) as *mut T
+    }
+
+    /// Return the tag.
+    #[inline]
+    pub fn tag(&self) -> usize {
+        let data = self.data as usize;
+        let low = data & Self::low_mask();
+        let high = (data & Self::high_mask())
+            .checked_shr(mem::size_of::<usize>() as u32 * 8 - TAGGED_PTR_HIGH_BITS)
+            .unwrap_or(0);
+        low | high << Self::low_bits()
+    }
+
+    /// Return TaggedPtr of current pointer and `tag`.
+    #[inline]
+    pub fn with_tag(&self, tag: usize) -> Self {
+        TaggedPtr::new(self.ptr(), tag)
+    }
+
+    /// Return TaggedPtr of `ptr` and current tag.
+    #[inline]
+    pub fn with_ptr(&self, ptr: *mut T) -> Self {
+        TaggedPtr::new(ptr, self.tag())
+    }
+}
+
+/// `TaggedPtr` which is accessed atomically.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::util::{AtomicTaggedPtr, TaggedPtr};
+/// use std::sync::atomic::Ordering;
+///
+/// let mut v = 1u64;
+/// let p = AtomicTaggedPtr::default();
+/// let old = p.load(Ordering::SeqCst);
+/// let new = TaggedPtr::new(&mut v, old.tag() + 1);
+/// assert_eq!(p.cas(old, new, Ordering::SeqCst), (old, true));
+/// assert_eq!(p.cas(old, new, Ordering::SeqCst), (new, false));
+/// ```
+///
+pub struct AtomicTaggedPtr<T> {
+    data: AtomicPtr<u8>,
+    _marker: PhantomData<*mut T>,
+}
+
+unsafe impl<T> Send for AtomicTaggedPtr<T> {}
+
+unsafe impl<T> Sync for AtomicTaggedPtr<T> {}
+
+impl<T> Default for AtomicTaggedPtr<T> {
+    fn default() -> Self {
+        AtomicTaggedPtr::new(TaggedPtr::null())
+    }
+}
+
+impl<T> AtomicTaggedPtr<T> {
+    /// Return AtomicTaggedPtr holding `ptr`.
+    #[inline]
+    pub fn new(ptr: TaggedPtr<T>) -> Self {
+        AtomicTaggedPtr {
+            data: AtomicPtr::new(ptr.data),
+            _marker: PhantomData,
+        }
     }
 
-    /// Atomic CAS raw pointer.
-    pub unsafe fn atomic_cxchg_raw_ptr<T>(
-        ptr: *mut *mut T,
-        old: *mut T,
-        src: *mut T,
-    ) -> (*mut T, bool) {
-        mem::transmute(intrinsics::atomic_cxchg(
-            ptr as *mut usize,
-            old as usize,
-            src as usize,
-        ))
+    /// Load the tagged pointer.
+    #[inline]
+    pub fn load(&self, order: Ordering) -> TaggedPtr<T> {
+        TaggedPtr::from_data(self.data.load(order))
     }
+
+    /// Store `ptr`.
+    #[inline]
+    pub fn store(&self, ptr: TaggedPtr<T>, order: Ordering) {
+        self.data.store(ptr.data, order)
+    }
+
+    /// Store `src` if current tagged pointer is `old`, so both pointer and tag must match. Return
+    /// the previous one and true if stored.
+    #[inline]
+    pub fn cas(
+        &self,
+        old: TaggedPtr<T>,
+        src: TaggedPtr<T>,
+        order: Ordering,
+    ) -> (TaggedPtr<T>, bool) {
+        match self
+            .data
+            .compare_exchange(old.data, src.data, order, cas_failure_ordering(order))
+        {
+            Ok(prev) => (TaggedPtr::from_data(prev), true),
+            Err(prev) => (TaggedPtr::from_data(prev), false),
+        }
+    }
+}
+
+/// Information of a thread registered by `get_thread_id`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThreadInfo {
+    /// ID returned by `get_thread_id`
+    pub tid: i64,
+    /// Name of the `std::thread::Thread`
+    pub name: Option<String>,
+    /// Label attached by user
+    pub label: Option<String>,
 }
 
-pub use self::atomic_x86::*;
+/// Registry of threads which have got their IDs by `get_thread_id` and haven't exited, so that
+/// applications can map internal thread IDs, such as those of `HazardEpoch`, to their own names.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::util;
+///
+/// let tid = util::get_thread_id();
+/// assert!(util::thread_registry().set_label(tid, "main-worker"));
+/// let info = util::thread_registry().get(tid).unwrap();
+/// assert_eq!(info.label, Some("main-worker".to_string()));
+/// assert!(util::thread_registry().threads().iter().any(|t| t.tid == tid));
+/// ```
+///
+pub struct ThreadRegistry {
+    threads: Mutex<BTreeMap<i64, ThreadInfo>>,
+}
+
+impl ThreadRegistry {
+    /// Return information of all registered threads, ordered by ID.
+    pub fn threads(&self) -> Vec<ThreadInfo> {
+        self.lock().values().cloned().collect()
+    }
 
-/// Yield current thread.
+    /// Return information of thread `tid`.
+    pub fn get(&self, tid: i64) -> Option<ThreadInfo> {
+        self.lock().get(&tid).cloned()
+    }
+
+    /// Attach `label` to thread `tid`. Return false if it's not registered.
+    pub fn set_label(&self, tid: i64, label: &str) -> bool {
+        match self.lock().get_mut(&tid) {
+            Some(info) => {
+                info.label = Some(label.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Attach `label` to current thread, register it if not yet. Return ID of current thread.
+    pub fn set_current_label(&self, label: &str) -> i64 {
+        let tid = get_thread_id();
+        self.set_label(tid, label);
+        tid
+    }
+
+    #[inline]
+    fn lock(&self) -> MutexGuard<BTreeMap<i64, ThreadInfo>> {
+        self.threads.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+static THREAD_REGISTRY_INIT: Once = ONCE_INIT;
+static mut THREAD_REGISTRY: *const ThreadRegistry = 0 as *const ThreadRegistry;
+
+/// Return the global thread registry.
+pub fn thread_registry() -> &'static ThreadRegistry {
+    unsafe {
+        THREAD_REGISTRY_INIT.call_once(|| {
+            THREAD_REGISTRY = Box::into_raw(Box::new(ThreadRegistry {
+                threads: Mutex::new(BTreeMap::new()),
+            }));
+        });
+        &*THREAD_REGISTRY
+    }
+}
+
+/// Remove current thread from the registry when it exits.
+struct ThreadRegistration(i64);
+
+impl Drop for ThreadRegistration {
+    fn drop(&mut self) {
+        thread_registry().lock().remove(&self.0);
+    }
+}
+
+fn register_current_thread(tid: i64) {
+    thread_local! {static REGISTRATION: Cell<Option<ThreadRegistration>> = Cell::new(None);};
+    let info = ThreadInfo {
+        tid,
+        name: thread::current().name().map(|name| name.to_string()),
+        label: None,
+    };
+    thread_registry().lock().insert(tid, info);
+    // Thread local storage may be being destroyed, then current thread is never removed.
+    let _ = REGISTRATION.try_with(|r| r.set(Some(ThreadRegistration(tid))));
+}
+
+/// Hint the CPU that current thread is spinning, which is `PAUSE` on `x86_64` and `ISB` on
+/// `aarch64`. `ISB` stalls for longer than `YIELD`, which most ARM cores treat as a nop.
 #[inline]
 pub fn pause() {
     atomic::spin_loop_hint();
 }
+
+/// How `Backoff::snooze` waits for another thread after spinning for a while. The right choice
+/// depends on deployment: dedicated cores prefer spinning, while oversubscribed virtual machines
+/// prefer giving up the cpu.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WaitPolicy {
+    /// Keep spinning with `pause`, never give up the cpu.
+    Spin,
+    /// Spin for a while, then `thread::yield_now`. It's the default policy.
+    SpinYield,
+    /// Always `thread::yield_now` instead of spinning.
+    Yield,
+    /// Spin for a while, then sleep for the duration.
+    Sleep(Duration),
+}
+
+impl Default for WaitPolicy {
+    fn default() -> Self {
+        WaitPolicy::SpinYield
+    }
+}
+
+impl WaitPolicy {
+    /// Encode into `u64`, `Sleep` takes codes from 3 and longer durations are saturated.
+    fn encode(&self) -> u64 {
+        match *self {
+            WaitPolicy::SpinYield => 0,
+            WaitPolicy::Spin => 1,
+            WaitPolicy::Yield => 2,
+            WaitPolicy::Sleep(d) => d.as_secs()
+                .saturating_mul(1_000_000_000)
+                .saturating_add(u64::from(d.subsec_nanos()))
+                .saturating_add(3),
+        }
+    }
+
+    fn decode(code: u64) -> WaitPolicy {
+        match code {
+            0 => WaitPolicy::SpinYield,
+            1 => WaitPolicy::Spin,
+            2 => WaitPolicy::Yield,
+            ns => WaitPolicy::Sleep(Duration::from_nanos(ns - 3)),
+        }
+    }
+}
+
+/// Process-wide `WaitPolicy` encoded by `WaitPolicy::encode`.
+static WAIT_POLICY: atomic::AtomicU64 = atomic::AtomicU64::new(0);
+
+/// Set the process-wide `WaitPolicy` used by `Backoff::new`. Structures with their own policy are
+/// not affected.
+pub fn set_wait_policy(policy: WaitPolicy) {
+    WAIT_POLICY.store(policy.encode(), ORDER_RELAXED);
+}
+
+/// Return the process-wide `WaitPolicy`.
+#[inline]
+pub fn wait_policy() -> WaitPolicy {
+    WaitPolicy::decode(WAIT_POLICY.load(ORDER_RELAXED))
+}
+
+/// Exponent limit of spinning in `Backoff`, after which `snooze` waits by `WaitPolicy` instead.
+const BACKOFF_SPIN_LIMIT: u32 = 6;
+
+/// Exponent limit of yielding in `Backoff`, after which it's completed.
+const BACKOFF_YIELD_LIMIT: u32 = 10;
+
+/// Exponential backoff with jitter for spinning loops. Each step spins a random number of `pause`
+/// in `[2^(step-1), 2^step]`, so that contending threads don't retry in lockstep.
+///
+/// * `spin` is for retrying a failed CAS, the other thread has made progress, so it never yields.
+/// * `snooze` is for waiting for another thread, it escalates to `thread::yield_now` after
+/// spinning for a while by default, so that hyper-threads and descheduled lock holders are not
+/// starved. It follows `WaitPolicy`, which is process-wide unless given by `with_policy`.
+/// * `is_completed` tells the caller that it's better to block in other ways.
+///
+/// # Examples
+///
+/// ```
+/// use rs_lockfree::util::Backoff;
+///
+/// let mut backoff = Backoff::new();
+/// while !backoff.is_completed() {
+///     backoff.snooze();
+/// }
+/// backoff.reset();
+/// assert!(!backoff.is_completed());
+/// ```
+///
+pub struct Backoff {
+    step: u32,
+    seed: u32,
+    policy: WaitPolicy,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new()
+    }
+}
+
+impl Backoff {
+    /// Return Backoff at its first step, with the process-wide `WaitPolicy`.
+    #[inline]
+    pub fn new() -> Self {
+        Backoff::with_policy(wait_policy())
+    }
+
+    /// Return Backoff at its first step, with `policy`.
+    #[inline]
+    pub fn with_policy(policy: WaitPolicy) -> Self {
+        Backoff {
+            step: 0,
+            seed: 0,
+            policy,
+        }
+    }
+
+    /// Restart from the first step.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+
+    /// Return true if `snooze` has yielded enough times, and the caller should block instead.
+    #[inline]
+    pub fn is_completed(&self) -> bool {
+        self.step > BACKOFF_YIELD_LIMIT
+    }
+
+    /// Xorshift, seeded lazily by thread id and time so that threads don't spin in lockstep.
+    #[inline]
+    fn next_random(&mut self) -> u32 {
+        if 0 == self.seed {
+            let seed = (get_thread_id() as u32).wrapping_mul(0x9E37_79B9)
+                ^ get_cur_microseconds_time() as u32;
+            self.seed = seed | 1;
+        }
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+
+    #[inline]
+    fn spin_jittered(&mut self, step: u32) {
+        let max = 1_u32 << step;
+        let spins = max - (self.next_random() & (max >> 1));
+        for _ in 0..spins {
+            pause();
+        }
+    }
+
+    /// Back off once after a failed CAS, spin longer than last time until the limit.
+    #[inline]
+    pub fn spin(&mut self) {
+        let step = self.step.min(BACKOFF_SPIN_LIMIT);
+        self.spin_jittered(step);
+        if self.step <= BACKOFF_SPIN_LIMIT {
+            self.step += 1;
+        }
+    }
+
+    /// Back off once while waiting for another thread, spin longer than last time, and wait by
+    /// `WaitPolicy` after spinning for a while.
+    pub fn snooze(&mut self) {
+        match self.policy {
+            WaitPolicy::Yield => thread::yield_now(),
+            _ if self.step <= BACKOFF_SPIN_LIMIT => {
+                let step = self.step;
+                self.spin_jittered(step);
+            }
+            WaitPolicy::Spin => self.spin_jittered(BACKOFF_SPIN_LIMIT),
+            WaitPolicy::SpinYield => thread::yield_now(),
+            WaitPolicy::Sleep(d) => thread::sleep(d),
+        }
+        if self.step <= BACKOFF_YIELD_LIMIT {
+            self.step += 1;
+        }
+    }
+}
+
+/// Cycle counter and latency histogram for instrumentation, enabled by feature `bench`.
+#[cfg(feature = "bench")]
+mod bench {
+    use super::get_cur_microseconds_time;
+    use std::fmt;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Once, ONCE_INIT};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Return current value of the cycle counter, which is `RDTSC` on `x86_64` and `CNTVCT_EL0` on
+    /// `aarch64`, and nanoseconds on other targets. It's not serializing, so it may be reordered
+    /// with nearby instructions, which doesn't matter for operations of hundreds of cycles.
+    #[cfg(target_arch = "x86_64")]
+    #[inline]
+    pub fn cycles() -> u64 {
+        unsafe { ::std::arch::x86_64::_rdtsc() }
+    }
+
+    /// Return current value of the cycle counter, which is `RDTSC` on `x86_64` and `CNTVCT_EL0` on
+    /// `aarch64`, and nanoseconds on other targets.
+    #[cfg(target_arch = "aarch64")]
+    #[inline]
+    pub fn cycles() -> u64 {
+        let v: u64;
+        unsafe {
+            ::std::arch::asm!("mrs {}, cntvct_el0", out(reg) v, options(nomem, nostack));
+        }
+        v
+    }
+
+    /// Return current value of the cycle counter, which is `RDTSC` on `x86_64` and `CNTVCT_EL0` on
+    /// `aarch64`, and nanoseconds on other targets.
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    #[inline]
+    pub fn cycles() -> u64 {
+        super::time::precise_time_ns()
+    }
+
+    static CYCLES_PER_NS_INIT: Once = ONCE_INIT;
+    static mut CYCLES_PER_NS: f64 = 1.0;
+
+    /// Return the number of `cycles` per nanosecond, which is calibrated against the wall clock
+    /// for 10ms at the first call. Constant rate counters are assumed, which is true for modern
+    /// `x86_64` and all `aarch64` cpus.
+    pub fn cycles_per_ns() -> f64 {
+        unsafe {
+            CYCLES_PER_NS_INIT.call_once(|| {
+                if cfg!(any(target_arch = "x86_64", target_arch = "aarch64")) {
+                    let (t0, c0) = (get_cur_microseconds_time(), cycles());
+                    thread::sleep(Duration::from_millis(10));
+                    let (t1, c1) = (get_cur_microseconds_time(), cycles());
+                    if t1 > t0 && c1 > c0 {
+                        CYCLES_PER_NS = (c1 - c0) as f64 / ((t1 - t0) * 1000) as f64;
+                    }
+                }
+            });
+            CYCLES_PER_NS
+        }
+    }
+
+    /// Convert `cycles` to nanoseconds.
+    #[inline]
+    pub fn cycles_to_ns(cycles: u64) -> u64 {
+        (cycles as f64 / cycles_per_ns()) as u64
+    }
+
+    /// Bits of precision in each power of 2, so the relative error of a bucket is within 1/8.
+    const HISTOGRAM_SUB_BITS: u32 = 3;
+    const HISTOGRAM_SUB_COUNT: usize = 1 << HISTOGRAM_SUB_BITS;
+    const HISTOGRAM_BUCKET_COUNT: usize =
+        (64 - HISTOGRAM_SUB_BITS as usize) * HISTOGRAM_SUB_COUNT + HISTOGRAM_SUB_COUNT;
+
+    /// Histogram of `u64` values like latencies, with log-linear buckets whose relative error is
+    /// within 1/8. Recording is wait-free, so it can be shared by all threads under measurement.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rs_lockfree::util::{cycles, cycles_to_ns, Histogram};
+    ///
+    /// let hist = Histogram::new();
+    /// for _ in 0..100 {
+    ///     let start = cycles();
+    ///     hist.record(cycles_to_ns(cycles() - start));
+    /// }
+    /// assert_eq!(hist.count(), 100);
+    /// assert!(hist.percentile(50.0) <= hist.percentile(99.0));
+    /// ```
+    ///
+    pub struct Histogram {
+        buckets: Vec<AtomicU64>,
+        count: AtomicU64,
+        sum: AtomicU64,
+        min: AtomicU64,
+        max: AtomicU64,
+    }
+
+    impl Default for Histogram {
+        fn default() -> Self {
+            Histogram::new()
+        }
+    }
+
+    impl Histogram {
+        /// Return empty Histogram.
+        pub fn new() -> Self {
+            let mut buckets = Vec::with_capacity(HISTOGRAM_BUCKET_COUNT);
+            for _ in 0..HISTOGRAM_BUCKET_COUNT {
+                buckets.push(AtomicU64::new(0));
+            }
+            Histogram {
+                buckets,
+                count: AtomicU64::new(0),
+                sum: AtomicU64::new(0),
+                min: AtomicU64::new(u64::max_value()),
+                max: AtomicU64::new(0),
+            }
+        }
+
+        #[inline]
+        pub(super) fn bucket_index(v: u64) -> usize {
+            if v < 2 * HISTOGRAM_SUB_COUNT as u64 {
+                v as usize
+            } else {
+                let shift = 63 - v.leading_zeros() - HISTOGRAM_SUB_BITS;
+                shift as usize * HISTOGRAM_SUB_COUNT + (v >> shift) as usize
+            }
+        }
+
+        /// Return the maximum value of bucket `idx`.
+        #[inline]
+        pub(super) fn bucket_upper_bound(idx: usize) -> u64 {
+            if idx < 2 * HISTOGRAM_SUB_COUNT {
+                idx as u64
+            } else {
+                let shift = idx / HISTOGRAM_SUB_COUNT - 1;
+                let m = (idx % HISTOGRAM_SUB_COUNT + HISTOGRAM_SUB_COUNT) as u64;
+                (m << shift) | ((1 << shift) - 1)
+            }
+        }
+
+        /// Record one value.
+        #[inline]
+        pub fn record(&self, v: u64) {
+            self.buckets[Self::bucket_index(v)].fetch_add(1, Ordering::Relaxed);
+            self.count.fetch_add(1, Ordering::Relaxed);
+            self.sum.fetch_add(v, Ordering::Relaxed);
+            self.min.fetch_min(v, Ordering::Relaxed);
+            self.max.fetch_max(v, Ordering::Relaxed);
+        }
+
+        /// Record nanoseconds elapsed since `start`, which is returned by `cycles`.
+        #[inline]
+        pub fn record_since(&self, start: u64) {
+            self.record(cycles_to_ns(cycles().wrapping_sub(start)));
+        }
+
+        /// Return the number of recorded values.
+        #[inline]
+        pub fn count(&self) -> u64 {
+            self.count.load(Ordering::Relaxed)
+        }
+
+        /// Return the minimum recorded value, 0 if empty.
+        pub fn min(&self) -> u64 {
+            if 0 == self.count() {
+                0
+            } else {
+                self.min.load(Ordering::Relaxed)
+            }
+        }
+
+        /// Return the maximum recorded value.
+        pub fn max(&self) -> u64 {
+            self.max.load(Ordering::Relaxed)
+        }
+
+        /// Return the mean of recorded values, 0 if empty.
+        pub fn mean(&self) -> f64 {
+            match self.count() {
+                0 => 0.0,
+                count => self.sum.load(Ordering::Relaxed) as f64 / count as f64,
+            }
+        }
+
+        /// Return the value below which `p` percent of recorded values fall, within the error of
+        /// buckets. 0 if empty.
+        pub fn percentile(&self, p: f64) -> u64 {
+            let count = self.count();
+            if 0 == count {
+                return 0;
+            }
+            let rank = ((p / 100.0 * count as f64).ceil() as u64).max(1).min(count);
+            let mut seen = 0;
+            for (idx, bucket) in self.buckets.iter().enumerate() {
+                seen += bucket.load(Ordering::Relaxed);
+                if seen >= rank {
+                    return Self::bucket_upper_bound(idx).min(self.max()).max(self.min());
+                }
+            }
+            self.max()
+        }
+
+        /// Clear all recorded values. Values recorded concurrently may be partially lost.
+        pub fn reset(&self) {
+            for bucket in self.buckets.iter() {
+                bucket.store(0, Ordering::Relaxed);
+            }
+            self.count.store(0, Ordering::Relaxed);
+            self.sum.store(0, Ordering::Relaxed);
+            self.min.store(u64::max_value(), Ordering::Relaxed);
+            self.max.store(0, Ordering::Relaxed);
+        }
+    }
+
+    impl fmt::Debug for Histogram {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "count={} min={} mean={:.1} p50={} p99={} p99.9={} max={}",
+                self.count(),
+                self.min(),
+                self.mean(),
+                self.percentile(50.0),
+                self.percentile(99.0),
+                self.percentile(99.9),
+                self.max()
+            )
+        }
+    }
+}
+
+#[cfg(feature = "bench")]
+pub use self::bench::*;
+
+/// Contention counters of `SpinLock` and `SpinRWLock`, enabled by feature `lock-stats`.
+#[cfg(feature = "lock-stats")]
+mod lock_stats {
+    use super::AtomicU64;
+    use std::sync::atomic::Ordering;
+    use std::time::Instant;
+
+    /// Contention of a lock since it was made, returned by `contention_stats`.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct ContentionStats {
+        /// Times lockers backed off because the lock was held or their CAS failed.
+        pub spins: u64,
+        /// CAS on the lock word which failed because another thread changed it first.
+        pub failed_cas: u64,
+        /// Total nanoseconds lockers waited, from their first back off to acquiring or giving up.
+        pub wait_ns: u64,
+    }
+
+    /// Counters a lock accumulates `ContentionProbe`s into.
+    #[derive(Default)]
+    pub struct ContentionCounters {
+        spins: AtomicU64,
+        failed_cas: AtomicU64,
+        wait_ns: AtomicU64,
+    }
+
+    impl ContentionCounters {
+        /// Return zeroed counters.
+        #[inline]
+        pub const fn new() -> Self {
+            ContentionCounters {
+                spins: AtomicU64::new(0),
+                failed_cas: AtomicU64::new(0),
+                wait_ns: AtomicU64::new(0),
+            }
+        }
+
+        /// Return a snapshot of the counters, which are loaded one by one, so they may be torn.
+        pub fn load(&self) -> ContentionStats {
+            ContentionStats {
+                spins: self.spins.load(Ordering::Relaxed),
+                failed_cas: self.failed_cas.load(Ordering::Relaxed),
+                wait_ns: self.wait_ns.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Contention of one lock attempt, counted locally and added to `ContentionCounters` once by
+    /// `finish`, so an uncontended attempt touches no shared counter.
+    #[derive(Default)]
+    pub struct ContentionProbe {
+        spins: u64,
+        failed_cas: u64,
+        start: Option<Instant>,
+    }
+
+    impl ContentionProbe {
+        /// Return probe of a new attempt.
+        #[inline]
+        pub fn new() -> Self {
+            ContentionProbe::default()
+        }
+
+        /// Count a back off, starting the wait clock on the first one.
+        #[inline]
+        pub fn spin(&mut self) {
+            if self.start.is_none() {
+                self.start = Some(Instant::now());
+            }
+            self.spins += 1;
+        }
+
+        /// Count a failed CAS.
+        #[inline]
+        pub fn fail_cas(&mut self) {
+            self.failed_cas += 1;
+        }
+
+        /// Add current attempt to `counters`.
+        #[inline]
+        pub fn finish(&self, counters: &ContentionCounters) {
+            if 0 < self.failed_cas {
+                counters.failed_cas.fetch_add(self.failed_cas, Ordering::Relaxed);
+            }
+            if let Some(start) = self.start {
+                let wait = start.elapsed();
+                let wait_ns = wait.as_secs() * 1_000_000_000 + u64::from(wait.subsec_nanos());
+                counters.spins.fetch_add(self.spins, Ordering::Relaxed);
+                counters.wait_ns.fetch_add(wait_ns, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Empty contention counters without feature `lock-stats`, which cost nothing.
+#[cfg(not(feature = "lock-stats"))]
+mod lock_stats {
+    #[derive(Default)]
+    pub struct ContentionCounters;
+
+    impl ContentionCounters {
+        #[inline]
+        pub const fn new() -> Self {
+            ContentionCounters
+        }
+    }
+
+    #[derive(Default)]
+    pub struct ContentionProbe;
+
+    impl ContentionProbe {
+        #[inline]
+        pub fn new() -> Self {
+            ContentionProbe
+        }
+
+        #[inline]
+        pub fn spin(&mut self) {}
+
+        #[inline]
+        pub fn fail_cas(&mut self) {}
+
+        #[inline]
+        pub fn finish(&self, _counters: &ContentionCounters) {}
+    }
+}
+
+pub use self::lock_stats::*;
+
+/// Helpers pinning threads to cpus by crate `core_affinity`, with feature `affinity`.
+///
+/// Containers keep per-thread state by the id of `get_thread_id`, so placing threads by that id
+/// spreads them evenly over cpus and keeps the thread of each id on the same cpu.
+#[cfg(feature = "affinity")]
+pub mod affinity {
+    use core_affinity::{self, CoreId};
+    use numa;
+    use util;
+
+    /// Return cpus current thread is allowed to run on, empty if unknown.
+    pub fn cpus() -> Vec<usize> {
+        core_affinity::get_core_ids().map_or(vec![], |ids| ids.into_iter().map(|c| c.id).collect())
+    }
+
+    /// Pin current thread to `cpu`. Return false if `cpu` isn't allowed.
+    pub fn pin_current(cpu: usize) -> bool {
+        if !cpus().contains(&cpu) {
+            return false;
+        }
+        core_affinity::set_for_current(CoreId { id: cpu });
+        true
+    }
+
+    /// Pin current thread to cpu `get_thread_id() % n` of the `n` allowed cpus. Return the cpu,
+    /// None if cpus are unknown.
+    pub fn pin_round_robin() -> Option<usize> {
+        let cpus = cpus();
+        if cpus.is_empty() {
+            return None;
+        }
+        let cpu = cpus[util::get_thread_id() as usize % cpus.len()];
+        core_affinity::set_for_current(CoreId { id: cpu });
+        Some(cpu)
+    }
+
+    /// Pin current thread to a cpu of NUMA `node`, picked round-robin by `get_thread_id` among
+    /// its allowed cpus. Fall back to `pin_round_robin` if the cpus of `node` are unknown.
+    ///
+    /// Call it before anything records the node of current thread, like `numa::current_node` or
+    /// containers sharded by node, so that the recorded node is `node`.
+    pub fn pin_to_node(node: usize) -> Option<usize> {
+        let allowed = cpus();
+        let cpus: Vec<_> = numa::topology()
+            .node_cpus(node)
+            .into_iter()
+            .filter(|cpu| allowed.contains(cpu))
+            .collect();
+        if cpus.is_empty() {
+            return pin_round_robin();
+        }
+        let cpu = cpus[util::get_thread_id() as usize % cpus.len()];
+        core_affinity::set_for_current(CoreId { id: cpu });
+        Some(cpu)
+    }
+
+    /// Pin current thread to a cpu of node `get_thread_id() % node_count`, so that threads are
+    /// spread over nodes one after another. Return the cpu, None if cpus are unknown.
+    pub fn pin_numa_round_robin() -> Option<usize> {
+        let node_count = numa::topology().node_count();
+        pin_to_node(util::get_thread_id() as usize % node_count)
+    }
+}
+
+mod test {
+    #[test]
+    fn test_atomic_portable() {
+        use std::ptr;
+        use util::atomic_portable::*;
+        use util::{atomic_cxchg_raw_ptr, atomic_load_raw_ptr, atomic_store_raw_ptr};
+        let mut a = -1_i64;
+        assert_eq!(unsafe { sync_fetch_and_add(&mut a, 2) }, -1);
+        assert_eq!(unsafe { sync_add_and_fetch(&mut a, -3) }, -2);
+        let mut b = 255_u8;
+        assert_eq!(unsafe { sync_fetch_and_add(&mut b, 1) }, 255);
+        assert_eq!(b, 0);
+        let mut c = -5_i32;
+        assert_eq!(unsafe { sync_add_and_fetch(&mut c, 5) }, 0);
+        unsafe {
+            assert_eq!(sync_fetch_and_add_relaxed(&mut c, -1), 0);
+            atomic_store_relaxed(&mut a, 7);
+            assert_eq!((atomic_load_relaxed(&a), atomic_load_relaxed(&c)), (7, -1));
+        }
+
+        let mut x = 1;
+        let mut y = 2;
+        let mut p: *mut i32 = &mut x;
+        unsafe {
+            assert_eq!(atomic_load_raw_ptr(&p), &mut x as *mut _);
+            assert!(!atomic_cxchg_raw_ptr(&mut p, ptr::null_mut(), &mut y).1);
+            assert!(atomic_cxchg_raw_ptr(&mut p, &mut x, &mut y).1);
+            assert_eq!(*atomic_load_raw_ptr(&p), 2);
+            atomic_store_raw_ptr(&mut p, ptr::null_mut());
+        }
+        assert!(p.is_null());
+        let tid = get_thread_id();
+        assert_eq!(tid, get_thread_id());
+    }
+    #[test]
+    fn test_thread_registry() {
+        use std::thread;
+        use util::{get_thread_id, thread_registry};
+        let t = thread::Builder::new()
+            .name("registry-test".to_string())
+            .spawn(|| {
+                let tid = thread_registry().set_current_label("worker");
+                let info = thread_registry().get(tid).unwrap();
+                assert_eq!(info.name, Some("registry-test".to_string()));
+                assert_eq!(info.label, Some("worker".to_string()));
+                assert_eq!(tid, get_thread_id());
+                tid
+            })
+            .unwrap();
+        let tid = t.join().unwrap();
+        assert!(thread_registry().get(tid).is_none());
+        assert!(!thread_registry().set_label(tid, "exited"));
+        let tid = get_thread_id();
+        assert!(thread_registry().threads().iter().any(|t| t.tid == tid));
+    }
+    #[test]
+    fn test_atomic_layout() {
+        use std::mem;
+        use util::{AtomicI64, AtomicRawPtr, AtomicU64};
+        // Natural alignment is kept even where `u64` is 4-byte aligned, like i686 and armv7.
+        assert_eq!(mem::align_of::<AtomicI64>(), 8);
+        assert_eq!(mem::align_of::<AtomicU64>(), 8);
+        assert_eq!(mem::size_of::<AtomicRawPtr<u64>>(), mem::size_of::<usize>());
+        assert_eq!(mem::align_of::<AtomicRawPtr<u64>>(), mem::align_of::<usize>());
+    }
+
+    // `cmpxchg16b` is not supported by Miri.
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_atomic_cxchg_u128() {
+        use util;
+        if !util::has_cxchg_u128() {
+            return;
+        }
+        let mut v = util::WrappedAlign64Type(1u128 << 64 | 1);
+        unsafe {
+            assert_eq!(util::atomic_cxchg_u128(v.as_mut_ptr(), 1, 2), (1 << 64 | 1, false));
+            assert_eq!(
+                util::atomic_cxchg_u128(v.as_mut_ptr(), 1 << 64 | 1, u128::max_value()),
+                (1 << 64 | 1, true)
+            );
+        }
+        assert_eq!(*v, u128::max_value());
+    }
+
+    #[test]
+    fn test_tagged_ptr() {
+        use std::ptr;
+        use std::sync::atomic::Ordering;
+        use util::{AtomicTaggedPtr, TaggedPtr};
+        assert_eq!(TaggedPtr::<u8>::tag_bits(), TaggedPtr::<u32>::tag_bits() - 2);
+        let max_tag = TaggedPtr::<u64>::max_tag();
+        let mut v = 0u64;
+        let p = TaggedPtr::new(&mut v, max_tag);
+        assert_eq!(p.ptr(), &mut v as *mut u64);
+        assert_eq!(p.tag(), max_tag);
+        assert_eq!(p.with_tag(max_tag + 1).tag(), 0);
+        assert_eq!(p.with_ptr(ptr::null_mut()).tag(), max_tag);
+        assert!(p.with_ptr(ptr::null_mut()).ptr().is_null());
+        for tag in 0..16 {
+            assert_eq!(p.with_tag(tag).tag(), tag & max_tag);
+            assert_eq!(p.with_tag(tag).ptr(), p.ptr());
+        }
+
+        let a = AtomicTaggedPtr::new(p);
+        let q = p.with_tag(1);
+        assert!(!a.cas(q, p, Ordering::SeqCst).1);
+        assert_eq!(a.cas(p, q, Ordering::SeqCst), (p, true));
+        a.store(TaggedPtr::null(), Ordering::SeqCst);
+        assert_eq!(a.load(Ordering::SeqCst), TaggedPtr::default());
+    }
+
+    #[test]
+    fn test_wait_policy() {
+        use std::time::Duration;
+        use util::{self, Backoff, WaitPolicy};
+        let policies = [
+            WaitPolicy::Spin,
+            WaitPolicy::SpinYield,
+            WaitPolicy::Yield,
+            WaitPolicy::Sleep(Duration::new(1, 2)),
+            WaitPolicy::Sleep(Duration::from_micros(1)),
+        ];
+        for &policy in policies.iter() {
+            assert_eq!(WaitPolicy::decode(policy.encode()), policy);
+            let mut backoff = Backoff::with_policy(policy);
+            if let WaitPolicy::Sleep(_) = policy {
+                continue;
+            }
+            while !backoff.is_completed() {
+                backoff.snooze();
+            }
+        }
+        assert_eq!(util::wait_policy(), WaitPolicy::default());
+        util::set_wait_policy(WaitPolicy::Yield);
+        assert_eq!(util::wait_policy(), WaitPolicy::Yield);
+        util::set_wait_policy(WaitPolicy::default());
+    }
+
+    #[cfg(feature = "bench")]
+    #[test]
+    fn test_histogram() {
+        use util::{cycles, Histogram};
+        for v in 0..100_000u64 {
+            let idx = Histogram::bucket_index(v);
+            assert!(v <= Histogram::bucket_upper_bound(idx));
+            assert!(Histogram::bucket_upper_bound(idx) - v <= v / 8);
+        }
+        let idx = Histogram::bucket_index(u64::max_value());
+        assert_eq!(Histogram::bucket_upper_bound(idx), u64::max_value());
+
+        let hist = Histogram::new();
+        assert_eq!(hist.percentile(50.0), 0);
+        for v in 1..1001 {
+            hist.record(v);
+        }
+        assert_eq!((hist.count(), hist.min(), hist.max()), (1000, 1, 1000));
+        assert_eq!(hist.mean(), 500.5);
+        let p50 = hist.percentile(50.0);
+        assert!(500 <= p50 && p50 <= 500 + 500 / 8);
+        assert_eq!(hist.percentile(100.0), 1000);
+        hist.record_since(cycles());
+        assert_eq!(hist.count(), 1001);
+        hist.reset();
+        assert_eq!((hist.count(), hist.max()), (0, 0));
+    }
+
+    #[test]
+    fn test_atomic_wrapper() {
+        use std::ptr;
+        use std::sync::atomic::Ordering;
+        use util::{AtomicI64, AtomicRawPtr, AtomicU64};
+        let a = AtomicI64::new(-1);
+        assert_eq!(a.fetch_add(2, Ordering::SeqCst), -1);
+        assert_eq!(a.add_and_fetch(-3, Ordering::AcqRel), -2);
+        assert_eq!(a.cas(0, 1, Ordering::SeqCst), (-2, false));
+        assert_eq!(a.cas(-2, 1, Ordering::Release), (-2, true));
+        assert_eq!(a.load(Ordering::Acquire), 1);
+        let mut b = AtomicU64::default();
+        b.store(u64::max_value(), Ordering::Relaxed);
+        assert_eq!(b.add_and_fetch(1, Ordering::SeqCst), 0);
+        *b.get_mut() += 1;
+        assert_eq!(b.load(Ordering::SeqCst), 1);
+
+        let mut x = 1;
+        let mut y = 2;
+        let mut p = AtomicRawPtr::new(&mut x as *mut i32);
+        assert!(!p.cas(ptr::null_mut(), &mut y, Ordering::SeqCst).1);
+        assert_eq!(p.swap(&mut y, Ordering::SeqCst), &mut x as *mut _);
+        assert_eq!(unsafe { *p.load(Ordering::SeqCst) }, 2);
+        *p.get_mut() = ptr::null_mut();
+        assert!(p.load(Ordering::Relaxed).is_null());
+    }
+
+    #[test]
+    fn test_effective_order() {
+        use std::sync::atomic::Ordering;
+        use util::{effective_order, ORDER_ACQUIRE, ORDER_ACQ_REL, ORDER_RELAXED, ORDER_RELEASE};
+        let orders = [ORDER_ACQUIRE, ORDER_RELEASE, ORDER_ACQ_REL, ORDER_RELAXED];
+        if cfg!(feature = "seqcst-paranoid") {
+            assert!(orders.iter().all(|&order| Ordering::SeqCst == order));
+            assert_eq!(effective_order(Ordering::Relaxed), Ordering::SeqCst);
+        } else {
+            assert_eq!(orders[3], Ordering::Relaxed);
+            assert_eq!(effective_order(Ordering::Acquire), Ordering::Acquire);
+        }
+    }
+
+    #[test]
+    fn test_try_box_into_raw() {
+        use util::try_box_into_raw;
+        let p = try_box_into_raw(vec![1, 2]).unwrap();
+        assert_eq!(unsafe { Box::from_raw(p) }, Box::new(vec![1, 2]));
+        let p = try_box_into_raw(()).unwrap();
+        unsafe { drop(Box::from_raw(p)) };
+    }
+
+    #[test]
+    fn test_racy_read_write() {
+        use util::{racy_read, racy_write};
+        let mut v = (1u8, 2u64, [3u16; 3]);
+        unsafe {
+            racy_write(&mut v, (4, 5, [6; 3]));
+            assert_eq!(racy_read(&v), (4, 5, [6; 3]));
+        }
+    }
+}
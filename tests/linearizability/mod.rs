@@ -0,0 +1,140 @@
+//! Recording of concurrent operation histories, and checking them against sequential
+//! specifications by the algorithm of Wing & Gong, with states memoized as Lowe suggests.
+//!
+//! Each thread records its operations to its own `Vec<Event>` by `Clock::call`, which stamps the
+//! invocation and the response by a shared logical clock, so that an operation responding before
+//! another is invoked has a smaller response stamp than the invocation stamp of the other. The
+//! merged history is linearizable if its operations can be ordered so that each one takes effect
+//! between its invocation and response, and the order is accepted by the specification.
+//!
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Operation of a queue or stack with its result.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Op<T> {
+    Push(T),
+    Pop(Option<T>),
+}
+
+/// Operation recorded with the stamps of its invocation and response.
+#[derive(Clone, Debug)]
+pub struct Event<T> {
+    pub op: Op<T>,
+    pub invoke: u64,
+    pub response: u64,
+}
+
+/// Logical clock shared by threads recording one history.
+pub struct Clock(AtomicU64);
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock(AtomicU64::new(0))
+    }
+
+    fn tick(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Run `f`, which performs an operation and returns it with its result, and record it to
+    /// `log`.
+    pub fn call<T, F>(&self, log: &mut Vec<Event<T>>, f: F)
+    where
+        F: FnOnce() -> Op<T>,
+    {
+        let invoke = self.tick();
+        let op = f();
+        let response = self.tick();
+        log.push(Event {
+            op,
+            invoke,
+            response,
+        });
+    }
+}
+
+/// Sequential specification of a container.
+pub trait Spec<T>: Clone + Hash + Eq {
+    /// Apply `op` to current state, and return false if its result is not allowed.
+    fn apply(&mut self, op: &Op<T>) -> bool;
+}
+
+/// Specification of a FIFO queue.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Fifo<T>(pub VecDeque<T>);
+
+impl<T: Clone + Hash + Eq> Spec<T> for Fifo<T> {
+    fn apply(&mut self, op: &Op<T>) -> bool {
+        match *op {
+            Op::Push(ref v) => {
+                self.0.push_back(v.clone());
+                true
+            }
+            Op::Pop(ref r) => self.0.pop_front() == *r,
+        }
+    }
+}
+
+/// Specification of a LIFO stack.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Lifo<T>(pub Vec<T>);
+
+impl<T: Clone + Hash + Eq> Spec<T> for Lifo<T> {
+    fn apply(&mut self, op: &Op<T>) -> bool {
+        match *op {
+            Op::Push(ref v) => {
+                self.0.push(v.clone());
+                true
+            }
+            Op::Pop(ref r) => self.0.pop() == *r,
+        }
+    }
+}
+
+/// Return true if `history`, which holds at most 64 events, is linearizable from state `init`.
+pub fn check<T, S>(history: &[Event<T>], init: S) -> bool
+where
+    S: Spec<T>,
+{
+    assert!(history.len() <= 64);
+    let all = if 64 == history.len() {
+        !0
+    } else {
+        (1u64 << history.len()) - 1
+    };
+    search(history, all, 0, init, &mut HashSet::new())
+}
+
+fn search<T, S>(
+    history: &[Event<T>],
+    all: u64,
+    done: u64,
+    state: S,
+    memo: &mut HashSet<(u64, S)>,
+) -> bool
+where
+    S: Spec<T>,
+{
+    if all == done {
+        return true;
+    }
+    if !memo.insert((done, state.clone())) {
+        // Same linearized operations leading to the same state have failed.
+        return false;
+    }
+    let pending = (0..history.len()).filter(|&i| 0 == done & (1 << i));
+    let min_response = pending.clone().map(|i| history[i].response).min().unwrap();
+    for i in pending {
+        // An operation can't take effect after another one has responded before it's invoked.
+        if history[i].invoke > min_response {
+            continue;
+        }
+        let mut next = state.clone();
+        if next.apply(&history[i].op) && search(history, all, done | (1 << i), next, memo) {
+            return true;
+        }
+    }
+    false
+}
@@ -0,0 +1,46 @@
+#![cfg(not(feature = "single_thread"))]
+
+extern crate rs_lockfree;
+
+use rs_lockfree::hazard_epoch::HazardEpoch;
+use rs_lockfree::util;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// A reader holding its handle shows up with its tid and how long it has held it, oldest first.
+#[test]
+fn test_stuck_reader() {
+    let mut h = HazardEpoch::default_new_in_heap();
+    let addr = &mut *h as *mut HazardEpoch as usize;
+    let (acquired_tx, acquired_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let reader = thread::spawn(move || unsafe {
+        let h = &mut *(addr as *mut HazardEpoch);
+        let handle = h.try_acquire().unwrap();
+        acquired_tx.send(util::get_thread_id()).unwrap();
+        release_rx.recv().unwrap();
+        h.release(handle);
+    });
+    let reader_tid = acquired_rx.recv().unwrap();
+    assert!(h.active_handles().iter().all(|a| a.tid as i64 == reader_tid));
+
+    thread::sleep(Duration::from_millis(20));
+    unsafe { h.retire() };
+    let handle = h.try_acquire().unwrap();
+    let active = h.active_handles();
+    assert_eq!(active.len(), 2);
+    assert_eq!(active[0].tid as i64, reader_tid);
+    assert!(active[0].held_us >= 20_000);
+    assert_eq!(active[1].tid as i64, util::get_thread_id());
+    assert!(active[0].version < active[1].version);
+    assert!(active[1].held_us < active[0].held_us);
+
+    release_tx.send(()).unwrap();
+    reader.join().unwrap();
+    let active = h.active_handles();
+    assert_eq!(active.len(), 1);
+    assert_eq!(active[0].tid as i64, util::get_thread_id());
+    unsafe { h.release(handle) };
+    assert!(h.active_handles().is_empty());
+}
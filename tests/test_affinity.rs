@@ -0,0 +1,36 @@
+#![cfg(feature = "affinity")]
+
+extern crate rs_lockfree;
+
+use rs_lockfree::numa;
+use rs_lockfree::util::{self, affinity};
+use std::thread;
+
+// Thread ids are never reused, so it runs in its own test binary.
+#[test]
+fn test_base() {
+    let cpus = affinity::cpus();
+    assert!(!cpus.is_empty());
+    assert!(!affinity::pin_current(usize::max_value()));
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let cpus = cpus.clone();
+            thread::spawn(move || {
+                let cpu = affinity::pin_round_robin().unwrap();
+                assert_eq!(cpu, cpus[util::get_thread_id() as usize % cpus.len()]);
+                assert!(affinity::pin_current(cpu));
+                if let Some(cpu) = numa::current_cpu() {
+                    assert!(cpus.contains(&cpu));
+                }
+                let cpu = affinity::pin_numa_round_robin().unwrap();
+                assert!(cpus.contains(&cpu));
+                let cpu = affinity::pin_to_node(0).unwrap();
+                assert!(cpus.contains(&cpu));
+                assert_eq!(numa::current_node(), numa::topology().cpu_node(cpu));
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::arc_swap::HazardArcSwap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+struct ShardPtr<T>(*mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+// A replaced value is released once no reader holds it, unless its `Arc` is held elsewhere.
+#[test]
+fn test_release() {
+    let v1 = Arc::new(1);
+    let v2 = Arc::new(2);
+    let mut slot = HazardArcSwap::new_in_heap(v1.clone());
+    assert_eq!(Arc::strong_count(&v1), 2);
+    {
+        let guard = slot.load();
+        assert_eq!(*guard, 1);
+        // Loading doesn't touch the reference count.
+        assert_eq!(Arc::strong_count(&v1), 2);
+    }
+    let old = slot.swap(v2.clone());
+    assert!(Arc::ptr_eq(&old, &v1));
+    drop(old);
+    assert_eq!(*slot.load_full(), 2);
+    slot.store(Arc::new(3));
+    drop(slot);
+    assert_eq!(Arc::strong_count(&v1), 1);
+    assert_eq!(Arc::strong_count(&v2), 1);
+}
+
+// Readers always see a whole value while a writer replaces it, and every value is released in
+// the end.
+#[test]
+fn test_concurrent() {
+    let test_num = 10000;
+    let first = Arc::new((0usize, 0usize));
+    let mut slot = HazardArcSwap::new_in_heap(first.clone());
+    let slot_ptr = &mut *slot as *mut HazardArcSwap<(usize, usize)>;
+    let done = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..3)
+        .map(|_| {
+            let mut slot = ShardPtr::new(slot_ptr);
+            let done = done.clone();
+            thread::spawn(move || {
+                let slot = slot.as_mut();
+                let mut last = 0;
+                while !done.load(Ordering::SeqCst) {
+                    let guard = slot.load();
+                    assert_eq!(guard.1, guard.0 * 2);
+                    assert!(last <= guard.0);
+                    last = guard.0;
+                }
+            })
+        })
+        .collect();
+    let values: Vec<_> = (1..test_num).map(|i| Arc::new((i, i * 2))).collect();
+    for v in values.iter() {
+        slot.store(v.clone());
+    }
+    done.store(true, Ordering::SeqCst);
+    for t in readers {
+        t.join().unwrap();
+    }
+    drop(slot);
+    assert_eq!(Arc::strong_count(&first), 1);
+    assert!(values.iter().all(|v| 1 == Arc::strong_count(v)));
+}
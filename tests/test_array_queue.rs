@@ -0,0 +1,64 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::array_queue::ArrayQueue;
+use rs_lockfree::error::QueueError;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_push_blocking() {
+    let queue = Arc::new(ArrayQueue::<usize, 4>::new());
+    let test_num = 10000;
+    let producers: Vec<_> = (0..2)
+        .map(|_| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for i in 0..test_num {
+                    queue.push_blocking(i);
+                }
+            })
+        })
+        .collect();
+    let mut sum = 0;
+    let mut cnt = 0;
+    while cnt < test_num * 2 {
+        match queue.pop() {
+            Some(v) => {
+                sum += v;
+                cnt += 1;
+            }
+            None => thread::yield_now(),
+        }
+    }
+    for t in producers {
+        t.join().unwrap();
+    }
+    assert_eq!(sum, test_num * (test_num - 1));
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn test_push_timeout() {
+    let queue = Arc::new(ArrayQueue::<usize, 2>::new());
+    queue.push_timeout(1, Duration::from_millis(10)).unwrap();
+    queue.push_blocking(2);
+    let e = queue.push_timeout(3, Duration::from_millis(10)).unwrap_err();
+    assert_eq!(e.value, 3);
+    match e.error {
+        QueueError::Timeout { op, .. } => assert_eq!(op, "push_timeout"),
+        _ => panic!("unexpected error {}", e.error),
+    }
+
+    // A pop wakes the parked producer.
+    let consumer = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            queue.pop()
+        })
+    };
+    queue.push_timeout(3, Duration::from_secs(10)).unwrap();
+    assert_eq!(consumer.join().unwrap(), Some(1));
+    assert_eq!((queue.pop(), queue.pop(), queue.pop()), (Some(2), Some(3), None));
+}
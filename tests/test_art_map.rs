@@ -0,0 +1,238 @@
+#![feature(core_intrinsics)]
+
+extern crate rs_lockfree;
+
+use rs_lockfree::art_map;
+use std::cell::RefCell;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::thread;
+use std::intrinsics;
+
+struct GlobalConf {
+    map: Box<art_map::ArtMap<u64>>,
+    loop_cnt: u64,
+    writer_cnt: i64,
+    read_hit: i64,
+}
+
+struct ShardPtr<T>(pub *mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+unsafe impl<T> Sync for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+impl<T> Copy for ShardPtr<T> {}
+
+impl<T> Clone for ShardPtr<T> {
+    fn clone(&self) -> Self {
+        ShardPtr(self.0)
+    }
+}
+
+impl<T> Deref for ShardPtr<T> {
+    type Target = *mut T;
+
+    fn deref(&self) -> &<Self as Deref>::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ShardPtr<T> {
+    fn deref_mut(&mut self) -> &mut <Self as Deref>::Target {
+        &mut self.0
+    }
+}
+
+struct Node<'a> {
+    cnt: &'a RefCell<i32>,
+}
+
+impl<'a> Drop for Node<'a> {
+    fn drop(&mut self) {
+        *self.cnt.borrow_mut() += 1;
+    }
+}
+
+#[test]
+fn test_base() {
+    let mut map = unsafe { art_map::ArtMap::default_new_in_stack() };
+    assert!(map.is_empty());
+    assert!(map.get(b"").is_none());
+    let test_num = 1000_usize;
+    for i in 0..test_num {
+        assert!(map.insert(format!("{}", i).as_bytes(), i as i64));
+    }
+    assert!(map.insert(b"", -1));
+    assert_eq!(map.len(), test_num + 1);
+    for i in 0..test_num {
+        assert_eq!(map.get(format!("{}", i).as_bytes()), Some(i as i64));
+    }
+    assert_eq!(map.get(b""), Some(-1));
+    assert!(!map.contains_key(b"1000"));
+    for i in 0..test_num {
+        if i % 2 == 0 {
+            assert!(map.remove(format!("{}", i).as_bytes()));
+        }
+    }
+    assert!(!map.remove(b"0"));
+    for i in 0..test_num {
+        assert_eq!(map.contains_key(format!("{}", i).as_bytes()), i % 2 == 1);
+    }
+    assert_eq!(map.len(), test_num / 2 + 1);
+}
+
+#[test]
+fn test_scan_prefix() {
+    let mut map = unsafe { art_map::ArtMap::default_new_in_stack() };
+    let keys: [&[u8]; 8] = [b"b", b"ab", b"abc", b"a", b"abd", b"ac", b"", b"abcd"];
+    for (i, k) in keys.iter().enumerate() {
+        map.insert(k, i);
+    }
+    let mut res = vec![];
+    map.scan_prefix(b"ab", |k, _| res.push(k.to_vec()));
+    assert_eq!(
+        res,
+        vec![b"ab".to_vec(), b"abc".to_vec(), b"abcd".to_vec(), b"abd".to_vec()]
+    );
+    res.clear();
+    map.scan_prefix(b"", |k, _| res.push(k.to_vec()));
+    let mut sorted: Vec<Vec<u8>> = keys.iter().map(|k| k.to_vec()).collect();
+    sorted.sort();
+    assert_eq!(res, sorted);
+    res.clear();
+    map.scan_prefix(b"abx", |k, _| res.push(k.to_vec()));
+    assert!(res.is_empty());
+}
+
+#[test]
+fn test_memory_leak() {
+    let cnt = RefCell::new(0);
+    {
+        let mut map = unsafe { art_map::ArtMap::default_new_in_stack() };
+        for i in 0..300 {
+            map.insert(&[i as u8, (i / 256) as u8], Node { cnt: &cnt });
+        }
+        map.insert(&[0, 0], Node { cnt: &cnt });
+        for i in 0..100 {
+            map.remove(&[i as u8, (i / 256) as u8]);
+        }
+    }
+    assert_eq!(*cnt.borrow(), 301);
+}
+
+fn make_key(writer: u64, i: u64) -> Vec<u8> {
+    format!("{}/{}", writer, i).into_bytes()
+}
+
+unsafe fn writer_thread(mut global_conf: ShardPtr<GlobalConf>, writer: u64) {
+    let global_conf = global_conf.as_mut();
+    for i in 0..global_conf.loop_cnt {
+        assert!(global_conf.map.insert(&make_key(writer, i), writer * 1_000_000 + i));
+        if i % 3 == 0 {
+            assert!(global_conf.map.remove(&make_key(writer, i)));
+        }
+    }
+    intrinsics::atomic_xadd(&mut global_conf.writer_cnt, -1);
+}
+
+unsafe fn reader_thread(mut global_conf: ShardPtr<GlobalConf>, writer_count: u64) {
+    let global_conf = global_conf.as_mut();
+    let mut hit = 0;
+    while intrinsics::atomic_load(&global_conf.writer_cnt) != 0 {
+        for writer in 0..writer_count {
+            let mut last = None;
+            global_conf.map.scan_prefix(format!("{}/", writer).as_bytes(), |k, v| {
+                assert_eq!(k, make_key(writer, v - writer * 1_000_000).as_slice());
+                assert!(last.as_ref().map_or(true, |l: &Vec<u8>| l.as_slice() < k));
+                last = Some(k.to_vec());
+                hit += 1;
+            });
+        }
+    }
+    intrinsics::atomic_xadd(&mut global_conf.read_hit, hit);
+}
+
+#[test]
+fn test_multi_threads() {
+    let writer_count = 3_u64;
+    let reader_count = 3_u64;
+
+    let mut global_conf = GlobalConf {
+        map: art_map::ArtMap::default_new_in_heap(),
+        loop_cnt: 20000,
+        writer_cnt: writer_count as i64,
+        read_hit: 0,
+    };
+
+    let global_conf_ptr = ShardPtr::new(&mut global_conf as *mut _);
+
+    let mut threads = vec![];
+
+    for writer in 0..writer_count {
+        threads.push(thread::spawn(move || unsafe {
+            writer_thread(global_conf_ptr, writer);
+        }));
+    }
+
+    for _ in 0..reader_count {
+        threads.push(thread::spawn(move || unsafe {
+            reader_thread(global_conf_ptr, writer_count);
+        }));
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    println!("read_hit {}", global_conf.read_hit);
+
+    let loop_cnt = global_conf.loop_cnt;
+    for writer in 0..writer_count {
+        for i in 0..loop_cnt {
+            let v = global_conf.map.get(&make_key(writer, i));
+            if i % 3 == 0 {
+                assert!(v.is_none());
+            } else {
+                assert_eq!(v, Some(writer * 1_000_000 + i));
+            }
+        }
+    }
+    assert_eq!(
+        global_conf.map.len() as u64,
+        writer_count * (loop_cnt - (loop_cnt + 2) / 3)
+    );
+}
+
+// A panicking reader callback must not leak the handle of current thread, or every later access
+// of it would fail.
+#[test]
+fn test_panic_in_reader() {
+    use std::panic::{self, AssertUnwindSafe};
+    let mut map = art_map::ArtMap::default_new_in_heap();
+    assert_eq!(map.try_insert(b"a", 1).unwrap(), true);
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+        map.get_with(b"a", |_| panic!("reader panics"));
+    }));
+    assert!(ret.is_err());
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+        map.scan_prefix(b"", |_, _| panic!("reader panics"));
+    }));
+    assert!(ret.is_err());
+    assert_eq!(map.try_insert(b"b", 2).unwrap(), true);
+    assert_eq!(map.try_get_with(b"a", |v| *v).unwrap(), Some(1));
+    assert_eq!(map.try_remove(b"a").unwrap(), true);
+    let mut keys = vec![];
+    map.try_scan_prefix(b"", |k, _| keys.push(k.to_vec())).unwrap();
+    assert_eq!(keys, vec![b"b".to_vec()]);
+}
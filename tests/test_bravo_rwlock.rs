@@ -0,0 +1,42 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::bravo_rwlock::BravoRwLock;
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn test_multi_threads() {
+    let writer_count = 2;
+    let reader_count = 2;
+    let loop_cnt = 50000;
+    let lock = Arc::new(BravoRwLock::new([0_u64; 8]));
+
+    let mut threads = vec![];
+    for _ in 0..writer_count {
+        let lock = lock.clone();
+        threads.push(thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                for x in lock.write().iter_mut() {
+                    *x += 1;
+                }
+            }
+        }));
+    }
+    for _ in 0..reader_count {
+        let lock = lock.clone();
+        threads.push(thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                let v = lock.read();
+                for x in v.iter() {
+                    assert_eq!(*x, v[0]);
+                }
+            }
+        }));
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert_eq!(*lock.read(), [writer_count * loop_cnt; 8]);
+}
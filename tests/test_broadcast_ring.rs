@@ -0,0 +1,54 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::broadcast_ring::broadcast;
+use rs_lockfree::error::QueueError;
+use std::thread;
+
+// One publisher overwrites a small ring while subscribers read at their own pace. Each subscriber
+// gets untorn elements in order, and every element is either received or counted as missed.
+#[test]
+fn test_broadcast() {
+    let subscriber_num = 3;
+    let test_num: u64 = 100000;
+    let (mut publisher, sub) = broadcast::<(u64, u64)>(16);
+    let subscribers: Vec<_> = (0..subscriber_num)
+        .map(|idx| {
+            let mut sub = sub.clone();
+            thread::spawn(move || {
+                let mut received = 0;
+                let mut missed = 0;
+                let mut next = 0;
+                while next < test_num {
+                    match sub.try_recv() {
+                        Ok(Some((a, b))) => {
+                            assert_eq!(b, a * 3);
+                            assert!(a >= next);
+                            next = a + 1;
+                            received += 1;
+                        }
+                        Ok(None) => {
+                            if 0 == idx {
+                                thread::yield_now();
+                            }
+                        }
+                        Err(QueueError::Lagged { missed: n, .. }) => {
+                            missed += n;
+                            next += n;
+                        }
+                        Err(e) => panic!("unexpected error {}", e),
+                    }
+                }
+                assert_eq!(received + missed, test_num);
+                received
+            })
+        })
+        .collect();
+    drop(sub);
+    for i in 0..test_num {
+        publisher.publish((i, i * 3));
+    }
+    assert_eq!(publisher.published_count(), test_num);
+    for t in subscribers {
+        assert!(t.join().unwrap() > 0);
+    }
+}
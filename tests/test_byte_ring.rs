@@ -0,0 +1,53 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::byte_ring::ByteRing;
+use std::sync::Arc;
+use std::thread;
+
+// Producers push records of varying lengths into a small ring, retrying when full, while the
+// consumer drains. Records of each producer arrive in order and intact. Spinning threads take
+// thread ids, so it has its own test binary.
+#[test]
+fn test_mpsc() {
+    let producer_num = 3;
+    let test_num = 5000u32;
+    let ring = Arc::new(ByteRing::with_capacity(256));
+    let producers: Vec<_> = (0..producer_num)
+        .map(|id| {
+            let ring = ring.clone();
+            thread::spawn(move || {
+                for seq in 0..test_num {
+                    // Producer id, sequence, and padding of a length depending on both.
+                    let mut record = vec![id as u8];
+                    record.extend_from_slice(&seq.to_le_bytes());
+                    record.resize(5 + (seq as usize * 7 + id) % 40, id as u8);
+                    while ring.try_push(&record).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+    let mut next = vec![0u32; producer_num];
+    let mut cnt = 0;
+    while cnt < producer_num * test_num as usize {
+        let n = unsafe {
+            ring.drain(|r| {
+                let id = r[0] as usize;
+                let seq = u32::from_le_bytes([r[1], r[2], r[3], r[4]]);
+                assert_eq!(seq, next[id]);
+                assert_eq!(r.len(), 5 + (seq as usize * 7 + id) % 40);
+                assert!(r[5..].iter().all(|&b| b == id as u8));
+                next[id] += 1;
+            })
+        };
+        if 0 == n {
+            thread::yield_now();
+        }
+        cnt += n;
+    }
+    for t in producers {
+        t.join().unwrap();
+    }
+    assert!(ring.is_empty());
+}
@@ -0,0 +1,116 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::channel::{sync_channel, TryRecvError, TrySendError};
+use rs_lockfree::error::QueueError;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_multi_threads() {
+    let producer_count = 3_u64;
+    let consumer_count = 3_u64;
+    let loop_cnt = 50000_u64;
+
+    let (tx, rx) = sync_channel(16);
+
+    let mut producer_threads = vec![];
+    let mut consumer_threads = vec![];
+
+    for p in 0..producer_count {
+        let tx = tx.clone();
+        producer_threads.push(thread::spawn(move || {
+            for i in 0..loop_cnt {
+                tx.send(p * loop_cnt + i).unwrap();
+            }
+        }));
+    }
+    drop(tx);
+
+    for _ in 0..consumer_count {
+        let rx = rx.clone();
+        consumer_threads.push(thread::spawn(move || {
+            let mut sum = 0;
+            let mut cnt = 0;
+            while let Ok(v) = rx.recv() {
+                sum += v;
+                cnt += 1;
+            }
+            (sum, cnt)
+        }));
+    }
+    drop(rx);
+
+    for t in producer_threads {
+        t.join().unwrap();
+    }
+
+    let (mut sum, mut cnt) = (0, 0);
+    for t in consumer_threads {
+        let (s, c) = t.join().unwrap();
+        sum += s;
+        cnt += c;
+    }
+
+    let total = producer_count * loop_cnt;
+    assert_eq!(cnt, total);
+    assert_eq!(sum, total * (total - 1) / 2);
+}
+
+#[test]
+fn test_base() {
+    let (tx, rx) = sync_channel(2);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    tx.send(1).unwrap();
+    tx.try_send(2).unwrap();
+    assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+    assert_eq!(rx.recv().unwrap(), 1);
+    let tx2 = tx.clone();
+    tx2.send(3).unwrap();
+    drop(tx);
+    drop(tx2);
+    assert_eq!(rx.recv().unwrap(), 2);
+    assert_eq!(rx.try_recv().unwrap(), 3);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+    let (tx, rx) = sync_channel(1);
+    drop(rx);
+    assert_eq!(tx.send(1).unwrap_err().0, 1);
+}
+
+#[test]
+fn test_timeout() {
+    let timeout = Duration::from_millis(10);
+    let (tx, rx) = sync_channel(1);
+    assert_eq!(
+        rx.recv_timeout(timeout),
+        Err(QueueError::Timeout {
+            op: "recv_timeout",
+            timeout,
+        })
+    );
+    tx.send_timeout(1, timeout).unwrap();
+    let e = tx.send_timeout(2, timeout).unwrap_err();
+    assert_eq!(
+        e.error,
+        QueueError::Timeout {
+            op: "send_timeout",
+            timeout,
+        }
+    );
+    assert_eq!(e.into_inner(), 2);
+    let t = thread::spawn(move || {
+        tx.send_timeout(2, Duration::from_secs(10)).unwrap();
+    });
+    assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(1));
+    assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(2));
+    t.join().unwrap();
+    assert_eq!(
+        rx.recv_timeout(timeout),
+        Err(QueueError::Closed { op: "recv_timeout" })
+    );
+
+    let (tx, rx) = sync_channel(1);
+    drop(rx);
+    let e = tx.send_timeout(1, timeout).unwrap_err();
+    assert_eq!(e.error, QueueError::Closed { op: "try_send" });
+}
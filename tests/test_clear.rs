@@ -0,0 +1,155 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::lockfree_queue;
+use rs_lockfree::lockfree_stack;
+use std::thread;
+
+struct ShardPtr<T>(*mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+impl<T> Copy for ShardPtr<T> {}
+
+impl<T> Clone for ShardPtr<T> {
+    fn clone(&self) -> Self {
+        ShardPtr(self.0)
+    }
+}
+
+// Clearing at the same time as pushes and pops keeps the structure intact, and popped, cleared
+// and remaining elements add up to the pushed ones.
+#[test]
+fn test_queue() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 20000;
+    let mut queue = lockfree_queue::LockFreeQueue::<i64>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let queue_ptr = ShardPtr::new(&mut *queue as *mut lockfree_queue::LockFreeQueue<i64>);
+    let producer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut queue_ptr = queue_ptr;
+            let queue = queue_ptr.as_mut();
+            for i in 0..test_num {
+                queue.push(i);
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    let consumer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut queue_ptr = queue_ptr;
+            let queue = queue_ptr.as_mut();
+            let mut popped = vec![];
+            while !done.load(Ordering::SeqCst) {
+                match queue.pop() {
+                    Some(v) => popped.push(v),
+                    None => thread::yield_now(),
+                }
+            }
+            assert!(popped.windows(2).all(|w| w[0] < w[1]));
+            popped.len()
+        })
+    };
+    let mut cleared = 0;
+    while !done.load(Ordering::SeqCst) {
+        cleared += queue.clear();
+        thread::yield_now();
+    }
+    producer.join().unwrap();
+    let popped = consumer.join().unwrap();
+    let mut rest = 0;
+    while queue.pop().is_some() {
+        rest += 1;
+    }
+    assert_eq!(popped + cleared + rest, test_num as usize);
+}
+
+// Clearing at the same time as pushes and pops keeps the structure intact, and popped, cleared
+// and remaining elements add up to the pushed ones.
+#[test]
+fn test_stack() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 20000;
+    let mut stack = lockfree_stack::LockFreeStack::<i64>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let stack_ptr = ShardPtr::new(&mut *stack as *mut lockfree_stack::LockFreeStack<i64>);
+    let producer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut stack_ptr = stack_ptr;
+            let stack = stack_ptr.as_mut();
+            for i in 0..test_num {
+                stack.push(i);
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    let consumer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut stack_ptr = stack_ptr;
+            let stack = stack_ptr.as_mut();
+            let mut popped = 0;
+            while !done.load(Ordering::SeqCst) {
+                match stack.pop() {
+                    Some(_) => popped += 1,
+                    None => thread::yield_now(),
+                }
+            }
+            popped
+        })
+    };
+    let mut cleared = 0;
+    while !done.load(Ordering::SeqCst) {
+        cleared += stack.clear();
+        thread::yield_now();
+    }
+    producer.join().unwrap();
+    let popped = consumer.join().unwrap();
+    let mut rest = 0;
+    while stack.pop().is_some() {
+        rest += 1;
+    }
+    assert_eq!(popped + cleared + rest, test_num as usize);
+}
+
+struct PanicOnDrop(bool);
+
+impl Drop for PanicOnDrop {
+    fn drop(&mut self) {
+        assert!(!self.0, "drop panics");
+    }
+}
+
+// A panicking destructor stops clearing, but the handle is released, or every later operation of
+// current thread would fail.
+#[test]
+fn test_panic_on_drop() {
+    use std::panic::{self, AssertUnwindSafe};
+    let mut queue = lockfree_queue::LockFreeQueue::default_new_in_heap();
+    let mut stack = lockfree_stack::LockFreeStack::default_new_in_heap();
+    for i in 0..3 {
+        queue.push(PanicOnDrop(1 == i));
+        stack.push(PanicOnDrop(1 == i));
+    }
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| queue.clear())).is_err());
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| stack.clear())).is_err());
+    assert!(queue.try_push(PanicOnDrop(false)).is_ok());
+    assert!(stack.try_push(PanicOnDrop(false)).is_ok());
+    assert_eq!(queue.try_clear().unwrap(), 1);
+    assert_eq!(stack.try_clear().unwrap(), 1);
+}
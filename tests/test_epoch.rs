@@ -0,0 +1,129 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::epoch::{self, Atomic, Owned, Shared};
+use rs_lockfree::hazard_epoch::HazardEpoch;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+// Treiber stack written against the API of `crossbeam-epoch`, except `pin` taking a `HazardEpoch`.
+struct TreiberStack<T> {
+    head: Atomic<Node<T>>,
+}
+
+struct Node<T> {
+    data: ManuallyDrop<T>,
+    next: Atomic<Node<T>>,
+}
+
+impl<T> TreiberStack<T> {
+    fn new() -> TreiberStack<T> {
+        TreiberStack {
+            head: Atomic::null(),
+        }
+    }
+
+    fn push(&self, h: &mut HazardEpoch, t: T) {
+        let mut n = Owned::new(Node {
+            data: ManuallyDrop::new(t),
+            next: Atomic::null(),
+        });
+        let guard = epoch::pin(h);
+        loop {
+            let head = self.head.load(Ordering::Relaxed, &guard);
+            n.next.store(head, Ordering::Relaxed);
+            match self.head
+                .compare_exchange(head, n, Ordering::Release, Ordering::Relaxed, &guard)
+            {
+                Ok(_) => break,
+                Err(e) => n = e.new,
+            }
+        }
+    }
+
+    fn pop(&self, h: &mut HazardEpoch) -> Option<T> {
+        let guard = epoch::pin(h);
+        loop {
+            let head = self.head.load(Ordering::Acquire, &guard);
+            match unsafe { head.as_ref() } {
+                Some(h) => {
+                    let next = h.next.load(Ordering::Relaxed, &guard);
+                    if self.head
+                        .compare_exchange(head, next, Ordering::Relaxed, Ordering::Relaxed, &guard)
+                        .is_ok()
+                    {
+                        unsafe {
+                            guard.defer_destroy(head);
+                            return Some(ManuallyDrop::into_inner(ptr::read(&(*h).data)));
+                        }
+                    }
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+struct Counted(Arc<AtomicUsize>);
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_base() {
+    let mut h = HazardEpoch::default_new_in_heap();
+    let a = Atomic::new(1);
+    {
+        let guard = epoch::pin(&mut h);
+        let old = a.load(Ordering::SeqCst, &guard);
+        let new = Owned::new(2).into_shared(&guard);
+        let stored = a.compare_exchange(old, new, Ordering::SeqCst, Ordering::SeqCst, &guard);
+        assert_eq!(stored.unwrap(), new);
+        let e = a.compare_exchange(old, Owned::new(3), Ordering::SeqCst, Ordering::SeqCst, &guard)
+            .unwrap_err();
+        assert_eq!(e.current, new);
+        assert_eq!(*e.new, 3);
+        assert_eq!(e.new.into_inner(), 3);
+        unsafe { guard.defer_destroy(old) };
+        assert!(Shared::<i32>::null().is_null());
+        assert_eq!(unsafe { new.as_raw().as_ref() }, Some(&2));
+    }
+    assert_eq!(unsafe { a.into_owned().into_inner() }, 2);
+}
+
+// Each value is popped and dropped once, while nodes are left to `HazardEpoch`.
+#[test]
+fn test_treiber_stack() {
+    let test_num = 10000;
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let mut h = HazardEpoch::default_new_in_heap();
+    let stack = Arc::new(TreiberStack::new());
+    let addr = &mut *h as *mut HazardEpoch as usize;
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let stack = stack.clone();
+            let dropped = dropped.clone();
+            thread::spawn(move || {
+                let h = unsafe { &mut *(addr as *mut HazardEpoch) };
+                let mut popped = 0;
+                for _ in 0..test_num {
+                    stack.push(h, Counted(dropped.clone()));
+                    if stack.pop(h).is_some() {
+                        popped += 1;
+                    }
+                }
+                popped
+            })
+        })
+        .collect();
+    let popped: usize = workers.into_iter().map(|t| t.join().unwrap()).sum();
+    assert_eq!(popped, 4 * test_num);
+    assert!(stack.pop(&mut h).is_none());
+    assert_eq!(dropped.load(Ordering::SeqCst), 4 * test_num);
+    drop(h);
+}
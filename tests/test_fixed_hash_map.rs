@@ -0,0 +1,77 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::fixed_hash_map::FixedHashMap;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::thread;
+
+struct ShardPtr<T>(pub *mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+unsafe impl<T> Sync for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+impl<T> Copy for ShardPtr<T> {}
+
+impl<T> Clone for ShardPtr<T> {
+    fn clone(&self) -> Self {
+        ShardPtr(self.0)
+    }
+}
+
+impl<T> Deref for ShardPtr<T> {
+    type Target = *mut T;
+
+    fn deref(&self) -> &<Self as Deref>::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ShardPtr<T> {
+    fn deref_mut(&mut self) -> &mut <Self as Deref>::Target {
+        &mut self.0
+    }
+}
+
+#[test]
+fn test_multi_threads() {
+    let thread_count = 4;
+    let key_count = 50000_u64;
+
+    let mut map = FixedHashMap::with_capacity(key_count as usize);
+    let map_ptr = ShardPtr::new(&mut map as *mut FixedHashMap<u64, u64>);
+
+    let mut threads = vec![];
+    for t in 0..thread_count {
+        threads.push(thread::spawn(move || {
+            let mut map_ptr = map_ptr;
+            let map = map_ptr.as_mut();
+            for i in 0..key_count {
+                // all threads insert the same keys in different orders
+                let key = (i * (t + 1)) % key_count;
+                assert!(map.insert(key, key * 3));
+                assert_eq!(map.get(&key), Some(key * 3));
+            }
+        }));
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // duplicated slots of one key would be counted more than once
+    assert_eq!(map.len() as u64, key_count);
+    for i in 0..key_count {
+        assert_eq!(map.get(&i), Some(i * 3));
+    }
+}
@@ -0,0 +1,80 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::flat_combining::FlatCombining;
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::thread;
+
+struct ShardPtr<T>(pub *mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+unsafe impl<T> Sync for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+impl<T> Copy for ShardPtr<T> {}
+
+impl<T> Clone for ShardPtr<T> {
+    fn clone(&self) -> Self {
+        ShardPtr(self.0)
+    }
+}
+
+impl<T> Deref for ShardPtr<T> {
+    type Target = *mut T;
+
+    fn deref(&self) -> &<Self as Deref>::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ShardPtr<T> {
+    fn deref_mut(&mut self) -> &mut <Self as Deref>::Target {
+        &mut self.0
+    }
+}
+
+#[test]
+fn test_multi_threads() {
+    let thread_count = 4_usize;
+    let loop_cnt = 20000_usize;
+
+    let mut fc = FlatCombining::new_in_heap(VecDeque::<(usize, usize)>::new());
+    let fc_ptr = ShardPtr::new(&mut *fc as *mut FlatCombining<VecDeque<(usize, usize)>>);
+
+    let mut threads = vec![];
+    for t in 0..thread_count {
+        threads.push(thread::spawn(move || {
+            let mut fc_ptr = fc_ptr;
+            let fc = fc_ptr.as_mut();
+            for i in 0..loop_cnt {
+                fc.apply(|q| q.push_back((t, i)));
+                if i % 2 == 1 {
+                    assert!(fc.apply(|q| q.pop_front()).is_some());
+                }
+            }
+        }));
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let q = fc.into_inner();
+    assert_eq!(q.len(), thread_count * loop_cnt / 2);
+    let mut last: Vec<Option<usize>> = vec![None; thread_count];
+    for &(t, i) in q.iter() {
+        assert!(last[t].map_or(true, |l| l < i));
+        last[t] = Some(i);
+    }
+}
@@ -0,0 +1,123 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::gen_arena::{GenArena, Handle};
+use rs_lockfree::util;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+struct ShardPtr<T>(*mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+impl<T> Copy for ShardPtr<T> {}
+
+impl<T> Clone for ShardPtr<T> {
+    fn clone(&self) -> Self {
+        ShardPtr(self.0)
+    }
+}
+
+// Threads take thread ids of `HazardEpoch`, so tests have their own binary.
+#[test]
+fn test_base() {
+    let mut arena = GenArena::with_capacity(4);
+    assert_eq!(arena.capacity(), 4);
+    let handles: Vec<_> = (0..4).map(|i| arena.insert(i).unwrap()).collect();
+    assert_eq!(arena.insert(4).unwrap_err().value, 4);
+    for (i, h) in handles.iter().enumerate() {
+        assert_eq!(arena.get(*h, |v| *v), Some(i));
+    }
+    assert!(arena.remove(handles[1]));
+    assert!(!arena.remove(handles[1]));
+    assert!(!arena.contains(handles[1]));
+    let h = arena.insert(5).unwrap();
+    assert_eq!(h.index, handles[1].index);
+    assert_ne!(h.generation, handles[1].generation);
+    assert_eq!(arena.get(handles[1], |v| *v), None);
+    assert_eq!(arena.get(h, |v| *v), Some(5));
+    let invalid = Handle {
+        index: 100,
+        generation: 1,
+    };
+    assert!(!arena.contains(invalid));
+    assert!(!arena.remove(invalid));
+}
+
+#[test]
+fn test_drop() {
+    use std::rc::Rc;
+    let v = Rc::new(0);
+    {
+        let mut arena = GenArena::with_capacity(4);
+        let a = arena.insert(v.clone()).unwrap();
+        arena.insert(v.clone()).unwrap();
+        assert!(arena.remove(a));
+        assert_eq!(arena.get(a, |v| Rc::strong_count(v)), None);
+    }
+    assert_eq!(Rc::strong_count(&v), 1);
+}
+
+// A panicking `f` of `get` releases the handle, or every later operation of current thread would
+// fail.
+#[test]
+fn test_get_panic() {
+    use std::panic::{self, AssertUnwindSafe};
+    let mut arena = GenArena::with_capacity(4);
+    let h = arena.insert(1).unwrap();
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+        arena.get(h, |_| panic!("f panics"))
+    }));
+    assert!(ret.is_err());
+    assert_eq!(arena.try_get(h, |v| *v), Ok(Some(1)));
+    assert_eq!(arena.try_remove(h), Ok(true));
+}
+
+// Threads insert, read and remove objects of their own, and read handles of others, which
+// either find the object inserted with it or fail.
+#[test]
+fn test_multi_threads() {
+    let thread_num = 4;
+    let test_num = 10000;
+    let mut arena = GenArena::<(usize, usize)>::with_capacity(8);
+    let arena_ptr = ShardPtr(&mut *arena as *mut GenArena<(usize, usize)>);
+    let shared: &'static Vec<util::AtomicU64> = Box::leak(Box::new(
+        (0..thread_num)
+            .map(|_| util::AtomicU64::new(u64::max_value()))
+            .collect(),
+    ));
+    let threads: Vec<_> = (0..thread_num)
+        .map(|id| {
+            thread::spawn(move || {
+                let arena = unsafe { &mut *arena_ptr.0 };
+                for seq in 0..test_num {
+                    let h = loop {
+                        match arena.insert((id, seq)) {
+                            Ok(h) => break h,
+                            Err(_) => thread::yield_now(),
+                        }
+                    };
+                    let packed = u64::from(h.generation) << 32 | u64::from(h.index);
+                    shared[id].store(packed, Ordering::SeqCst);
+                    let other = shared[(id + 1) % thread_num].load(Ordering::SeqCst);
+                    if u64::max_value() != other {
+                        let other = Handle {
+                            index: other as u32,
+                            generation: (other >> 32) as u32,
+                        };
+                        if let Some((oid, _)) = arena.get(other, |v| *v) {
+                            assert_eq!(oid, (id + 1) % thread_num);
+                        }
+                    }
+                    assert_eq!(arena.get(h, |v| *v), Some((id, seq)));
+                    assert!(arena.remove(h));
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    for i in 0..8 {
+        assert!(arena.insert((0, i)).is_ok());
+    }
+}
@@ -12,7 +12,7 @@ use std::ops::DerefMut;
 use std::time;
 use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch, HazardNodeT};
 use rs_lockfree::util;
-use rs_lockfree::error::Status;
+use rs_lockfree::error::{HazardError, Status};
 use std::ptr;
 
 struct TestObj {
@@ -94,9 +94,7 @@ unsafe fn read_thread_func(mut global_conf: ShardPtr<GlobalConf>) {
     let global_conf = global_conf.as_mut();
     let checker = TestObj::new(&mut global_conf.cnt);
     for _ in 0..global_conf.read_loops {
-        let mut handle = 0u64;
-        let ret = global_conf.h.acquire(&mut handle);
-        assert_eq!(ret, Status::Success);
+        let handle = global_conf.h.try_acquire().unwrap();
         let v = util::atomic_load_raw_ptr(&global_conf.v);
         assert!(*v == checker);
         global_conf.h.release(handle);
@@ -117,7 +115,7 @@ unsafe fn write_thread_func(mut global_conf: ShardPtr<GlobalConf>) {
         } {
             old = curr;
         }
-        global_conf.h.add_node(old);
+        global_conf.h.try_add_node(old).unwrap();
     }
 }
 
@@ -243,18 +241,17 @@ fn test_multi_thread() {
     assert_eq!(0, global_conf.cnt);
 }
 
+// Reclaiming is deferred until `retire`, unlike single thread mode.
+#[cfg(not(feature = "single_thread"))]
 #[test]
 fn test_base() {
     unsafe {
         let mut he = Box::new(HazardEpoch::default_new_in_stack());
         let mut cnt = 0i64;
-        let mut handle = 0u64;
-        let ret = he.acquire(&mut handle);
-        assert_eq!(ret, Status::Success);
+        let handle = he.try_acquire().unwrap();
         for i in 0..64i64 {
             let tmp = Box::new(TestObj::new(&mut cnt));
-            let ret = he.add_node(Box::into_raw(tmp));
-            assert_eq!(Status::Success, ret);
+            assert!(he.try_add_node(Box::into_raw(tmp)).is_ok());
             assert_eq!(i + 1, cnt);
         }
         he.retire();
@@ -264,18 +261,17 @@ fn test_base() {
         assert_eq!(cnt, 0);
 
         for i in 0..32i64 {
-            assert_eq!(
-                he.add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt)))),
-                Status::Success
+            assert!(
+                he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+                    .is_ok()
             );
             assert_eq!(cnt, i + 1);
         }
 
-        assert_eq!(he.acquire(&mut handle), Status::Success);
+        let handle = he.try_acquire().unwrap();
         for i in 32..64i64 {
             let tmp = Box::new(TestObj::new(&mut cnt));
-            let ret = he.add_node(Box::into_raw(tmp));
-            assert_eq!(Status::Success, ret);
+            assert!(he.try_add_node(Box::into_raw(tmp)).is_ok());
             assert_eq!(i + 1, cnt);
         }
 
@@ -286,9 +282,123 @@ fn test_base() {
         assert_eq!(cnt, 0);
 
         for _ in 0..2i64 {
-            assert_eq!(he.acquire(&mut handle), Status::Success);
-            assert_eq!(he.acquire(&mut handle), Status::Busy);
+            let handle = he.try_acquire().unwrap();
+            match he.try_acquire() {
+                Err(HazardError::Busy { tid }) => assert_eq!(tid, util::get_thread_id()),
+                _ => panic!("second acquire should be busy"),
+            }
             he.release(handle);
         }
+        assert_eq!(
+            he.try_add_node(ptr::null_mut::<TestObj>()),
+            Err(HazardError::InvalidParam { op: "add_node" })
+        );
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_deprecated_status() {
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        let mut handle = 0u64;
+        assert_eq!(he.acquire(&mut handle), Status::Success);
+        assert_eq!(he.acquire(&mut handle), Status::Busy);
+        let tmp = Box::new(TestObj::new(&mut cnt));
+        assert_eq!(he.add_node(Box::into_raw(tmp)), Status::Success);
+        assert_eq!(he.add_node(ptr::null_mut::<TestObj>()), Status::InvalidParam);
+        he.release(handle);
+        he.retire();
+        assert_eq!(cnt, 0);
+    }
+}
+
+#[cfg(feature = "debug-assert-invariants")]
+#[test]
+fn test_verify() {
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        assert_eq!(he.verify(), Ok(()));
+        let handle = he.try_acquire().unwrap();
+        let addr_he = &mut *he as *mut HazardEpoch as usize;
+        let addr_cnt = &mut cnt as *mut i64 as usize;
+        thread::spawn(move || {
+            let he = &mut *(addr_he as *mut HazardEpoch);
+            for _ in 0..8 {
+                let tmp = Box::new(TestObj::new(&mut *(addr_cnt as *mut i64)));
+                he.try_add_node(Box::into_raw(tmp)).unwrap();
+            }
+            he.retire();
+        }).join()
+            .unwrap();
+        assert_eq!(cnt, 8);
+        assert_eq!(he.verify(), Ok(()));
+        he.release(handle);
+        he.retire();
+        assert_eq!(cnt, 0);
+        assert_eq!(he.verify(), Ok(()));
+    }
+}
+
+#[test]
+fn test_footprint() {
+    use rs_lockfree::hazard_epoch::MAX_THREAD_COUNT;
+
+    // Thread stores are allocated on registering, only pointers are held inline.
+    assert!(mem::size_of::<HazardEpoch>() <= 1024 + MAX_THREAD_COUNT * mem::size_of::<usize>());
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_stack();
+        let mut cnt = 0i64;
+        he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+            .unwrap();
+        drop(he);
+        assert_eq!(cnt, 0);
+    }
+}
+
+#[test]
+fn test_diagnostic_hook() {
+    use rs_lockfree::diagnostics::Diagnostic;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CNT: AtomicUsize = AtomicUsize::new(0);
+    fn count(diagnostic: &Diagnostic) {
+        assert_eq!(diagnostic.target, "rs_lockfree::hazard_epoch");
+        CNT.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let mut he = HazardEpoch::default_new_in_heap();
+    he.set_diagnostic_hook(Some(count));
+    let handle = he.try_acquire().unwrap();
+    assert_eq!(CNT.load(Ordering::SeqCst), 0);
+    assert!(he.try_acquire().is_err());
+    assert_eq!(CNT.load(Ordering::SeqCst), 1);
+    unsafe {
+        assert!(he.try_add_node(ptr::null_mut::<TestObj>()).is_err());
+        assert_eq!(CNT.load(Ordering::SeqCst), 2);
+        he.release(handle);
+    }
+}
+
+#[cfg(feature = "single_thread")]
+#[test]
+fn test_single_thread() {
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+            .unwrap();
+        assert_eq!(cnt, 0);
+        let handle = he.try_acquire().unwrap();
+        for _ in 0..4 {
+            he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+                .unwrap();
+        }
+        assert_eq!(cnt, 4);
+        he.release(handle);
+        assert_eq!(cnt, 0);
+        assert_eq!(he.atomic_load_hazard_waiting_count(), 0);
     }
 }
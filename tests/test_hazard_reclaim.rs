@@ -0,0 +1,406 @@
+#![cfg(not(feature = "single_thread"))]
+
+extern crate rs_lockfree;
+
+use rs_lockfree::error::HazardError;
+use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch, HazardNodeT};
+use rs_lockfree::util;
+use std::thread;
+
+struct TestObj {
+    base: BaseHazardNode,
+    cnt: *mut i64,
+}
+
+impl HazardNodeT for TestObj {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut BaseHazardNode
+    }
+}
+
+impl Drop for TestObj {
+    fn drop(&mut self) {
+        unsafe {
+            util::sync_fetch_and_add(self.cnt, -1);
+        }
+    }
+}
+
+impl TestObj {
+    fn new(cnt: &mut i64) -> TestObj {
+        unsafe {
+            util::sync_fetch_and_add(cnt, 1);
+        }
+        TestObj {
+            base: BaseHazardNode::default(),
+            cnt,
+        }
+    }
+}
+
+// Kept runs of nodes are moved as a whole, and still reclaimed when their handle is released.
+#[test]
+fn test_retire_runs() {
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        for _ in 0..8 {
+            let tmp = Box::new(TestObj::new(&mut cnt));
+            he.try_add_node(Box::into_raw(tmp)).unwrap();
+        }
+        let handle = he.try_acquire().unwrap();
+        for i in 1..5i64 {
+            for _ in 0..8 {
+                let tmp = Box::new(TestObj::new(&mut cnt));
+                he.try_add_node(Box::into_raw(tmp)).unwrap();
+            }
+            he.retire();
+            // Nodes added before acquiring share the version of the handle, so are kept too.
+            assert_eq!(cnt, 8 * i + 8);
+            assert_eq!(he.atomic_load_hazard_waiting_count(), cnt);
+        }
+        he.release(handle);
+        he.retire();
+        assert_eq!(cnt, 0);
+        assert_eq!(he.atomic_load_hazard_waiting_count(), 0);
+    }
+}
+
+// Interval to update minimum version cache shrinks while waiting objects grow, and recovers once
+// they are reclaimed.
+#[test]
+fn test_adaptive_cache_time() {
+    unsafe {
+        let mut he = HazardEpoch::new_in_heap(64, 64000);
+        let mut cnt = 0i64;
+        assert_eq!(he.min_version_cache_time_us(), 64000);
+        let handle = he.try_acquire().unwrap();
+        for _ in 0..8 {
+            let tmp = Box::new(TestObj::new(&mut cnt));
+            he.try_add_node(Box::into_raw(tmp)).unwrap();
+            he.retire();
+        }
+        assert_eq!(he.min_version_cache_time_us(), 1000);
+        he.release(handle);
+        for _ in 0..8 {
+            he.retire();
+        }
+        assert_eq!(cnt, 0);
+        assert_eq!(he.min_version_cache_time_us(), 64000);
+    }
+}
+
+// Handle held by another thread is seen through the handle count, and retries of acquiring
+// leave no count behind.
+#[test]
+fn test_handle_summary() {
+    use std::sync::mpsc;
+
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        let addr_he = &mut *he as *mut HazardEpoch as usize;
+        let (acquired_tx, acquired_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let t = thread::spawn(move || {
+            let he = &mut *(addr_he as *mut HazardEpoch);
+            let handle = he.try_acquire().unwrap();
+            acquired_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            he.release(handle);
+        });
+        acquired_rx.recv().unwrap();
+        let tmp = Box::new(TestObj::new(&mut cnt));
+        he.try_add_node(Box::into_raw(tmp)).unwrap();
+        he.retire();
+        assert_eq!(cnt, 1);
+        release_tx.send(()).unwrap();
+        t.join().unwrap();
+        he.retire();
+        assert_eq!(cnt, 0);
+
+        let handle = he.try_acquire().unwrap();
+        he.release(handle);
+        he.release(handle);
+        let tmp = Box::new(TestObj::new(&mut cnt));
+        he.try_add_node(Box::into_raw(tmp)).unwrap();
+        he.retire();
+        assert_eq!(cnt, 0);
+    }
+}
+
+// Objects are reclaimed on release while only one thread has registered, and deferred once
+// another one does.
+#[test]
+fn test_sole_thread() {
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        for _ in 0..2 {
+            let handle = he.try_acquire().unwrap();
+            for _ in 0..4 {
+                let tmp = Box::new(TestObj::new(&mut cnt));
+                he.try_add_node(Box::into_raw(tmp)).unwrap();
+            }
+            assert_eq!(cnt, 4);
+            he.release(handle);
+            assert_eq!(cnt, 0);
+            assert_eq!(he.atomic_load_hazard_waiting_count(), 0);
+        }
+
+        let addr_he = &mut *he as *mut HazardEpoch as usize;
+        thread::spawn(move || {
+            let he = &mut *(addr_he as *mut HazardEpoch);
+            let handle = he.try_acquire().unwrap();
+            he.release(handle);
+        }).join()
+            .unwrap();
+        let handle = he.try_acquire().unwrap();
+        let tmp = Box::new(TestObj::new(&mut cnt));
+        he.try_add_node(Box::into_raw(tmp)).unwrap();
+        he.release(handle);
+        assert_eq!(cnt, 1);
+        he.retire();
+        assert_eq!(cnt, 0);
+    }
+}
+
+// Reaching the pending limit reclaims synchronously, and rejects if a held handle stalls
+// reclaiming under `PendingPolicy::Reject`.
+#[test]
+fn test_pending_limit() {
+    use rs_lockfree::hazard_epoch::PendingPolicy;
+
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        he.set_pending_limit(Some(4), PendingPolicy::Reclaim);
+        for _ in 0..16 {
+            let tmp = Box::new(TestObj::new(&mut cnt));
+            he.try_add_node(Box::into_raw(tmp)).unwrap();
+            assert!(cnt < 4);
+        }
+
+        let handle = he.try_acquire().unwrap();
+        for _ in 0..8 {
+            let tmp = Box::new(TestObj::new(&mut cnt));
+            he.try_add_node(Box::into_raw(tmp)).unwrap();
+        }
+        assert!(he.check_pending().is_ok());
+        he.set_pending_limit(Some(4), PendingPolicy::Reject);
+        match he.check_pending() {
+            Err(HazardError::PendingLimit { pending, limit }) => {
+                assert_eq!(pending, cnt);
+                assert_eq!(limit, 4);
+            }
+            r => panic!("check_pending should reject, got {:?}", r),
+        }
+        he.release(handle);
+        assert!(he.check_pending().is_ok());
+        he.set_pending_limit(None, PendingPolicy::Reject);
+        he.retire();
+        assert_eq!(cnt, 0);
+    }
+}
+
+// Objects are reclaimed by the boundaries given to `flush_before`, and still protected by held
+// handles.
+#[test]
+fn test_flush_before() {
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        // Another registered thread turns off reclaiming on release of a sole thread.
+        let addr_he = &mut *he as *mut HazardEpoch as usize;
+        thread::spawn(move || {
+            let he = &mut *(addr_he as *mut HazardEpoch);
+            let handle = he.try_acquire().unwrap();
+            he.release(handle);
+        }).join()
+            .unwrap();
+
+        let handle = he.try_acquire().unwrap();
+        he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+            .unwrap();
+        let first = he.advance();
+        he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+            .unwrap();
+        assert_eq!(he.flush_before(first), 0);
+        he.release(handle);
+        assert_eq!(cnt, 2);
+        assert_eq!(he.flush_before(first), 1);
+        assert_eq!(cnt, 1);
+        let second = he.advance();
+        assert_eq!(he.flush_before(second), 1);
+        assert_eq!(cnt, 0);
+    }
+}
+
+// In frame mode, releasing never reclaims, and objects of a frame are reclaimed by the end of the
+// next one.
+#[test]
+fn test_frame_mode() {
+    unsafe {
+        let mut he = HazardEpoch::new_in_heap(2, 200000);
+        let mut cnt = 0i64;
+        he.set_frame_mode(true);
+        // Objects added and waiting in each frame, and reclaimed at its end.
+        for &(add, waiting, reclaimed) in [(8, 8, 0), (4, 12, 8), (0, 4, 4)].iter() {
+            let handle = he.try_acquire().unwrap();
+            for _ in 0..add {
+                he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+                    .unwrap();
+            }
+            he.release(handle);
+            assert_eq!(cnt, waiting);
+            assert_eq!(he.end_frame(), reclaimed);
+        }
+        assert_eq!(cnt, 0);
+    }
+}
+
+// The backlog of an idle thread is reclaimed by another one, and held objects are kept.
+#[test]
+fn test_flush_thread() {
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        let addr_he = &mut *he as *mut HazardEpoch as usize;
+        let addr_cnt = &mut cnt as *mut i64 as usize;
+        let tid = thread::spawn(move || {
+            let he = &mut *(addr_he as *mut HazardEpoch);
+            for _ in 0..8 {
+                let tmp = Box::new(TestObj::new(&mut *(addr_cnt as *mut i64)));
+                he.try_add_node(Box::into_raw(tmp)).unwrap();
+            }
+            util::get_thread_id() as u16
+        }).join()
+            .unwrap();
+        assert_eq!(cnt, 8);
+        assert_eq!(he.flush_thread(u16::max_value()), 0);
+
+        assert_eq!(he.flush_thread(tid), 8);
+        assert_eq!(cnt, 0);
+        let handle = he.try_acquire().unwrap();
+        he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+            .unwrap();
+        assert_eq!(he.flush_all(), 0);
+        assert_eq!(he.atomic_load_hazard_waiting_count(), 1);
+        he.release(handle);
+        assert_eq!(he.flush_all(), 1);
+        assert_eq!(cnt, 0);
+    }
+}
+
+// Waiting for reclamation returns once objects up to the version are reclaimed, and waits for
+// held handles, which the future polls again for.
+#[test]
+fn test_wait_until_reclaimed() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        let addr_he = &mut *he as *mut HazardEpoch as usize;
+        let addr_cnt = &mut cnt as *mut i64 as usize;
+        thread::spawn(move || {
+            let he = &mut *(addr_he as *mut HazardEpoch);
+            for _ in 0..8 {
+                let tmp = Box::new(TestObj::new(&mut *(addr_cnt as *mut i64)));
+                he.try_add_node(Box::into_raw(tmp)).unwrap();
+            }
+        }).join()
+            .unwrap();
+        let boundary = he.advance();
+        he.wait_until_reclaimed(boundary);
+        assert_eq!(cnt, 0);
+
+        let handle = he.try_acquire().unwrap();
+        he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+            .unwrap();
+        let boundary = he.advance();
+        let mut cx = Context::from_waker(Waker::noop());
+        {
+            let waiter = &mut *(addr_he as *mut HazardEpoch);
+            let mut reclaimed = waiter.wait_until_reclaimed_async(boundary);
+            for _ in 0..4 {
+                assert!(Pin::new(&mut reclaimed).poll(&mut cx).is_pending());
+            }
+            he.release(handle);
+            assert_eq!(Pin::new(&mut reclaimed).poll(&mut cx), Poll::Ready(()));
+        }
+        assert_eq!(cnt, 0);
+    }
+}
+
+struct ListNode {
+    obj: TestObj,
+    key: i64,
+    next: util::AtomicRawPtr<ListNode>,
+}
+
+impl HazardNodeT for ListNode {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        self.obj.get_base_hazard_node()
+    }
+}
+
+impl Drop for ListNode {
+    fn drop(&mut self) {}
+}
+
+// Objects found by a cursor stay readable while another thread unlinks and retires them.
+#[test]
+fn test_cursor() {
+    use std::sync::atomic::Ordering;
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        let head = util::AtomicRawPtr::default();
+        for key in (0..8).rev() {
+            let node = Box::new(ListNode {
+                obj: TestObj::new(&mut cnt),
+                key,
+                next: util::AtomicRawPtr::new(head.load(Ordering::SeqCst)),
+            });
+            head.store(Box::into_raw(node), Ordering::SeqCst);
+        }
+        let addr_he = &mut *he as *mut HazardEpoch as usize;
+        let addr_head = &head as *const util::AtomicRawPtr<ListNode> as usize;
+        {
+            let cursor = he.cursor().unwrap();
+            let (_, node) = cursor.find(&head, |n| &n.next, |n| n.key == 3);
+            let node = node.unwrap();
+            thread::spawn(move || {
+                let he = &mut *(addr_he as *mut HazardEpoch);
+                let head = &*(addr_head as *const util::AtomicRawPtr<ListNode>);
+                let cursor = he.cursor().unwrap();
+                let (link, node) = cursor.find(head, |n| &n.next, |n| n.key >= 3);
+                let node = node.unwrap() as *const ListNode as *mut ListNode;
+                let next = (*node).next.load(Ordering::SeqCst);
+                assert!(link.cas(node, next, Ordering::SeqCst).1);
+                cursor.retire(node).unwrap();
+            }).join()
+                .unwrap();
+            let flusher = &mut *(addr_he as *mut HazardEpoch);
+            flusher.flush_all();
+            assert_eq!(cnt, 8);
+            assert_eq!(node.key, 3);
+            assert_eq!(cursor.find(&head, |n| &n.next, |n| n.key >= 3).1.unwrap().key, 4);
+            let (link, node) = cursor.find(&head, |n| &n.next, |n| n.key > 7);
+            assert!(node.is_none());
+            assert!(link.load(Ordering::SeqCst).is_null());
+        }
+        he.flush_all();
+        assert_eq!(cnt, 7);
+        let mut node = head.load(Ordering::SeqCst);
+        while !node.is_null() {
+            let next = (*node).next.load(Ordering::SeqCst);
+            he.try_add_node(node).unwrap();
+            node = next;
+        }
+        he.flush_all();
+        assert_eq!(cnt, 0);
+    }
+}
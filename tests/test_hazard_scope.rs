@@ -0,0 +1,119 @@
+#![cfg(not(feature = "single_thread"))]
+
+extern crate rs_lockfree;
+
+use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch, HazardNodeT};
+use rs_lockfree::util;
+use std::thread;
+
+struct TestObj {
+    base: BaseHazardNode,
+    cnt: *mut i64,
+}
+
+impl HazardNodeT for TestObj {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut BaseHazardNode
+    }
+}
+
+impl Drop for TestObj {
+    fn drop(&mut self) {
+        unsafe {
+            util::sync_fetch_and_add(self.cnt, -1);
+        }
+    }
+}
+
+impl TestObj {
+    fn new(cnt: &mut i64) -> TestObj {
+        unsafe {
+            util::sync_fetch_and_add(cnt, 1);
+        }
+        TestObj {
+            base: BaseHazardNode::default(),
+            cnt,
+        }
+    }
+}
+
+// A handle left held by a panicking worker is released by its scope, but not without one.
+#[test]
+fn test_panic() {
+    unsafe {
+        for &scoped in [true, false].iter() {
+            let mut he = HazardEpoch::default_new_in_heap();
+            let mut cnt = 0i64;
+            let addr_he = &mut *he as *mut HazardEpoch as usize;
+            let ret = thread::spawn(move || {
+                let he = &mut *(addr_he as *mut HazardEpoch);
+                let access = |he: &mut HazardEpoch| {
+                    let _handle = he.try_acquire().unwrap();
+                    panic!("not released");
+                };
+                if scoped {
+                    he.scope(access).unwrap();
+                } else {
+                    access(he);
+                }
+            }).join();
+            assert!(ret.is_err());
+            he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+                .unwrap();
+            if scoped {
+                assert_eq!(he.flush_all(), 1);
+                assert_eq!(cnt, 0);
+            } else {
+                assert_eq!(he.flush_all(), 0);
+                assert_eq!(cnt, 1);
+                // The leaked handle blocks reclamation until `HazardEpoch` is dropped.
+                drop(he);
+                assert_eq!(cnt, 0);
+            }
+        }
+    }
+}
+
+// Objects added by a worker are reclaimed when its scope exits, instead of waiting in its store.
+#[test]
+fn test_flush_on_exit() {
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        let addr_he = &mut *he as *mut HazardEpoch as usize;
+        let addr_cnt = &mut cnt as *mut i64 as usize;
+        let added = thread::spawn(move || {
+            let he = &mut *(addr_he as *mut HazardEpoch);
+            he.scope(|he| {
+                for _ in 0..8 {
+                    let tmp = Box::new(TestObj::new(&mut *(addr_cnt as *mut i64)));
+                    he.try_add_node(Box::into_raw(tmp)).unwrap();
+                }
+                8
+            }).unwrap()
+        }).join()
+            .unwrap();
+        assert_eq!(added, 8);
+        assert_eq!(cnt, 0);
+        assert_eq!(he.atomic_load_hazard_waiting_count(), 0);
+    }
+}
+
+// A handle held on entry is left to its owner.
+#[test]
+fn test_held_on_entry() {
+    unsafe {
+        let mut he = HazardEpoch::default_new_in_heap();
+        let mut cnt = 0i64;
+        let handle = he.try_acquire().unwrap();
+        he.scope(|he| {
+            he.try_add_node(Box::into_raw(Box::new(TestObj::new(&mut cnt))))
+                .unwrap();
+        }).unwrap();
+        assert_eq!(cnt, 1);
+        assert_eq!(he.flush_all(), 0);
+        he.release(handle);
+        he.flush_all();
+        assert_eq!(cnt, 0);
+    }
+}
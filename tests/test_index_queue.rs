@@ -0,0 +1,66 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::index_queue::IndexQueue;
+use std::thread;
+
+// Few slots for many elements, so slots are recycled all the time. Spinning threads take thread
+// ids, so it has its own test binary.
+#[test]
+fn test_mpmc() {
+    let producer_num = 2;
+    let consumer_num = 2;
+    let test_num = 20000;
+    let queue: &'static IndexQueue<(usize, usize)> =
+        Box::leak(Box::new(IndexQueue::with_capacity(4)));
+    let producers: Vec<_> = (0..producer_num)
+        .map(|id| {
+            thread::spawn(move || {
+                for seq in 0..test_num {
+                    let mut v = (id, seq);
+                    loop {
+                        match queue.try_push(v) {
+                            Ok(()) => break,
+                            Err(e) => {
+                                v = e.value;
+                                thread::yield_now();
+                            }
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    let consumers: Vec<_> = (0..consumer_num)
+        .map(|_| {
+            thread::spawn(move || {
+                // Elements of each producer are popped in order by each consumer.
+                let mut last = vec![None; producer_num];
+                let mut cnt = 0;
+                let mut idle = 0;
+                while idle < 1000 {
+                    match queue.pop() {
+                        Some((id, seq)) => {
+                            assert!(last[id].map_or(true, |l| l < seq));
+                            last[id] = Some(seq);
+                            cnt += 1;
+                            idle = 0;
+                        }
+                        None => {
+                            idle += 1;
+                            thread::yield_now();
+                        }
+                    }
+                }
+                cnt
+            })
+        })
+        .collect();
+    for t in producers {
+        t.join().unwrap();
+    }
+    let mut cnt: usize = consumers.into_iter().map(|t| t.join().unwrap()).sum();
+    while queue.pop().is_some() {
+        cnt += 1;
+    }
+    assert_eq!(cnt, producer_num * test_num);
+}
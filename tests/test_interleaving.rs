@@ -0,0 +1,125 @@
+#![cfg(feature = "test-hooks")]
+
+extern crate rs_lockfree;
+
+use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch, HazardNodeT};
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use rs_lockfree::test_hooks;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+struct TestObj {
+    base: BaseHazardNode,
+}
+
+impl HazardNodeT for TestObj {
+    fn get_base_hazard_node(&self) -> *mut BaseHazardNode {
+        &self.base as *const _ as *mut BaseHazardNode
+    }
+}
+
+impl Drop for TestObj {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn new_obj() -> *mut TestObj {
+    Box::into_raw(Box::new(TestObj {
+        base: BaseHazardNode::default(),
+    }))
+}
+
+#[test]
+fn test_queue_tail_link() {
+    let _guard = test_hooks::exclusive();
+    let mut queue = LockFreeQueue::default_new_in_heap();
+    let p = &mut *queue as *mut LockFreeQueue<i32> as usize;
+    test_hooks::pause(test_hooks::QUEUE_TAIL_LINK);
+    let t = thread::spawn(move || unsafe { (*(p as *mut LockFreeQueue<i32>)).push(1) });
+    test_hooks::wait_paused(test_hooks::QUEUE_TAIL_LINK);
+
+    // Node of 1 owns the tail, but the old tail doesn't link to it yet.
+    assert_eq!(queue.pop(), None);
+    queue.push(2);
+    assert_eq!(test_hooks::hits(test_hooks::QUEUE_TAIL_LINK), 2);
+    assert_eq!(queue.pop(), None);
+
+    test_hooks::resume(test_hooks::QUEUE_TAIL_LINK);
+    t.join().unwrap();
+    assert_eq!(queue.pop(), Some(1));
+    assert_eq!(queue.pop(), Some(2));
+    assert_eq!(queue.pop(), None);
+    test_hooks::reset();
+}
+
+#[test]
+fn test_queue_tail_link_delay() {
+    let _guard = test_hooks::exclusive();
+    let test_num = 100;
+    let mut queue = LockFreeQueue::default_new_in_heap();
+    let p = &mut *queue as *mut LockFreeQueue<i32> as usize;
+    test_hooks::set_delay(test_hooks::QUEUE_TAIL_LINK, Some(Duration::from_millis(1)));
+    let producers: Vec<_> = (0..2)
+        .map(|id| {
+            thread::spawn(move || {
+                for i in 0..test_num {
+                    unsafe { (*(p as *mut LockFreeQueue<i32>)).push(id * test_num + i) };
+                }
+            })
+        })
+        .collect();
+
+    // Elements of each producer are popped in order, even if some are pushed in the window.
+    let mut last = [-1, -1];
+    let mut cnt = 0;
+    while cnt < 2 * test_num {
+        match queue.pop() {
+            Some(v) => {
+                let id = (v / test_num) as usize;
+                assert!(last[id] < v);
+                last[id] = v;
+                cnt += 1;
+            }
+            None => thread::yield_now(),
+        }
+    }
+    for t in producers {
+        t.join().unwrap();
+    }
+    assert_eq!(last, [test_num - 1, 2 * test_num - 1]);
+    test_hooks::reset();
+}
+
+#[test]
+fn test_retire_swap() {
+    let _guard = test_hooks::exclusive();
+    let mut he = HazardEpoch::default_new_in_heap();
+    let p = &mut *he as *mut HazardEpoch as usize;
+    let dropped = DROPPED.load(Ordering::SeqCst);
+    test_hooks::pause(test_hooks::RETIRE_SWAP);
+    let t = thread::spawn(move || unsafe {
+        let he = &mut *(p as *mut HazardEpoch);
+        he.try_add_node(new_obj()).unwrap();
+        he.retire();
+    });
+    test_hooks::wait_paused(test_hooks::RETIRE_SWAP);
+
+    // The list of the paused thread is swapped out, so reclaiming it again finds nothing, and
+    // its node is still counted as waiting.
+    unsafe {
+        he.try_add_node(new_obj()).unwrap();
+        he.retire();
+    }
+    assert_eq!(DROPPED.load(Ordering::SeqCst), dropped + 1);
+    assert_eq!(he.atomic_load_hazard_waiting_count(), 1);
+
+    test_hooks::resume(test_hooks::RETIRE_SWAP);
+    t.join().unwrap();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), dropped + 2);
+    assert_eq!(he.atomic_load_hazard_waiting_count(), 0);
+    test_hooks::reset();
+}
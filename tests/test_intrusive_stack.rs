@@ -0,0 +1,62 @@
+#![cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+
+extern crate rs_lockfree;
+
+use rs_lockfree::intrusive_stack::{IntrusiveNodeT, IntrusiveStack, Link};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+struct Node {
+    link: Link<Node>,
+    users: AtomicUsize,
+}
+
+impl IntrusiveNodeT for Node {
+    fn get_link(&self) -> &Link<Node> {
+        &self.link
+    }
+}
+
+// Threads take nodes from a shared free list and put them back right away, which is the pattern
+// leading to `ABA problem`. A node is never held by two threads at once. Spinning threads take
+// thread ids, so it has its own test binary.
+#[test]
+fn test_recycle() {
+    let node_num = 4;
+    let nodes: &'static Vec<Node> = Box::leak(Box::new(
+        (0..node_num)
+            .map(|_| Node {
+                link: Link::new(),
+                users: AtomicUsize::new(0),
+            })
+            .collect(),
+    ));
+    let stack: &'static IntrusiveStack<Node> = Box::leak(Box::new(IntrusiveStack::new()));
+    for node in nodes.iter() {
+        stack.push(node);
+    }
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            thread::spawn(move || {
+                for i in 0..10000 {
+                    if let Some(node) = stack.pop() {
+                        assert_eq!(node.users.fetch_add(1, Ordering::SeqCst), 0);
+                        if 0 == i % 7 {
+                            thread::yield_now();
+                        }
+                        node.users.fetch_sub(1, Ordering::SeqCst);
+                        stack.push(node);
+                    }
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let mut cnt = 0;
+    while stack.pop().is_some() {
+        cnt += 1;
+    }
+    assert_eq!(cnt, node_num);
+}
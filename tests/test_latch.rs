@@ -0,0 +1,66 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::latch::Latch;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// Workers finish a phase each, and the coordinator sees all their writes once the latch of the
+// phase is released.
+#[test]
+fn test_phases() {
+    let worker_count = 4;
+    let phase_count = 50;
+    let done = Arc::new(AtomicUsize::new(0));
+    let latches: Arc<Vec<Latch>> = Arc::new(
+        (0..phase_count)
+            .map(|_| Latch::new(worker_count))
+            .collect(),
+    );
+    let workers: Vec<_> = (0..worker_count)
+        .map(|_| {
+            let (done, latches) = (done.clone(), latches.clone());
+            thread::spawn(move || {
+                for latch in latches.iter() {
+                    done.fetch_add(1, Ordering::Relaxed);
+                    latch.count_down();
+                }
+            })
+        })
+        .collect();
+    for (phase, latch) in latches.iter().enumerate() {
+        latch.wait();
+        assert!(done.load(Ordering::Relaxed) >= (phase + 1) * worker_count);
+    }
+    for t in workers {
+        t.join().unwrap();
+    }
+}
+
+// Waiters outlast spinning and park, then all of them are woken by the last count down.
+#[test]
+fn test_parked_waiters() {
+    let latch = Arc::new(Latch::new(2));
+    let woken = Arc::new(AtomicUsize::new(0));
+    let waiters: Vec<_> = (0..3)
+        .map(|_| {
+            let (latch, woken) = (latch.clone(), woken.clone());
+            thread::spawn(move || {
+                latch.wait();
+                woken.fetch_add(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(50));
+    latch.count_down();
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(woken.load(Ordering::SeqCst), 0);
+    latch.count_down();
+    for t in waiters {
+        t.join().unwrap();
+    }
+    assert_eq!(woken.load(Ordering::SeqCst), 3);
+    // Released, so later waits return at once.
+    latch.wait();
+}
@@ -0,0 +1,168 @@
+extern crate rs_lockfree;
+
+mod linearizability;
+
+use linearizability::{check, Clock, Event, Fifo, Lifo, Op, Spec};
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use rs_lockfree::lockfree_stack::LockFreeStack;
+use std::collections::VecDeque;
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+
+const THREAD_COUNT: usize = 3;
+const OP_COUNT: usize = 6;
+const ROUND_COUNT: usize = 1000;
+
+/// Container under test, which is shared by raw pointer like other tests of this crate.
+trait Container: 'static {
+    fn push(&mut self, v: usize);
+    fn pop(&mut self) -> Option<usize>;
+}
+
+impl Container for LockFreeQueue<usize> {
+    fn push(&mut self, v: usize) {
+        LockFreeQueue::push(self, v)
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        LockFreeQueue::pop(self)
+    }
+}
+
+impl Container for LockFreeStack<usize> {
+    fn push(&mut self, v: usize) {
+        LockFreeStack::push(self, v)
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        LockFreeStack::pop(self)
+    }
+}
+
+fn xorshift(seed: &mut u64) -> u64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed
+}
+
+/// Run `ROUND_COUNT` rounds of random operations on `container` by `THREAD_COUNT` threads, and
+/// check the history of each round from empty state `init`, without pops returning nothing if
+/// `skip_empty`. Threads are reused by all rounds, because thread ids of `HazardEpoch` are never
+/// reused.
+fn run<C, S>(container: Box<C>, init: S, skip_empty: bool)
+where
+    C: Container,
+    S: Spec<usize>,
+{
+    let addr = Box::into_raw(container) as usize;
+    let barrier = Arc::new(Barrier::new(THREAD_COUNT + 1));
+    let logs = Arc::new(Mutex::new(vec![Vec::new(); THREAD_COUNT]));
+    let clock = Arc::new(Clock::new());
+    let threads: Vec<_> = (0..THREAD_COUNT)
+        .map(|id| {
+            let barrier = barrier.clone();
+            let logs = logs.clone();
+            let clock = clock.clone();
+            thread::spawn(move || {
+                let c = unsafe { &mut *(addr as *mut C) };
+                let mut seed = id as u64 + 1;
+                for round in 0..ROUND_COUNT {
+                    let mut log = Vec::with_capacity(OP_COUNT);
+                    barrier.wait();
+                    for i in 0..OP_COUNT {
+                        if 0 == xorshift(&mut seed) % 2 {
+                            let v = (round * THREAD_COUNT + id) * OP_COUNT + i;
+                            clock.call(&mut log, || {
+                                c.push(v);
+                                Op::Push(v)
+                            });
+                        } else {
+                            clock.call(&mut log, || Op::Pop(c.pop()));
+                        }
+                        if 0 == xorshift(&mut seed) % 3 {
+                            thread::yield_now();
+                        }
+                    }
+                    logs.lock().unwrap()[id] = log;
+                    barrier.wait();
+                    barrier.wait();
+                }
+            })
+        })
+        .collect();
+    let c = unsafe { &mut *(addr as *mut C) };
+    for round in 0..ROUND_COUNT {
+        barrier.wait();
+        barrier.wait();
+        let history: Vec<Event<usize>> = logs.lock()
+            .unwrap()
+            .iter_mut()
+            .flat_map(|log| log.drain(..))
+            .filter(|e| !skip_empty || Op::Pop(None) != e.op)
+            .collect();
+        assert!(
+            check(&history, init.clone()),
+            "round {} is not linearizable: {:?}",
+            round,
+            history
+        );
+        while c.pop().is_some() {}
+        barrier.wait();
+    }
+    for t in threads {
+        t.join().unwrap();
+    }
+    drop(unsafe { Box::from_raw(addr as *mut C) });
+}
+
+#[test]
+fn test_queue() {
+    // Pop returns nothing while the head push is in the tail-link window, even if pushes after
+    // it have completed, see `test_queue_tail_link` of `tests/test_interleaving.rs`. So only the
+    // order of elements is checked.
+    run(LockFreeQueue::default_new_in_heap(), Fifo(VecDeque::new()), true);
+}
+
+#[test]
+fn test_stack() {
+    run(LockFreeStack::default_new_in_heap(), Lifo(Vec::new()), false);
+}
+
+#[test]
+fn test_check() {
+    fn event(op: Op<usize>, invoke: u64, response: u64) -> Event<usize> {
+        Event {
+            op,
+            invoke,
+            response,
+        }
+    }
+
+    // Push of 1 responds before push of 2 is invoked, so pop must return 1 first.
+    let sequential = vec![
+        event(Op::Push(1), 0, 1),
+        event(Op::Push(2), 2, 3),
+        event(Op::Pop(Some(2)), 4, 5),
+    ];
+    assert!(!check(&sequential, Fifo(VecDeque::new())));
+    assert!(check(&sequential, Lifo(Vec::new())));
+
+    // Pushes overlap, so either order is allowed.
+    let overlapped = vec![
+        event(Op::Push(1), 0, 3),
+        event(Op::Push(2), 1, 2),
+        event(Op::Pop(Some(2)), 4, 5),
+        event(Op::Pop(Some(1)), 6, 7),
+    ];
+    assert!(check(&overlapped, Fifo(VecDeque::new())));
+    assert!(check(&overlapped, Lifo(Vec::new())));
+
+    // Pop returns nothing while an element is surely in the queue.
+    let lost = vec![
+        event(Op::Push(1), 0, 1),
+        event(Op::Pop(None), 2, 3),
+        event(Op::Pop(Some(1)), 4, 5),
+    ];
+    assert!(!check(&lost, Fifo(VecDeque::new())));
+}
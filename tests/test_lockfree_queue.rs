@@ -207,3 +207,191 @@ fn test_multi_threads() {
 
     assert_eq!(produced, consumed);
 }
+
+// Elements are either popped or stolen exactly once, and stolen runs keep their order.
+#[test]
+fn test_split_off() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 10000;
+    let mut queue = lockfree_queue::LockFreeQueue::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let queue_ptr = ShardPtr::new(&mut *queue as *mut lockfree_queue::LockFreeQueue<i64>);
+    let producer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut queue_ptr = queue_ptr;
+            let queue = queue_ptr.as_mut();
+            for i in 0..test_num {
+                queue.push(i);
+                if 0 == i % 64 {
+                    thread::yield_now();
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    let consumer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut queue_ptr = queue_ptr;
+            let queue = queue_ptr.as_mut();
+            let mut popped = vec![];
+            loop {
+                let finished = done.load(Ordering::SeqCst);
+                match queue.pop() {
+                    Some(v) => popped.push(v),
+                    None if finished => break,
+                    None => thread::yield_now(),
+                }
+            }
+            popped
+        })
+    };
+    let mut stolen = vec![];
+    for round in 0.. {
+        let finished = done.load(Ordering::SeqCst);
+        let mut part = if 0 == round % 2 {
+            queue.steal_half()
+        } else {
+            queue.split_off(7)
+        };
+        let mut run = vec![];
+        while let Some(v) = part.pop() {
+            run.push(v);
+        }
+        assert!(run.windows(2).all(|w| w[0] < w[1]));
+        stolen.extend(run);
+        if finished {
+            break;
+        }
+        thread::yield_now();
+    }
+    producer.join().unwrap();
+    let mut all = consumer.join().unwrap();
+    assert!(queue.pop().is_none());
+    all.extend(stolen);
+    all.sort();
+    assert_eq!(all, (0..test_num).collect::<Vec<_>>());
+}
+
+// Elements are removed while others are pushed and popped, and none is both removed and popped.
+#[test]
+fn test_retain() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 10000;
+    let mut queue = lockfree_queue::LockFreeQueue::<i64>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let queue_ptr = ShardPtr::new(&mut *queue as *mut lockfree_queue::LockFreeQueue<i64>);
+    let producer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut queue_ptr = queue_ptr;
+            let queue = queue_ptr.as_mut();
+            for i in 0..test_num {
+                queue.push(i);
+                if 0 == i % 64 {
+                    thread::yield_now();
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    let consumer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut queue_ptr = queue_ptr;
+            let queue = queue_ptr.as_mut();
+            let mut popped = vec![];
+            loop {
+                let finished = done.load(Ordering::SeqCst);
+                match queue.pop() {
+                    Some(v) => popped.push(v),
+                    None if finished => break,
+                    None => thread::yield_now(),
+                }
+            }
+            popped
+        })
+    };
+    let mut removed = 0;
+    while !done.load(Ordering::SeqCst) {
+        removed += queue.retain(|v| 0 != v % 3);
+        thread::yield_now();
+    }
+    producer.join().unwrap();
+    let popped = consumer.join().unwrap();
+    assert!(popped.windows(2).all(|w| w[0] < w[1]));
+    // Only multiples of 3 are removed.
+    let kept: Vec<_> = popped.iter().cloned().filter(|v| 0 != v % 3).collect();
+    assert_eq!(kept, (0..test_num).filter(|v| 0 != v % 3).collect::<Vec<_>>());
+    assert_eq!(popped.len() + removed, test_num as usize);
+    assert!(queue.pop().is_none());
+}
+
+#[test]
+fn test_debug_format() {
+    let mut queue = lockfree_queue::LockFreeQueue::default_new_in_heap();
+    assert_eq!(format!("{:?}", queue), "LockFreeQueue { len: 0, elements: [] }");
+    for i in 0..20 {
+        queue.push(i);
+    }
+    // Removed elements are neither counted nor shown.
+    queue.retain(|v| 1 != *v);
+    assert_eq!(queue.pop(), Some(0));
+    assert_eq!(
+        format!("{:?}", queue),
+        "LockFreeQueue { len: 18, elements: [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, \
+         17, ..] }"
+    );
+    queue.pop();
+    queue.pop();
+    assert_eq!(
+        format!("{:?}", queue),
+        "LockFreeQueue { len: 16, elements: [4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, \
+         19] }"
+    );
+}
+
+// Formatting walks elements while others are pushed and popped.
+#[test]
+fn test_debug() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 10000;
+    let mut queue = lockfree_queue::LockFreeQueue::<i64>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let queue_ptr = ShardPtr::new(&mut *queue as *mut lockfree_queue::LockFreeQueue<i64>);
+    let worker = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut queue_ptr = queue_ptr;
+            let queue = queue_ptr.as_mut();
+            for i in 0..test_num {
+                queue.push(i);
+                if 0 == i % 3 {
+                    queue.pop();
+                }
+                if 0 == i % 64 {
+                    thread::yield_now();
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    while !done.load(Ordering::SeqCst) {
+        let s = format!("{:?}", queue);
+        assert!(s.starts_with("LockFreeQueue { len: "));
+        let elements = &s[s.find('[').unwrap() + 1..s.find(']').unwrap()];
+        let values: Vec<i64> = elements
+            .split(", ")
+            .filter(|v| !v.is_empty() && ".." != *v)
+            .map(|v| v.parse().unwrap())
+            .collect();
+        assert!(values.len() <= 16);
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+        thread::yield_now();
+    }
+    worker.join().unwrap();
+}
@@ -207,3 +207,68 @@ fn test_multi_threads() {
 
     assert_eq!(produced, consumed);
 }
+
+#[test]
+fn test_debug_format() {
+    let mut stack = lockfree_stack::LockFreeStack::default_new_in_heap();
+    assert_eq!(format!("{:?}", stack), "LockFreeStack { len: 0, elements: [] }");
+    for i in 0..20 {
+        stack.push(i);
+    }
+    assert_eq!(stack.pop(), Some(19));
+    assert_eq!(
+        format!("{:?}", stack),
+        "LockFreeStack { len: 19, elements: [18, 17, 16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, \
+         3, ..] }"
+    );
+    stack.pop();
+    stack.pop();
+    stack.pop();
+    assert_eq!(
+        format!("{:?}", stack),
+        "LockFreeStack { len: 16, elements: [15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, \
+         0] }"
+    );
+}
+
+// Formatting walks elements while others are pushed and popped.
+#[test]
+fn test_debug() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 10000;
+    let mut stack = lockfree_stack::LockFreeStack::<i64>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let stack_ptr = ShardPtr::new(&mut *stack as *mut lockfree_stack::LockFreeStack<i64>);
+    let worker = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut stack_ptr = stack_ptr;
+            let stack = stack_ptr.as_mut();
+            for i in 0..test_num {
+                stack.push(i);
+                if 0 == i % 3 {
+                    stack.pop();
+                }
+                if 0 == i % 64 {
+                    thread::yield_now();
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    while !done.load(Ordering::SeqCst) {
+        let s = format!("{:?}", stack);
+        assert!(s.starts_with("LockFreeStack { len: "));
+        let elements = &s[s.find('[').unwrap() + 1..s.find(']').unwrap()];
+        let values: Vec<i64> = elements
+            .split(", ")
+            .filter(|v| !v.is_empty() && ".." != *v)
+            .map(|v| v.parse().unwrap())
+            .collect();
+        assert!(values.len() <= 16);
+        assert!(values.windows(2).all(|w| w[0] > w[1]));
+        thread::yield_now();
+    }
+    worker.join().unwrap();
+}
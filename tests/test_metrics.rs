@@ -0,0 +1,69 @@
+#![cfg(feature = "metrics")]
+
+extern crate metrics;
+extern crate metrics_util;
+extern crate rs_lockfree;
+
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use metrics_util::{CompositeKey, MetricKind};
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use rs_lockfree::lockfree_stack::LockFreeStack;
+use std::collections::HashMap;
+
+fn snapshot(recorder: &DebuggingRecorder) -> HashMap<(MetricKind, String), DebugValue> {
+    recorder
+        .snapshotter()
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value): (CompositeKey, _, _, _)| {
+            ((key.kind(), key.key().name().to_string()), value)
+        })
+        .collect()
+}
+
+fn gauge(values: &HashMap<(MetricKind, String), DebugValue>, name: &str) -> f64 {
+    match values[&(MetricKind::Gauge, name.to_string())] {
+        DebugValue::Gauge(v) => v.into_inner(),
+        _ => unreachable!(),
+    }
+}
+
+fn counter(values: &HashMap<(MetricKind, String), DebugValue>, name: &str) -> u64 {
+    match values[&(MetricKind::Counter, name.to_string())] {
+        DebugValue::Counter(v) => v,
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_base() {
+    let recorder = DebuggingRecorder::new();
+    metrics::with_local_recorder(&recorder, || {
+        let mut queue = LockFreeQueue::default_new_in_heap();
+        let mut stack = LockFreeStack::default_new_in_heap();
+        for i in 0..10 {
+            queue.push(i);
+            stack.push(i);
+        }
+        for _ in 0..4 {
+            queue.pop().unwrap();
+            stack.pop().unwrap();
+        }
+        let values = snapshot(&recorder);
+        assert_eq!(gauge(&values, "rs_lockfree_queue_depth"), 6.0);
+        assert_eq!(gauge(&values, "rs_lockfree_stack_depth"), 6.0);
+        assert_eq!(gauge(&values, "rs_lockfree_hazard_thread_count"), 2.0);
+        let waiting = gauge(&values, "rs_lockfree_hazard_waiting_count");
+        assert!(8.0 >= waiting);
+
+        drop(queue);
+        drop(stack);
+        let values = snapshot(&recorder);
+        assert_eq!(gauge(&values, "rs_lockfree_queue_depth"), 0.0);
+        assert_eq!(gauge(&values, "rs_lockfree_stack_depth"), 0.0);
+        assert_eq!(gauge(&values, "rs_lockfree_hazard_thread_count"), 0.0);
+        assert_eq!(gauge(&values, "rs_lockfree_hazard_waiting_count"), 0.0);
+        assert_eq!(counter(&values, "rs_lockfree_hazard_reclaimed_total"), 8);
+    });
+}
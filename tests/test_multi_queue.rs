@@ -0,0 +1,86 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::multi_queue::MultiQueue;
+use std::thread;
+
+struct ShardPtr<T>(*mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+impl<T> Copy for ShardPtr<T> {}
+
+impl<T> Clone for ShardPtr<T> {
+    fn clone(&self) -> Self {
+        ShardPtr(self.0)
+    }
+}
+
+// Elements pushed by one thread stay in order in its shard.
+#[test]
+fn test_base() {
+    let mut queue = MultiQueue::with_shards(3);
+    assert_eq!(queue.shard_count(), 3);
+    assert!(queue.pop().is_none());
+    for i in 0..100 {
+        queue.push(i);
+    }
+    for i in 0..100 {
+        assert_eq!(queue.try_pop(), Ok(Some(i)));
+    }
+    assert_eq!(queue.try_pop(), Ok(None));
+}
+
+// Consumers steal elements of producers from other shards, and each element is popped once.
+// Threads take thread ids of `HazardEpoch`, so it has its own test binary.
+#[test]
+fn test_mpmc() {
+    let producer_num = 2;
+    let consumer_num = 2;
+    let test_num = 20000;
+    let mut queue = MultiQueue::with_shards(4);
+    let queue_ptr = ShardPtr(&mut *queue as *mut MultiQueue<(usize, usize)>);
+    let producers: Vec<_> = (0..producer_num)
+        .map(|id| {
+            thread::spawn(move || {
+                let queue = unsafe { &mut *queue_ptr.0 };
+                for seq in 0..test_num {
+                    queue.push((id, seq));
+                }
+            })
+        })
+        .collect();
+    let consumers: Vec<_> = (0..consumer_num)
+        .map(|_| {
+            thread::spawn(move || {
+                let queue = unsafe { &mut *queue_ptr.0 };
+                // Each producer pushes to one shard, so its elements are popped in order.
+                let mut last = vec![None; producer_num];
+                let mut cnt = 0;
+                let mut idle = 0;
+                while idle < 1000 {
+                    match queue.pop() {
+                        Some((id, seq)) => {
+                            assert!(last[id].map_or(true, |l| l < seq));
+                            last[id] = Some(seq);
+                            cnt += 1;
+                            idle = 0;
+                        }
+                        None => {
+                            idle += 1;
+                            thread::yield_now();
+                        }
+                    }
+                }
+                cnt
+            })
+        })
+        .collect();
+    for t in producers {
+        t.join().unwrap();
+    }
+    let mut cnt: usize = consumers.into_iter().map(|t| t.join().unwrap()).sum();
+    while queue.pop().is_some() {
+        cnt += 1;
+    }
+    assert_eq!(cnt, producer_num * test_num);
+}
@@ -0,0 +1,52 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::once_map::OnceMap;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Threads race to compute the same keys, and each is computed once. Waiting threads spin with
+// `Backoff`, which takes thread ids, so it has its own test binary.
+#[test]
+fn test_race() {
+    let key_num = 64;
+    let map: &'static OnceMap<usize, usize> = Box::leak(Box::new(OnceMap::with_capacity(8)));
+    let calls: &'static AtomicUsize = Box::leak(Box::new(AtomicUsize::new(0)));
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            thread::spawn(move || {
+                for i in 0..key_num {
+                    let v = map.get_or_init(i, || {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        // Widen the window for other threads to find the pending entry.
+                        thread::yield_now();
+                        i + 1
+                    });
+                    assert_eq!(*v, i + 1);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), key_num);
+    for i in 0..key_num {
+        assert_eq!(map.get(&i), Some(&(i + 1)));
+    }
+}
+
+// A key whose computing panicked stays poisoned, and other keys are not affected.
+#[test]
+fn test_poison() {
+    let map = OnceMap::with_capacity(4);
+    let ret = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        map.get_or_init(1, || panic!("init fails"));
+    }));
+    assert!(ret.is_err());
+    let ret = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        map.get_or_init(1, || 10);
+    }));
+    assert!(ret.is_err());
+    assert_eq!(map.get_or_init(2, || 20), &20);
+}
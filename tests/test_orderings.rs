@@ -0,0 +1,64 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+struct ShardPtr<T>(*mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+// Elements are published by the release store of `next` and the state of each node, without
+// `SeqCst`. Each element is a heap block written right before pushing, so a consumer reaching a
+// node before its contents would see a torn block.
+#[test]
+fn test_publish_by_link() {
+    let test_num = 20000;
+    let consumer_count = 2;
+    let mut queue = LockFreeQueue::<Box<[usize; 8]>>::default_new_in_heap();
+    let popped = Arc::new(AtomicUsize::new(0));
+    let consumers: Vec<_> = (0..consumer_count)
+        .map(|idx| {
+            let mut queue_ptr = ShardPtr::new(&mut *queue as *mut LockFreeQueue<Box<[usize; 8]>>);
+            let popped = popped.clone();
+            thread::spawn(move || {
+                let queue = queue_ptr.as_mut();
+                let mut last = None;
+                while popped.load(Ordering::SeqCst) < test_num {
+                    // One consumer claims elements in place by `pop_if`, the other takes them.
+                    let v = if 0 == idx {
+                        queue.pop_if(|_| true)
+                    } else {
+                        queue.pop()
+                    };
+                    if let Some(block) = v {
+                        assert!(block.iter().all(|&w| w == block[0]), "torn block {:?}", block);
+                        assert!(last < Some(block[0]));
+                        last = Some(block[0]);
+                        popped.fetch_add(1, Ordering::SeqCst);
+                    } else {
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+    for i in 0..test_num {
+        queue.push(Box::new([i; 8]));
+    }
+    for t in consumers {
+        t.join().unwrap();
+    }
+    assert!(queue.pop().is_none());
+}
@@ -0,0 +1,86 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::overwrite_ring::OverwriteRing;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Threads take thread ids of `HazardEpoch`, so tests have their own binary.
+#[test]
+fn test_base() {
+    let mut ring = OverwriteRing::with_capacity(4);
+    assert_eq!(ring.capacity(), 4);
+    assert_eq!(ring.pop(), None);
+    for i in 0..3 {
+        ring.push(i);
+    }
+    assert_eq!(ring.pop(), Some(0));
+    for i in 3..10 {
+        ring.push(i);
+    }
+    // The slot of 0 is reused by 4, and 1 to 5 are overwritten.
+    assert_eq!(ring.overwritten_count(), 5);
+    for i in 6..10 {
+        assert_eq!(ring.pop(), Some(i));
+    }
+    assert_eq!(ring.pop(), None);
+}
+
+#[test]
+fn test_drop() {
+    use std::rc::Rc;
+    let v = Rc::new(0);
+    {
+        let mut ring = OverwriteRing::with_capacity(2);
+        for _ in 0..4 {
+            ring.push(v.clone());
+        }
+        assert_eq!(ring.pop().map(|v| Rc::strong_count(&v)), Some(3));
+    }
+    assert_eq!(Rc::strong_count(&v), 1);
+}
+
+// Producers overwrite a small ring while a consumer pops. Elements of each producer are popped in
+// order, and each element is either popped or overwritten.
+#[test]
+fn test_overwrite() {
+    let producer_num = 3;
+    let test_num = 20000;
+    let ring: &'static mut OverwriteRing<(usize, usize)> =
+        Box::leak(OverwriteRing::with_capacity(8));
+    let addr = ring as *mut OverwriteRing<(usize, usize)> as usize;
+    let done: &'static AtomicUsize = Box::leak(Box::new(AtomicUsize::new(0)));
+    let producers: Vec<_> = (0..producer_num)
+        .map(|id| {
+            thread::spawn(move || {
+                let ring = unsafe { &mut *(addr as *mut OverwriteRing<(usize, usize)>) };
+                for seq in 0..test_num {
+                    ring.push((id, seq));
+                }
+                done.fetch_add(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    let mut last = vec![None; producer_num];
+    let mut cnt = 0;
+    let check = |v: (usize, usize), last: &mut Vec<Option<usize>>| {
+        assert!(last[v.0].map_or(true, |l| l < v.1));
+        last[v.0] = Some(v.1);
+    };
+    while done.load(Ordering::SeqCst) < producer_num {
+        match ring.pop() {
+            Some(v) => {
+                check(v, &mut last);
+                cnt += 1;
+            }
+            None => thread::yield_now(),
+        }
+    }
+    for t in producers {
+        t.join().unwrap();
+    }
+    while let Some(v) = ring.pop() {
+        check(v, &mut last);
+        cnt += 1;
+    }
+    assert_eq!(cnt as u64 + ring.overwritten_count(), (producer_num * test_num) as u64);
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "rayon")]
+
+extern crate rayon;
+extern crate rs_lockfree;
+
+use rayon::prelude::*;
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use rs_lockfree::lockfree_stack::LockFreeStack;
+
+// Workers never take thread ids of `HazardEpoch`, so the pool may have more threads than
+// `MAX_THREAD_COUNT`.
+#[test]
+fn test_base() {
+    let test_num = 10000;
+    let mut queue = LockFreeQueue::default_new_in_heap();
+    let mut stack = LockFreeStack::default_new_in_heap();
+    for i in 0..test_num {
+        queue.push(i);
+        stack.push(i);
+    }
+    let drain = queue.par_drain().chain(stack.par_drain());
+    assert!(queue.pop().is_none());
+    assert!(stack.pop().is_none());
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(32).build().unwrap();
+    let mut drained: Vec<_> = pool.install(move || drain.collect());
+    drained.sort();
+    let expected: Vec<_> = (0..test_num).flat_map(|i| vec![i, i]).collect();
+    assert_eq!(drained, expected);
+    queue.push(0);
+    assert_eq!(queue.par_drain().len(), 1);
+}
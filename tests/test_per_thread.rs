@@ -0,0 +1,52 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::per_thread::{PerCpu, PerThreadShard};
+use rs_lockfree::util;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+// Threads take thread ids, so tests have their own binary.
+#[test]
+fn test_per_thread_shard() {
+    let test_num = 10000;
+    let counters = Arc::new(PerThreadShard::<util::AtomicU64>::new());
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let counters = counters.clone();
+            thread::spawn(move || {
+                for _ in 0..test_num {
+                    counters.get().fetch_add(1, Ordering::Relaxed);
+                }
+                util::get_thread_id() as usize
+            })
+        })
+        .collect();
+    for t in workers {
+        let tid = t.join().unwrap();
+        assert_eq!(counters.get_of(tid).unwrap().load(Ordering::Relaxed), test_num);
+    }
+    let sum: u64 = counters.iter().map(|c| c.load(Ordering::Relaxed)).sum();
+    assert_eq!(sum, 4 * test_num);
+}
+
+#[test]
+fn test_per_cpu() {
+    let test_num = 10000;
+    let counters = Arc::new(PerCpu::<util::AtomicU64>::new());
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let counters = counters.clone();
+            thread::spawn(move || {
+                for _ in 0..test_num {
+                    counters.get().fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+    for t in workers {
+        t.join().unwrap();
+    }
+    let sum: u64 = counters.iter().map(|c| c.load(Ordering::Relaxed)).sum();
+    assert_eq!(sum, 4 * test_num);
+}
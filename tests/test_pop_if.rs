@@ -0,0 +1,171 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::lockfree_queue;
+use rs_lockfree::lockfree_stack;
+use std::thread;
+
+struct ShardPtr<T>(*mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+impl<T> Copy for ShardPtr<T> {}
+
+impl<T> Clone for ShardPtr<T> {
+    fn clone(&self) -> Self {
+        ShardPtr(self.0)
+    }
+}
+
+// Each consumer only takes elements of its parity from the head, so it gets them in order, and
+// taken and remaining elements add up to the pushed ones.
+#[test]
+fn test_queue() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 20000;
+    let mut queue = lockfree_queue::LockFreeQueue::<i64>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let queue_ptr = ShardPtr::new(&mut *queue as *mut lockfree_queue::LockFreeQueue<i64>);
+    let producer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut queue_ptr = queue_ptr;
+            let queue = queue_ptr.as_mut();
+            for i in 0..test_num {
+                queue.push(i);
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    let consumers: Vec<_> = (0..2)
+        .map(|parity| {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut queue_ptr = queue_ptr;
+                let queue = queue_ptr.as_mut();
+                let mut taken = vec![];
+                while !done.load(Ordering::SeqCst) {
+                    match queue.pop_if(|v| parity == v % 2) {
+                        Some(v) => taken.push(v),
+                        None => thread::yield_now(),
+                    }
+                }
+                assert!(taken.iter().all(|v| parity == v % 2));
+                assert!(taken.windows(2).all(|w| w[0] < w[1]));
+                taken.len()
+            })
+        })
+        .collect();
+    producer.join().unwrap();
+    let taken: usize = consumers.into_iter().map(|t| t.join().unwrap()).sum();
+    let mut rest = vec![];
+    while let Some(v) = queue.pop() {
+        rest.push(v);
+    }
+    assert!(rest.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(taken + rest.len(), test_num as usize);
+}
+
+// Each consumer only takes elements of its parity, so every element it gets passed its predicate,
+// and taken and remaining elements add up to the pushed ones.
+#[test]
+fn test_stack() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 20000;
+    let mut stack = lockfree_stack::LockFreeStack::<i64>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let stack_ptr = ShardPtr::new(&mut *stack as *mut lockfree_stack::LockFreeStack<i64>);
+    let producer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut stack_ptr = stack_ptr;
+            let stack = stack_ptr.as_mut();
+            for i in 0..test_num {
+                stack.push(i);
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    let consumers: Vec<_> = (0..2)
+        .map(|parity| {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut stack_ptr = stack_ptr;
+                let stack = stack_ptr.as_mut();
+                let mut taken = vec![];
+                while !done.load(Ordering::SeqCst) {
+                    match stack.pop_if(|v| parity == v % 2) {
+                        Some(v) => taken.push(v),
+                        None => thread::yield_now(),
+                    }
+                }
+                assert!(taken.iter().all(|v| parity == v % 2));
+                taken.len()
+            })
+        })
+        .collect();
+    producer.join().unwrap();
+    let taken: usize = consumers.into_iter().map(|t| t.join().unwrap()).sum();
+    assert_eq!(stack.pop_if(|_| false), None);
+    let mut rest = 0;
+    while stack.pop().is_some() {
+        rest += 1;
+    }
+    assert_eq!(taken + rest, test_num as usize);
+}
+
+// Elements removed by `retain` are skipped, and the predicate sees the first remaining one.
+#[test]
+fn test_queue_after_retain() {
+    let mut queue = lockfree_queue::LockFreeQueue::<i64>::default_new_in_heap();
+    for i in 0..6 {
+        queue.push(i);
+    }
+    assert_eq!(queue.retain(|v| v % 2 == 1), 3);
+    assert_eq!(queue.pop_if(|&v| v > 1), None);
+    assert_eq!(queue.pop_if(|&v| v == 1), Some(1));
+    assert_eq!(queue.pop(), Some(3));
+    assert_eq!(queue.pop_if(|_| true), Some(5));
+    assert_eq!(queue.pop_if(|_| true), None);
+}
+
+// A panicking predicate leaves the top in place and releases the handle, or every later operation
+// of current thread would fail.
+#[test]
+fn test_stack_panic() {
+    use std::panic::{self, AssertUnwindSafe};
+    let mut stack = lockfree_stack::LockFreeStack::<i64>::default_new_in_heap();
+    stack.push(1);
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+        stack.pop_if(|_| panic!("predicate panics"))
+    }));
+    assert!(ret.is_err());
+    assert_eq!(stack.try_pop_if(|&v| v == 1), Ok(Some(1)));
+    assert_eq!(stack.pop(), None);
+}
+
+// A panicking predicate leaves the first element in place and releases the handle, or every later
+// operation of current thread would fail.
+#[test]
+fn test_queue_panic() {
+    use std::panic::{self, AssertUnwindSafe};
+    let mut queue = lockfree_queue::LockFreeQueue::<i64>::default_new_in_heap();
+    queue.push(1);
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+        queue.pop_if(|_| panic!("predicate panics"))
+    }));
+    assert!(ret.is_err());
+    assert_eq!(queue.try_pop_if(|&v| v == 1), Ok(Some(1)));
+    assert_eq!(queue.pop(), None);
+}
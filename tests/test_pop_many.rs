@@ -0,0 +1,145 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::lockfree_queue;
+use rs_lockfree::lockfree_stack;
+use std::thread;
+
+struct ShardPtr<T>(*mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+impl<T> Copy for ShardPtr<T> {}
+
+impl<T> Clone for ShardPtr<T> {
+    fn clone(&self) -> Self {
+        ShardPtr(self.0)
+    }
+}
+
+// Batches of one consumer come in FIFO order, and no element is lost or duplicated.
+#[test]
+fn test_queue() {
+    use std::mem::MaybeUninit;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 10000;
+    let mut queue = lockfree_queue::LockFreeQueue::<i64>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let queue_ptr = ShardPtr::new(&mut *queue as *mut lockfree_queue::LockFreeQueue<i64>);
+    let producer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut queue_ptr = queue_ptr;
+            let queue = queue_ptr.as_mut();
+            for i in 0..test_num {
+                queue.push(i);
+                if 0 == i % 64 {
+                    thread::yield_now();
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    let consumers: Vec<_> = (0..2)
+        .map(|_| {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut queue_ptr = queue_ptr;
+                let queue = queue_ptr.as_mut();
+                let mut buf = [MaybeUninit::uninit(); 7];
+                let mut popped = vec![];
+                loop {
+                    let finished = done.load(Ordering::SeqCst);
+                    let n = queue.pop_many(&mut buf);
+                    let batch: Vec<i64> =
+                        buf[..n].iter().map(|v| unsafe { v.assume_init() }).collect();
+                    assert!(batch.windows(2).all(|w| w[0] < w[1]));
+                    popped.extend(batch);
+                    if 0 == n {
+                        if finished {
+                            break;
+                        }
+                        thread::yield_now();
+                    }
+                }
+                popped
+            })
+        })
+        .collect();
+    producer.join().unwrap();
+    let mut popped: Vec<i64> = consumers
+        .into_iter()
+        .flat_map(|t| t.join().unwrap())
+        .collect();
+    popped.sort();
+    assert_eq!(popped, (0..test_num).collect::<Vec<_>>());
+}
+
+// Batches of one consumer come in LIFO order, and no element is lost or duplicated.
+#[test]
+fn test_stack() {
+    use std::mem::MaybeUninit;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 10000;
+    let mut stack = lockfree_stack::LockFreeStack::<i64>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let stack_ptr = ShardPtr::new(&mut *stack as *mut lockfree_stack::LockFreeStack<i64>);
+    let producer = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut stack_ptr = stack_ptr;
+            let stack = stack_ptr.as_mut();
+            for i in 0..test_num {
+                stack.push(i);
+                if 0 == i % 64 {
+                    thread::yield_now();
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    let consumers: Vec<_> = (0..2)
+        .map(|_| {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut stack_ptr = stack_ptr;
+                let stack = stack_ptr.as_mut();
+                let mut buf = [MaybeUninit::uninit(); 7];
+                let mut popped = vec![];
+                loop {
+                    let finished = done.load(Ordering::SeqCst);
+                    let n = stack.pop_many(&mut buf);
+                    let batch: Vec<i64> =
+                        buf[..n].iter().map(|v| unsafe { v.assume_init() }).collect();
+                    assert!(batch.windows(2).all(|w| w[0] > w[1]));
+                    popped.extend(batch);
+                    if 0 == n {
+                        if finished {
+                            break;
+                        }
+                        thread::yield_now();
+                    }
+                }
+                popped
+            })
+        })
+        .collect();
+    producer.join().unwrap();
+    let mut popped: Vec<i64> = consumers
+        .into_iter()
+        .flat_map(|t| t.join().unwrap())
+        .collect();
+    popped.sort();
+    assert_eq!(popped, (0..test_num).collect::<Vec<_>>());
+}
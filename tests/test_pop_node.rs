@@ -0,0 +1,81 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::lockfree_queue::{LockFreeQueue, NodeBox};
+use std::sync::mpsc;
+use std::thread;
+
+struct ShardPtr<T>(*mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+#[test]
+fn test_base() {
+    let mut queue = LockFreeQueue::default_new_in_heap();
+    assert!(queue.pop_node().is_none());
+    for i in 0..4 {
+        queue.push(i);
+    }
+    // Elements removed by `retain` are skipped.
+    assert_eq!(queue.retain(|&v| 0 != v % 2), 2);
+    let (v, node) = queue.pop_node().unwrap();
+    assert_eq!(v, 1);
+    queue.push_with_node(4, node.unwrap());
+    assert_eq!(queue.pop_node().map(|(v, _)| v), Some(3));
+    assert_eq!(queue.pop(), Some(4));
+    assert!(queue.pop_node().is_none());
+    queue.push_with_node(5, NodeBox::default());
+    assert_eq!(queue.pop(), Some(5));
+}
+
+// A consumer sends nodes back to the producer, which allocates only when none is returned. Elements
+// arrive in order, and nodes are recycled.
+#[test]
+fn test_recycle() {
+    let test_num = 20000;
+    let mut queue = LockFreeQueue::<usize>::default_new_in_heap();
+    let (node_tx, node_rx) = mpsc::channel();
+    let mut queue_ptr = ShardPtr::new(&mut *queue as *mut LockFreeQueue<usize>);
+    let producer = thread::spawn(move || {
+        let queue = queue_ptr.as_mut();
+        let mut allocated = 0;
+        for i in 0..test_num {
+            match node_rx.try_recv() {
+                Ok(node) => queue.push_with_node(i, node),
+                Err(_) => {
+                    queue.push(i);
+                    allocated += 1;
+                }
+            }
+        }
+        allocated
+    });
+    let mut recycled = 0;
+    let mut expected = 0;
+    while expected < test_num {
+        match queue.pop_node() {
+            Some((v, node)) => {
+                assert_eq!(v, expected);
+                expected += 1;
+                if let Some(node) = node {
+                    recycled += 1;
+                    let _ = node_tx.send(node);
+                }
+            }
+            None => thread::yield_now(),
+        }
+    }
+    let allocated = producer.join().unwrap();
+    assert!(0 < recycled);
+    assert!(allocated < test_num);
+    assert!(queue.pop().is_none());
+}
@@ -0,0 +1,182 @@
+extern crate rs_lockfree;
+#[cfg(feature = "serde")]
+extern crate bincode;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use std::cell::RefCell;
+#[cfg(feature = "serde")]
+use std::thread;
+
+#[cfg(feature = "serde")]
+struct ShardPtr<T>(*mut T);
+
+#[cfg(feature = "serde")]
+unsafe impl<T> Send for ShardPtr<T> {}
+
+#[cfg(feature = "serde")]
+impl<T> ShardPtr<T> {
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+struct Node<'a, T> {
+    cnt: &'a RefCell<i32>,
+    v: T,
+}
+
+impl<'a, T> Drop for Node<'a, T> {
+    fn drop(&mut self) {
+        *self.cnt.borrow_mut() += 1;
+    }
+}
+
+#[test]
+fn test_base() {
+    let mut queue = unsafe { LockFreeQueue::default_new_in_stack() };
+    assert!(queue.pop().is_none());
+    queue.push(1);
+    assert_eq!(queue.pop().unwrap(), 1);
+    let test_num = 100;
+    for i in 0..test_num {
+        queue.push(i);
+    }
+    for i in 0..test_num {
+        assert_eq!(queue.pop().unwrap(), i);
+    }
+}
+
+#[test]
+fn test_try_push_pop() {
+    let mut q = LockFreeQueue::default_new_in_heap();
+    assert_eq!(q.try_pop(), Ok(None));
+    assert!(q.try_push(1).is_ok());
+    assert_eq!(q.try_pop(), Ok(Some(1)));
+}
+
+#[test]
+fn test_memory_leak() {
+    let cnt = RefCell::new(0);
+    let test_num = 100;
+    {
+        let mut queue = unsafe { LockFreeQueue::default_new_in_stack() };
+        for i in 0..test_num {
+            queue.push(Node { cnt: &cnt, v: i });
+        }
+        assert_eq!(*cnt.borrow(), 0);
+        for i in 0..test_num / 2 {
+            assert_eq!(queue.pop().unwrap().v, i);
+        }
+        assert_eq!(*cnt.borrow(), test_num / 2);
+    }
+    // Elements left are dropped with the queue, and none of the popped is dropped twice.
+    assert_eq!(*cnt.borrow(), test_num);
+}
+
+// A panicking predicate leaves the element it checks in place and releases the handle, or every
+// later operation of current thread would fail.
+#[test]
+fn test_retain_panic() {
+    use std::panic::{self, AssertUnwindSafe};
+    let mut queue = LockFreeQueue::default_new_in_heap();
+    for i in 0..4 {
+        queue.push(i);
+    }
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+        queue.retain(|v| {
+            assert!(2 != *v, "predicate panics");
+            0 != *v
+        })
+    }));
+    assert!(ret.is_err());
+    assert_eq!(queue.try_pop(), Ok(Some(1)));
+    assert_eq!(queue.try_retain(|_| true), Ok(0));
+    assert_eq!(queue.pop(), Some(2));
+    assert_eq!(queue.pop(), Some(3));
+    assert_eq!(queue.pop(), None);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn test_serde() {
+    let mut queue = LockFreeQueue::default_new_in_heap();
+    for i in 0..4 {
+        queue.push(i);
+    }
+    let mut buf = vec![];
+    queue.serialize_snapshot(&mut serde_json::Serializer::new(&mut buf)).unwrap();
+    assert_eq!(buf, b"[0,1,2,3]");
+    assert_eq!(queue.pop(), Some(0));
+    let mut buf = vec![];
+    queue.serialize_drain(&mut serde_json::Serializer::new(&mut buf)).unwrap();
+    assert_eq!(buf, b"[1,2,3]");
+    assert_eq!(queue.pop(), None);
+    let mut de = serde_json::Deserializer::from_slice(&buf);
+    let mut queue = LockFreeQueue::<i32>::deserialize_in_heap(&mut de).unwrap();
+    assert_eq!(queue.pop(), Some(1));
+    assert_eq!(queue.pop(), Some(2));
+    assert_eq!(queue.pop(), Some(3));
+    assert_eq!(queue.pop(), None);
+}
+
+// Bincode needs the length of a sequence ahead, which `iter::from_fn` can't tell.
+#[test]
+#[cfg(feature = "serde")]
+fn test_serde_bincode() {
+    let mut queue = LockFreeQueue::default_new_in_heap();
+    for i in 0..4 {
+        queue.push(i);
+    }
+    assert_eq!(queue.pop(), Some(0));
+    let mut snapshot = vec![];
+    queue
+        .serialize_snapshot(&mut bincode::Serializer::new(&mut snapshot, bincode::options()))
+        .unwrap();
+    let mut drained = vec![];
+    queue
+        .serialize_drain(&mut bincode::Serializer::new(&mut drained, bincode::options()))
+        .unwrap();
+    assert_eq!(snapshot, drained);
+    assert_eq!(queue.pop(), None);
+    let mut de = bincode::Deserializer::from_slice(&drained, bincode::options());
+    let mut queue = LockFreeQueue::<i32>::deserialize_in_heap(&mut de).unwrap();
+    let values: Vec<_> = (0..4).map(|_| queue.pop()).collect();
+    assert_eq!(values, [Some(1), Some(2), Some(3), None]);
+}
+
+// Snapshots claim elements while another thread pushes and pops, so each one is a sorted run of
+// elements which are still alive.
+#[test]
+#[cfg(feature = "serde")]
+fn test_serialize_snapshot_concurrently() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    let test_num = 10000;
+    let mut queue = LockFreeQueue::<String>::default_new_in_heap();
+    let done = Arc::new(AtomicBool::new(false));
+    let mut queue_ptr = ShardPtr(&mut *queue as *mut LockFreeQueue<String>);
+    let worker = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let queue = queue_ptr.as_mut();
+            for i in 0..test_num {
+                queue.push(format!("{:05}", i));
+                if 0 == i % 3 {
+                    queue.pop();
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        })
+    };
+    while !done.load(Ordering::SeqCst) {
+        let mut buf = vec![];
+        queue
+            .serialize_snapshot(&mut bincode::Serializer::new(&mut buf, bincode::options()))
+            .unwrap();
+        let values: Vec<String> = bincode::Options::deserialize(bincode::options(), &buf).unwrap();
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+    }
+    worker.join().unwrap();
+}
@@ -0,0 +1,44 @@
+#![cfg(all(feature = "reclaim-delay", not(feature = "single_thread")))]
+
+extern crate rs_lockfree;
+
+use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// Objects added while a reader holds its handle wait for the reader, which shows in the tail of
+// delays. The histogram is process-wide, so it has its own test binary.
+#[test]
+fn test_stalled_reader() {
+    let mut h = HazardEpoch::default_new_in_heap();
+    let addr = &mut *h as *mut HazardEpoch as usize;
+    let (acquired_tx, acquired_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let reader = thread::spawn(move || unsafe {
+        let h = &mut *(addr as *mut HazardEpoch);
+        let handle = h.try_acquire().unwrap();
+        acquired_tx.send(()).unwrap();
+        release_rx.recv().unwrap();
+        h.release(handle);
+    });
+    acquired_rx.recv().unwrap();
+
+    unsafe {
+        for _ in 0..10 {
+            h.try_add_node(Box::into_raw(Box::new(BaseHazardNode::default())))
+                .unwrap();
+        }
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(h.flush_all(), 0);
+        release_tx.send(()).unwrap();
+        reader.join().unwrap();
+        assert_eq!(h.flush_all(), 10);
+    }
+
+    let delays = HazardEpoch::reclaim_delay();
+    assert_eq!(delays.count(), 10);
+    assert!(delays.min() >= 20_000);
+    assert!(delays.percentile(50.0) >= 20_000);
+    assert!(delays.max() >= delays.percentile(99.0));
+}
@@ -0,0 +1,131 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::hazard_epoch::{BaseHazardNode, HazardEpoch};
+
+unsafe fn add_nodes(h: &mut HazardEpoch, n: usize) {
+    for _ in 0..n {
+        h.try_add_node(Box::into_raw(Box::new(BaseHazardNode::default())))
+            .unwrap();
+    }
+}
+
+// Each release reclaims at most `limit` objects, and the backlog drains across releases.
+unsafe fn check_drain(h: &mut HazardEpoch, limit: i64, total: i64) {
+    let mut releases = 0;
+    while 0 < h.atomic_load_hazard_waiting_count() {
+        let waiting = h.atomic_load_hazard_waiting_count();
+        let handle = h.try_acquire().unwrap();
+        h.release(handle);
+        let reclaimed = waiting - h.atomic_load_hazard_waiting_count();
+        assert!(0 < reclaimed && reclaimed <= limit);
+        releases += 1;
+    }
+    assert!(releases >= (total + limit - 1) / limit);
+}
+
+#[test]
+fn test_single_thread() {
+    let mut h = HazardEpoch::default_new_in_heap();
+    h.set_max_reclaim_per_release(Some(4));
+    unsafe {
+        let handle = h.try_acquire().unwrap();
+        add_nodes(&mut h, 30);
+        h.release(handle);
+        assert_eq!(h.atomic_load_hazard_waiting_count(), 26);
+        check_drain(&mut h, 4, 26);
+
+        // No limit reclaims the whole backlog at once.
+        h.set_max_reclaim_per_release(None);
+        let handle = h.try_acquire().unwrap();
+        add_nodes(&mut h, 30);
+        h.release(handle);
+        assert_eq!(h.atomic_load_hazard_waiting_count(), 0);
+    }
+}
+
+#[cfg(not(feature = "single_thread"))]
+#[test]
+fn test_threshold() {
+    use std::thread;
+
+    let mut h = HazardEpoch::new_in_heap(8, 0);
+    h.set_max_reclaim_per_release(Some(3));
+    let addr = &mut *h as *mut HazardEpoch as usize;
+    // Register another thread, so releases scan instead of taking the single thread path.
+    thread::spawn(move || unsafe {
+        let h = &mut *(addr as *mut HazardEpoch);
+        let handle = h.try_acquire().unwrap();
+        h.release(handle);
+    }).join()
+        .unwrap();
+    unsafe {
+        let handle = h.try_acquire().unwrap();
+        add_nodes(&mut h, 20);
+        h.release(handle);
+        assert_eq!(h.atomic_load_hazard_waiting_count(), 17);
+        // Releases reclaim while the backlog of current thread is over the threshold.
+        while 8 < h.atomic_load_hazard_waiting_count() {
+            let waiting = h.atomic_load_hazard_waiting_count();
+            let handle = h.try_acquire().unwrap();
+            h.release(handle);
+            let reclaimed = waiting - h.atomic_load_hazard_waiting_count();
+            assert!(0 < reclaimed && reclaimed <= 3);
+        }
+        // Explicit reclamation is not limited.
+        assert!(0 < h.flush_all());
+        assert_eq!(h.atomic_load_hazard_waiting_count(), 0);
+    }
+}
+
+// Once all threads are over the threshold, each release reclaims thread stores of other threads
+// until the budget runs out, and the next release goes on from the store where the last one stopped.
+#[cfg(not(feature = "single_thread"))]
+#[test]
+fn test_foreign() {
+    use rs_lockfree::util;
+    use std::thread;
+
+    let mut h = HazardEpoch::new_in_heap(8, 0);
+    h.set_max_foreign_reclaim_per_release(Some(10));
+    let addr = &mut *h as *mut HazardEpoch as usize;
+    // Threads leave objects without releasing, so none of them has reclaimed.
+    let tids: Vec<_> = (0..3)
+        .map(|_| {
+            thread::spawn(move || unsafe {
+                add_nodes(&mut *(addr as *mut HazardEpoch), 20);
+                util::get_thread_id() as u16
+            }).join()
+                .unwrap()
+        })
+        .collect();
+    unsafe {
+        assert_eq!(h.atomic_load_hazard_waiting_count(), 60);
+        let mut releases = 0;
+        // Reclaiming is triggered while waiting objects are over 4 threads times the threshold.
+        while 32 < h.atomic_load_hazard_waiting_count() {
+            let waiting = h.atomic_load_hazard_waiting_count();
+            let handle = h.try_acquire().unwrap();
+            h.release(handle);
+            // A store is not split, so the budget of 10 is exceeded by the first one.
+            assert_eq!(waiting - h.atomic_load_hazard_waiting_count(), 20);
+            releases += 1;
+        }
+        assert_eq!(releases, 2);
+        // Two releases reclaimed different stores, leaving one for later.
+        let left: Vec<_> = tids.iter().map(|&tid| h.flush_thread(tid)).collect();
+        assert_eq!(left.iter().sum::<i64>(), 20);
+        assert_eq!(left.iter().filter(|&&n| 0 == n).count(), 2);
+
+        // No limit reclaims all stores at once.
+        h.set_max_foreign_reclaim_per_release(None);
+        for _ in 0..3 {
+            thread::spawn(move || unsafe {
+                add_nodes(&mut *(addr as *mut HazardEpoch), 20);
+            }).join()
+                .unwrap();
+        }
+        let handle = h.try_acquire().unwrap();
+        h.release(handle);
+        assert_eq!(h.atomic_load_hazard_waiting_count(), 0);
+    }
+}
@@ -0,0 +1,152 @@
+#![feature(core_intrinsics)]
+
+extern crate rs_lockfree;
+
+use rs_lockfree::seq_lock::SeqLock;
+use std::intrinsics;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::thread;
+
+struct GlobalConf {
+    lock: SeqLock<[u64; 8]>,
+    stop: u8,
+}
+
+struct ShardPtr<T>(pub *mut T);
+
+unsafe impl<T> Send for ShardPtr<T> {}
+
+unsafe impl<T> Sync for ShardPtr<T> {}
+
+impl<T> ShardPtr<T> {
+    fn new(data: *mut T) -> Self {
+        ShardPtr(data)
+    }
+
+    fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.0 }
+    }
+}
+
+impl<T> Copy for ShardPtr<T> {}
+
+impl<T> Clone for ShardPtr<T> {
+    fn clone(&self) -> Self {
+        ShardPtr(self.0)
+    }
+}
+
+impl<T> Deref for ShardPtr<T> {
+    type Target = *mut T;
+
+    fn deref(&self) -> &<Self as Deref>::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ShardPtr<T> {
+    fn deref_mut(&mut self) -> &mut <Self as Deref>::Target {
+        &mut self.0
+    }
+}
+
+unsafe fn writer_thread(mut global_conf: ShardPtr<GlobalConf>, loop_cnt: u64) {
+    let global_conf = global_conf.as_mut();
+    for _ in 0..loop_cnt {
+        global_conf.lock.update(|v| {
+            for x in v.iter_mut() {
+                *x += 1;
+            }
+        });
+    }
+}
+
+unsafe fn reader_thread(mut global_conf: ShardPtr<GlobalConf>) {
+    let global_conf = global_conf.as_mut();
+    let mut last = 0;
+    while intrinsics::atomic_load(&global_conf.stop) == 0 {
+        let v = global_conf.lock.read();
+        for x in v.iter() {
+            assert_eq!(*x, v[0]);
+        }
+        assert!(v[0] >= last);
+        last = v[0];
+    }
+}
+
+#[test]
+fn test_multi_threads() {
+    let writer_count = 2;
+    let reader_count = 2;
+    let loop_cnt = 100000;
+
+    let mut global_conf = GlobalConf {
+        lock: SeqLock::new([0; 8]),
+        stop: 0,
+    };
+    let global_conf_ptr = ShardPtr::new(&mut global_conf as *mut _);
+
+    let mut writer_threads = vec![];
+    let mut reader_threads = vec![];
+
+    for _ in 0..writer_count {
+        writer_threads.push(thread::spawn(move || unsafe {
+            writer_thread(global_conf_ptr, loop_cnt);
+        }));
+    }
+
+    for _ in 0..reader_count {
+        reader_threads.push(thread::spawn(move || unsafe {
+            reader_thread(global_conf_ptr);
+        }));
+    }
+
+    for t in writer_threads {
+        t.join().unwrap();
+    }
+
+    unsafe {
+        intrinsics::atomic_store(&mut global_conf.stop, 1);
+    }
+
+    for t in reader_threads {
+        t.join().unwrap();
+    }
+
+    assert_eq!(global_conf.lock.read(), [writer_count * loop_cnt; 8]);
+    assert_eq!(global_conf.lock.seq(), 2 * writer_count * loop_cnt);
+}
+
+// Shared by `Arc` only, and a panicking writer still makes the sequence even, so that later
+// readers and writers don't spin forever.
+#[test]
+fn test_shared_with_panic() {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Arc;
+    let lock = Arc::new(SeqLock::new((0_u64, 0_u64)));
+    let writers: Vec<_> = (0..2)
+        .map(|_| {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                for _ in 0..10000 {
+                    lock.update(|v| *v = (v.0 + 1, v.1 + 1));
+                }
+            })
+        })
+        .collect();
+    for t in writers {
+        t.join().unwrap();
+    }
+    let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+        lock.update(|v| {
+            v.0 = 0;
+            panic!("writer panics");
+        })
+    }));
+    assert!(ret.is_err());
+    assert_eq!(lock.seq() % 2, 0);
+    assert_eq!(lock.read(), (20000, 20000));
+    lock.write((1, 1));
+    assert_eq!(lock.read(), (1, 1));
+}
@@ -0,0 +1,64 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::index_queue::IndexQueue;
+use rs_lockfree::slot_ring::SlotRing;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+// Producers build messages in place in a small ring and send their slot indices through a queue,
+// while consumers claim and check them. Every message arrives intact, and all slots are freed.
+#[test]
+fn test_multi_threads() {
+    let producer_num = 2;
+    let consumer_num = 2;
+    let test_num = 20000usize;
+    let ring: Arc<SlotRing<Vec<usize>>> = Arc::new(SlotRing::with_capacity(8));
+    let queue = Arc::new(IndexQueue::with_capacity(8));
+    let received = Arc::new(AtomicUsize::new(0));
+
+    let mut threads = vec![];
+    for id in 0..producer_num {
+        let ring = ring.clone();
+        let queue = queue.clone();
+        threads.push(thread::spawn(move || {
+            for seq in 0..test_num {
+                let mut slot = ring.alloc();
+                slot.clear();
+                slot.extend((0..seq % 16).map(|_| id * test_num + seq));
+                let mut index = slot.into_index();
+                // Slots of the queue are freed a bit after popping, so it may be full for a while.
+                while let Err(e) = queue.try_push(index) {
+                    index = e.value;
+                    thread::yield_now();
+                }
+            }
+        }));
+    }
+    for _ in 0..consumer_num {
+        let ring = ring.clone();
+        let queue = queue.clone();
+        let received = received.clone();
+        threads.push(thread::spawn(move || {
+            while received.load(Ordering::SeqCst) < producer_num * test_num {
+                match queue.pop() {
+                    Some(index) => {
+                        let slot = ring.claim(index).unwrap();
+                        if let Some(&v) = slot.first() {
+                            assert_eq!(slot.len(), v % test_num % 16);
+                            assert!(slot.iter().all(|&x| x == v));
+                        }
+                        received.fetch_add(1, Ordering::SeqCst);
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+        }));
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(received.load(Ordering::SeqCst), producer_num * test_num);
+    assert_eq!(ring.free_count(), ring.capacity());
+}
@@ -0,0 +1,117 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::spin_lock::{MappedSpinLockGuard, SpinLock, SpinLockGuard};
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn test_multi_threads() {
+    let thread_count = 4;
+    let loop_cnt = 100000;
+    let lock = Arc::new(SpinLock::new((0_u64, 0_u64)));
+
+    let mut threads = vec![];
+    for _ in 0..thread_count {
+        let lock = lock.clone();
+        threads.push(thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                let mut guard = lock.lock().unwrap();
+                assert_eq!(guard.0, guard.1);
+                guard.0 += 1;
+                guard.1 += 1;
+            }
+        }));
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.lock().unwrap(), (thread_count * loop_cnt, thread_count * loop_cnt));
+}
+
+#[test]
+fn test_map() {
+    let lock = SpinLock::new((vec![1, 2], 0));
+    {
+        let mut items = SpinLockGuard::map(lock.lock().unwrap(), |v| &mut v.0);
+        items.push(3);
+        assert!(lock.is_locked());
+        let mut first = MappedSpinLockGuard::map(items, |v| &mut v[0]);
+        *first += 10;
+        assert_eq!(format!("{:?}", first), "11");
+    }
+    assert!(!lock.is_locked());
+
+    let guard = lock.lock().unwrap();
+    let guard = SpinLockGuard::try_map(guard, |v| v.0.get_mut(5)).unwrap_err();
+    let mut last = SpinLockGuard::try_map(guard, |v| v.0.last_mut()).unwrap();
+    *last += 1;
+    drop(last);
+    assert_eq!(*lock.lock().unwrap(), (vec![11, 2, 4], 0));
+}
+
+// Panicking while holding a mapped guard poisons the lock like the original guard.
+#[test]
+fn test_map_poison() {
+    let lock = Arc::new(SpinLock::new((0, 0)));
+    let lock2 = lock.clone();
+    let ret = thread::spawn(move || {
+        let mut count = SpinLockGuard::map(lock2.lock().unwrap(), |v| &mut v.1);
+        *count += 1;
+        panic!("poison current lock");
+    }).join();
+    assert!(ret.is_err());
+    assert!(!lock.is_locked());
+    assert!(lock.is_poisoned());
+    assert_eq!(*lock.lock().unwrap_err().into_inner(), (0, 1));
+}
+
+// A guard shares `&T` with every thread it's shared with, so it's `Sync` only if `T` is. It's
+// `Send` only without feature `debug_lock`, which requires the owner to unlock.
+#[test]
+fn test_guard_auto_traits() {
+    fn is_sync<T: Sync>(_: &T) {}
+    let lock = SpinLock::new(vec![1]);
+    let guard = lock.lock().unwrap();
+    is_sync(&guard);
+    #[cfg(not(feature = "debug_lock"))]
+    {
+        static LOCK: SpinLock<i32> = SpinLock::new(1);
+        let mut guard = LOCK.lock().unwrap();
+        *guard += 1;
+        thread::spawn(move || drop(guard)).join().unwrap();
+        assert_eq!(*LOCK.lock().unwrap(), 2);
+    }
+}
+
+#[cfg(feature = "lock-stats")]
+#[test]
+fn test_contention_stats() {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let lock = Arc::new(SpinLock::new(0));
+    *lock.lock().unwrap() += 1;
+    assert_eq!(lock.contention_stats(), Default::default());
+
+    let guard = lock.lock().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let locker = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            tx.send(()).unwrap();
+            *lock.lock().unwrap() += 1;
+        })
+    };
+    rx.recv().unwrap();
+    thread::sleep(Duration::from_millis(20));
+    drop(guard);
+    locker.join().unwrap();
+
+    let stats = lock.contention_stats();
+    assert!(0 < stats.spins);
+    assert!(stats.wait_ns >= 10_000_000);
+    assert_eq!(*lock.lock().unwrap(), 2);
+}
@@ -0,0 +1,145 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::spin_rwlock::{MappedSpinRwLockReadGuard, MappedSpinRwLockWriteGuard, SpinRwLock,
+                               SpinRwLockReadGuard, SpinRwLockWriteGuard};
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn test_multi_threads() {
+    let writer_count = 2;
+    let reader_count = 2;
+    let loop_cnt = 50000;
+    let lock = Arc::new(SpinRwLock::new([0_u64; 8]));
+
+    let mut threads = vec![];
+    for _ in 0..writer_count {
+        let lock = lock.clone();
+        threads.push(thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                for x in lock.write().iter_mut() {
+                    *x += 1;
+                }
+            }
+        }));
+    }
+    for _ in 0..reader_count {
+        let lock = lock.clone();
+        threads.push(thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                let v = lock.read();
+                for x in v.iter() {
+                    assert_eq!(*x, v[0]);
+                }
+                drop(v);
+                lock.try_optimistic_read(|v| {
+                    for x in v.iter() {
+                        assert_eq!(*x, v[0]);
+                    }
+                });
+            }
+        }));
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert_eq!(*lock.read(), [writer_count * loop_cnt; 8]);
+}
+
+#[test]
+fn test_map() {
+    let lock = SpinRwLock::new((String::from("name"), vec![1, 2, 3]));
+    {
+        let r1 = SpinRwLockReadGuard::map(lock.read(), |v| &v.1);
+        let r2 = SpinRwLockReadGuard::map(lock.read(), |v| &v.0[..]);
+        let first = MappedSpinRwLockReadGuard::map(r1, |v| &v[0]);
+        assert_eq!((*first, &*r2), (1, "name"));
+        assert!(lock.try_write().is_none());
+    }
+    let guard = SpinRwLockReadGuard::try_map(lock.read(), |v| v.1.get(3)).err().unwrap();
+    assert_eq!(*SpinRwLockReadGuard::try_map(guard, |v| v.1.get(2)).ok().unwrap(), 3);
+
+    {
+        let mut items = SpinRwLockWriteGuard::map(lock.write(), |v| &mut v.1);
+        items.push(4);
+        assert!(lock.try_read().is_none());
+        let items = MappedSpinRwLockWriteGuard::try_map(items, |v| v.get_mut(9)).err().unwrap();
+        let mut last = MappedSpinRwLockWriteGuard::map(items, |v| v.last_mut().unwrap());
+        *last += 1;
+        let last = MappedSpinRwLockWriteGuard::downgrade(last);
+        assert_eq!(*last, 5);
+        assert_eq!(*lock.read(), (String::from("name"), vec![1, 2, 3, 5]));
+        assert!(lock.try_write().is_none());
+    }
+    assert!(lock.try_write().is_some());
+}
+
+#[cfg(feature = "lock-stats")]
+#[test]
+fn test_contention_stats() {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let lock = Arc::new(SpinRwLock::new(0));
+    *lock.write() += 1;
+    assert_eq!(*lock.read(), 1);
+    assert_eq!(lock.contention_stats(), Default::default());
+
+    // A reader waits for the writer.
+    let guard = lock.write();
+    let (tx, rx) = mpsc::channel();
+    let reader = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            tx.send(()).unwrap();
+            *lock.read()
+        })
+    };
+    rx.recv().unwrap();
+    thread::sleep(Duration::from_millis(20));
+    drop(guard);
+    assert_eq!(reader.join().unwrap(), 1);
+
+    let stats = lock.contention_stats();
+    assert!(0 < stats.spins);
+    assert!(stats.wait_ns >= 10_000_000);
+}
+
+#[test]
+fn test_state() {
+    let lock = Arc::new(SpinRwLock::new(0));
+    assert_eq!(
+        (lock.reader_count(), lock.is_write_locked(), lock.has_pending_writer()),
+        (0, false, false)
+    );
+    {
+        let _w = lock.write();
+        assert!(lock.is_write_locked());
+        assert_eq!(lock.reader_count(), 0);
+    }
+    let r1 = lock.read();
+    let r2 = lock.read();
+    assert_eq!(lock.reader_count(), 2);
+    assert!(!lock.is_write_locked());
+
+    // A blocked writer is pending until the readers leave.
+    let writer = {
+        let lock = lock.clone();
+        thread::spawn(move || *lock.write() += 1)
+    };
+    while !lock.has_pending_writer() {
+        thread::yield_now();
+    }
+    assert!(lock.try_read().is_none());
+    drop(r1);
+    assert_eq!(lock.reader_count(), 1);
+    drop(r2);
+    writer.join().unwrap();
+    assert_eq!(
+        (lock.reader_count(), lock.is_write_locked(), lock.has_pending_writer()),
+        (0, false, false)
+    );
+    assert_eq!(*lock.read(), 1);
+}
@@ -0,0 +1,55 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::ticket_lock::TicketLock;
+use rs_lockfree::util::WaitPolicy;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn run_multi_threads(lock: TicketLock<Vec<u64>>, thread_count: usize, loop_cnt: u64) {
+    let lock = Arc::new(lock);
+
+    let mut threads = vec![];
+    for idx in 0..thread_count {
+        let lock = lock.clone();
+        threads.push(thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                lock.lock()[idx] += 1;
+            }
+        }));
+    }
+
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert!(!lock.is_locked());
+    assert_eq!(*lock.lock(), vec![loop_cnt; thread_count]);
+}
+
+#[test]
+fn test_multi_threads() {
+    let thread_count = 4;
+    run_multi_threads(TicketLock::new(vec![0; thread_count]), thread_count, 100000);
+}
+
+#[test]
+fn test_wait_policy() {
+    let thread_count = 4;
+    for &policy in [WaitPolicy::Yield, WaitPolicy::Sleep(Duration::from_micros(10))].iter() {
+        let lock = TicketLock::with_wait_policy(vec![0; thread_count], policy);
+        run_multi_threads(lock, thread_count, 1000);
+    }
+}
+
+// A guard shares `&T` with every thread it's shared with, so it's `Sync` only if `T` is.
+#[test]
+fn test_guard_auto_traits() {
+    fn is_sync<T: Sync>(_: &T) {}
+    static LOCK: TicketLock<u64> = TicketLock::new(1);
+    let mut guard = LOCK.lock();
+    is_sync(&guard);
+    *guard += 1;
+    thread::spawn(move || drop(guard)).join().unwrap();
+    assert_eq!(*LOCK.lock(), 2);
+}
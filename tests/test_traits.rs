@@ -0,0 +1,63 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::array_queue::ArrayQueue;
+use rs_lockfree::error::QueueError;
+use rs_lockfree::index_queue::IndexQueue;
+use rs_lockfree::lockfree_queue::LockFreeQueue;
+use rs_lockfree::lockfree_stack::LockFreeStack;
+use rs_lockfree::multi_queue::MultiQueue;
+use rs_lockfree::overwrite_ring::OverwriteRing;
+use rs_lockfree::tagged_stack::TaggedLockFreeStack;
+use rs_lockfree::traits::{ConcurrentQueue, ConcurrentStack};
+
+fn check_queue<Q: ConcurrentQueue<usize>>(queue: &mut Q, n: usize) {
+    assert_eq!(queue.try_pop(), Ok(None));
+    for i in 0..n {
+        assert!(queue.try_push(i).is_ok());
+    }
+    for i in 0..n {
+        assert_eq!(queue.try_pop(), Ok(Some(i)));
+    }
+    assert_eq!(queue.try_pop(), Ok(None));
+}
+
+fn check_stack<S: ConcurrentStack<usize>>(stack: &mut S, n: usize) {
+    assert_eq!(stack.try_pop(), Ok(None));
+    for i in 0..n {
+        assert!(stack.try_push(i).is_ok());
+    }
+    for i in (0..n).rev() {
+        assert_eq!(stack.try_pop(), Ok(Some(i)));
+    }
+    assert_eq!(stack.try_pop(), Ok(None));
+}
+
+#[test]
+fn test_queues() {
+    check_queue(&mut *LockFreeQueue::default_new_in_heap(), 100);
+    check_queue(&mut *MultiQueue::with_shards(1), 100);
+    check_queue(&mut *OverwriteRing::with_capacity(8), 8);
+    check_queue(&mut ArrayQueue::<usize, 8>::new(), 8);
+    check_queue(&mut IndexQueue::with_capacity(8), 8);
+}
+
+#[test]
+fn test_stacks() {
+    check_stack(&mut *LockFreeStack::default_new_in_heap(), 100);
+    check_stack(&mut *TaggedLockFreeStack::default_new_in_heap(), 100);
+}
+
+// Bounded queues give the element back through the trait once full.
+#[test]
+fn test_full() {
+    fn fill<Q: ConcurrentQueue<usize>>(queue: &mut Q, capacity: usize) {
+        for i in 0..capacity {
+            assert!(queue.try_push(i).is_ok());
+        }
+        let e = queue.try_push(capacity).unwrap_err();
+        assert_eq!(e.error, QueueError::Full { op: "try_push" });
+        assert_eq!(e.value, capacity);
+    }
+    fill(&mut ArrayQueue::<usize, 4>::new(), 4);
+    fill(&mut IndexQueue::with_capacity(4), 4);
+}
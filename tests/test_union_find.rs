@@ -0,0 +1,37 @@
+extern crate rs_lockfree;
+
+use rs_lockfree::union_find::UnionFind;
+use std::sync::Arc;
+use std::thread;
+
+// Threads merge the elements of each residue modulo 3 in interleaved orders, which ends in
+// exactly 3 sets. Spinning threads take thread ids, so it has its own test binary.
+#[test]
+fn test_concurrent_union() {
+    let n = 3000;
+    let thread_num = 4;
+    let sets = Arc::new(UnionFind::new(n));
+    let threads: Vec<_> = (0..thread_num)
+        .map(|id| {
+            let sets = sets.clone();
+            thread::spawn(move || {
+                let mut merged = 0;
+                for i in 0..n - 3 {
+                    // Each thread walks edges from a different start.
+                    let e = (i * 7 + id * n / thread_num) % (n - 3);
+                    if sets.union(e, e + 3) {
+                        merged += 1;
+                    }
+                    assert!(sets.same(e, e + 3));
+                }
+                merged
+            })
+        })
+        .collect();
+    let merged: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+    assert_eq!(merged, n - 3);
+    for i in 0..n {
+        assert!(sets.same(i, i % 3));
+        assert!(!sets.same(i, (i + 1) % 3));
+    }
+}